@@ -6,12 +6,13 @@ use quote::{format_ident, quote};
 use syn::parse::ParseStream;
 
 use parse::{
-    parse_composition_permute_repeat, parse_decomposition, parse_reduce, Composition,
-    Decomposition, Index, Operation, Shape,
+    left_axis_spans, parse_composition_permute_repeat, parse_decomposition, parse_reduce,
+    Composition, Decomposition, Index, Operation, Shape,
 };
 use tokens::{
-    to_tokens_composition, to_tokens_decomposition, to_tokens_permute, to_tokens_reduce,
-    to_tokens_repeat,
+    to_tokens_bindings, to_tokens_broadcast_checks, to_tokens_composition_shape,
+    to_tokens_contiguous, to_tokens_decomposition, to_tokens_decomposition_checks,
+    to_tokens_permute, to_tokens_rank_check, to_tokens_reduce, to_tokens_repeat, to_tokens_reshape,
 };
 
 pub fn einops(input: proc_macro2::TokenStream) -> syn::Result<proc_macro2::TokenStream> {
@@ -22,28 +23,161 @@ pub fn einops(input: proc_macro2::TokenStream) -> syn::Result<proc_macro2::Token
 
 #[derive(Debug)]
 struct ParsedExpression {
+    pattern: syn::LitStr,
     tensor: syn::Ident,
     tensor_expression: proc_macro2::TokenStream,
     expression: Expression,
+    // Set with the `contiguous = true` option, makes the output contiguous
+    contiguous: bool,
+    // Set with the `repeat = copy` option, copies repeated axes right away
+    // instead of repeating them with a view
+    repeat_copy: bool,
+    // Set with the `broadcast = strict` option, rejects input axes of length 1
+    // broadcast to new axes
+    strict_broadcast: bool,
+    // Set with the `empty_reduction = error` option, rejects every reduction
+    // of an empty axis
+    empty_reduction_error: bool,
+    // The input if it's an `einops!` call itself
+    nested: Option<Box<ParsedExpression>>,
+}
+
+// What the final composition of an expression does
+#[derive(Clone, Copy)]
+enum Compose {
+    Reshape,
+    // Its output is decomposed by an outer expression, which only reads the
+    // composed shape, so the two reshapes become one
+    Shape,
 }
 
 impl syn::parse::Parse for ParsedExpression {
     fn parse(input: syn::parse::ParseStream) -> syn::Result<Self> {
-        let expression: Expression = input.parse::<syn::LitStr>()?.parse()?;
+        let pattern = input.parse::<syn::LitStr>()?;
+        let expression: Expression = pattern.parse()?;
 
         input.parse::<syn::Token![,]>()?;
 
-        let (tensor_ident, tensor_tokens) = {
+        let (tensor_ident, tensor_tokens, expr) = {
             let tensor_ident = Ident::new("input", Span::call_site());
             let expr = input.parse::<syn::Expr>()?;
             let tensor_tokens = quote!(let #tensor_ident = #expr;);
-            (tensor_ident, tensor_tokens)
+            (tensor_ident, tensor_tokens, expr)
         };
 
+        // Nested calls are `einops!(..)?`, the ones that don't parse are left
+        // to their own expansion to report the error
+        let nested = match expr {
+            syn::Expr::Try(syn::ExprTry { ref expr, .. }) => match **expr {
+                syn::Expr::Macro(syn::ExprMacro { ref mac, .. })
+                    if mac
+                        .path
+                        .segments
+                        .last()
+                        .is_some_and(|segment| segment.ident == "einops") =>
+                {
+                    syn::parse2::<ParsedExpression>(mac.tokens.clone())
+                        .ok()
+                        .map(Box::new)
+                }
+                _ => None,
+            },
+            _ => None,
+        };
+
+        let mut contiguous = false;
+        let mut repeat_copy = false;
+        let mut strict_broadcast = false;
+        let mut empty_reduction_error = false;
+        while input.parse::<Option<syn::Token![,]>>()?.is_some() && !input.is_empty() {
+            let option = input.parse::<syn::Ident>()?;
+            input.parse::<syn::Token![=]>()?;
+            if option == "contiguous" {
+                contiguous = input.parse::<syn::LitBool>()?.value;
+            } else if option == "repeat" {
+                let strategy = input.parse::<syn::Ident>()?;
+                repeat_copy = match strategy.to_string().as_str() {
+                    "view" => false,
+                    "copy" => true,
+                    _ => {
+                        return Err(syn::Error::new(
+                            strategy.span(),
+                            format!(
+                                "Unknown repeat strategy `{}`, expected `view` or `copy`",
+                                strategy
+                            ),
+                        ))
+                    }
+                };
+                if expression.repeat.is_empty() {
+                    return Err(syn::Error::new(
+                        option.span(),
+                        "The `repeat` option needs a pattern that repeats axes",
+                    ));
+                }
+            } else if option == "broadcast" {
+                let policy = input.parse::<syn::Ident>()?;
+                strict_broadcast = match policy.to_string().as_str() {
+                    "implicit" => false,
+                    "strict" => true,
+                    _ => {
+                        return Err(syn::Error::new(
+                            policy.span(),
+                            format!(
+                                "Unknown broadcast policy `{}`, expected `implicit` or `strict`",
+                                policy
+                            ),
+                        ))
+                    }
+                };
+                if expression.repeat.is_empty() {
+                    return Err(syn::Error::new(
+                        option.span(),
+                        "The `broadcast` option needs a pattern that repeats axes",
+                    ));
+                }
+            } else if option == "empty_reduction" {
+                let policy = input.parse::<syn::Ident>()?;
+                empty_reduction_error = match policy.to_string().as_str() {
+                    "identity" => false,
+                    "error" => true,
+                    _ => {
+                        return Err(syn::Error::new(
+                            policy.span(),
+                            format!(
+                                "Unknown empty reduction policy `{}`, expected `identity` or `error`",
+                                policy
+                            ),
+                        ))
+                    }
+                };
+                if expression.reduce.is_empty() {
+                    return Err(syn::Error::new(
+                        option.span(),
+                        "The `empty_reduction` option needs a pattern that reduces axes",
+                    ));
+                }
+            } else {
+                return Err(syn::Error::new(
+                    option.span(),
+                    format!(
+                        "Unknown option `{}`, expected `contiguous`, `repeat`, `broadcast` or `empty_reduction`",
+                        option
+                    ),
+                ));
+            }
+        }
+
         Ok(Self {
+            pattern,
             tensor: tensor_ident,
             tensor_expression: tensor_tokens,
             expression,
+            contiguous,
+            repeat_copy,
+            strict_broadcast,
+            empty_reduction_error,
+            nested,
         })
     }
 }
@@ -64,6 +198,11 @@ struct Expression {
     repeat: Vec<(Index, Shape)>,
     // Step 5, Combining dimensions into a single dimension
     composition: Vec<Composition>,
+    // Axes of the left side given a size on the right side, which is ignored
+    ignored_sizes: Vec<String>,
+    // Axes of the left side grouped with new axes on the right side, with the
+    // sizes of the new axes
+    repeated_with: Vec<(String, Vec<Shape>)>,
 }
 
 impl syn::parse::Parse for Expression {
@@ -72,8 +211,14 @@ impl syn::parse::Parse for Expression {
 
         let reduce = parse_reduce(&decomposition);
 
-        let (composition, permute, repeat) =
-            parse_composition_permute_repeat(input, &decomposition)?;
+        let mut ignored_sizes = Vec::new();
+        let mut repeated_with = Vec::new();
+        let (composition, permute, repeat) = parse_composition_permute_repeat(
+            input,
+            &decomposition,
+            &mut ignored_sizes,
+            &mut repeated_with,
+        )?;
 
         Ok(Expression {
             requires_decomposition,
@@ -82,16 +227,215 @@ impl syn::parse::Parse for Expression {
             permute,
             repeat,
             composition,
+            ignored_sizes,
+            repeated_with,
         })
     }
 }
 
-impl quote::ToTokens for ParsedExpression {
-    fn to_tokens(&self, tokens: &mut proc_macro2::TokenStream) {
+impl Expression {
+    // If the size of every axis is given in the expression, returns the number
+    // of axes before repeating and the shape after composition. The shapes are
+    // then emitted as constants instead of being read from the tensor.
+    fn static_shapes(&self) -> Option<(usize, Vec<proc_macro2::TokenStream>)> {
+        let size = |shape: &Shape| match shape {
+            Shape::Lit(size) => quote!(#size),
+            Shape::Expr(expression) => quote!((#expression)),
+        };
+
+        // Sizes of the axes left after reducing
+        let mut axes = Vec::new();
+        for expression in &self.decomposition {
+            match expression {
+                Decomposition::Named {
+                    index: Index::Known(_),
+                    operation,
+                    shape: Some(shape),
+                    ..
+                } => {
+                    if operation.is_none() {
+                        axes.push(size(shape));
+                    }
+                }
+                _ => return None,
+            }
+        }
+
+        let mut axes = self
+            .permute
+            .iter()
+            .map(|index| match index {
+                Index::Known(i) => axes.get(*i).cloned(),
+                _ => None,
+            })
+            .collect::<Option<Vec<_>>>()?;
+        let naxes = axes.len();
+
+        for (index, shape) in &self.repeat {
+            match index {
+                Index::Known(i) if *i <= axes.len() => axes.insert(*i, size(shape)),
+                _ => return None,
+            }
+        }
+
+        let shape = self
+            .composition
+            .iter()
+            .map(|expression| match expression {
+                Composition::Individual(Index::Known(i))
+                | Composition::Combined {
+                    from: Index::Known(i),
+                    to: None,
+                } => axes.get(*i).cloned(),
+                Composition::Combined {
+                    from: Index::Known(from),
+                    to: Some(Index::Known(to)),
+                } => {
+                    let combined = axes.get(*from..=*to)?;
+                    Some(quote!(::candle_einops::checked_product(&[#(#combined),*])?))
+                }
+                _ => None,
+            })
+            .collect::<Option<Vec<_>>>()?;
+
+        Some((naxes, shape))
+    }
+
+    // Matches `b (c p1 p2) h w -> b c (h p1) (w p2)` and its inverse
+    // `b c (h p1) (w p2) -> b (c p1 p2) h w` with given sizes of `p1` and
+    // `p2`, returns whether it's the former and the two sizes
+    fn pixel_shuffle(&self) -> Option<(bool, proc_macro2::TokenStream, proc_macro2::TokenStream)> {
+        if !self.reduce.is_empty() || !self.repeat.is_empty() {
+            return None;
+        }
+
+        let axes = self
+            .decomposition
+            .iter()
+            .map(|expression| match expression {
+                Decomposition::Named {
+                    index: Index::Known(i),
+                    operation: None,
+                    shape,
+                    ..
+                } => Some((*i, shape.as_ref())),
+                Decomposition::Derived {
+                    index: Index::Known(i),
+                    operation: None,
+                    ..
+                } => Some((*i, None)),
+                _ => None,
+            })
+            .collect::<Option<Vec<_>>>()?;
+        let inputs = axes.iter().map(|(i, _)| *i).collect::<Vec<_>>();
+        let permute = self
+            .permute
+            .iter()
+            .map(|index| match index {
+                Index::Known(i) => Some(*i),
+                _ => None,
+            })
+            .collect::<Option<Vec<_>>>()?;
+        let composition = self
+            .composition
+            .iter()
+            .map(|expression| match expression {
+                Composition::Individual(Index::Known(i))
+                | Composition::Combined {
+                    from: Index::Known(i),
+                    to: None,
+                } => Some((*i, *i)),
+                Composition::Combined {
+                    from: Index::Known(from),
+                    to: Some(Index::Known(to)),
+                } => Some((*from, *to)),
+                _ => None,
+            })
+            .collect::<Option<Vec<_>>>()?;
+
+        // Positions of `p1` and `p2` on the left
+        let (depth_to_space, (p1, p2)) = match (&*inputs, &*permute, &*composition) {
+            ([0, 1, 1, 1, 2, 3], [0, 1, 4, 2, 5, 3], [(0, 0), (1, 1), (2, 3), (4, 5)]) => {
+                (true, (2, 3))
+            }
+            ([0, 1, 2, 2, 3, 3], [0, 1, 3, 5, 2, 4], [(0, 0), (1, 3), (4, 4), (5, 5)]) => {
+                (false, (3, 5))
+            }
+            _ => return None,
+        };
+        let size = |i: usize| match axes[i].1? {
+            Shape::Lit(size) => Some(quote!(#size)),
+            Shape::Expr(expression) => Some(quote!((#expression))),
+        };
+
+        Some((depth_to_space, size(p1)?, size(p2)?))
+    }
+}
+
+impl ParsedExpression {
+    // The nested input if its output is only reshaped before this expression
+    // decomposes it again
+    fn fused(&self) -> Option<&ParsedExpression> {
+        let inner = self.nested.as_deref()?;
+        let composes = inner
+            .expression
+            .composition
+            .iter()
+            .any(|expression| matches!(expression, Composition::Combined { .. }));
+        let fusable = composes
+            && !inner.contiguous
+            && self.expression.requires_decomposition
+            && inner.expression.pixel_shuffle().is_none()
+            && self.expression.pixel_shuffle().is_none();
+        fusable.then_some(inner)
+    }
+
+    // The innermost fused expression, which binds the input tensor
+    fn innermost(&self) -> &ParsedExpression {
+        match self.fused() {
+            Some(inner) => inner.innermost(),
+            None => self,
+        }
+    }
+
+    // Patterns of the fused expressions, innermost first
+    fn patterns(&self) -> Vec<&syn::LitStr> {
+        let mut patterns = self.fused().map_or_else(Vec::new, |inner| inner.patterns());
+        patterns.push(&self.pattern);
+        patterns
+    }
+
+    // Warnings for the sizes ignored by the fused expressions. There are no
+    // warnings for proc macros on stable Rust, so each one is the use of a
+    // deprecated constant whose note is the message, pointing at the pattern
+    fn warnings(&self) -> proc_macro2::TokenStream {
+        let mut warnings = self
+            .fused()
+            .map_or_else(Default::default, |inner| inner.warnings());
+        for name in &self.expression.ignored_sizes {
+            let note = format!(
+                "the size of `{}` on the right side is ignored, its length is read from the input",
+                name
+            );
+            warnings.extend(quote::quote_spanned! {self.pattern.span()=> {
+                #[deprecated(note = #note)]
+                #[allow(non_upper_case_globals)]
+                const ignored_size: () = ();
+                let () = ignored_size;
+            }});
+        }
+        warnings
+    }
+
+    fn transform_tokens(&self, compose: Compose) -> proc_macro2::TokenStream {
         let ParsedExpression {
             tensor: ref tensor_ident,
-            tensor_expression: ref tensor_tokens,
             ref expression,
+            contiguous,
+            repeat_copy,
+            strict_broadcast,
+            empty_reduction_error,
+            ..
         } = self;
         let Expression {
             requires_decomposition,
@@ -100,6 +444,8 @@ impl quote::ToTokens for ParsedExpression {
             ref permute,
             ref repeat,
             ref composition,
+            ref repeated_with,
+            ..
         } = expression;
 
         // Variable to store the shape slice
@@ -116,6 +462,7 @@ impl quote::ToTokens for ParsedExpression {
                 tensor_ident,
                 &ignored_len_ident,
                 &shape_ident,
+                &self.pattern,
             )
         } else {
             proc_macro2::TokenStream::new()
@@ -146,7 +493,13 @@ impl quote::ToTokens for ParsedExpression {
             let requires_ignored_len = reduce
                 .iter()
                 .any(|(index, _)| matches!(index, Index::Range(_) | Index::Unknown(_)));
-            let tokens = to_tokens_reduce(reduce, tensor_ident, &ignored_len_ident);
+            let tokens = to_tokens_reduce(
+                reduce,
+                tensor_ident,
+                &ignored_len_ident,
+                &self.pattern,
+                *empty_reduction_error,
+            );
             (tokens, requires_ignored_len)
         } else {
             (proc_macro2::TokenStream::new(), false)
@@ -157,43 +510,114 @@ impl quote::ToTokens for ParsedExpression {
             let requires_ignored_len = permute
                 .iter()
                 .any(|expression| matches!(expression, Index::Range(_) | Index::Unknown(_)));
-            let tokens = to_tokens_permute(permute, tensor_ident, &ignored_len_ident);
+            let tokens =
+                to_tokens_permute(permute, tensor_ident, &ignored_len_ident, &self.pattern);
             (tokens, requires_ignored_len)
         } else {
             (proc_macro2::TokenStream::new(), false)
         };
 
+        let static_shapes = expression.static_shapes();
+
         // If needed we generate tokens for repeating the tensor
         let (repeat_tokens, repeat_ignored_len) = if !repeat.is_empty() {
             let requires_ignored_len = repeat.iter().any(|(i, _)| matches!(i, Index::Unknown(_)));
-            let tokens = to_tokens_repeat(repeat, tensor_ident, &ignored_len_ident, &shape_ident);
+            let naxes = match static_shapes {
+                Some((naxes, _)) => quote!(#naxes),
+                None => quote!(#shape_ident.len()),
+            };
+            let mut tokens = to_tokens_repeat(
+                repeat,
+                tensor_ident,
+                &ignored_len_ident,
+                naxes,
+                &self.pattern,
+            );
+            if *repeat_copy {
+                tokens.extend(to_tokens_contiguous(tensor_ident, &self.pattern));
+            }
             (tokens, requires_ignored_len)
         } else {
             (proc_macro2::TokenStream::new(), false)
         };
 
         // If needed we generate tokens for combining dimensions of the tensor
-        let (composition_tokens, composition_ignored_len) = if composition
+        let (composition_shape, composition_ignored_len) = if composition
             .iter()
             .any(|expression| matches!(expression, Composition::Combined { .. }))
         {
-            let requires_ignored_len = composition.iter().any(|expression| {
+            if let Some((_, ref shape)) = static_shapes {
+                (quote!([#(#shape),*]), false)
+            } else {
+                let requires_ignored_len = composition.iter().any(|expression| {
+                    matches!(
+                        expression,
+                        Composition::Combined {
+                            from: Index::Unknown(_) | Index::Range(_),
+                            to: Some(Index::Unknown(_) | Index::Range(_)) | None
+                        } | Composition::Individual(Index::Range(_) | Index::Unknown(_))
+                    )
+                });
+                let shape =
+                    to_tokens_composition_shape(composition, &ignored_len_ident, &shape_ident);
+                (shape, requires_ignored_len)
+            }
+        } else {
+            (proc_macro2::TokenStream::new(), false)
+        };
+        let composition_tokens = if composition_shape.is_empty() {
+            proc_macro2::TokenStream::new()
+        } else {
+            to_tokens_reshape(composition_shape.clone(), tensor_ident, &self.pattern)
+        };
+
+        // Axes are checked against the sizes in the pattern, even the ones that
+        // aren't decomposed
+        let decomposition_check_tokens = to_tokens_decomposition_checks(
+            decomposition,
+            &self.pattern,
+            &ignored_len_ident,
+            &shape_ident,
+        );
+        let check_ignored_len = decomposition.iter().any(|expression| {
+            matches!(
+                expression,
+                Decomposition::Named {
+                    index: Index::Unknown(_),
+                    shape: Some(_),
+                    ..
+                }
+            )
+        });
+
+        // Axes of length 1 the pattern would broadcast without saying so
+        let (broadcast_check_tokens, broadcast_ignored_len) = if *strict_broadcast {
+            let tokens = to_tokens_broadcast_checks(
+                decomposition,
+                repeated_with,
+                &self.pattern,
+                &ignored_len_ident,
+                &shape_ident,
+            );
+            let requires_ignored_len = decomposition.iter().any(|expression| {
                 matches!(
                     expression,
-                    Composition::Combined {
-                        from: Index::Unknown(_) | Index::Range(_),
-                        to: Some(Index::Unknown(_) | Index::Range(_)) | None
-                    } | Composition::Individual(Index::Range(_) | Index::Unknown(_))
+                    Decomposition::Named {
+                        index: Index::Unknown(_),
+                        shape: None,
+                        operation: None,
+                        ..
+                    }
                 )
             });
-            let tokens =
-                to_tokens_composition(composition, tensor_ident, &ignored_len_ident, &shape_ident);
             (tokens, requires_ignored_len)
         } else {
             (proc_macro2::TokenStream::new(), false)
         };
 
         let ignored_len_tokens = if decomposition_ignored_len
+            || check_ignored_len
+            || broadcast_ignored_len
             || reduce_ignored_len
             || permute_ignored_len
             || repeat_ignored_len
@@ -223,32 +647,18 @@ impl quote::ToTokens for ParsedExpression {
             proc_macro2::TokenStream::new()
         };
 
-        // We need the shape of the tensor, if either of ignored length calculation, or
-        // decomposition operation has to take place
-        let shape_tokens = match (
-            ignored_len_tokens.is_empty(),
-            decomposition_tokens.is_empty(),
-            // If all of the shapes of input tensor is known, we can skip
-            // calculating the shape of the tensor
-            decomposition.iter().any(|expression| {
-                matches!(
-                    expression,
-                    Decomposition::Derived { .. } | Decomposition::Named { shape: None, .. }
-                )
-            }),
-        ) {
-            (true, true, _) => proc_macro2::TokenStream::new(),
-            (false, _, _) | (_, false, true) => {
-                quote!(let #shape_ident = ::candle_einops::Backend::shape(&#tensor_ident);)
-            }
-            (_, false, false) => proc_macro2::TokenStream::new(),
-        };
+        // The shape of the input is always read once to validate it, even when
+        // every axis has a size in the pattern and the shapes are constants
+        let shape_tokens =
+            quote!(let #shape_ident = ::candle_einops::Backend::shape(&#tensor_ident););
 
         // We have to recalculate the shape of the tensor before repeat transformation
         let repeat_shape_tokens = if repeat_tokens.is_empty() ||
+            // Constant shapes don't need the shape of the tensor
+            static_shapes.is_some() ||
             // We can skip it if non of the first three transformations happen,
-            // and we already have the shape slice from the ignored length calculation
-            (tokens_empty.iter().take(3).all(|x| *x) && !ignored_len_tokens.is_empty())
+            // the shape read from the input is still the same
+            tokens_empty.iter().take(3).all(|x| *x)
         {
             proc_macro2::TokenStream::new()
         } else {
@@ -257,24 +667,53 @@ impl quote::ToTokens for ParsedExpression {
 
         // We have to recalculate the shape of the tensor before composition transformation
         let composition_shape_tokens = if composition_tokens.is_empty() ||
+            // Constant shapes don't need the shape of the tensor
+            static_shapes.is_some() ||
             // We can skip it if non of the first four transformations happen,
-            // and we already have the shape slice from the ignored length calculation
-            (tokens_empty.iter().take(4).all(|x| *x) && !ignored_len_tokens.is_empty())
+            // the shape read from the input is still the same
+            tokens_empty.iter().take(4).all(|x| *x)
         {
             proc_macro2::TokenStream::new()
         } else {
             quote!(let #shape_ident = ::candle_einops::Backend::shape(&#tensor_ident);)
         };
 
-        let code = quote! {{
+        // The shape read from the input checks its number of axes, before it's
+        // indexed by position
+        let rank_check_tokens = to_tokens_rank_check(decomposition, &self.pattern, &shape_ident);
+
+        let composition_tokens = match compose {
+            Compose::Reshape => composition_tokens,
+            Compose::Shape => quote!(let #shape_ident = #composition_shape;),
+        };
+
+        // A fused input leaves its output uncomposed and hands over its shape
+        let (fused_tokens, shape_tokens) = match self.fused() {
+            Some(inner) => (
+                inner.transform_tokens(Compose::Shape),
+                proc_macro2::TokenStream::new(),
+            ),
+            None => (proc_macro2::TokenStream::new(), shape_tokens),
+        };
+
+        let contiguous_tokens = if *contiguous {
+            to_tokens_contiguous(tensor_ident, &self.pattern)
+        } else {
+            proc_macro2::TokenStream::new()
+        };
+
+        let transform_tokens = quote! {
             #error_tokens
 
-            #tensor_tokens
+            #fused_tokens
 
             #shape_tokens
+            #rank_check_tokens
 
             #ignored_len_tokens
 
+            #decomposition_check_tokens
+            #broadcast_check_tokens
             #decomposition_tokens
 
             #reduce_tokens
@@ -286,8 +725,84 @@ impl quote::ToTokens for ParsedExpression {
 
             #composition_shape_tokens
             #composition_tokens
+        };
+
+        // Pixel shuffles have dedicated kernels on some backends, the
+        // transformations only run if the backend doesn't have one
+        let transform_tokens = match expression.pixel_shuffle() {
+            Some((depth_to_space, p1, p2)) => {
+                let kind = if depth_to_space {
+                    "depth_to_space"
+                } else {
+                    "space_to_depth"
+                };
+                let method = format_ident!("{}", kind);
+                let pattern = &self.pattern;
+                quote! {
+                    let #tensor_ident = match ::candle_einops::Backend::#method(&#tensor_ident, #p1, #p2) {
+                        ::std::option::Option::Some(output) => {
+                            ::candle_einops::trace::before(#pattern, #kind, &#tensor_ident);
+                            ::candle_einops::trace::step(#pattern, #kind, &#tensor_ident, ::std::result::Result::Ok(&output));
+                            output
+                        }
+                        ::std::option::Option::None => {
+                            #transform_tokens
+
+                            #tensor_ident
+                        }
+                    };
+                }
+            }
+            None => transform_tokens,
+        };
 
-            #tensor_ident
+        quote! {
+            #transform_tokens
+
+            #contiguous_tokens
+        }
+    }
+}
+
+impl quote::ToTokens for ParsedExpression {
+    fn to_tokens(&self, tokens: &mut proc_macro2::TokenStream) {
+        let pattern = &self.pattern;
+        let tensor_ident = &self.tensor;
+        let innermost = self.innermost();
+        let tensor_tokens = &innermost.tensor_expression;
+        let transform_tokens = self.transform_tokens(Compose::Reshape);
+
+        let patterns = self.patterns();
+        let shape_ident = format_ident!("{}_{}", tensor_ident, "shape");
+        let bindings = to_tokens_bindings(&innermost.expression.decomposition, &shape_ident);
+        // Text of every axis on the left side of the innermost pattern
+        let innermost_pattern = innermost.pattern.value();
+        let axes = left_axis_spans(&innermost_pattern)
+            .into_iter()
+            .map(|(offset, len)| innermost_pattern[offset..offset + len].to_string());
+
+        // The transformations are wrapped in a closure so backend errors can be
+        // propagated with `?`, the whole expression evaluates to a `Result`
+        let warnings = self.warnings();
+
+        let code = quote! {{
+            #warnings
+            #tensor_tokens
+
+            (|| -> ::std::result::Result<_, ::candle_einops::EinopsError> {
+                // Work done by fused inputs is counted for the outermost pattern
+                let _scope = ::candle_einops::metrics::enter(#pattern);
+                let _span = ::candle_einops::trace::enter(#pattern, &#tensor_ident);
+
+                #transform_tokens
+
+                ::std::result::Result::Ok(#tensor_ident)
+            })()
+            .map_err(|error| {
+                let #shape_ident = ::candle_einops::Backend::shape(&#tensor_ident);
+                let bindings = #bindings;
+                error.with_context(&[#(#patterns),*], &[#(#axes),*], #shape_ident, bindings)
+            })
         }};
 
         code.to_tokens(tokens);
@@ -196,10 +196,13 @@ pub fn parse_decomposition(input: ParseStream) -> syn::Result<(Vec<Decomposition
 }
 
 fn parse_left_parenthesized(input: ParseStream, index: Index) -> syn::Result<Vec<Decomposition>> {
+    let span = input.span();
     let content;
     syn::parenthesized!(content in input);
 
     let mut content_expression = Vec::new();
+    // Names of the axes without a size, only the first one can be inferred
+    let mut unknown = Vec::new();
 
     let (derived_name, derived_index, running_mul, shape_expr) = (0..)
         .into_iter()
@@ -213,7 +216,12 @@ fn parse_left_parenthesized(input: ParseStream, index: Index) -> syn::Result<Vec
                 let mut update_values = |name, shape, operation| {
                     if let Some(size) = shape {
                         match size {
-                            Shape::Lit(lit_size) => running_mul *= lit_size,
+                            Shape::Lit(lit_size) => {
+                                running_mul =
+                                    usize::checked_mul(running_mul, lit_size).ok_or_else(|| {
+                                        content.error("The product of the sizes overflows")
+                                    })?
+                            }
                             Shape::Expr(ref expression) => shape_expr.push(expression.clone()),
                         }
                         content_expression.push(Decomposition::Named {
@@ -222,14 +230,11 @@ fn parse_left_parenthesized(input: ParseStream, index: Index) -> syn::Result<Vec
                             operation,
                             shape: Some(size),
                         });
-                    } else {
-                        if derived_name.is_some() {
-                            // We cannot have more than one dimension with unknown shape
-                            return Err(content
-                                .error("Shape information required to complete decomposition"));
-                        }
+                    } else if derived_name.is_none() {
                         derived_name = Some(name);
                         derived_index = Some(i);
+                    } else {
+                        unknown.push(name);
                     }
                     Ok(())
                 };
@@ -269,6 +274,14 @@ fn parse_left_parenthesized(input: ParseStream, index: Index) -> syn::Result<Vec
             },
         )?;
 
+    if !unknown.is_empty() {
+        return Err(ambiguous_inference_error(
+            span,
+            derived_name.as_deref().unwrap(),
+            &unknown,
+        ));
+    }
+
     // We add the `Derived` dimension at the end at its index,
     // once we have the running multiple of all the other shapes
     // inside the parenthesis
@@ -280,7 +293,7 @@ fn parse_left_parenthesized(input: ParseStream, index: Index) -> syn::Result<Vec
                 index,
                 operation: None,
                 shape_calc: quote::quote!(
-                    (#running_mul * [#(#shape_expr),*].iter().product::<usize>())
+                    ::candle_einops::checked_product(&[#running_mul #(, #shape_expr)*])
                 ),
             },
         );
@@ -289,6 +302,39 @@ fn parse_left_parenthesized(input: ParseStream, index: Index) -> syn::Result<Vec
     Ok(content_expression)
 }
 
+// Lists the axes of a group that have no size, and suggests sizing all of
+// them but the first
+fn ambiguous_inference_error(span: proc_macro2::Span, first: &str, rest: &[String]) -> syn::Error {
+    let candidates = std::iter::once(first)
+        .chain(rest.iter().map(String::as_str))
+        .collect::<Vec<_>>();
+    let bind = rest.iter().map(String::as_str).collect::<Vec<_>>();
+    syn::Error::new(
+        span,
+        format!(
+            "Ambiguous inference: the lengths of {} are all unknown, only one axis of a \
+             group can be inferred. Give {} {} like `{}:2`",
+            list_names(&candidates),
+            list_names(&bind),
+            if bind.len() == 1 { "a size" } else { "sizes" },
+            rest[0]
+        ),
+    )
+}
+
+// `a`, `b` and `c`
+fn list_names(names: &[&str]) -> String {
+    let names = names
+        .iter()
+        .map(|name| format!("`{}`", name))
+        .collect::<Vec<_>>();
+    match names.split_last() {
+        Some((last, [])) => last.clone(),
+        Some((last, others)) => format!("{} and {}", others.join(", "), last),
+        None => String::new(),
+    }
+}
+
 fn parse_braced_expression(content: ParseStream) -> syn::Result<(String, Shape)> {
     let shape_expression;
     syn::braced!(shape_expression in content);
@@ -353,9 +399,14 @@ pub fn parse_reduce(decomposition: &[Decomposition]) -> Vec<(Index, Operation)>
 }
 
 #[allow(clippy::type_complexity)]
+//
+// Axes of the left side given a size on the right side are added to `ignored`,
+// their length is read from the input and the size isn't used
 pub fn parse_composition_permute_repeat(
     input: ParseStream,
     decomposition: &[Decomposition],
+    ignored: &mut Vec<String>,
+    repeated_with: &mut Vec<(String, Vec<Shape>)>,
 ) -> syn::Result<(Vec<Composition>, Vec<Index>, Vec<(Index, Shape)>)> {
     // We calculate the span to report errors later
     let input_span = input.span();
@@ -441,7 +492,14 @@ pub fn parse_composition_permute_repeat(
             i += parenthesized_len;
             if input.peek(token::Paren) {
                 let (combined, combined_permute, combined_repeat, combined_len) =
-                    parse_right_parenthesized(input, i, &mut index_fn, &positions)?;
+                    parse_right_parenthesized(
+                        input,
+                        i,
+                        &mut index_fn,
+                        &positions,
+                        ignored,
+                        repeated_with,
+                    )?;
                 parenthesized_len += combined_len.saturating_sub(1);
                 permute.extend(combined_permute);
                 repeat.extend(combined_repeat);
@@ -449,13 +507,15 @@ pub fn parse_composition_permute_repeat(
             } else if input.peek(syn::Ident) {
                 let (name, shape) = parse_identifier(input)?;
                 if let Some(index) = positions.get(&name) {
+                    if shape.is_some() {
+                        ignored.push(name);
+                    }
                     permute.push(index.clone());
                 } else {
                     // New identifiers represents repetition
-                    repeat.push((
-                        index_fn(i),
-                        shape.expect("New identifier on the right should have a shape"),
-                    ));
+                    let shape =
+                        shape.ok_or_else(|| unknown_axis_error(input, &name, &positions))?;
+                    repeat.push((index_fn(i), shape));
                 }
                 composition.push(Composition::Individual(index_fn(i)))
             } else if input.peek(syn::LitInt) {
@@ -507,12 +567,16 @@ fn parse_right_parenthesized(
     start_index: usize,
     index_fn: &mut Box<dyn Fn(usize) -> Index>,
     positions: &HashMap<String, Index>,
+    ignored: &mut Vec<String>,
+    repeated_with: &mut Vec<(String, Vec<Shape>)>,
 ) -> syn::Result<(Composition, Vec<Index>, Vec<(Index, Shape)>, usize)> {
     let content;
     syn::parenthesized!(content in input);
 
     let mut permute = Vec::new();
     let mut repeat = Vec::new();
+    // Axes of the left side in the parenthesis
+    let mut names = Vec::new();
 
     // Closure to parse one entry in the expression and update
     // the relevant lists
@@ -530,12 +594,14 @@ fn parse_right_parenthesized(
         } else if content.peek(syn::Ident) {
             let (name, shape) = parse_identifier(content)?;
             if let Some(index) = positions.get(&name) {
+                if shape.is_some() {
+                    ignored.push(name.clone());
+                }
                 permute.push(index.clone());
+                names.push(name);
             } else {
-                repeat.push((
-                    index_fn(index),
-                    shape.expect("New identifier with no shape specified on the right side"),
-                ));
+                let shape = shape.ok_or_else(|| unknown_axis_error(content, &name, positions))?;
+                repeat.push((index_fn(index), shape));
             }
             Ok(index_fn(index))
         } else if content.peek(syn::LitInt) {
@@ -545,6 +611,7 @@ fn parse_right_parenthesized(
             let (name, shape) = parse_braced_expression(content)?;
             if let Some(index) = positions.get(&name) {
                 permute.push(index.clone());
+                names.push(name);
             } else {
                 repeat.push((index_fn(index), shape));
             }
@@ -576,9 +643,125 @@ fn parse_right_parenthesized(
         0
     };
 
+    // The axes of the left side are broadcast to the new axes next to them
+    if !repeat.is_empty() {
+        let sizes = repeat.iter().map(|(_, shape)| shape.clone()).collect::<Vec<_>>();
+        repeated_with.extend(names.into_iter().map(|name| (name, sizes.clone())));
+    }
+
     Ok((Composition::Combined { from, to }, permute, repeat, len))
 }
 
+// Error for an axis on the right side that's neither on the left side nor has a
+// size, suggests the closest axis of the left side as it's likely a typo
+fn unknown_axis_error(
+    input: ParseStream,
+    name: &str,
+    positions: &HashMap<String, Index>,
+) -> syn::Error {
+    let closest = positions
+        .keys()
+        .filter(|axis| axis.as_str() != "..")
+        .map(|axis| (edit_distance(name, axis), axis))
+        .filter(|&(distance, _)| distance <= name.chars().count() / 3)
+        .min();
+    match closest {
+        Some((_, axis)) => syn::Error::new(
+            input.span(),
+            format!(
+                "Unknown axis `{}` on the right side, did you mean `{}`?",
+                name, axis
+            ),
+        ),
+        None => syn::Error::new(
+            input.span(),
+            format!(
+                "Unknown axis `{}` on the right side, new axes need a size like `{}:2`",
+                name, name
+            ),
+        ),
+    }
+}
+
+// Number of single character insertions, deletions and substitutions turning
+// `a` into `b`
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b = b.chars().collect::<Vec<_>>();
+    let mut previous = (0..=b.len()).collect::<Vec<_>>();
+    for (i, x) in a.chars().enumerate() {
+        let mut current = vec![i + 1; b.len() + 1];
+        for (j, &y) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(x != y);
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+// Byte offset and length of the axes on the left side of `pattern`, by their
+// position. Parenthesized groups are a single axis, reductions like `sum(a b)`
+// are one axis per reduced dimension
+pub fn left_axis_spans(pattern: &str) -> Vec<(usize, usize)> {
+    let left = pattern.split("->").next().unwrap_or_default();
+    let bytes = left.as_bytes();
+
+    // End of the group opened at `start`, or of the word starting there
+    let item_end = |start: usize| {
+        let (open, close) = match bytes[start] {
+            b'(' => (b'(', b')'),
+            b'{' => (b'{', b'}'),
+            _ => {
+                return left[start..]
+                    .find(|c: char| c.is_whitespace() || c == '(' || c == ')')
+                    .map_or(left.len(), |len| start + len)
+            }
+        };
+        let mut depth = 0;
+        for (i, &byte) in bytes.iter().enumerate().skip(start) {
+            if byte == open {
+                depth += 1;
+            } else if byte == close {
+                depth -= 1;
+                if depth == 0 {
+                    return i + 1;
+                }
+            }
+        }
+        left.len()
+    };
+
+    let mut spans = Vec::new();
+    let mut i = 0;
+    while i < left.len() {
+        if bytes[i].is_ascii_whitespace() {
+            i += 1;
+            continue;
+        }
+        let end = item_end(i);
+        let is_reduction = ["min", "max", "sum", "mean", "prod"].contains(&&left[i..end])
+            && bytes.get(end) == Some(&b'(');
+        if is_reduction {
+            let close = item_end(end);
+            let mut j = end + 1;
+            while j < close - 1 {
+                if bytes[j].is_ascii_whitespace() {
+                    j += 1;
+                    continue;
+                }
+                let inner_end = item_end(j).min(close - 1);
+                spans.push((j, inner_end - j));
+                j = inner_end;
+            }
+            i = close;
+        } else {
+            spans.push((i, end - i));
+            i = end.max(i + 1);
+        }
+    }
+    spans
+}
+
 fn peek_reduce_kw(input: ParseStream) -> bool {
     input.peek(kw::min)
         | input.peek(kw::max)
@@ -657,3 +840,85 @@ fn parse_usize(input: ParseStream) -> syn::Result<usize> {
     let len = input.parse::<syn::LitInt>()?;
     len.base10_parse::<usize>()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn error(tokens: proc_macro2::TokenStream) -> String {
+        match syn::parse2::<super::super::ParsedExpression>(tokens) {
+            Ok(_) => panic!("the expression parsed"),
+            Err(error) => error.to_string(),
+        }
+    }
+
+    #[test]
+    fn parse_edit_distance() {
+        assert_eq!(edit_distance("batch", "batch"), 0);
+        assert_eq!(edit_distance("btch", "batch"), 1);
+        assert_eq!(edit_distance("batxh", "batch"), 1);
+        assert_eq!(edit_distance("heigth", "height"), 2);
+        assert_eq!(edit_distance("", "abc"), 3);
+        assert_eq!(edit_distance("channels", "c"), 7);
+    }
+
+    #[test]
+    fn parse_unknown_axis() {
+        // One edit in five characters is within a third of the length
+        assert_eq!(
+            error(quote::quote!("batch t -> btch t", x)),
+            "Unknown axis `btch` on the right side, did you mean `batch`?"
+        );
+        assert_eq!(
+            error(quote::quote!("height w -> w heigth", x)),
+            "Unknown axis `heigth` on the right side, did you mean `height`?"
+        );
+        // Two edits in five characters aren't, and names of one character
+        // are never suggested
+        assert_eq!(
+            error(quote::quote!("batch t -> bacth t", x)),
+            "Unknown axis `bacth` on the right side, new axes need a size like `bacth:2`"
+        );
+        assert_eq!(
+            error(quote::quote!("a b -> a c", x)),
+            "Unknown axis `c` on the right side, new axes need a size like `c:2`"
+        );
+        assert_eq!(
+            error(quote::quote!("batch t -> time t", x)),
+            "Unknown axis `time` on the right side, new axes need a size like `time:2`"
+        );
+    }
+
+    #[test]
+    fn parse_ignored_sizes() {
+        let parsed = |tokens| match syn::parse2::<super::super::ParsedExpression>(tokens) {
+            Ok(parsed) => parsed,
+            Err(error) => panic!("{}", error),
+        };
+        let expression = parsed(quote::quote!("a b -> (b:3 a) a2:2", x));
+        assert_eq!(expression.expression.ignored_sizes, ["b"]);
+        assert!(expression.warnings().to_string().contains("deprecated"));
+
+        let expression = parsed(quote::quote!("a (b c:2) -> a:4 b c r:3", x));
+        assert_eq!(expression.expression.ignored_sizes, ["a"]);
+        let expression = parsed(quote::quote!("a (b c:2) -> a b c r:3", x));
+        assert!(expression.expression.ignored_sizes.is_empty());
+        assert!(expression.warnings().is_empty());
+    }
+
+    #[test]
+    fn parse_ambiguous_inference() {
+        assert_eq!(
+            error(quote::quote!("(a b) c -> a b c", x)),
+            "Ambiguous inference: the lengths of `a` and `b` are all unknown, only one axis of a \
+             group can be inferred. Give `b` a size like `b:2`"
+        );
+        assert_eq!(
+            error(quote::quote!("(h w c) -> h w c", x)),
+            "Ambiguous inference: the lengths of `h`, `w` and `c` are all unknown, only one axis \
+             of a group can be inferred. Give `w` and `c` sizes like `w:2`"
+        );
+        let error = ambiguous_inference_error(proc_macro2::Span::call_site(), "a", &["b".into()]);
+        assert!(error.to_string().ends_with("Give `b` a size like `b:2`"));
+    }
+}
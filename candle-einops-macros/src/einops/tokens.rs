@@ -1,10 +1,10 @@
-use crate::einops::{Composition, Decomposition, Index, Operation, Shape};
+use crate::einops::{left_axis_spans, Composition, Decomposition, Index, Operation, Shape};
 
 use quote::quote;
 
-pub fn to_tokens_composition(
+// Shape of the tensor after combining dimensions
+pub fn to_tokens_composition_shape(
     right_expression: &[Composition],
-    tensor_ident: &syn::Ident,
     ignored_len_ident: &syn::Ident,
     shape_ident: &syn::Ident,
 ) -> proc_macro2::TokenStream {
@@ -109,7 +109,7 @@ pub fn to_tokens_composition(
         },
     );
 
-    let composition_shape = match (
+    match (
         before_ignored.is_empty(),
         ignored.is_empty(),
         after_ignored.is_empty(),
@@ -120,7 +120,7 @@ pub fn to_tokens_composition(
                 .into_iter()
                 .chain(#ignored)
                 .into_iter()
-                .collect::<Vec<_>>()
+                .collect::<::candle_einops::SmallAxes<_>>()
         ),
         (false, false, false) => quote!(
             [#(#before_ignored),*]
@@ -128,28 +128,72 @@ pub fn to_tokens_composition(
                 .chain(#ignored)
                 .chain([#(#after_ignored),*].into_iter())
                 .into_iter()
-                .collect::<Vec<_>>()
+                .collect::<::candle_einops::SmallAxes<_>>()
 
         ),
         (true, false, false) => quote!(
             #ignored
                 .chain([#(#after_ignored),*].into_iter())
                 .into_iter()
-                .collect::<Vec<_>>()
+                .collect::<::candle_einops::SmallAxes<_>>()
         ),
         _ => unreachable!(),
-    };
+    }
+}
 
+// Runs `operation` on the tensor, which evaluates to a `Result`, between the
+// calls to `trace::before` and `trace::step`, before propagating its error
+pub fn to_tokens_step(
+    pattern: &syn::LitStr,
+    kind: &str,
+    tensor_ident: &syn::Ident,
+    operation: proc_macro2::TokenStream,
+) -> proc_macro2::TokenStream {
     quote!(
-        let #tensor_ident = ::candle_einops::Backend::reshape(#tensor_ident, &#composition_shape);
+        let #tensor_ident = {
+            ::candle_einops::trace::before(#pattern, #kind, &#tensor_ident);
+            let output = #operation;
+            ::candle_einops::trace::step(#pattern, #kind, &#tensor_ident, output.as_ref());
+            output?
+        };
     )
 }
 
+pub fn to_tokens_contiguous(
+    tensor_ident: &syn::Ident,
+    pattern: &syn::LitStr,
+) -> proc_macro2::TokenStream {
+    to_tokens_step(
+        pattern,
+        "contiguous",
+        tensor_ident,
+        quote!(::candle_einops::Backend::contiguous(&#tensor_ident)),
+    )
+}
+
+// Reshapes through a view when the backend supports it, copying otherwise
+pub fn to_tokens_reshape(
+    shape: proc_macro2::TokenStream,
+    tensor_ident: &syn::Ident,
+    pattern: &syn::LitStr,
+) -> proc_macro2::TokenStream {
+    let operation = quote!({
+        let shape = #shape;
+        match ::candle_einops::Backend::reshape_view(&#tensor_ident, &shape) {
+            ::std::option::Option::Some(view) => ::std::result::Result::Ok(view),
+            ::std::option::Option::None => ::candle_einops::Backend::reshape(&#tensor_ident, &shape),
+        }
+    });
+    to_tokens_step(pattern, "reshape", tensor_ident, operation)
+}
+
 pub fn to_tokens_repeat(
     repeat: &[(Index, Shape)],
     tensor_ident: &syn::Ident,
     ignored_len_ident: &syn::Ident,
-    shape_ident: &syn::Ident,
+    // Number of axes before repeating
+    naxes: proc_macro2::TokenStream,
+    pattern: &syn::LitStr,
 ) -> proc_macro2::TokenStream {
     let n_repeats = repeat.len();
     let repeat_pos_len = repeat.iter().map(|expression| match expression {
@@ -162,17 +206,19 @@ pub fn to_tokens_repeat(
         _ => unreachable!(),
     });
 
-    quote!(
-        let #tensor_ident = ::candle_einops::Backend::add_axes(
-            #tensor_ident, #shape_ident.len() + #n_repeats, &[#(#repeat_pos_len),*]
-        );
-    )
+    let operation = quote!(
+        ::candle_einops::Backend::add_axes(
+            &#tensor_ident, #naxes + #n_repeats, &[#(#repeat_pos_len),*]
+        )
+    );
+    to_tokens_step(pattern, "add_axes", tensor_ident, operation)
 }
 
 pub fn to_tokens_permute(
     permute: &[Index],
     tensor_ident: &syn::Ident,
     ignored_len_ident: &syn::Ident,
+    pattern: &syn::LitStr,
 ) -> proc_macro2::TokenStream {
     let (before_ignored, ignored_permute, after_ignored, _) = permute.iter().fold(
         (
@@ -223,7 +269,7 @@ pub fn to_tokens_permute(
                 .into_iter()
                 .chain(#ignored_permute)
                 .into_iter()
-                .collect::<Vec<_>>()
+                .collect::<::candle_einops::SmallAxes<_>>()
         ),
         (false, false, false) => quote!(
             [#(#before_ignored),*]
@@ -231,27 +277,35 @@ pub fn to_tokens_permute(
                 .chain(#ignored_permute)
                 .chain([#(#after_ignored),*].into_iter())
                 .into_iter()
-                .collect::<Vec<_>>()
+                .collect::<::candle_einops::SmallAxes<_>>()
 
         ),
         (true, false, false) => quote!(
             #ignored_permute
                 .chain([#(#after_ignored),*].into_iter())
                 .into_iter()
-                .collect::<Vec<_>>()
+                .collect::<::candle_einops::SmallAxes<_>>()
         ),
         _ => unreachable!(),
     };
 
-    quote!(
-        let #tensor_ident = ::candle_einops::Backend::transpose(#tensor_ident, &#permute_indices);
-    )
+    let operation = quote!({
+        let axes = #permute_indices;
+        match ::candle_einops::Backend::transpose_view(&#tensor_ident, &axes) {
+            ::std::option::Option::Some(view) => ::std::result::Result::Ok(view),
+            ::std::option::Option::None => ::candle_einops::Backend::transpose(&#tensor_ident, &axes),
+        }
+    });
+    to_tokens_step(pattern, "transpose", tensor_ident, operation)
 }
 
 pub fn to_tokens_reduce(
     reduce: &[(Index, Operation)],
     tensor_ident: &syn::Ident,
     ignored_len_ident: &syn::Ident,
+    pattern: &syn::LitStr,
+    // Set with the `empty_reduction = error` option
+    empty_reduction_error: bool,
 ) -> proc_macro2::TokenStream {
     let (reduce_indices, reduce_operations, ignored_indices, ignored_operations) =
         reduce.iter().fold(
@@ -295,46 +349,47 @@ pub fn to_tokens_reduce(
             },
         );
 
-    match (
+    let axes_operations = match (
         ignored_indices,
         ignored_operations,
         reduce_indices.is_empty(),
     ) {
-        (Some(ignored_indices), Some(ignored_operations), true) => {
-            quote!(
-                let #tensor_ident = ::candle_einops::Backend::reduce_axes(
-                    #tensor_ident,
-                    &mut #ignored_indices
-                        .zip(#ignored_operations)
-                        .collect::<Vec<(_, _)>>()
-                );
-            )
-        }
-        (Some(ignored_indices), Some(ignored_operations), false) => {
-            quote!(
-                let #tensor_ident = ::candle_einops::Backend::reduce_axes(
-                    #tensor_ident,
-                    &mut [#(#reduce_indices),*]
+        (Some(ignored_indices), Some(ignored_operations), true) => quote!(
+            #ignored_indices
+                .zip(#ignored_operations)
+                .collect::<::candle_einops::SmallAxes<(_, _)>>()
+        ),
+        (Some(ignored_indices), Some(ignored_operations), false) => quote!(
+            [#(#reduce_indices),*]
+                .into_iter()
+                .chain(#ignored_indices)
+                .zip(
+                    [#(#reduce_operations),*]
                         .into_iter()
-                        .chain(#ignored_indices)
-                        .zip(
-                            [#(#reduce_operations),*]
-                                .into_iter()
-                                .chain(#ignored_operations)
-                        )
-                        .collect::<Vec<(_, _)>>()
-                );
-            )
-        }
-        (None, None, false) => {
-            quote!(
-                let #tensor_ident = ::candle_einops::Backend::reduce_axes(
-                    #tensor_ident, &mut [#((#reduce_indices, #reduce_operations)),*]
-                );
-            )
-        }
+                        .chain(#ignored_operations)
+                )
+                .collect::<::candle_einops::SmallAxes<(_, _)>>()
+        ),
+        (None, None, false) => quote!([#((#reduce_indices, #reduce_operations)),*]),
         _ => unreachable!(),
-    }
+    };
+
+    // Empty axes are checked against the policy, the same for every backend
+    let policy = if empty_reduction_error {
+        quote!(::candle_einops::policy::EmptyReduction::Error)
+    } else {
+        quote!(::candle_einops::policy::EmptyReduction::Identity)
+    };
+    let operation = quote!({
+        let axes_operations = &mut #axes_operations;
+        ::candle_einops::policy::check_reduction(
+            &::candle_einops::Backend::shape(&#tensor_ident),
+            &*axes_operations,
+            #policy,
+        )
+        .and_then(|()| ::candle_einops::Backend::reduce_axes(&#tensor_ident, axes_operations))
+    });
+    to_tokens_step(pattern, "reduce_axes", tensor_ident, operation)
 }
 
 pub fn to_tokens_decomposition(
@@ -342,6 +397,7 @@ pub fn to_tokens_decomposition(
     tensor_ident: &syn::Ident,
     ignored_len_ident: &syn::Ident,
     shape_ident: &syn::Ident,
+    pattern: &syn::LitStr,
 ) -> proc_macro2::TokenStream {
     let (known_indices, ignored_indices, unknown_indices) = left_expression.iter().fold(
         (Vec::new(), proc_macro2::TokenStream::new(), Vec::new()),
@@ -365,7 +421,7 @@ pub fn to_tokens_decomposition(
                     index: Index::Known(i),
                     shape_calc,
                     ..
-                } => known_indices.push(quote!(#shape_ident[#i] / #shape_calc)),
+                } => known_indices.push(quote!(#shape_ident[#i] / #shape_calc?)),
                 Decomposition::Named {
                     index: Index::Range(i),
                     ..
@@ -393,7 +449,7 @@ pub fn to_tokens_decomposition(
                     shape_calc,
                     ..
                 } => unknown_indices
-                    .push(quote!(#shape_ident[#i + #ignored_len_ident - 1] / #shape_calc)),
+                    .push(quote!(#shape_ident[#i + #ignored_len_ident - 1] / #shape_calc?)),
                 _ => unreachable!(),
             }
             (known_indices, ignored_indices, unknown_indices)
@@ -413,7 +469,7 @@ pub fn to_tokens_decomposition(
                 .into_iter()
                 .chain(#ignored_indices)
                 .into_iter()
-                .collect::<Vec<_>>()
+                .collect::<::candle_einops::SmallAxes<_>>()
         ),
         (false, false, false) => quote!(
             [#(#known_indices),*]
@@ -421,21 +477,297 @@ pub fn to_tokens_decomposition(
                 .chain(#ignored_indices)
                 .chain([#(#unknown_indices),*].into_iter())
                 .into_iter()
-                .collect::<Vec<_>>()
+                .collect::<::candle_einops::SmallAxes<_>>()
         ),
         (true, false, false) => quote!(
             #ignored_indices
                 .chain([#(#unknown_indices),*].into_iter())
                 .into_iter()
-                .collect::<Vec<_>>()
+                .collect::<::candle_einops::SmallAxes<_>>()
         ),
         (true, false, true) => quote!(
-            #ignored_indices.collect::<Vec<_>>()
+            #ignored_indices.collect::<::candle_einops::SmallAxes<_>>()
         ),
         _ => unreachable!(),
     };
 
+    to_tokens_reshape(decomposition_shape, tensor_ident, pattern)
+}
+
+// Checks that every input axis can be decomposed into the given lengths
+pub fn to_tokens_decomposition_checks(
+    left_expression: &[Decomposition],
+    pattern: &syn::LitStr,
+    ignored_len_ident: &syn::Ident,
+    shape_ident: &syn::Ident,
+) -> proc_macro2::TokenStream {
+    let index = |expression: &Decomposition| match expression {
+        Decomposition::Named { index, .. } | Decomposition::Derived { index, .. } => index.clone(),
+    };
+    let pattern_value = pattern.value();
+    let spans = left_axis_spans(&pattern_value);
+    // Points the error at the axis in the pattern
+    let error = |position: usize, kind: proc_macro2::TokenStream| match spans.get(position) {
+        Some((offset, len)) => quote!(
+            ::candle_einops::EinopsError::from(#kind).with_label(#pattern, #offset, #len)
+        ),
+        None => quote!(::candle_einops::EinopsError::from(#kind)),
+    };
+
+    left_expression
+        .chunk_by(|a, b| index(a) == index(b))
+        .filter_map(|group| {
+            let (position, axis) = match index(&group[0]) {
+                Index::Known(i) => (i, quote!(#i)),
+                Index::Unknown(i) => (i, quote!(#i + #ignored_len_ident - 1)),
+                Index::Range(_) => return None,
+            };
+            let group_text = spans
+                .get(position)
+                .map_or("", |&(offset, len)| &pattern_value[offset..offset + len]);
+            // Names and lengths of the axes with a size
+            let (names, sizes): (Vec<_>, Vec<_>) = group
+                .iter()
+                .filter_map(|expression| match expression {
+                    Decomposition::Named {
+                        name,
+                        shape: Some(Shape::Lit(size)),
+                        ..
+                    } => Some((name, quote!(#size))),
+                    Decomposition::Named {
+                        name,
+                        shape: Some(Shape::Expr(size)),
+                        ..
+                    } => Some((name, quote!(#size))),
+                    _ => None,
+                })
+                .unzip();
+            let sizes_tokens =
+                quote!(::std::vec![#((::std::borrow::Cow::Borrowed(#names), #sizes)),*]);
+            let derived = group
+                .iter()
+                .any(|expression| matches!(expression, Decomposition::Derived { .. }));
+            // Sizes from expressions can multiply past `usize::MAX`
+            let product = error(
+                position,
+                quote!(::candle_einops::ErrorKind::Overflow {
+                    lengths: ::std::vec![#(#sizes),*],
+                }),
+            );
+            let product = quote!(
+                [#(#sizes),*]
+                    .iter()
+                    .try_fold(1usize, |product, &size| product.checked_mul(size))
+                    .ok_or_else(|| #product)?
+            );
+            match derived {
+                true if group.len() > 1 => {
+                    let error = error(
+                        position,
+                        quote!(::candle_einops::ErrorKind::NotDivisible {
+                            axis,
+                            group: ::std::borrow::Cow::Borrowed(#group_text),
+                            sizes: #sizes_tokens,
+                            size,
+                            divisor,
+                        }),
+                    );
+                    Some(quote!(
+                        let (axis, divisor): (usize, usize) = (#axis, #product);
+                        let size: usize = #shape_ident[axis];
+                        if size.checked_rem(divisor) != ::std::option::Option::Some(0) {
+                            return ::std::result::Result::Err(#error);
+                        }
+                    ))
+                }
+                true => None,
+                // A group with an axis without a size can have any length
+                false if sizes.len() < group.len() => None,
+                false => {
+                    let error = error(
+                        position,
+                        quote!(::candle_einops::ErrorKind::ShapeMismatch {
+                            axis,
+                            group: ::std::borrow::Cow::Borrowed(#group_text),
+                            sizes: #sizes_tokens,
+                            expected,
+                            found,
+                        }),
+                    );
+                    Some(quote!(
+                        let (axis, expected): (usize, usize) = (#axis, #product);
+                        let found: usize = #shape_ident[axis];
+                        if found != expected {
+                            return ::std::result::Result::Err(#error);
+                        }
+                    ))
+                }
+            }
+        })
+        .collect()
+}
+
+// Checks that the input has as many axes as the left side of the pattern, the
+// shape is indexed by position afterwards
+pub fn to_tokens_rank_check(
+    left_expression: &[Decomposition],
+    pattern: &syn::LitStr,
+    shape_ident: &syn::Ident,
+) -> proc_macro2::TokenStream {
+    let naxes = left_axis_spans(&pattern.value()).len();
+    let ignores = left_expression.iter().any(|expression| {
+        matches!(
+            expression,
+            Decomposition::Named {
+                index: Index::Range(_),
+                ..
+            }
+        )
+    });
+    let (expected, mismatch) = if ignores {
+        // `..` can stand for no axes at all
+        let expected = naxes - 1;
+        (expected, quote!(#shape_ident.len() < #expected))
+    } else {
+        (naxes, quote!(#shape_ident.len() != #naxes))
+    };
     quote!(
-        let #tensor_ident = ::candle_einops::Backend::reshape(#tensor_ident, &#decomposition_shape);
+        if #mismatch {
+            return ::std::result::Result::Err(::candle_einops::ErrorKind::RankMismatch {
+                expected: #expected,
+                found: #shape_ident.len(),
+            }.into());
+        }
     )
 }
+
+// Lengths of the named axes of the decomposition, read from `shape_ident` without
+// assuming it matches the pattern since it's only used to describe errors
+pub fn to_tokens_bindings(
+    left_expression: &[Decomposition],
+    shape_ident: &syn::Ident,
+) -> proc_macro2::TokenStream {
+    let last_unknown_index = left_expression
+        .iter()
+        .rev()
+        .find_map(|expression| match expression {
+            Decomposition::Named {
+                index: Index::Unknown(i) | Index::Range(i),
+                ..
+            }
+            | Decomposition::Derived {
+                index: Index::Unknown(i),
+                ..
+            } => Some(*i),
+            _ => None,
+        });
+    let axis = |index: &Index| match index {
+        Index::Known(i) => Some(quote!(::std::option::Option::Some(#i))),
+        Index::Unknown(i) => {
+            let last_unknown_index = last_unknown_index.unwrap();
+            Some(quote!(
+                #shape_ident.len().checked_sub(#last_unknown_index).map(|len| #i + len - 1)
+            ))
+        }
+        Index::Range(_) => None,
+    };
+
+    let bindings = left_expression.iter().filter_map(|expression| {
+        let (name, len) = match expression {
+            Decomposition::Named {
+                name,
+                shape: Some(Shape::Lit(size)),
+                ..
+            } => (name, quote!(::std::option::Option::Some(#size))),
+            Decomposition::Named {
+                name,
+                shape: Some(Shape::Expr(size)),
+                ..
+            } => (name, quote!(::std::option::Option::Some(#size))),
+            Decomposition::Named {
+                name,
+                index,
+                shape: None,
+                ..
+            } => {
+                let axis = axis(index)?;
+                (
+                    name,
+                    quote!(#axis.and_then(|axis| #shape_ident.get(axis).copied())),
+                )
+            }
+            Decomposition::Derived {
+                name,
+                index,
+                shape_calc,
+                ..
+            } => {
+                let axis = axis(index)?;
+                (
+                    name,
+                    quote!(#axis
+                        .and_then(|axis| #shape_ident.get(axis))
+                        .and_then(|len| len.checked_div(#shape_calc.ok()?))),
+                )
+            }
+        };
+        Some(quote!((#name, #len)))
+    });
+
+    quote!(
+        [#(#bindings),*]
+            .into_iter()
+            .filter_map(|(name, len): (&'static str, ::std::option::Option<usize>)| {
+                ::std::option::Option::Some((name, len?))
+            })
+            .collect::<::std::vec::Vec<_>>()
+    )
+}
+
+// Under the strict broadcast policy, the axes of the input of length 1 that
+// have no size in the pattern are rejected when they're grouped with new axes
+// making the output axis longer
+pub fn to_tokens_broadcast_checks(
+    left_expression: &[Decomposition],
+    repeated_with: &[(String, Vec<Shape>)],
+    pattern: &syn::LitStr,
+    ignored_len_ident: &syn::Ident,
+    shape_ident: &syn::Ident,
+) -> proc_macro2::TokenStream {
+    let index = |expression: &Decomposition| match expression {
+        Decomposition::Named { index, .. } | Decomposition::Derived { index, .. } => index.clone(),
+    };
+    let spans = left_axis_spans(&pattern.value());
+
+    left_expression
+        .chunk_by(|a, b| index(a) == index(b))
+        .filter_map(|group| {
+            let [Decomposition::Named {
+                name,
+                index,
+                operation: None,
+                shape: None,
+            }] = group
+            else {
+                return None;
+            };
+            let (_, sizes) = repeated_with.iter().find(|(repeated, _)| repeated == name)?;
+            let (position, axis) = match index {
+                Index::Known(i) => (*i, quote!(#i)),
+                Index::Unknown(i) => (*i, quote!(#i + #ignored_len_ident - 1)),
+                Index::Range(_) => return None,
+            };
+            let sizes = sizes.iter().map(|shape| match shape {
+                Shape::Lit(len) => quote!(#len),
+                Shape::Expr(expr) => quote!(#expr),
+            });
+            let len = quote!(1usize #(* #sizes)*);
+            let label = spans.get(position).map(
+                |(offset, len)| quote!(.map_err(|error| error.with_label(#pattern, #offset, #len))),
+            );
+            Some(quote!(
+                ::candle_einops::policy::check_broadcast(&#shape_ident, #axis, #name, #len)#label?;
+            ))
+        })
+        .collect()
+}
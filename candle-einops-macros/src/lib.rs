@@ -2,11 +2,52 @@ mod einops;
 
 /// Macro to perform tensor transformations using simple expressions
 ///
+/// The expression evaluates to a `Result`, errors raised by the backend
+/// are returned as `EinopsError`. Axes whose length doesn't match the sizes
+/// in the pattern are reported as `ErrorKind::ShapeMismatch` or
+/// `ErrorKind::NotDivisible`. The error includes the pattern, the input's
+/// shape and the lengths of the named axes
+///
 /// # Example
 ///
 /// ```no_run
-/// let output = einops!("h w c -> c h w", &input);
+/// let output = einops!("h w c -> c h w", &input)?;
+/// ```
+///
+/// Outputs are not guaranteed to be contiguous, pass `contiguous = true`
+/// to copy the output into a contiguous tensor when needed
+///
+/// ```no_run
+/// let output = einops!("h w c -> c h w", &input, contiguous = true)?;
+/// ```
+///
+/// Repeated axes are stride 0 views by default, which costs nothing until a
+/// later operation needs the data laid out. Pass `repeat = copy` to copy
+/// them right away when the output is used elementwise many times
+///
+/// ```no_run
+/// let output = einops!("h w -> h w repeat:3", &input, repeat = copy)?;
 /// ```
+///
+/// Input axes of length 1 are broadcast to the new axes grouped with them,
+/// pass `broadcast = strict` to return an `ErrorKind::ImplicitBroadcast`
+/// error instead
+///
+/// ```no_run
+/// let output = einops!("b c -> b (c repeat:3)", &input, broadcast = strict)?;
+/// ```
+///
+/// Sums of axes of length 0 are 0 and the other reductions of them return
+/// an `ErrorKind::EmptyReduction` error, pass `empty_reduction = error` to
+/// reject sums as well
+///
+/// ```no_run
+/// let output = einops!("b sum(t) c -> b c", &input, empty_reduction = error)?;
+/// ```
+///
+/// Sizes are read from the input for the axes of the left side, a size given
+/// to one of them on the right side, like `a b -> b a:3`, is ignored with a
+/// warning
 #[proc_macro]
 pub fn einops(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
     einops::einops(input.into())
@@ -0,0 +1,344 @@
+//! A backend for a mock accelerator, to show what `Backend` asks of tensor
+//! libraries other than candle
+//!
+//! Tensors are a buffer on the device with a shape, strides and an offset.
+//! Reshapes of contiguous tensors, transposes, narrowing and new axes only
+//! change the strides, every operation moving data is a kernel launched on
+//! the device's queue, which the example prints. The kernels run on the host
+//! here, a real accelerator would dispatch them to its compute pipeline.
+//!
+//! ```text
+//! cargo run --example accelerator
+//! ```
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use candle_einops::{einops, Backend, DType, Device, EinopsError, Operation};
+
+// Kernels launched on the device, in order
+#[derive(Default)]
+struct Queue(RefCell<Vec<String>>);
+
+impl Queue {
+    fn launch(&self, kernel: String) {
+        self.0.borrow_mut().push(kernel);
+    }
+
+    fn take(&self) -> Vec<String> {
+        self.0.take()
+    }
+}
+
+#[derive(Clone)]
+struct DeviceTensor {
+    buffer: Rc<Vec<f32>>,
+    shape: Vec<usize>,
+    strides: Vec<usize>,
+    offset: usize,
+    queue: Rc<Queue>,
+}
+
+// Strides of a contiguous tensor of shape `shape`
+fn strides(shape: &[usize]) -> Vec<usize> {
+    let mut strides = vec![1; shape.len()];
+    for axis in (0..shape.len().saturating_sub(1)).rev() {
+        strides[axis] = strides[axis + 1] * shape[axis + 1];
+    }
+    strides
+}
+
+// Indices of every element of a tensor of shape `shape`, in row-major order
+fn indices(shape: &[usize]) -> impl Iterator<Item = Vec<usize>> + '_ {
+    let len = shape.iter().product::<usize>();
+    (0..len).map(move |mut flat| {
+        let mut index = vec![0; shape.len()];
+        for axis in (0..shape.len()).rev() {
+            index[axis] = flat % shape[axis];
+            flat /= shape[axis];
+        }
+        index
+    })
+}
+
+impl DeviceTensor {
+    fn upload(data: Vec<f32>, shape: &[usize], queue: &Rc<Queue>) -> Self {
+        queue.launch(format!("upload {:?}", shape));
+        Self {
+            buffer: Rc::new(data),
+            shape: shape.to_vec(),
+            strides: strides(shape),
+            offset: 0,
+            queue: queue.clone(),
+        }
+    }
+
+    fn download(&self) -> Vec<f32> {
+        indices(&self.shape).map(|index| self.get(&index)).collect()
+    }
+
+    fn get(&self, index: &[usize]) -> f32 {
+        let position = index
+            .iter()
+            .zip(&self.strides)
+            .map(|(i, stride)| i * stride)
+            .sum::<usize>();
+        self.buffer[self.offset + position]
+    }
+
+    // The same buffer seen with another layout
+    fn view(&self, shape: Vec<usize>, strides: Vec<usize>, offset: usize) -> Self {
+        Self {
+            buffer: self.buffer.clone(),
+            shape,
+            strides,
+            offset,
+            queue: self.queue.clone(),
+        }
+    }
+
+    // A new buffer written by the kernel `kernel`
+    fn output(&self, kernel: &str, data: Vec<f32>, shape: Vec<usize>) -> Self {
+        self.queue
+            .launch(format!("{} {:?} -> {:?}", kernel, self.shape, shape));
+        Self {
+            buffer: Rc::new(data),
+            strides: strides(&shape),
+            shape,
+            offset: 0,
+            queue: self.queue.clone(),
+        }
+    }
+}
+
+impl Backend for DeviceTensor {
+    type Output = DeviceTensor;
+
+    fn shape(&self) -> Vec<usize> {
+        self.shape.clone()
+    }
+
+    fn reshape(&self, shape: &[usize]) -> Result<Self::Output, EinopsError> {
+        if shape.iter().product::<usize>() != self.shape.iter().product::<usize>() {
+            return Err(EinopsError::new(format!(
+                "cannot reshape tensor of shape {:?} to {:?}",
+                self.shape, shape
+            )));
+        }
+        match self.reshape_view(shape) {
+            Some(view) => Ok(view),
+            None => Backend::reshape(&self.contiguous()?, shape),
+        }
+    }
+
+    fn transpose(&self, axes: &[usize]) -> Result<Self::Output, EinopsError> {
+        self.transpose_view(axes).ok_or_else(|| {
+            EinopsError::new(format!(
+                "invalid permutation {:?} for tensor of shape {:?}",
+                axes, self.shape
+            ))
+        })
+    }
+
+    fn reshape_view(&self, shape: &[usize]) -> Option<Self::Output> {
+        let same_len = shape.iter().product::<usize>() == self.shape.iter().product::<usize>();
+        (same_len && self.is_contiguous())
+            .then(|| self.view(shape.to_vec(), strides(shape), self.offset))
+    }
+
+    fn transpose_view(&self, axes: &[usize]) -> Option<Self::Output> {
+        let mut sorted = axes.to_vec();
+        sorted.sort_unstable();
+        if sorted.len() != self.shape.len() || sorted.iter().enumerate().any(|(i, &a)| i != a) {
+            return None;
+        }
+        Some(self.view(
+            axes.iter().map(|&axis| self.shape[axis]).collect(),
+            axes.iter().map(|&axis| self.strides[axis]).collect(),
+            self.offset,
+        ))
+    }
+
+    fn reduce_axes(
+        &self,
+        axes_operations: &mut [(usize, Operation)],
+    ) -> Result<Self::Output, EinopsError> {
+        // All axes are reduced with the same operation by `einops!` patterns
+        let Some(&(_, operation)) = axes_operations.first() else {
+            return Ok(self.clone());
+        };
+        let reduced = |axis| axes_operations.iter().any(|&(reduced, _)| reduced == axis);
+        let kept = (0..self.shape.len())
+            .filter(|&axis| !reduced(axis))
+            .collect::<Vec<_>>();
+        let shape = kept
+            .iter()
+            .map(|&axis| self.shape[axis])
+            .collect::<Vec<_>>();
+        let output_strides = strides(&shape);
+
+        let mut data = vec![None::<f32>; shape.iter().product()];
+        for index in indices(&self.shape) {
+            let position = kept
+                .iter()
+                .zip(&output_strides)
+                .map(|(&axis, stride)| index[axis] * stride)
+                .sum::<usize>();
+            let value = self.get(&index);
+            data[position] = Some(match (data[position], operation) {
+                (None, _) => value,
+                (Some(total), Operation::Min) => total.min(value),
+                (Some(total), Operation::Max) => total.max(value),
+                (Some(total), Operation::Sum | Operation::Mean) => total + value,
+            });
+        }
+        let count = self.shape.iter().product::<usize>() / data.len().max(1);
+        let data = data
+            .into_iter()
+            .map(|total| match operation {
+                Operation::Mean => total.unwrap_or(f32::NAN) / count as f32,
+                _ => total.unwrap_or(0.0),
+            })
+            .collect();
+        Ok(self.output("reduce", data, shape))
+    }
+
+    fn add_axes(
+        &self,
+        naxes: usize,
+        pos2len: &[(usize, usize)],
+    ) -> Result<Self::Output, EinopsError> {
+        let mut shape = self.shape.clone();
+        let mut strides = self.strides.clone();
+        for &(axis, len) in pos2len {
+            // Repeated axes read the same elements again
+            shape.insert(axis, len);
+            strides.insert(axis, 0);
+        }
+        if shape.len() != naxes {
+            return Err(EinopsError::new(format!(
+                "expected {} axes after inserting new axes, found {}",
+                naxes,
+                shape.len()
+            )));
+        }
+        Ok(self.view(shape, strides, self.offset))
+    }
+
+    fn narrow(&self, axis: usize, start: usize, len: usize) -> Result<Self::Output, EinopsError> {
+        if axis >= self.shape.len() || start + len > self.shape[axis] {
+            return Err(EinopsError::new(format!(
+                "cannot narrow axis {} of tensor with shape {:?}",
+                axis, self.shape
+            )));
+        }
+        let mut shape = self.shape.clone();
+        shape[axis] = len;
+        let offset = self.offset + start * self.strides[axis];
+        Ok(self.view(shape, self.strides.clone(), offset))
+    }
+
+    fn broadcast(&self, shape: &[usize]) -> Result<Self::Output, EinopsError> {
+        let leading = shape.len().checked_sub(self.shape.len());
+        let fits = leading.is_some_and(|leading| {
+            self.shape
+                .iter()
+                .zip(&shape[leading..])
+                .all(|(&len, &target)| len == target || len == 1)
+        });
+        let Some(leading) = leading.filter(|_| fits) else {
+            return Err(EinopsError::new(format!(
+                "cannot broadcast tensor of shape {:?} to {:?}",
+                self.shape, shape
+            )));
+        };
+        let mut strides = vec![0; leading];
+        strides.extend(
+            self.shape
+                .iter()
+                .zip(&self.strides)
+                .zip(&shape[leading..])
+                .map(|((&len, &stride), &target)| if len == target { stride } else { 0 }),
+        );
+        Ok(self.view(shape.to_vec(), strides, self.offset))
+    }
+
+    fn is_contiguous(&self) -> bool {
+        self.strides == strides(&self.shape)
+    }
+
+    fn contiguous(&self) -> Result<Self::Output, EinopsError> {
+        if self.is_contiguous() {
+            return Ok(self.clone());
+        }
+        Ok(self.output("gather", self.download(), self.shape.clone()))
+    }
+
+    fn concat(inputs: &[&Self], axis: usize) -> Result<Self::Output, EinopsError> {
+        let Some(first) = inputs.first() else {
+            return Err(EinopsError::new("cannot concatenate no tensors"));
+        };
+        let mut shape = first.shape.clone();
+        shape[axis] = inputs.iter().map(|input| input.shape[axis]).sum();
+        let data = indices(&shape)
+            .map(|mut index| {
+                let mut inputs = inputs.iter();
+                let mut input = inputs.next().expect("there is an input");
+                while index[axis] >= input.shape[axis] {
+                    index[axis] -= input.shape[axis];
+                    input = inputs.next().expect("the index is in the output");
+                }
+                input.get(&index)
+            })
+            .collect();
+        Ok(first.output("concat", data, shape))
+    }
+
+    fn index_select(&self, axis: usize, picked: &[usize]) -> Result<Self::Output, EinopsError> {
+        let mut shape = self.shape.clone();
+        shape[axis] = picked.len();
+        let data = indices(&shape)
+            .map(|mut index| {
+                index[axis] = picked[index[axis]];
+                self.get(&index)
+            })
+            .collect();
+        Ok(self.output("index_select", data, shape))
+    }
+
+    fn dtype(&self) -> Option<DType> {
+        Some(DType::F32)
+    }
+
+    // Layers pick the order of their steps that launches the fewest kernels
+    // on accelerators
+    fn device(&self) -> Option<Device> {
+        Some(Device::Cuda(0))
+    }
+}
+
+fn main() -> Result<(), EinopsError> {
+    let queue = Rc::new(Queue::default());
+    let input = DeviceTensor::upload((0..24).map(|x| x as f32).collect(), &[2, 3, 4], &queue);
+
+    // Transposes are views, the reshape after one gathers the data once
+    let output = einops!("a b c -> c (a b)", &input)?;
+    assert_eq!(output.shape, [4, 6]);
+    assert_eq!(output.download()[..6], [0.0, 4.0, 8.0, 12.0, 16.0, 20.0]);
+
+    let sums = einops!("a sum(b) c -> a c", &input)?;
+    assert_eq!(
+        sums.download(),
+        [12.0, 15.0, 18.0, 21.0, 48.0, 51.0, 54.0, 57.0]
+    );
+
+    // Repeats are stride 0 views until they're laid out
+    let repeated = einops!("a b c -> a b c r:2", &input)?;
+    assert_eq!(repeated.shape, [2, 3, 4, 2]);
+    assert_eq!(repeated.download()[..4], [0.0, 0.0, 1.0, 1.0]);
+
+    for kernel in queue.take() {
+        println!("{}", kernel);
+    }
+    Ok(())
+}
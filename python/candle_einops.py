@@ -0,0 +1,227 @@
+"""Python bindings of the runtime patterns of candle-einops
+
+The functions call the C API of the `ffi` feature through ctypes, so Python
+and Rust apply a pattern with the same engine. Inputs are row-major float32
+buffers, like `array.array("f")`, `numpy.float32` arrays or the numpy arrays of
+candle tensors, with their shape. Outputs are `array.array("f")` with the
+shape of the output.
+
+The library is built with
+
+    cargo rustc --release --features ffi --lib --crate-type cdylib
+
+and found in `target/release`, or at the path in `CANDLE_EINOPS_LIBRARY`.
+
+    >>> recipe = Recipe("b c h w -> b h w c")
+    >>> output, shape = recipe.apply(image, (2, 3, 4, 4))
+    >>> output, shape = reduce(image, (2, 3, 4, 4), "b c h w -> b c", "mean")
+"""
+
+import array
+import ctypes
+import os
+import sys
+
+__all__ = ["EinopsError", "Recipe", "rearrange", "reduce", "repeat"]
+
+REARRANGE = 0
+REPEAT = 1
+REDUCTIONS = {"min": 2, "max": 3, "sum": 4, "mean": 5}
+
+# Outputs have at most this many axes
+MAX_RANK = 64
+
+
+class EinopsError(ValueError):
+    """Error of a pattern or of the shape of an input"""
+
+
+def _library_path():
+    path = os.environ.get("CANDLE_EINOPS_LIBRARY")
+    if path:
+        return path
+    name = {
+        "darwin": "libcandle_einops.dylib",
+        "win32": "candle_einops.dll",
+    }.get(sys.platform, "libcandle_einops.so")
+    root = os.path.dirname(os.path.dirname(os.path.abspath(__file__)))
+    return os.path.join(root, "target", "release", name)
+
+
+def _load():
+    library = ctypes.CDLL(_library_path())
+    size_p = ctypes.POINTER(ctypes.c_size_t)
+    float_p = ctypes.POINTER(ctypes.c_float)
+
+    library.einops_last_error.restype = ctypes.c_char_p
+    library.einops_last_error.argtypes = []
+    library.einops_recipe_new.restype = ctypes.c_void_p
+    library.einops_recipe_new.argtypes = [ctypes.c_char_p, ctypes.c_uint32]
+    library.einops_recipe_free.restype = None
+    library.einops_recipe_free.argtypes = [ctypes.c_void_p]
+    library.einops_output_shape.restype = ctypes.c_int
+    library.einops_output_shape.argtypes = [
+        ctypes.c_void_p,
+        size_p,
+        ctypes.c_size_t,
+        size_p,
+        ctypes.c_size_t,
+        size_p,
+    ]
+    library.einops_apply_f32.restype = ctypes.c_int
+    library.einops_apply_f32.argtypes = [
+        ctypes.c_void_p,
+        float_p,
+        size_p,
+        ctypes.c_size_t,
+        float_p,
+        ctypes.c_size_t,
+    ]
+    return library
+
+
+_library = None
+
+
+def _lib():
+    global _library
+    if _library is None:
+        _library = _load()
+    return _library
+
+
+def _check(status):
+    if status != 0:
+        raise EinopsError(_lib().einops_last_error().decode("utf-8", "replace"))
+
+
+def _shape(shape):
+    shape = tuple(int(length) for length in shape)
+    return (ctypes.c_size_t * len(shape))(*shape), len(shape)
+
+
+def _product(shape):
+    product = 1
+    for length in shape:
+        product *= int(length)
+    return product
+
+
+def _kind(kind, operation):
+    if kind == "rearrange":
+        return REARRANGE
+    if kind == "repeat":
+        return REPEAT
+    if kind == "reduce":
+        if operation not in REDUCTIONS:
+            raise EinopsError(
+                "unknown reduction {!r}, expected one of {}".format(
+                    operation, ", ".join(REDUCTIONS)
+                )
+            )
+        return REDUCTIONS[operation]
+    raise EinopsError(
+        "unknown recipe kind {!r}, expected rearrange, reduce or repeat".format(kind)
+    )
+
+
+def _floats(data):
+    """The float32 elements of `data`, without copying buffers"""
+    try:
+        view = memoryview(data)
+    except TypeError:
+        view = memoryview(array.array("f", data))
+    if not view.c_contiguous:
+        raise EinopsError("expected a row-major buffer")
+    # Raw bytes are read as float32, like the buffers of `bytes` or `bytearray`
+    if view.format not in ("f", "B", "b", "c") or view.nbytes % 4 != 0:
+        raise EinopsError(
+            "expected a buffer of float32, found format {!r}".format(view.format)
+        )
+    return view.cast("B").cast("f")
+
+
+class Recipe:
+    """A pattern compiled once and applied to inputs of any shape it accepts
+
+    `kind` is "rearrange", "repeat" or "reduce", reductions take their
+    `operation`, "min", "max", "sum" or "mean". Sizes of axes are written in
+    the pattern, like `b (h w:2) -> b h w`.
+    """
+
+    def __init__(self, pattern, kind="rearrange", operation=None):
+        self.pattern = pattern
+        self._recipe = _lib().einops_recipe_new(
+            pattern.encode("utf-8"), _kind(kind, operation)
+        )
+        if not self._recipe:
+            _check(-1)
+
+    def __del__(self):
+        recipe, self._recipe = getattr(self, "_recipe", None), None
+        if recipe and _library is not None:
+            _library.einops_recipe_free(recipe)
+
+    def __repr__(self):
+        return "Recipe({!r})".format(self.pattern)
+
+    def output_shape(self, shape):
+        """Shape of the output for an input of shape `shape`"""
+        shape, rank = _shape(shape)
+        output = (ctypes.c_size_t * MAX_RANK)()
+        output_rank = ctypes.c_size_t()
+        _check(
+            _lib().einops_output_shape(
+                self._recipe, shape, rank, output, MAX_RANK, ctypes.byref(output_rank)
+            )
+        )
+        return tuple(output[: output_rank.value])
+
+    def apply(self, data, shape):
+        """Applies the recipe to the row-major `data` of shape `shape`
+
+        Returns the output as an `array.array("f")` and its shape.
+        """
+        input = _floats(data)
+        if len(input) != _product(shape):
+            raise EinopsError(
+                "expected {} elements for shape {}, found {}".format(
+                    _product(shape), tuple(shape), len(input)
+                )
+            )
+        output_shape = self.output_shape(shape)
+        length = _product(output_shape)
+        output = array.array("f", bytes(4 * length))
+        shape, rank = _shape(shape)
+        input_type = ctypes.c_float * len(input)
+        if input.readonly:
+            input_buffer = input_type.from_buffer_copy(input)
+        else:
+            input_buffer = input_type.from_buffer(input)
+        output_buffer = (ctypes.c_float * length).from_buffer(output)
+        _check(
+            _lib().einops_apply_f32(
+                self._recipe,
+                input_buffer,
+                shape,
+                rank,
+                output_buffer,
+                length,
+            )
+        )
+        return output, output_shape
+
+
+def rearrange(data, shape, pattern):
+    """Rearranges the row-major `data` of shape `shape` with `pattern`"""
+    return Recipe(pattern, "rearrange").apply(data, shape)
+
+
+def reduce(data, shape, pattern, operation):
+    """Reduces the axes of `data` missing on the right side of `pattern`"""
+    return Recipe(pattern, "reduce", operation).apply(data, shape)
+
+
+def repeat(data, shape, pattern):
+    """Repeats `data` along the new axes on the right side of `pattern`"""
+    return Recipe(pattern, "repeat").apply(data, shape)
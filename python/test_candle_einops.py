@@ -0,0 +1,62 @@
+import array
+import unittest
+
+from candle_einops import EinopsError, Recipe, rearrange, reduce, repeat
+
+
+class RecipeTest(unittest.TestCase):
+    def test_rearrange(self):
+        recipe = Recipe("h (w p:2) -> p h w")
+        self.assertEqual(recipe.output_shape((2, 4)), (2, 2, 2))
+
+        output, shape = recipe.apply(array.array("f", range(8)), (2, 4))
+        self.assertEqual(shape, (2, 2, 2))
+        self.assertEqual(list(output), [0, 2, 4, 6, 1, 3, 5, 7])
+
+        # Recipes are applied to every shape their pattern accepts
+        output, shape = recipe.apply(array.array("f", range(4)), (1, 4))
+        self.assertEqual((list(output), shape), ([0, 2, 1, 3], (2, 1, 2)))
+
+        output, shape = rearrange(range(6), (2, 3), "a b -> b a")
+        self.assertEqual((list(output), shape), ([0, 3, 1, 4, 2, 5], (3, 2)))
+
+    def test_reduce(self):
+        output, shape = reduce(range(8), (2, 4), "a b -> a", "sum")
+        self.assertEqual((list(output), shape), ([6, 22], (2,)))
+        output, shape = reduce(range(8), (2, 4), "a b -> b", "max")
+        self.assertEqual((list(output), shape), ([4, 5, 6, 7], (4,)))
+        output, _ = reduce(range(8), (2, 4), "a (b c:2) -> a b", "mean")
+        self.assertEqual(list(output), [0.5, 2.5, 4.5, 6.5])
+
+    def test_repeat(self):
+        output, shape = repeat(range(2), (2,), "a -> a r:3")
+        self.assertEqual((list(output), shape), ([0, 0, 0, 1, 1, 1], (2, 3)))
+
+    def test_buffers(self):
+        # Read-only buffers are copied, raw bytes are read as float32
+        data = array.array("f", range(4))
+        output, _ = rearrange(bytes(data), (2, 2), "a b -> b a")
+        self.assertEqual(list(output), [0, 2, 1, 3])
+        output, _ = rearrange(memoryview(data), (2, 2), "a b -> b a")
+        self.assertEqual(list(output), [0, 2, 1, 3])
+
+        with self.assertRaisesRegex(EinopsError, "float32"):
+            rearrange(array.array("d", range(4)), (2, 2), "a b -> b a")
+        with self.assertRaisesRegex(EinopsError, "row-major"):
+            rearrange(memoryview(data)[::2], (2,), "a -> a")
+
+    def test_errors(self):
+        with self.assertRaisesRegex(EinopsError, "not divisible by 2"):
+            Recipe("h (w p:2) -> p h w").apply(range(6), (2, 3))
+        with self.assertRaisesRegex(EinopsError, "expected 8 elements"):
+            rearrange(range(6), (2, 4), "a b -> b a")
+        with self.assertRaises(EinopsError):
+            Recipe("a b -> a c")
+        with self.assertRaisesRegex(EinopsError, "unknown reduction"):
+            Recipe("a b -> a", "reduce", "prod")
+        with self.assertRaisesRegex(EinopsError, "unknown recipe kind"):
+            Recipe("a b -> a", "permute")
+
+
+if __name__ == "__main__":
+    unittest.main()
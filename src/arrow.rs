@@ -0,0 +1,242 @@
+use std::ops::Range;
+use std::sync::Arc;
+
+use arrow_array::types::ArrowPrimitiveType;
+use arrow_array::{Array, ArrayRef, FixedSizeListArray, PrimitiveArray};
+use arrow_schema::Field;
+
+use crate::cpu::{CpuTensor, Element};
+use crate::{Backend, DType, Device, EinopsError, Operation, ScanOp};
+
+/// A column of nested arrow fixed-size lists with primitive values of type `T`
+///
+/// The outer rows are the first axis, every level of nesting adds another axis,
+/// so a `FixedSizeList<FixedSizeList<Float32, 4>, 3>` column of 10 rows has the
+/// shape `[10, 3, 4]`. Outputs are `CpuTensor`s, which convert back into a
+/// column with `try_into`.
+#[derive(Debug, Clone)]
+pub struct ArrowTensor<T: ArrowPrimitiveType> {
+    array: FixedSizeListArray,
+    values: PrimitiveArray<T>,
+    // Range of `values` covered by `array`, arrays can be slices of larger arrays
+    range: Range<usize>,
+    shape: Vec<usize>,
+}
+
+impl<T: ArrowPrimitiveType> ArrowTensor<T> {
+    /// Wraps `array`, the innermost values have to be a `PrimitiveArray<T>` and
+    /// none of the levels can contain nulls
+    pub fn try_new(array: FixedSizeListArray) -> Result<Self, EinopsError> {
+        let mut shape = vec![array.len()];
+        let mut range = 0..array.len();
+        let mut level: ArrayRef = Arc::new(array.clone());
+
+        let values = loop {
+            if level.null_count() > 0 {
+                return Err(EinopsError::new(
+                    "arrow arrays with nulls are not supported",
+                ));
+            }
+            if let Some(list) = level.as_any().downcast_ref::<FixedSizeListArray>() {
+                let size = list.value_length() as usize;
+                let start = if range.is_empty() {
+                    0
+                } else {
+                    list.value_offset(range.start) as usize
+                };
+                shape.push(size);
+                range = start..start + range.len() * size;
+                level = list.values().clone();
+            } else if let Some(values) = level.as_any().downcast_ref::<PrimitiveArray<T>>() {
+                break values.clone();
+            } else {
+                return Err(EinopsError::new(format!(
+                    "expected fixed-size lists of {:?} values, found {:?}",
+                    T::DATA_TYPE,
+                    level.data_type()
+                )));
+            }
+        };
+
+        Ok(Self {
+            array,
+            values,
+            range,
+            shape,
+        })
+    }
+
+    pub fn shape(&self) -> &[usize] {
+        &self.shape
+    }
+
+    pub fn into_inner(self) -> FixedSizeListArray {
+        self.array
+    }
+
+    fn to_tensor(&self) -> Result<CpuTensor<T::Native>, EinopsError> {
+        CpuTensor::new(
+            self.values.values()[self.range.clone()].to_vec(),
+            self.shape.clone(),
+        )
+    }
+}
+
+impl<T: ArrowPrimitiveType> TryFrom<CpuTensor<T::Native>> for ArrowTensor<T> {
+    type Error = EinopsError;
+
+    fn try_from(tensor: CpuTensor<T::Native>) -> Result<Self, Self::Error> {
+        let shape = tensor.shape().to_vec();
+        if shape.len() < 2 {
+            return Err(EinopsError::new(format!(
+                "expected a tensor with at least 2 axes, found shape {:?}",
+                shape
+            )));
+        }
+
+        // Lists are nested from the innermost axis outwards
+        let mut level: ArrayRef =
+            Arc::new(PrimitiveArray::<T>::new(tensor.into_data().into(), None));
+        for &size in shape[1..].iter().rev() {
+            let field = Arc::new(Field::new_list_field(level.data_type().clone(), false));
+            let list = FixedSizeListArray::try_new(field, size as i32, level, None)
+                .map_err(EinopsError::wrap)?;
+            level = Arc::new(list);
+        }
+
+        let array = level
+            .as_any()
+            .downcast_ref::<FixedSizeListArray>()
+            .unwrap()
+            .clone();
+        Self::try_new(array)
+    }
+}
+
+impl<T> Backend for ArrowTensor<T>
+where
+    T: ArrowPrimitiveType,
+    T::Native: Element,
+{
+    type Output = CpuTensor<T::Native>;
+
+    fn shape(&self) -> Vec<usize> {
+        self.shape.clone()
+    }
+
+    fn reshape(&self, shape: &[usize]) -> Result<Self::Output, EinopsError> {
+        Backend::reshape(&self.to_tensor()?, shape)
+    }
+
+    fn transpose(&self, axes: &[usize]) -> Result<Self::Output, EinopsError> {
+        Backend::transpose(&self.to_tensor()?, axes)
+    }
+
+    fn reduce_axes(
+        &self,
+        axes_operations: &mut [(usize, Operation)],
+    ) -> Result<Self::Output, EinopsError> {
+        Backend::reduce_axes(&self.to_tensor()?, axes_operations)
+    }
+
+    fn add_axes(
+        &self,
+        naxes: usize,
+        pos2len: &[(usize, usize)],
+    ) -> Result<Self::Output, EinopsError> {
+        Backend::add_axes(&self.to_tensor()?, naxes, pos2len)
+    }
+
+    fn narrow(&self, axis: usize, start: usize, len: usize) -> Result<Self::Output, EinopsError> {
+        Backend::narrow(&self.to_tensor()?, axis, start, len)
+    }
+
+    fn broadcast(&self, shape: &[usize]) -> Result<Self::Output, EinopsError> {
+        Backend::broadcast(&self.to_tensor()?, shape)
+    }
+
+    // The values are always copied into a new buffer
+    fn is_contiguous(&self) -> bool {
+        false
+    }
+
+    fn contiguous(&self) -> Result<Self::Output, EinopsError> {
+        self.to_tensor()
+    }
+
+    fn concat(inputs: &[&Self], axis: usize) -> Result<Self::Output, EinopsError> {
+        let tensors = inputs
+            .iter()
+            .map(|input| input.to_tensor())
+            .collect::<Result<Vec<_>, _>>()?;
+        Backend::concat(&tensors.iter().collect::<Vec<_>>(), axis)
+    }
+
+    fn index_select(&self, axis: usize, indices: &[usize]) -> Result<Self::Output, EinopsError> {
+        Backend::index_select(&self.to_tensor()?, axis, indices)
+    }
+
+    fn scan(&self, axis: usize, operation: ScanOp) -> Result<Self::Output, EinopsError> {
+        Backend::scan(&self.to_tensor()?, axis, operation)
+    }
+
+    fn contract(&self, rhs: &Self) -> Result<Self::Output, EinopsError> {
+        Backend::contract(&self.to_tensor()?, &rhs.to_tensor()?)
+    }
+
+    fn dtype(&self) -> Option<DType> {
+        T::Native::dtype()
+    }
+
+    fn to_dtype(&self, dtype: DType) -> Result<Self::Output, EinopsError> {
+        Backend::to_dtype(&self.to_tensor()?, dtype)
+    }
+
+    fn device(&self) -> Option<Device> {
+        Some(Device::Cpu)
+    }
+
+    fn to_device(&self, device: Device) -> Result<Self::Output, EinopsError> {
+        if device != Device::Cpu {
+            return Err(EinopsError::new(format!(
+                "cannot move tensor to {:?}, only the CPU is supported",
+                device
+            )));
+        }
+        self.to_tensor()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow_array::types::Int32Type;
+
+    #[test]
+    fn arrow_round_trip() {
+        let tensor = CpuTensor::new((0..24).collect::<Vec<i32>>(), vec![2, 3, 4]).unwrap();
+
+        let column = ArrowTensor::<Int32Type>::try_from(tensor.clone()).unwrap();
+        assert_eq!(column.shape(), &[2, 3, 4]);
+        assert_eq!(column.into_inner().value_length(), 3);
+
+        let column = ArrowTensor::<Int32Type>::try_from(tensor.clone()).unwrap();
+        assert_eq!(Backend::contiguous(&column).unwrap(), tensor);
+    }
+
+    #[test]
+    fn arrow_sliced() {
+        let tensor = CpuTensor::new((0..24).collect::<Vec<i32>>(), vec![4, 2, 3]).unwrap();
+        let array = ArrowTensor::<Int32Type>::try_from(tensor)
+            .unwrap()
+            .into_inner()
+            .slice(1, 2);
+
+        let column = ArrowTensor::<Int32Type>::try_new(array).unwrap();
+        assert_eq!(column.shape(), &[2, 2, 3]);
+        assert_eq!(
+            Backend::contiguous(&column).unwrap().data(),
+            (6..18).collect::<Vec<_>>()
+        );
+    }
+}
@@ -0,0 +1,122 @@
+//! Rearrangements of attention layers, for any backend
+//!
+//! Activations are `[batch, tokens, features]` and heads are
+//! `[batch, heads, tokens, head_dim]`, with the features of a token being its
+//! heads one after the other.
+
+use crate::{einops, Backend, EinopsError, ErrorKind};
+
+/// Splits the features of `x` into `n_heads` heads, `b t (h d) -> b h t d`
+///
+/// Keys and values of grouped-query attention are split with their own,
+/// smaller number of heads, see [`repeat_kv_heads`].
+pub fn split_heads<T: Backend<Output = T>>(x: &T, n_heads: usize) -> Result<T, EinopsError> {
+    einops!("b t ({n_heads} d) -> b {n_heads} t d", x)
+}
+
+/// Inverse of [`split_heads`], `b h t d -> b t (h d)`
+pub fn merge_heads<T: Backend<Output = T>>(x: &T) -> Result<T, EinopsError> {
+    einops!("b h t d -> b t (h d)", x)
+}
+
+/// Repeats every head of `kv` so it has `n_heads` heads, `b h t d -> b (h r) t d`
+///
+/// Grouped-query attention shares every key and value head between
+/// `n_heads / kv_heads` neighbouring query heads, which have to divide evenly.
+pub fn repeat_kv_heads<T: Backend<Output = T>>(kv: &T, n_heads: usize) -> Result<T, EinopsError> {
+    let shape = kv.shape();
+    if shape.len() != 4 {
+        return Err(ErrorKind::RankMismatch {
+            expected: 4,
+            found: shape.len(),
+        }
+        .into());
+    }
+    // The heads of the output are the groups `(h r)` of the kv heads
+    let kv_heads = shape[1];
+    if kv_heads == 0 || !n_heads.is_multiple_of(kv_heads) {
+        return Err(ErrorKind::NotDivisible {
+            axis: 1,
+            group: "(h r)".into(),
+            sizes: vec![("h".into(), kv_heads)],
+            size: n_heads,
+            divisor: kv_heads,
+        }
+        .into());
+    }
+
+    let repeats = n_heads / kv_heads;
+    einops!("b h t d -> b (h {repeats}) t d", kv)
+}
+
+/// How rotary position embeddings pair the features of a head
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum RotaryStyle {
+    /// Pairs feature `i` with feature `i + d / 2`, like GPT-NeoX and Llama,
+    /// `.. (two d) -> .. d two`
+    NeoX,
+    /// Pairs neighbouring features `2 i` and `2 i + 1`, like GPT-J,
+    /// `.. (d two) -> .. d two`
+    GptJ,
+}
+
+/// Pairs the features of the last axis of `x` the way `style` rotates them,
+/// `.. d -> .. (d / 2) 2`
+///
+/// Element `[.., i, 0]` of the output is rotated with `[.., i, 1]` by the
+/// angle of frequency `i`, whatever the style. The two styles split the same
+/// features differently, so weights trained with one give wrong results
+/// without an error with the other.
+pub fn split_rotary<T: Backend<Output = T>>(x: &T, style: RotaryStyle) -> Result<T, EinopsError> {
+    let shape = x.shape();
+    let Some(&features) = shape.last() else {
+        return Err(ErrorKind::RankMismatch {
+            expected: 1,
+            found: 0,
+        }
+        .into());
+    };
+    if !features.is_multiple_of(2) {
+        return Err(ErrorKind::NotDivisible {
+            axis: shape.len() - 1,
+            group: "(d two)".into(),
+            sizes: vec![("two".into(), 2)],
+            size: features,
+            divisor: 2,
+        }
+        .into());
+    }
+
+    match style {
+        RotaryStyle::NeoX => einops!(".. (two:2 d) -> .. d two", x),
+        RotaryStyle::GptJ => einops!(".. (d two:2) -> .. d two", x),
+    }
+}
+
+/// Inverse of [`split_rotary`] for the same `style`, `.. d 2 -> .. (d * 2)`
+pub fn merge_rotary<T: Backend<Output = T>>(x: &T, style: RotaryStyle) -> Result<T, EinopsError> {
+    let shape = x.shape();
+    if shape.len() < 2 {
+        return Err(ErrorKind::RankMismatch {
+            expected: 2,
+            found: shape.len(),
+        }
+        .into());
+    }
+    let pairs = shape[shape.len() - 1];
+    if pairs != 2 {
+        return Err(ErrorKind::ShapeMismatch {
+            axis: shape.len() - 1,
+            group: "two".into(),
+            sizes: Vec::new(),
+            expected: 2,
+            found: pairs,
+        }
+        .into());
+    }
+
+    match style {
+        RotaryStyle::NeoX => einops!(".. d two:2 -> .. (two d)", x),
+        RotaryStyle::GptJ => einops!(".. d two:2 -> .. (d two)", x),
+    }
+}
@@ -1,10 +1,197 @@
-use crate::Operation;
+use crate::{DType, Device, EinopsError, Operation, ScanOp};
 
+/// Tensor operations the `einops!` macro is expanded into
+///
+/// Every method borrows the tensor and returns an owned `Output`, so
+/// `Backend::transpose(&tensor, ..)` and `Backend::transpose(&&tensor, ..)`
+/// behave the same. References to backends are backends as well.
 pub trait Backend {
     type Output;
-    fn shape(self) -> Vec<usize>;
-    fn reshape(self, shape: &[usize]) -> Self::Output;
-    fn transpose(self, axes: &[usize]) -> Self::Output;
-    fn reduce_axes(self, axes_operations: &mut [(usize, Operation)]) -> Self::Output;
-    fn add_axes(self, naxes: usize, pos2len: &[(usize, usize)]) -> Self::Output;
+    /// Read from the tensor's metadata, `einops!` plans every operation from
+    /// it so it must not synchronize with the device or read the data
+    fn shape(&self) -> Vec<usize>;
+    fn reshape(&self, shape: &[usize]) -> Result<Self::Output, EinopsError>;
+    fn transpose(&self, axes: &[usize]) -> Result<Self::Output, EinopsError>;
+    /// Reshapes without copying, `None` unless the output shares the data of
+    /// the input. `einops!` tries it before [`Backend::reshape`], which reports
+    /// invalid shapes
+    fn reshape_view(&self, shape: &[usize]) -> Option<Self::Output> {
+        let _ = shape;
+        None
+    }
+    /// Permutes the axes without copying, `None` unless the output shares the
+    /// data of the input. `einops!` tries it before [`Backend::transpose`]
+    fn transpose_view(&self, axes: &[usize]) -> Option<Self::Output> {
+        let _ = axes;
+        None
+    }
+    /// Rearranges `b (c p1 p2) h w -> b c (h p1) (w p2)` with a dedicated
+    /// kernel, `None` if the backend has none. `einops!` tries it before the
+    /// reshapes and permutation of patterns of that form
+    fn depth_to_space(&self, p1: usize, p2: usize) -> Option<Self::Output> {
+        let _ = (p1, p2);
+        None
+    }
+    /// Inverse of [`Backend::depth_to_space`], `b c (h p1) (w p2) -> b (c p1 p2) h w`
+    fn space_to_depth(&self, p1: usize, p2: usize) -> Option<Self::Output> {
+        let _ = (p1, p2);
+        None
+    }
+    fn reduce_axes(
+        &self,
+        axes_operations: &mut [(usize, Operation)],
+    ) -> Result<Self::Output, EinopsError>;
+    fn add_axes(
+        &self,
+        naxes: usize,
+        pos2len: &[(usize, usize)],
+    ) -> Result<Self::Output, EinopsError>;
+    /// Keeps `len` elements of `axis`, starting at `start`
+    fn narrow(&self, axis: usize, start: usize, len: usize) -> Result<Self::Output, EinopsError>;
+    /// Expands axes of length 1 to `shape`, new leading axes can be added as well.
+    /// Backends are free to return a view instead of copying the data
+    fn broadcast(&self, shape: &[usize]) -> Result<Self::Output, EinopsError>;
+    /// Whether [`Backend::contiguous`] can return the data without copying it
+    fn is_contiguous(&self) -> bool;
+    /// Returns the tensor with its data laid out in row-major order
+    fn contiguous(&self) -> Result<Self::Output, EinopsError>;
+    /// Joins `inputs` along the existing `axis`, all other axes have to match
+    fn concat(inputs: &[&Self], axis: usize) -> Result<Self::Output, EinopsError>;
+    /// Picks the elements of `axis` at `indices`, indices can repeat and be in any order
+    fn index_select(&self, axis: usize, indices: &[usize]) -> Result<Self::Output, EinopsError>;
+    /// Accumulates `operation` along `axis`, element `i` of the output combines
+    /// elements `0..=i` of the input
+    fn scan(&self, axis: usize, operation: ScanOp) -> Result<Self::Output, EinopsError> {
+        let _ = (axis, operation);
+        Err(EinopsError::new("scan is not supported by this backend"))
+    }
+    /// Batched matrix product of a `[.., m, k]` and a `[.., k, n]` tensor with
+    /// the same leading axes, backends without a native matmul don't have to
+    /// implement it
+    fn contract(&self, rhs: &Self) -> Result<Self::Output, EinopsError> {
+        let _ = rhs;
+        Err(EinopsError::new(
+            "contract is not supported by this backend",
+        ))
+    }
+    /// Element type of the tensor, `None` if the backend doesn't know it or it
+    /// has no [`DType`] equivalent
+    fn dtype(&self) -> Option<DType> {
+        None
+    }
+    /// Casts the elements to `dtype`
+    fn to_dtype(&self, dtype: DType) -> Result<Self::Output, EinopsError> {
+        let _ = dtype;
+        Err(EinopsError::new("casting is not supported by this backend"))
+    }
+    /// Device the tensor is stored on, `None` if the backend doesn't know it or
+    /// it has no [`Device`] equivalent
+    fn device(&self) -> Option<Device> {
+        None
+    }
+    /// Copies the tensor to `device`
+    fn to_device(&self, device: Device) -> Result<Self::Output, EinopsError> {
+        let _ = device;
+        Err(EinopsError::new(
+            "moving tensors is not supported by this backend",
+        ))
+    }
+}
+
+impl<T: Backend + ?Sized> Backend for &T {
+    type Output = T::Output;
+
+    fn shape(&self) -> Vec<usize> {
+        (**self).shape()
+    }
+
+    fn reshape(&self, shape: &[usize]) -> Result<Self::Output, EinopsError> {
+        (**self).reshape(shape)
+    }
+
+    fn transpose(&self, axes: &[usize]) -> Result<Self::Output, EinopsError> {
+        (**self).transpose(axes)
+    }
+
+    fn reshape_view(&self, shape: &[usize]) -> Option<Self::Output> {
+        (**self).reshape_view(shape)
+    }
+
+    fn transpose_view(&self, axes: &[usize]) -> Option<Self::Output> {
+        (**self).transpose_view(axes)
+    }
+
+    fn depth_to_space(&self, p1: usize, p2: usize) -> Option<Self::Output> {
+        (**self).depth_to_space(p1, p2)
+    }
+
+    fn space_to_depth(&self, p1: usize, p2: usize) -> Option<Self::Output> {
+        (**self).space_to_depth(p1, p2)
+    }
+
+    fn reduce_axes(
+        &self,
+        axes_operations: &mut [(usize, Operation)],
+    ) -> Result<Self::Output, EinopsError> {
+        (**self).reduce_axes(axes_operations)
+    }
+
+    fn add_axes(
+        &self,
+        naxes: usize,
+        pos2len: &[(usize, usize)],
+    ) -> Result<Self::Output, EinopsError> {
+        (**self).add_axes(naxes, pos2len)
+    }
+
+    fn narrow(&self, axis: usize, start: usize, len: usize) -> Result<Self::Output, EinopsError> {
+        (**self).narrow(axis, start, len)
+    }
+
+    fn broadcast(&self, shape: &[usize]) -> Result<Self::Output, EinopsError> {
+        (**self).broadcast(shape)
+    }
+
+    fn is_contiguous(&self) -> bool {
+        (**self).is_contiguous()
+    }
+
+    fn contiguous(&self) -> Result<Self::Output, EinopsError> {
+        (**self).contiguous()
+    }
+
+    fn concat(inputs: &[&Self], axis: usize) -> Result<Self::Output, EinopsError> {
+        T::concat(
+            &inputs.iter().map(|input| **input).collect::<Vec<_>>(),
+            axis,
+        )
+    }
+
+    fn index_select(&self, axis: usize, indices: &[usize]) -> Result<Self::Output, EinopsError> {
+        (**self).index_select(axis, indices)
+    }
+
+    fn scan(&self, axis: usize, operation: ScanOp) -> Result<Self::Output, EinopsError> {
+        (**self).scan(axis, operation)
+    }
+
+    fn contract(&self, rhs: &Self) -> Result<Self::Output, EinopsError> {
+        (**self).contract(rhs)
+    }
+
+    fn dtype(&self) -> Option<DType> {
+        (**self).dtype()
+    }
+
+    fn to_dtype(&self, dtype: DType) -> Result<Self::Output, EinopsError> {
+        (**self).to_dtype(dtype)
+    }
+
+    fn device(&self) -> Option<Device> {
+        (**self).device()
+    }
+
+    fn to_device(&self, device: Device) -> Result<Self::Output, EinopsError> {
+        (**self).to_device(device)
+    }
 }
@@ -0,0 +1,228 @@
+//! Reusable checks for [`Backend`] implementations
+//!
+//! Every check builds `f32` tensors with `new`, which receives row-major data
+//! and a shape, runs one operation and compares the row-major data returned by
+//! `read` against the results of the candle backend. The checks panic on a
+//! mismatch. [`backend_conformance!`](crate::backend_conformance!) generates a
+//! test for each of them:
+//!
+//! ```ignore
+//! candle_einops::backend_conformance!(
+//!     my_tensor,
+//!     |data, shape| MyTensor::new(data, shape),
+//!     |tensor: &MyTensor| tensor.to_vec()
+//! );
+//! ```
+
+use crate::{Backend, Operation};
+
+fn check<B: Backend>(
+    output: B::Output,
+    read: &impl Fn(&B::Output) -> Vec<f32>,
+    shape: &[usize],
+    data: &[f32],
+    case: &str,
+) where
+    B::Output: Backend,
+{
+    assert_eq!(Backend::shape(&output), shape, "shape of {}", case);
+    assert_eq!(read(&output), data, "data of {}", case);
+}
+
+fn arange(len: usize) -> Vec<f32> {
+    (0..len).map(|x| x as f32).collect()
+}
+
+pub fn reshape<B: Backend>(
+    new: impl Fn(Vec<f32>, &[usize]) -> B,
+    read: impl Fn(&B::Output) -> Vec<f32>,
+) where
+    B::Output: Backend,
+{
+    let tensor = new(arange(24), &[2, 3, 4]);
+    check::<B>(
+        Backend::reshape(&tensor, &[6, 4]).unwrap(),
+        &read,
+        &[6, 4],
+        &arange(24),
+        "reshape to [6, 4]",
+    );
+    check::<B>(
+        Backend::reshape(&tensor, &[24]).unwrap(),
+        &read,
+        &[24],
+        &arange(24),
+        "reshape to [24]",
+    );
+    assert!(Backend::reshape(&tensor, &[5, 5]).is_err());
+}
+
+pub fn transpose<B: Backend>(
+    new: impl Fn(Vec<f32>, &[usize]) -> B,
+    read: impl Fn(&B::Output) -> Vec<f32>,
+) where
+    B::Output: Backend,
+{
+    let tensor = new(arange(6), &[2, 3]);
+    check::<B>(
+        Backend::transpose(&tensor, &[1, 0]).unwrap(),
+        &read,
+        &[3, 2],
+        &[0.0, 3.0, 1.0, 4.0, 2.0, 5.0],
+        "transpose [1, 0]",
+    );
+
+    let tensor = new(arange(8), &[2, 2, 2]);
+    check::<B>(
+        Backend::transpose(&tensor, &[2, 0, 1]).unwrap(),
+        &read,
+        &[2, 2, 2],
+        &[0.0, 2.0, 4.0, 6.0, 1.0, 3.0, 5.0, 7.0],
+        "transpose [2, 0, 1]",
+    );
+}
+
+pub fn reduce_axes<B: Backend>(
+    new: impl Fn(Vec<f32>, &[usize]) -> B,
+    read: impl Fn(&B::Output) -> Vec<f32>,
+) where
+    B::Output: Backend,
+{
+    let tensor = new(arange(24), &[2, 3, 4]);
+    check::<B>(
+        Backend::reduce_axes(&tensor, &mut [(0, Operation::Sum), (2, Operation::Sum)]).unwrap(),
+        &read,
+        &[3],
+        &[60.0, 92.0, 124.0],
+        "sum of axes 0 and 2",
+    );
+    check::<B>(
+        Backend::reduce_axes(&tensor, &mut [(1, Operation::Max)]).unwrap(),
+        &read,
+        &[2, 4],
+        &[8.0, 9.0, 10.0, 11.0, 20.0, 21.0, 22.0, 23.0],
+        "max of axis 1",
+    );
+    check::<B>(
+        Backend::reduce_axes(&tensor, &mut [(2, Operation::Min)]).unwrap(),
+        &read,
+        &[2, 3],
+        &[0.0, 4.0, 8.0, 12.0, 16.0, 20.0],
+        "min of axis 2",
+    );
+    check::<B>(
+        Backend::reduce_axes(&tensor, &mut [(0, Operation::Mean), (1, Operation::Mean)]).unwrap(),
+        &read,
+        &[4],
+        &[10.0, 11.0, 12.0, 13.0],
+        "mean of axes 0 and 1",
+    );
+}
+
+pub fn add_axes<B: Backend>(
+    new: impl Fn(Vec<f32>, &[usize]) -> B,
+    read: impl Fn(&B::Output) -> Vec<f32>,
+) where
+    B::Output: Backend,
+{
+    let tensor = new(arange(2), &[2]);
+    check::<B>(
+        Backend::add_axes(&tensor, 3, &[(0, 2), (2, 3)]).unwrap(),
+        &read,
+        &[2, 2, 3],
+        &[0.0, 0.0, 0.0, 1.0, 1.0, 1.0, 0.0, 0.0, 0.0, 1.0, 1.0, 1.0],
+        "add axes at 0 and 2",
+    );
+}
+
+pub fn narrow<B: Backend>(
+    new: impl Fn(Vec<f32>, &[usize]) -> B,
+    read: impl Fn(&B::Output) -> Vec<f32>,
+) where
+    B::Output: Backend,
+{
+    let tensor = new(arange(6), &[2, 3]);
+    check::<B>(
+        Backend::narrow(&tensor, 1, 1, 2).unwrap(),
+        &read,
+        &[2, 2],
+        &[1.0, 2.0, 4.0, 5.0],
+        "narrow axis 1 to 1..3",
+    );
+    assert!(Backend::narrow(&tensor, 1, 2, 2).is_err());
+}
+
+pub fn broadcast<B: Backend>(
+    new: impl Fn(Vec<f32>, &[usize]) -> B,
+    read: impl Fn(&B::Output) -> Vec<f32>,
+) where
+    B::Output: Backend,
+{
+    let tensor = new(arange(2), &[2, 1]);
+    check::<B>(
+        Backend::broadcast(&tensor, &[2, 2, 3]).unwrap(),
+        &read,
+        &[2, 2, 3],
+        &[0.0, 0.0, 0.0, 1.0, 1.0, 1.0, 0.0, 0.0, 0.0, 1.0, 1.0, 1.0],
+        "broadcast to [2, 2, 3]",
+    );
+    assert!(Backend::broadcast(&tensor, &[3, 3]).is_err());
+}
+
+pub fn concat<B: Backend>(
+    new: impl Fn(Vec<f32>, &[usize]) -> B,
+    read: impl Fn(&B::Output) -> Vec<f32>,
+) where
+    B::Output: Backend,
+{
+    let a = new(arange(4), &[2, 2]);
+    let b = new(vec![4.0, 5.0], &[2, 1]);
+    check::<B>(
+        B::concat(&[&a, &b], 1).unwrap(),
+        &read,
+        &[2, 3],
+        &[0.0, 1.0, 4.0, 2.0, 3.0, 5.0],
+        "concat along axis 1",
+    );
+    assert!(B::concat(&[&a, &b], 0).is_err());
+}
+
+pub fn index_select<B: Backend>(
+    new: impl Fn(Vec<f32>, &[usize]) -> B,
+    read: impl Fn(&B::Output) -> Vec<f32>,
+) where
+    B::Output: Backend,
+{
+    let tensor = new(arange(6), &[2, 3]);
+    check::<B>(
+        Backend::index_select(&tensor, 1, &[2, 0, 2]).unwrap(),
+        &read,
+        &[2, 3],
+        &[2.0, 0.0, 2.0, 5.0, 3.0, 5.0],
+        "select [2, 0, 2] of axis 1",
+    );
+    assert!(Backend::index_select(&tensor, 1, &[3]).is_err());
+}
+
+/// Generates a module of tests running every check of
+/// [`backend_conformance`](crate::backend_conformance) for a backend
+#[macro_export]
+macro_rules! backend_conformance {
+    ($name:ident, $new:expr, $read:expr $(,)?) => {
+        mod $name {
+            #[allow(unused_imports)]
+            use super::*;
+
+            $crate::backend_conformance!(@tests $new, $read;
+                reshape transpose reduce_axes add_axes narrow broadcast concat index_select);
+        }
+    };
+    (@tests $new:expr, $read:expr; $($check:ident)*) => {
+        $(
+            #[test]
+            fn $check() {
+                $crate::backend_conformance::$check($new, $read);
+            }
+        )*
+    };
+}
@@ -0,0 +1,281 @@
+use crate::{Backend, DType, Device, EinopsError, Operation, ScanOp};
+
+/// Minimal set of operations needed to hook a tensor type into `einops!`
+///
+/// Only the first five methods have to be implemented, the remaining ones
+/// return an error unless the tensor type supports them. Wrap the tensor in a
+/// [`Bridge`] to use it with the macro.
+pub trait TensorOps: Sized {
+    fn shape(&self) -> Vec<usize>;
+    fn reshape(&self, shape: &[usize]) -> Result<Self, EinopsError>;
+    /// Reorders the axes, output axis `i` is input axis `axes[i]`
+    fn permute(&self, axes: &[usize]) -> Result<Self, EinopsError>;
+    /// Reduces `axis` away
+    fn reduce(&self, axis: usize, operation: Operation) -> Result<Self, EinopsError>;
+    /// Expands axes of length 1 to `shape`, which has the same number of axes
+    fn expand(&self, shape: &[usize]) -> Result<Self, EinopsError>;
+
+    /// Returns the tensor with its data laid out in row-major order, defaults to
+    /// reshaping the tensor to its own shape
+    fn contiguous(&self) -> Result<Self, EinopsError> {
+        self.reshape(&self.shape())
+    }
+
+    fn narrow(&self, axis: usize, start: usize, len: usize) -> Result<Self, EinopsError> {
+        let _ = (axis, start, len);
+        Err(EinopsError::new(
+            "narrow is not supported by this tensor type",
+        ))
+    }
+
+    fn concat(inputs: &[&Self], axis: usize) -> Result<Self, EinopsError> {
+        let _ = (inputs, axis);
+        Err(EinopsError::new(
+            "concat is not supported by this tensor type",
+        ))
+    }
+
+    fn index_select(&self, axis: usize, indices: &[usize]) -> Result<Self, EinopsError> {
+        let _ = (axis, indices);
+        Err(EinopsError::new(
+            "index_select is not supported by this tensor type",
+        ))
+    }
+
+    fn scan(&self, axis: usize, operation: ScanOp) -> Result<Self, EinopsError> {
+        let _ = (axis, operation);
+        Err(EinopsError::new(
+            "scan is not supported by this tensor type",
+        ))
+    }
+
+    fn contract(&self, rhs: &Self) -> Result<Self, EinopsError> {
+        let _ = rhs;
+        Err(EinopsError::new(
+            "contract is not supported by this tensor type",
+        ))
+    }
+
+    fn dtype(&self) -> Option<DType> {
+        None
+    }
+
+    fn to_dtype(&self, dtype: DType) -> Result<Self, EinopsError> {
+        let _ = dtype;
+        Err(EinopsError::new(
+            "casting is not supported by this tensor type",
+        ))
+    }
+
+    fn device(&self) -> Option<Device> {
+        None
+    }
+
+    fn to_device(&self, device: Device) -> Result<Self, EinopsError> {
+        let _ = device;
+        Err(EinopsError::new(
+            "moving tensors is not supported by this tensor type",
+        ))
+    }
+}
+
+/// Implements [`Backend`] for any tensor implementing [`TensorOps`]
+///
+/// ```ignore
+/// let output = einops!("a b -> b a", &Bridge(tensor))?.into_inner();
+/// ```
+#[derive(Debug, Clone)]
+pub struct Bridge<T>(pub T);
+
+impl<T> Bridge<T> {
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<T: TensorOps> Backend for Bridge<T> {
+    type Output = Bridge<T>;
+
+    fn shape(&self) -> Vec<usize> {
+        self.0.shape()
+    }
+
+    fn reshape(&self, shape: &[usize]) -> Result<Self::Output, EinopsError> {
+        self.0.reshape(shape).map(Bridge)
+    }
+
+    fn transpose(&self, axes: &[usize]) -> Result<Self::Output, EinopsError> {
+        self.0.permute(axes).map(Bridge)
+    }
+
+    fn reduce_axes(
+        &self,
+        axes_operations: &mut [(usize, Operation)],
+    ) -> Result<Self::Output, EinopsError> {
+        axes_operations.sort_by_key(|(axis, _)| *axis);
+
+        // Reduce the last axes first so the positions of the others don't change
+        let mut operations = axes_operations.iter().rev();
+        let mut output = match operations.next() {
+            Some(&(axis, operation)) => self.0.reduce(axis, operation)?,
+            None => return self.0.reshape(&self.0.shape()).map(Bridge),
+        };
+        for &(axis, operation) in operations {
+            output = output.reduce(axis, operation)?;
+        }
+
+        Ok(Bridge(output))
+    }
+
+    fn add_axes(
+        &self,
+        naxes: usize,
+        pos2len: &[(usize, usize)],
+    ) -> Result<Self::Output, EinopsError> {
+        let mut unsqueezed = self.0.shape();
+        let mut expanded = unsqueezed.clone();
+
+        for &(axis_pos, axis_len) in pos2len {
+            if axis_pos > unsqueezed.len() {
+                return Err(EinopsError::new(format!(
+                    "cannot insert axis at position {} of tensor with shape {:?}",
+                    axis_pos, unsqueezed
+                )));
+            }
+            unsqueezed.insert(axis_pos, 1);
+            expanded.insert(axis_pos, axis_len);
+        }
+        if expanded.len() != naxes {
+            return Err(EinopsError::new(format!(
+                "expected {} axes after inserting new axes, found {}",
+                naxes,
+                expanded.len()
+            )));
+        }
+
+        self.0.reshape(&unsqueezed)?.expand(&expanded).map(Bridge)
+    }
+
+    fn narrow(&self, axis: usize, start: usize, len: usize) -> Result<Self::Output, EinopsError> {
+        self.0.narrow(axis, start, len).map(Bridge)
+    }
+
+    fn broadcast(&self, shape: &[usize]) -> Result<Self::Output, EinopsError> {
+        let mut unsqueezed = self.0.shape();
+        let leading = shape.len().checked_sub(unsqueezed.len()).ok_or_else(|| {
+            EinopsError::new(format!(
+                "cannot broadcast tensor of shape {:?} to {:?}",
+                unsqueezed, shape
+            ))
+        })?;
+        unsqueezed.splice(0..0, std::iter::repeat_n(1, leading));
+
+        self.0.reshape(&unsqueezed)?.expand(shape).map(Bridge)
+    }
+
+    // Tensor types don't report their layout through `TensorOps`
+    fn is_contiguous(&self) -> bool {
+        false
+    }
+
+    fn contiguous(&self) -> Result<Self::Output, EinopsError> {
+        self.0.contiguous().map(Bridge)
+    }
+
+    fn concat(inputs: &[&Self], axis: usize) -> Result<Self::Output, EinopsError> {
+        let tensors = inputs.iter().map(|input| &input.0).collect::<Vec<_>>();
+        T::concat(&tensors, axis).map(Bridge)
+    }
+
+    fn index_select(&self, axis: usize, indices: &[usize]) -> Result<Self::Output, EinopsError> {
+        self.0.index_select(axis, indices).map(Bridge)
+    }
+
+    fn scan(&self, axis: usize, operation: ScanOp) -> Result<Self::Output, EinopsError> {
+        self.0.scan(axis, operation).map(Bridge)
+    }
+
+    fn contract(&self, rhs: &Self) -> Result<Self::Output, EinopsError> {
+        self.0.contract(&rhs.0).map(Bridge)
+    }
+
+    fn dtype(&self) -> Option<DType> {
+        self.0.dtype()
+    }
+
+    fn to_dtype(&self, dtype: DType) -> Result<Self::Output, EinopsError> {
+        self.0.to_dtype(dtype).map(Bridge)
+    }
+
+    fn device(&self) -> Option<Device> {
+        self.0.device()
+    }
+
+    fn to_device(&self, device: Device) -> Result<Self::Output, EinopsError> {
+        self.0.to_device(device).map(Bridge)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Only tracks the shape, which is all the bridge needs to be checked
+    #[derive(Debug, Clone)]
+    struct Shaped(Vec<usize>);
+
+    impl TensorOps for Shaped {
+        fn shape(&self) -> Vec<usize> {
+            self.0.clone()
+        }
+
+        fn reshape(&self, shape: &[usize]) -> Result<Self, EinopsError> {
+            assert_eq!(
+                shape.iter().product::<usize>(),
+                self.0.iter().product::<usize>()
+            );
+            Ok(Shaped(shape.to_vec()))
+        }
+
+        fn permute(&self, axes: &[usize]) -> Result<Self, EinopsError> {
+            Ok(Shaped(axes.iter().map(|&axis| self.0[axis]).collect()))
+        }
+
+        fn reduce(&self, axis: usize, _operation: Operation) -> Result<Self, EinopsError> {
+            let mut shape = self.0.clone();
+            shape.remove(axis);
+            Ok(Shaped(shape))
+        }
+
+        fn expand(&self, shape: &[usize]) -> Result<Self, EinopsError> {
+            assert_eq!(shape.len(), self.0.len());
+            Ok(Shaped(shape.to_vec()))
+        }
+    }
+
+    #[test]
+    fn bridge_reduce() {
+        let tensor = Bridge(Shaped(vec![2, 3, 4]));
+
+        let output =
+            Backend::reduce_axes(&tensor, &mut [(0, Operation::Sum), (2, Operation::Max)]).unwrap();
+        assert_eq!(output.into_inner().0, [3]);
+    }
+
+    #[test]
+    fn bridge_add_axes() {
+        let tensor = Bridge(Shaped(vec![2, 3]));
+
+        let output = Backend::add_axes(&tensor, 4, &[(0, 5), (2, 6)]).unwrap();
+        assert_eq!(output.into_inner().0, [5, 2, 6, 3]);
+
+        let output = Backend::broadcast(&tensor, &[4, 2, 3]).unwrap();
+        assert_eq!(output.into_inner().0, [4, 2, 3]);
+    }
+
+    #[test]
+    fn bridge_unsupported() {
+        let tensor = Bridge(Shaped(vec![2, 3]));
+        assert!(Backend::narrow(&tensor, 0, 0, 1).is_err());
+    }
+}
@@ -0,0 +1,529 @@
+use burn_tensor::backend::Backend as BurnBackend;
+use burn_tensor::{Element, Float, Int, Numeric, Tensor, TensorData};
+
+use crate::{Backend, DType, EinopsError, Operation};
+
+/// Highest tensor rank supported by [`BurnTensor`]
+pub const MAX_RANK: usize = 6;
+
+/// A burn tensor whose rank is tracked at runtime
+///
+/// burn encodes the rank of a tensor as a const generic, while einops patterns
+/// change the rank of a tensor at runtime. Wrap a tensor with `BurnTensor::from`
+/// before passing it to `einops!`, and convert the result back with `try_into`.
+#[derive(Debug, Clone)]
+pub enum BurnTensor<B: BurnBackend, K: Numeric<B> = Float> {
+    Rank1(Tensor<B, 1, K>),
+    Rank2(Tensor<B, 2, K>),
+    Rank3(Tensor<B, 3, K>),
+    Rank4(Tensor<B, 4, K>),
+    Rank5(Tensor<B, 5, K>),
+    Rank6(Tensor<B, 6, K>),
+}
+
+// Binds the inner tensor of every variant to `$tensor` and evaluates `$body`
+macro_rules! with_tensor {
+    ($burn_tensor:expr, $tensor:ident => $body:expr) => {
+        match $burn_tensor {
+            BurnTensor::Rank1($tensor) => $body,
+            BurnTensor::Rank2($tensor) => $body,
+            BurnTensor::Rank3($tensor) => $body,
+            BurnTensor::Rank4($tensor) => $body,
+            BurnTensor::Rank5($tensor) => $body,
+            BurnTensor::Rank6($tensor) => $body,
+        }
+    };
+}
+
+macro_rules! impl_conversions {
+    ($($rank:literal => $variant:ident),*) => {
+        $(
+            impl<B: BurnBackend, K: Numeric<B>> From<Tensor<B, $rank, K>> for BurnTensor<B, K> {
+                fn from(tensor: Tensor<B, $rank, K>) -> Self {
+                    BurnTensor::$variant(tensor)
+                }
+            }
+
+            impl<B: BurnBackend, K: Numeric<B>> TryFrom<BurnTensor<B, K>> for Tensor<B, $rank, K> {
+                type Error = EinopsError;
+
+                fn try_from(tensor: BurnTensor<B, K>) -> Result<Self, Self::Error> {
+                    match tensor {
+                        BurnTensor::$variant(tensor) => Ok(tensor),
+                        tensor => Err(EinopsError::new(format!(
+                            "expected a tensor of rank {}, found rank {}",
+                            $rank,
+                            tensor.rank()
+                        ))),
+                    }
+                }
+            }
+        )*
+    };
+}
+
+impl_conversions!(1 => Rank1, 2 => Rank2, 3 => Rank3, 4 => Rank4, 5 => Rank5, 6 => Rank6);
+
+impl<B: BurnBackend, K> BurnTensor<B, K>
+where
+    K: Numeric<B>,
+    K::Elem: Element,
+{
+    pub fn rank(&self) -> usize {
+        with_tensor!(self, tensor => tensor.dims().len())
+    }
+
+    pub fn dims(&self) -> Vec<usize> {
+        with_tensor!(self, tensor => tensor.dims().to_vec())
+    }
+
+    fn reshape(self, shape: &[usize]) -> Result<Self, EinopsError> {
+        if self.dims() == shape {
+            return Ok(self);
+        }
+        let elem_count = self.dims().iter().product::<usize>();
+        if shape.iter().product::<usize>() != elem_count {
+            return Err(EinopsError::new(format!(
+                "cannot reshape tensor of shape {:?} to {:?}",
+                self.dims(),
+                shape
+            )));
+        }
+        with_tensor!(self, tensor => reshape_tensor(tensor, shape))
+    }
+
+    fn transpose(self, axes: &[usize]) -> Result<Self, EinopsError> {
+        let mut sorted = axes.to_vec();
+        sorted.sort_unstable();
+        if sorted.len() != self.rank() || sorted.iter().enumerate().any(|(i, &axis)| i != axis) {
+            return Err(EinopsError::new(format!(
+                "invalid permutation {:?} for tensor of rank {}",
+                axes,
+                self.rank()
+            )));
+        }
+        if sorted == axes {
+            return Ok(self);
+        }
+        Ok(with_tensor!(self, tensor => {
+            let mut permutation = [0; MAX_RANK];
+            permutation
+                .iter_mut()
+                .zip(axes)
+                .for_each(|(p, &axis)| *p = axis as isize);
+            tensor.permute(permutation[..axes.len()].try_into().unwrap()).into()
+        }))
+    }
+
+    fn reduce_axis(self, axis: usize, operation: Operation) -> Result<Self, EinopsError> {
+        let mut shape = self.dims();
+        if axis >= shape.len() {
+            return Err(EinopsError::new(format!(
+                "cannot reduce axis {} of tensor with shape {:?}",
+                axis, shape
+            )));
+        }
+        let reduced: Self = with_tensor!(self, tensor => match operation {
+            Operation::Min => tensor.min_dim(axis).into(),
+            Operation::Max => tensor.max_dim(axis).into(),
+            Operation::Sum => tensor.sum_dim(axis).into(),
+            Operation::Mean => tensor.mean_dim(axis).into(),
+        });
+        // burn keeps reduced dimensions, a fully reduced tensor stays a rank 1 tensor
+        shape.remove(axis);
+        if shape.is_empty() {
+            shape.push(1);
+        }
+        reduced.reshape(&shape)
+    }
+
+    fn narrow(self, axis: usize, start: usize, len: usize) -> Result<Self, EinopsError> {
+        let shape = self.dims();
+        // burn panics on an out of bounds range
+        if axis >= shape.len() || start + len > shape[axis] {
+            return Err(EinopsError::new(format!(
+                "cannot narrow axis {} of tensor with shape {:?} to {}..{}",
+                axis,
+                shape,
+                start,
+                start + len
+            )));
+        }
+        Ok(with_tensor!(self, tensor => tensor.narrow(axis, start, len).into()))
+    }
+
+    fn index_select(self, axis: usize, indices: &[usize]) -> Result<Self, EinopsError> {
+        let shape = self.dims();
+        // burn panics on out of bounds indices
+        if axis >= shape.len() || indices.iter().any(|&i| i >= shape[axis]) {
+            return Err(EinopsError::new(format!(
+                "cannot select indices {:?} of axis {} of tensor with shape {:?}",
+                indices, axis, shape
+            )));
+        }
+        let indices = TensorData::new(
+            indices.iter().map(|&i| i as i64).collect::<Vec<_>>(),
+            [indices.len()],
+        );
+        Ok(with_tensor!(self, tensor => {
+            let indices = Tensor::<B, 1, Int>::from_data(indices, &tensor.device());
+            tensor.select(axis, indices).into()
+        }))
+    }
+
+    fn broadcast(self, shape: &[usize]) -> Result<Self, EinopsError> {
+        let dims = self.dims();
+        // burn panics on incompatible shapes
+        let compatible = shape.len() >= dims.len()
+            && dims
+                .iter()
+                .zip(&shape[shape.len() - dims.len()..])
+                .all(|(&len, &target_len)| len == target_len || len == 1);
+        if !compatible {
+            return Err(EinopsError::new(format!(
+                "cannot broadcast tensor of shape {:?} to {:?}",
+                dims, shape
+            )));
+        }
+        with_tensor!(self, tensor => expand_tensor(tensor, shape))
+    }
+}
+
+fn reshape_tensor<B: BurnBackend, K: Numeric<B>, const D: usize>(
+    tensor: Tensor<B, D, K>,
+    shape: &[usize],
+) -> Result<BurnTensor<B, K>, EinopsError> {
+    Ok(match *shape {
+        [a] => tensor.reshape([a]).into(),
+        [a, b] => tensor.reshape([a, b]).into(),
+        [a, b, c] => tensor.reshape([a, b, c]).into(),
+        [a, b, c, d] => tensor.reshape([a, b, c, d]).into(),
+        [a, b, c, d, e] => tensor.reshape([a, b, c, d, e]).into(),
+        [a, b, c, d, e, f] => tensor.reshape([a, b, c, d, e, f]).into(),
+        _ => {
+            return Err(EinopsError::new(format!(
+                "burn tensors of rank {} are not supported, supported ranks are 1 to {}",
+                shape.len(),
+                MAX_RANK
+            )))
+        }
+    })
+}
+
+fn concat_tensors<B: BurnBackend, K: Numeric<B>, const D: usize>(
+    first: Tensor<B, D, K>,
+    rest: &[&BurnTensor<B, K>],
+    axis: usize,
+) -> Result<BurnTensor<B, K>, EinopsError>
+where
+    Tensor<B, D, K>: TryFrom<BurnTensor<B, K>, Error = EinopsError> + Into<BurnTensor<B, K>>,
+{
+    let mut tensors = vec![first];
+    for tensor in rest {
+        tensors.push(BurnTensor::clone(tensor).try_into()?);
+    }
+    Ok(Tensor::cat(tensors, axis).into())
+}
+
+fn expand_tensor<B: BurnBackend, K: Numeric<B>, const D: usize>(
+    tensor: Tensor<B, D, K>,
+    shape: &[usize],
+) -> Result<BurnTensor<B, K>, EinopsError> {
+    Ok(match *shape {
+        [a] => tensor.expand([a]).into(),
+        [a, b] => tensor.expand([a, b]).into(),
+        [a, b, c] => tensor.expand([a, b, c]).into(),
+        [a, b, c, d] => tensor.expand([a, b, c, d]).into(),
+        [a, b, c, d, e] => tensor.expand([a, b, c, d, e]).into(),
+        [a, b, c, d, e, f] => tensor.expand([a, b, c, d, e, f]).into(),
+        _ => {
+            return Err(EinopsError::new(format!(
+                "burn tensors of rank {} are not supported, supported ranks are 1 to {}",
+                shape.len(),
+                MAX_RANK
+            )))
+        }
+    })
+}
+
+impl<B: BurnBackend, K> Backend for BurnTensor<B, K>
+where
+    K: Numeric<B>,
+    K::Elem: Element,
+{
+    type Output = BurnTensor<B, K>;
+
+    fn shape(&self) -> Vec<usize> {
+        self.dims()
+    }
+
+    fn reshape(&self, shape: &[usize]) -> Result<Self::Output, EinopsError> {
+        self.clone().reshape(shape)
+    }
+
+    fn transpose(&self, axes: &[usize]) -> Result<Self::Output, EinopsError> {
+        self.clone().transpose(axes)
+    }
+
+    fn reduce_axes(
+        &self,
+        axes_operations: &mut [(usize, Operation)],
+    ) -> Result<Self::Output, EinopsError> {
+        let mut output = self.clone();
+
+        axes_operations.sort_by_key(|(axis, _)| *axis);
+
+        for (axis, operation) in axes_operations.iter().rev() {
+            output = output.reduce_axis(*axis, *operation)?;
+        }
+
+        Ok(output)
+    }
+
+    fn add_axes(
+        &self,
+        naxes: usize,
+        pos2len: &[(usize, usize)],
+    ) -> Result<Self::Output, EinopsError> {
+        let mut shape = self.dims();
+
+        for &(axis_pos, _) in pos2len {
+            if axis_pos > shape.len() {
+                return Err(EinopsError::new(format!(
+                    "cannot insert axis at position {} of tensor with shape {:?}",
+                    axis_pos, shape
+                )));
+            }
+            shape.insert(axis_pos, 1);
+        }
+        if shape.len() != naxes {
+            return Err(EinopsError::new(format!(
+                "expected {} axes after inserting new axes, found {}",
+                naxes,
+                shape.len()
+            )));
+        }
+
+        let output = self.clone().reshape(&shape)?;
+        for &(axis_pos, axis_len) in pos2len {
+            shape[axis_pos] = axis_len;
+        }
+
+        output.broadcast(&shape)
+    }
+
+    fn narrow(&self, axis: usize, start: usize, len: usize) -> Result<Self::Output, EinopsError> {
+        self.clone().narrow(axis, start, len)
+    }
+
+    fn broadcast(&self, shape: &[usize]) -> Result<Self::Output, EinopsError> {
+        self.clone().broadcast(shape)
+    }
+
+    fn concat(inputs: &[&Self], axis: usize) -> Result<Self::Output, EinopsError> {
+        let first = inputs
+            .first()
+            .ok_or_else(|| EinopsError::new("cannot concatenate an empty list of tensors"))?;
+        let shape = first.dims();
+        // burn panics on mismatching shapes
+        for input in inputs {
+            let dims = input.dims();
+            let matches = axis < shape.len()
+                && dims.len() == shape.len()
+                && (0..shape.len()).all(|i| i == axis || dims[i] == shape[i]);
+            if !matches {
+                return Err(EinopsError::new(format!(
+                    "cannot concatenate tensors of shape {:?} and {:?} along axis {}",
+                    shape, dims, axis
+                )));
+            }
+        }
+        with_tensor!(BurnTensor::clone(first), tensor => concat_tensors(tensor, &inputs[1..], axis))
+    }
+
+    fn index_select(&self, axis: usize, indices: &[usize]) -> Result<Self::Output, EinopsError> {
+        self.clone().index_select(axis, indices)
+    }
+
+    // burn doesn't expose the memory layout, backends make their tensors
+    // contiguous when an operation needs it
+    fn is_contiguous(&self) -> bool {
+        true
+    }
+
+    fn contiguous(&self) -> Result<Self::Output, EinopsError> {
+        Ok(self.clone())
+    }
+
+    // Only float tensors can be cast in burn, so casting isn't supported
+    fn dtype(&self) -> Option<DType> {
+        Some(match with_tensor!(self, tensor => tensor.dtype()) {
+            burn_tensor::DType::U8 => DType::U8,
+            burn_tensor::DType::U16 => DType::U16,
+            burn_tensor::DType::U32 => DType::U32,
+            burn_tensor::DType::U64 => DType::U64,
+            burn_tensor::DType::I8 => DType::I8,
+            burn_tensor::DType::I16 => DType::I16,
+            burn_tensor::DType::I32 => DType::I32,
+            burn_tensor::DType::I64 => DType::I64,
+            burn_tensor::DType::F16 => DType::F16,
+            burn_tensor::DType::BF16 => DType::BF16,
+            burn_tensor::DType::F32 => DType::F32,
+            burn_tensor::DType::F64 => DType::F64,
+            _ => return None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use burn_ndarray::NdArray;
+
+    type TestBackend = NdArray<f32>;
+
+    #[test]
+    fn burn_reduce() {
+        let device = Default::default();
+        let tests = vec![(
+            Tensor::<TestBackend, 1>::from_floats(
+                [0.0, 1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0, 8.0, 9.0, 10.0, 11.0],
+                &device,
+            )
+            .reshape([2, 2, 3]),
+            [(0, Operation::Max), (2, Operation::Sum)],
+            TensorData::from([21.0f32, 30.0]),
+        )];
+
+        for (tensor, mut axes_operations, expected) in tests {
+            let output = BurnTensor::from(tensor)
+                .reduce_axes(&mut axes_operations)
+                .unwrap();
+            let output: Tensor<TestBackend, 1> = output.try_into().unwrap();
+            assert_eq!(output.into_data(), expected);
+        }
+    }
+
+    #[test]
+    fn burn_transpose() {
+        let device = Default::default();
+        let tests = vec![(
+            Tensor::<TestBackend, 1, Int>::arange(0..24, &device).reshape([2, 3, 4]),
+            &[2, 0, 1],
+            TensorData::from([
+                [[0i64, 4, 8], [12, 16, 20]],
+                [[1, 5, 9], [13, 17, 21]],
+                [[2, 6, 10], [14, 18, 22]],
+                [[3, 7, 11], [15, 19, 23]],
+            ]),
+        )];
+
+        for (tensor, axes, expected) in tests {
+            let output = Backend::transpose(&BurnTensor::from(tensor), axes).unwrap();
+            let output: Tensor<TestBackend, 3, Int> = output.try_into().unwrap();
+            assert_eq!(output.into_data(), expected);
+        }
+    }
+
+    #[test]
+    fn burn_add_axes() {
+        let device = Default::default();
+        let tests = vec![(
+            Tensor::<TestBackend, 1, Int>::arange(0..6, &device).reshape([1, 2, 3]),
+            5,
+            &[(0, 5), (3, 3)],
+            [5, 1, 2, 3, 3],
+        )];
+
+        for (tensor, naxes, pos2len, expected) in tests {
+            let output = BurnTensor::from(tensor).add_axes(naxes, pos2len).unwrap();
+            assert_eq!(output.dims(), expected);
+            let output: Tensor<TestBackend, 5, Int> = output.try_into().unwrap();
+            let values = output.into_data().to_vec::<i64>().unwrap();
+            assert_eq!(&values[..9], &[0, 1, 2, 0, 1, 2, 0, 1, 2]);
+            assert_eq!(&values[9..18], &[3, 4, 5, 3, 4, 5, 3, 4, 5]);
+        }
+    }
+
+    #[test]
+    fn burn_narrow() {
+        let device = Default::default();
+        let tests = vec![(
+            Tensor::<TestBackend, 1, Int>::arange(0..24, &device).reshape([2, 3, 4]),
+            (1, 1, 2),
+            TensorData::from([
+                [[4i64, 5, 6, 7], [8, 9, 10, 11]],
+                [[16, 17, 18, 19], [20, 21, 22, 23]],
+            ]),
+        )];
+
+        for (tensor, (axis, start, len), expected) in tests {
+            let tensor = BurnTensor::from(tensor);
+            assert!(Backend::narrow(&tensor, axis, start, 3).is_err());
+            let output = Backend::narrow(&tensor, axis, start, len).unwrap();
+            let output: Tensor<TestBackend, 3, Int> = output.try_into().unwrap();
+            assert_eq!(output.into_data(), expected);
+        }
+    }
+
+    #[test]
+    fn burn_broadcast() {
+        let device = Default::default();
+        let tests = vec![(
+            Tensor::<TestBackend, 1, Int>::arange(0..2, &device).reshape([2, 1]),
+            &[3, 2, 2],
+            TensorData::from([[[0i64, 0], [1, 1]]; 3]),
+        )];
+
+        for (tensor, shape, expected) in tests {
+            let tensor = BurnTensor::from(tensor);
+            assert!(Backend::broadcast(&tensor, &[3, 2]).is_err());
+            let output = Backend::broadcast(&tensor, shape).unwrap();
+            let output: Tensor<TestBackend, 3, Int> = output.try_into().unwrap();
+            assert_eq!(output.into_data(), expected);
+        }
+    }
+
+    #[test]
+    fn burn_concat() {
+        let device = Default::default();
+        let a = BurnTensor::from(Tensor::<TestBackend, 2, Int>::from_ints(
+            [[0], [3]],
+            &device,
+        ));
+        let b = BurnTensor::from(Tensor::<TestBackend, 2, Int>::from_ints(
+            [[1, 2], [4, 5]],
+            &device,
+        ));
+
+        assert!(Backend::concat(&[&a, &b], 0).is_err());
+        let output = Backend::concat(&[&a, &b], 1).unwrap();
+        let output: Tensor<TestBackend, 2, Int> = output.try_into().unwrap();
+        assert_eq!(
+            output.into_data(),
+            TensorData::from([[0i64, 1, 2], [3, 4, 5]])
+        );
+    }
+
+    #[test]
+    fn burn_index_select() {
+        let device = Default::default();
+        let tensor =
+            BurnTensor::from(Tensor::<TestBackend, 1, Int>::arange(0..6, &device).reshape([2, 3]));
+
+        assert!(Backend::index_select(&tensor, 1, &[3]).is_err());
+        let output = Backend::index_select(&tensor, 1, &[2, 0, 2]).unwrap();
+        let output: Tensor<TestBackend, 2, Int> = output.try_into().unwrap();
+        assert_eq!(
+            output.into_data(),
+            TensorData::from([[2i64, 0, 2], [5, 3, 5]])
+        );
+    }
+
+    #[test]
+    fn burn_dtype() {
+        let device = Default::default();
+        let tensor = BurnTensor::from(Tensor::<TestBackend, 1, Int>::arange(0..6, &device));
+        assert_eq!(Backend::dtype(&tensor), Some(DType::I64));
+    }
+}
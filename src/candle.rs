@@ -1,63 +1,694 @@
-use candle_core::{Shape, Tensor};
+use candle_core::quantized::QTensor;
+use candle_core::{Shape, Tensor, Var};
 
-use crate::{Backend, Operation};
+use crate::{metrics, Backend, DType, EinopsError, Operation, ScanOp};
 
-impl<T: AsRef<Tensor>> Backend for T {
+fn dtype_from_candle(dtype: candle_core::DType) -> DType {
+    match dtype {
+        candle_core::DType::U8 => DType::U8,
+        candle_core::DType::U32 => DType::U32,
+        candle_core::DType::I64 => DType::I64,
+        candle_core::DType::BF16 => DType::BF16,
+        candle_core::DType::F16 => DType::F16,
+        candle_core::DType::F32 => DType::F32,
+        candle_core::DType::F64 => DType::F64,
+    }
+}
+
+fn device_from_candle(device: &candle_core::Device) -> crate::Device {
+    match device.location() {
+        candle_core::DeviceLocation::Cpu => crate::Device::Cpu,
+        candle_core::DeviceLocation::Cuda { gpu_id } => crate::Device::Cuda(gpu_id),
+        candle_core::DeviceLocation::Metal { gpu_id } => crate::Device::Metal(gpu_id),
+    }
+}
+
+// Fails if candle was built without support for the device
+fn device_to_candle(device: crate::Device) -> Result<candle_core::Device, EinopsError> {
+    Ok(match device {
+        crate::Device::Cpu => candle_core::Device::Cpu,
+        crate::Device::Cuda(ordinal) => candle_core::Device::new_cuda(ordinal)?,
+        crate::Device::Metal(ordinal) => candle_core::Device::new_metal(ordinal)?,
+    })
+}
+
+fn dtype_to_candle(dtype: DType) -> Result<candle_core::DType, EinopsError> {
+    Ok(match dtype {
+        DType::U8 => candle_core::DType::U8,
+        DType::U32 => candle_core::DType::U32,
+        DType::I64 => candle_core::DType::I64,
+        DType::BF16 => candle_core::DType::BF16,
+        DType::F16 => candle_core::DType::F16,
+        DType::F32 => candle_core::DType::F32,
+        DType::F64 => candle_core::DType::F64,
+        _ => {
+            return Err(EinopsError::new(format!(
+                "candle tensors of dtype {:?} are not supported",
+                dtype
+            )))
+        }
+    })
+}
+
+fn is_identity(tensor: &Tensor, axes: &[usize]) -> bool {
+    axes.len() == tensor.rank() && axes.iter().enumerate().all(|(i, &axis)| i == axis)
+}
+
+// Reduces a run of axes sorted in ascending order
+fn reduce_run(tensor: &Tensor, run: &[(usize, Operation)]) -> candle_core::Result<Tensor> {
+    let (first, operation) = run[0];
+    let last = run[run.len() - 1].0;
+    let reduce = |tensor: &Tensor, axis| {
+        metrics::record_kernel();
+        match operation {
+            Operation::Min => tensor.min(axis),
+            Operation::Max => tensor.max(axis),
+            Operation::Sum => tensor.sum(axis),
+            Operation::Mean => tensor.mean(axis),
+            // TODO: implement prod
+        }
+    };
+    match operation {
+        _ if run.len() == 1 => reduce(tensor, first),
+        Operation::Sum => {
+            metrics::record_kernel();
+            tensor.sum(run.iter().map(|&(axis, _)| axis).collect::<Vec<_>>())
+        }
+        Operation::Mean => {
+            metrics::record_kernel();
+            tensor.mean(run.iter().map(|&(axis, _)| axis).collect::<Vec<_>>())
+        }
+        // Merging the axes of a contiguous tensor is a view, so the run is
+        // reduced with a single kernel
+        Operation::Min | Operation::Max if last < tensor.rank() && tensor.is_contiguous() => {
+            let mut dims = tensor.dims().to_vec();
+            let merged = dims.drain(first..=last).product();
+            dims.insert(first, merged);
+            reduce(&tensor.reshape(dims)?, first)
+        }
+        Operation::Min | Operation::Max => {
+            let mut output = reduce(tensor, last)?;
+            for &(axis, _) in run[..run.len() - 1].iter().rev() {
+                output = reduce(&output, axis)?;
+            }
+            Ok(output)
+        }
+    }
+}
+
+// Reports a copy of the data of `tensor` to the metrics
+fn record_copy(tensor: &Tensor) {
+    metrics::record_copy(tensor.elem_count() * tensor.dtype().size_in_bytes());
+}
+
+/// Reshapes a tensor whose axes are a permutation of a contiguous layout
+/// without copying, by undoing the permutation, reshaping the contiguous
+/// tensor and permuting the new axes back into place
+///
+/// Returns `None` when the reshape merges axes that aren't adjacent in the
+/// underlying layout, those need a copy.
+fn reshape_permuted(tensor: &Tensor, shape: &[usize]) -> Option<Tensor> {
+    let dims = tensor.dims();
+    let stride = tensor.stride();
+    if dims.contains(&0) || shape.iter().product::<usize>() != dims.iter().product::<usize>() {
+        return None;
+    }
+
+    // Axes from the largest stride to the smallest, the order they have in
+    // the underlying layout
+    let mut order = (0..dims.len()).collect::<Vec<_>>();
+    order.sort_by_key(|&axis| std::cmp::Reverse(stride[axis]));
+    let base = tensor.permute(order.as_slice()).ok()?;
+    if !base.is_contiguous() {
+        return None;
+    }
+    let mut position = vec![0; dims.len()];
+    for (i, &axis) in order.iter().enumerate() {
+        position[axis] = i;
+    }
+
+    // Groups of input axes and the output axes they are reshaped into
+    let mut groups = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < dims.len() || j < shape.len() {
+        let (input_start, output_start) = (i, j);
+        let (mut input_len, mut output_len) = (1, 1);
+        if i < dims.len() {
+            input_len *= dims[i];
+            i += 1;
+        }
+        if j < shape.len() {
+            output_len *= shape[j];
+            j += 1;
+        }
+        while input_len != output_len {
+            if input_len < output_len && i < dims.len() {
+                input_len *= dims[i];
+                i += 1;
+            } else if output_len < input_len && j < shape.len() {
+                output_len *= shape[j];
+                j += 1;
+            } else {
+                return None;
+            }
+        }
+
+        let input = input_start..i;
+        if input.is_empty()
+            || input
+                .clone()
+                .skip(1)
+                .any(|axis| position[axis] != position[axis - 1] + 1)
+        {
+            return None;
+        }
+        groups.push((input, output_start..j));
+    }
+
+    let mut base_order = (0..groups.len()).collect::<Vec<_>>();
+    base_order.sort_by_key(|&group| position[groups[group].0.start]);
+
+    let mut base_shape = Vec::with_capacity(shape.len());
+    let mut offsets = vec![0; groups.len()];
+    for &group in &base_order {
+        offsets[group] = base_shape.len();
+        base_shape.extend_from_slice(&shape[groups[group].1.clone()]);
+    }
+    let axes = groups
+        .iter()
+        .zip(&offsets)
+        .flat_map(|((_, output), &offset)| offset..offset + output.len())
+        .collect::<Vec<_>>();
+
+    base.reshape(base_shape).ok()?.permute(axes).ok()
+}
+
+impl Backend for Tensor {
     type Output = Tensor;
 
-    fn shape(self) -> Vec<usize> {
-        self.as_ref().dims().to_vec()
+    fn shape(&self) -> Vec<usize> {
+        self.dims().to_vec()
+    }
+
+    // Patterns like `b (h) w -> b h w` plan reshapes to the current shape,
+    // returning the tensor keeps them out of the op graph
+    fn reshape(&self, shape: &[usize]) -> Result<Self::Output, EinopsError> {
+        if let Some(view) = Backend::reshape_view(self, shape) {
+            return Ok(view);
+        }
+        let output = Tensor::reshape(self, Shape::from_dims(shape))?;
+        record_copy(&output);
+        Ok(output)
+    }
+
+    // candle records identity permutes in the op graph like any other
+    fn transpose(&self, axes: &[usize]) -> Result<Self::Output, EinopsError> {
+        if is_identity(self, axes) {
+            return Ok(self.clone());
+        }
+        Ok(self.permute(axes)?)
     }
 
-    fn reshape(self, shape: &[usize]) -> Self::Output {
-        let shape = Shape::from_dims(shape);
-        self.as_ref().reshape(shape).unwrap()
+    // candle copies when reshaping tensors that aren't contiguous, permuted
+    // tensors can often be reshaped as views anyway
+    fn reshape_view(&self, shape: &[usize]) -> Option<Self::Output> {
+        if self.dims() == shape {
+            return Some(self.clone());
+        }
+        if !self.is_contiguous() {
+            return reshape_permuted(self, shape);
+        }
+        Tensor::reshape(self, Shape::from_dims(shape)).ok()
     }
 
-    fn transpose(self, axes: &[usize]) -> Self::Output {
-        self.as_ref().permute(axes).unwrap()
+    fn transpose_view(&self, axes: &[usize]) -> Option<Self::Output> {
+        if is_identity(self, axes) {
+            return Some(self.clone());
+        }
+        self.permute(axes).ok()
     }
 
-    fn reduce_axes(self, axes_operations: &mut [(usize, Operation)]) -> Self::Output {
-        let mut output = self.as_ref().clone();
+    fn reduce_axes(
+        &self,
+        axes_operations: &mut [(usize, Operation)],
+    ) -> Result<Self::Output, EinopsError> {
+        // `einops!` passes the axes in ascending order already
+        if axes_operations.windows(2).any(|w| w[0].0 > w[1].0) {
+            axes_operations.sort_unstable_by_key(|(axis, _)| *axis);
+        }
+
+        // Reducing an axis of length 1 only removes it, which squeezing does
+        // without a kernel
+        let dims = self.dims();
+        if axes_operations
+            .iter()
+            .any(|&(axis, _)| dims.get(axis) == Some(&1))
+        {
+            let (squeezed, mut rest): (Vec<_>, Vec<_>) = axes_operations
+                .iter()
+                .partition(|&&(axis, _)| dims.get(axis) == Some(&1));
+            let mut input = self.clone();
+            for &(axis, _) in squeezed.iter().rev() {
+                input = input.squeeze(axis)?;
+            }
+            for (axis, _) in &mut rest {
+                *axis -= squeezed.iter().filter(|&&(s, _)| s < *axis).count();
+            }
+            return Backend::reduce_axes(&input, &mut rest);
+        }
+
+        // Runs of axes reduced with the same operation are reduced together,
+        // last run first. candle sums and averages over any set of axes in one
+        // call, minima and maxima only take a single axis so their runs are
+        // limited to adjacent axes
+        let mut output: Option<Tensor> = None;
+        let mut end = axes_operations.len();
+        while end > 0 {
+            let operation = axes_operations[end - 1].1;
+            let mut start = end - 1;
+            while start > 0 {
+                let (previous, previous_operation) = axes_operations[start - 1];
+                let joins = previous_operation == operation
+                    && (matches!(operation, Operation::Sum | Operation::Mean)
+                        || previous + 1 == axes_operations[start].0);
+                if !joins {
+                    break;
+                }
+                start -= 1;
+            }
 
-        axes_operations.sort_by_key(|(axis, _)| *axis);
+            let input = output.as_ref().unwrap_or(self);
+            output = Some(reduce_run(input, &axes_operations[start..end])?);
+            end = start;
+        }
+
+        Ok(output.unwrap_or_else(|| self.clone()))
+    }
+
+    fn add_axes(
+        &self,
+        naxes: usize,
+        pos2len: &[(usize, usize)],
+    ) -> Result<Self::Output, EinopsError> {
+        let mut unsqueezed = self.dims().to_vec();
+        let mut expanded = unsqueezed.clone();
+
+        for &(axis_pos, axis_len) in pos2len {
+            if axis_pos > unsqueezed.len() {
+                return Err(EinopsError::new(format!(
+                    "cannot insert axis at position {} of tensor with shape {:?}",
+                    axis_pos, unsqueezed
+                )));
+            }
+            unsqueezed.insert(axis_pos, 1);
+            expanded.insert(axis_pos, axis_len);
+        }
+        if expanded.len() != naxes {
+            return Err(EinopsError::new(format!(
+                "expected {} axes after inserting new axes, found {}",
+                naxes,
+                expanded.len()
+            )));
+        }
+
+        // All axes of length 1 are inserted with a single reshape, a copy for
+        // non-contiguous tensors so those are unsqueezed axis by axis
+        let output = if Tensor::is_contiguous(self) {
+            Tensor::reshape(self, unsqueezed)?
+        } else {
+            pos2len
+                .iter()
+                .try_fold(self.clone(), |output, &(axis_pos, _)| {
+                    output.unsqueeze(axis_pos)
+                })?
+        };
+
+        // Broadcasting only creates a view, the data is copied once the
+        // following operation needs a contiguous tensor
+        Backend::broadcast(&output, &expanded)
+    }
+
+    fn narrow(&self, axis: usize, start: usize, len: usize) -> Result<Self::Output, EinopsError> {
+        Ok(Tensor::narrow(self, axis, start, len)?)
+    }
+
+    fn broadcast(&self, shape: &[usize]) -> Result<Self::Output, EinopsError> {
+        Ok(self.broadcast_as(shape)?)
+    }
+
+    fn is_contiguous(&self) -> bool {
+        Tensor::is_contiguous(self)
+    }
 
-        for (axis, operation) in axes_operations.iter().rev() {
-            output = match operation {
-                Operation::Min => output.min(*axis).unwrap(),
-                Operation::Max => output.max(*axis).unwrap(),
-                Operation::Sum => output.sum(&[*axis][..]).unwrap(),
-                Operation::Mean => output.mean(&[*axis][..]).unwrap(),
-                // TODO: implement prod
+    fn contiguous(&self) -> Result<Self::Output, EinopsError> {
+        let output = Tensor::contiguous(self)?;
+        if !Tensor::is_contiguous(self) {
+            record_copy(&output);
+        }
+        Ok(output)
+    }
+
+    fn concat(inputs: &[&Self], axis: usize) -> Result<Self::Output, EinopsError> {
+        let output = Tensor::cat(inputs, axis)?;
+        record_copy(&output);
+        Ok(output)
+    }
+
+    fn index_select(&self, axis: usize, indices: &[usize]) -> Result<Self::Output, EinopsError> {
+        let indices = Tensor::from_iter(indices.iter().map(|&i| i as u32), self.device())?;
+        let output = Tensor::index_select(self, &indices, axis)?;
+        record_copy(&output);
+        Ok(output)
+    }
+
+    fn scan(&self, axis: usize, operation: ScanOp) -> Result<Self::Output, EinopsError> {
+        if axis >= self.rank() {
+            return Err(EinopsError::new(format!(
+                "cannot scan axis {} of tensor with shape {:?}",
+                axis,
+                self.dims()
+            )));
+        }
+        metrics::record_kernel();
+        if let ScanOp::Sum = operation {
+            return Ok(self.cumsum(axis)?);
+        }
+
+        // candle has no cumulative product or maximum, the slices along `axis`
+        // are accumulated one by one
+        let len = self.dim(axis)?;
+        if len == 0 {
+            return Ok(self.clone());
+        }
+        let mut slices = Vec::with_capacity(len);
+        for i in 0..len {
+            let slice = Tensor::narrow(self, axis, i, 1)?;
+            let slice = match (slices.last(), operation) {
+                (None, _) => slice,
+                (Some(previous), ScanOp::Prod) => slice.mul(previous)?,
+                (Some(previous), _) => slice.maximum(previous)?,
             };
+            slices.push(slice);
         }
+        Ok(Tensor::cat(&slices, axis)?)
+    }
 
-        output
+    fn contract(&self, rhs: &Self) -> Result<Self::Output, EinopsError> {
+        metrics::record_kernel();
+        Ok(self.matmul(rhs)?)
     }
 
-    fn add_axes(self, naxes: usize, pos2len: &[(usize, usize)]) -> Self::Output {
-        let mut output = self.as_ref().clone();
+    fn dtype(&self) -> Option<DType> {
+        Some(dtype_from_candle(Tensor::dtype(self)))
+    }
 
-        let mut repeats = vec![1; naxes];
+    fn to_dtype(&self, dtype: DType) -> Result<Self::Output, EinopsError> {
+        let output = Tensor::to_dtype(self, dtype_to_candle(dtype)?)?;
+        if output.dtype() != Tensor::dtype(self) {
+            record_copy(&output);
+        }
+        Ok(output)
+    }
 
-        for &(axis_pos, axis_len) in pos2len {
-            output = output.unsqueeze(axis_pos).unwrap();
-            repeats[axis_pos] = axis_len;
+    fn device(&self) -> Option<crate::Device> {
+        Some(device_from_candle(Tensor::device(self)))
+    }
+
+    fn to_device(&self, device: crate::Device) -> Result<Self::Output, EinopsError> {
+        let output = Tensor::to_device(self, &device_to_candle(device)?)?;
+        if !output.device().same_device(Tensor::device(self)) {
+            record_copy(&output);
         }
+        Ok(output)
+    }
+}
 
-        let shape = Shape::from_dims(&repeats[..]);
-        output.repeat(shape).unwrap()
+/// Applies `rearrange` to `input` chunk by chunk along `axis` and writes the
+/// rearranged chunks one after the other into `output` along `output_axis`
+///
+/// Only one rearranged chunk is held in memory besides `input` and `output`,
+/// which has to be contiguous.
+///
+/// ```ignore
+/// let output = Tensor::zeros((heads, vocab, dim), input.dtype(), input.device())?;
+/// rearrange_chunked(&input, 0, 4096, &output, 1, |chunk| {
+///     einops!("v (h d) -> h v d", h: heads, chunk)
+/// })?;
+/// ```
+pub fn rearrange_chunked(
+    input: &Tensor,
+    axis: usize,
+    chunk_len: usize,
+    output: &Tensor,
+    output_axis: usize,
+    rearrange: impl Fn(&Tensor) -> Result<Tensor, EinopsError>,
+) -> Result<(), EinopsError> {
+    if axis >= input.rank() || chunk_len == 0 {
+        return Err(EinopsError::new(format!(
+            "cannot split axis {} of tensor with shape {:?} into chunks of length {}",
+            axis,
+            input.dims(),
+            chunk_len
+        )));
+    }
+
+    let len = input.dims()[axis];
+    let mut offset = 0;
+    for start in (0..len).step_by(chunk_len) {
+        let chunk = input.narrow(axis, start, chunk_len.min(len - start))?;
+        let chunk = rearrange(&chunk)?.contiguous()?;
+        output.slice_set(&chunk, output_axis, offset)?;
+        offset += chunk.dims()[output_axis];
+    }
+
+    Ok(())
+}
+
+// Types that are turned into a `Tensor` before any operation is applied,
+// `shape` and `is_contiguous` are read without converting the input
+macro_rules! impl_converted_backend {
+    ($(
+        [$($generics:tt)*] $ty:ty => |$input:ident| {
+            shape: $shape:expr,
+            is_contiguous: $is_contiguous:expr,
+            tensor: $tensor:expr $(,)?
+        }
+    )*) => {
+        $(
+            impl<$($generics)*> Backend for $ty {
+                type Output = Tensor;
+
+                fn shape(&self) -> Vec<usize> {
+                    let $input = self;
+                    $shape
+                }
+
+                fn reshape(&self, shape: &[usize]) -> Result<Self::Output, EinopsError> {
+                    let $input = self;
+                    Backend::reshape(&$tensor, shape)
+                }
+
+                fn transpose(&self, axes: &[usize]) -> Result<Self::Output, EinopsError> {
+                    let $input = self;
+                    Backend::transpose(&$tensor, axes)
+                }
+
+                fn reduce_axes(
+                    &self,
+                    axes_operations: &mut [(usize, Operation)],
+                ) -> Result<Self::Output, EinopsError> {
+                    let $input = self;
+                    Backend::reduce_axes(&$tensor, axes_operations)
+                }
+
+                fn add_axes(
+                    &self,
+                    naxes: usize,
+                    pos2len: &[(usize, usize)],
+                ) -> Result<Self::Output, EinopsError> {
+                    let $input = self;
+                    Backend::add_axes(&$tensor, naxes, pos2len)
+                }
+
+                fn narrow(
+                    &self,
+                    axis: usize,
+                    start: usize,
+                    len: usize,
+                ) -> Result<Self::Output, EinopsError> {
+                    let $input = self;
+                    Backend::narrow(&$tensor, axis, start, len)
+                }
+
+                fn broadcast(&self, shape: &[usize]) -> Result<Self::Output, EinopsError> {
+                    let $input = self;
+                    Backend::broadcast(&$tensor, shape)
+                }
+
+                fn is_contiguous(&self) -> bool {
+                    #[allow(unused_variables)]
+                    let $input = self;
+                    $is_contiguous
+                }
+
+                fn contiguous(&self) -> Result<Self::Output, EinopsError> {
+                    let $input = self;
+                    Backend::contiguous(&$tensor)
+                }
+
+                fn concat(inputs: &[&Self], axis: usize) -> Result<Self::Output, EinopsError> {
+                    let tensors = inputs
+                        .iter()
+                        .map(|&$input| -> Result<Tensor, EinopsError> { Ok(Tensor::clone(&$tensor)) })
+                        .collect::<Result<Vec<_>, _>>()?;
+                    Backend::concat(&tensors.iter().collect::<Vec<_>>(), axis)
+                }
+
+                fn index_select(
+                    &self,
+                    axis: usize,
+                    indices: &[usize],
+                ) -> Result<Self::Output, EinopsError> {
+                    let $input = self;
+                    Backend::index_select(&$tensor, axis, indices)
+                }
+
+                fn scan(&self, axis: usize, operation: ScanOp) -> Result<Self::Output, EinopsError> {
+                    let $input = self;
+                    Backend::scan(&$tensor, axis, operation)
+                }
+
+                fn contract(&self, rhs: &Self) -> Result<Self::Output, EinopsError> {
+                    let lhs = {
+                        let $input = self;
+                        Tensor::clone(&$tensor)
+                    };
+                    let $input = rhs;
+                    Backend::contract(&lhs, &$tensor)
+                }
+
+                fn dtype(&self) -> Option<DType> {
+                    let $input = self;
+                    // The dtype is unknown if the conversion fails
+                    let dtype = || -> Result<Option<DType>, EinopsError> { Ok(Backend::dtype(&$tensor)) };
+                    dtype().ok().flatten()
+                }
+
+                fn to_dtype(&self, dtype: DType) -> Result<Self::Output, EinopsError> {
+                    let $input = self;
+                    Backend::to_dtype(&$tensor, dtype)
+                }
+
+                fn device(&self) -> Option<crate::Device> {
+                    let $input = self;
+                    // The device is unknown if the conversion fails
+                    let device = || -> Result<Option<crate::Device>, EinopsError> { Ok(Backend::device(&$tensor)) };
+                    device().ok().flatten()
+                }
+
+                fn to_device(&self, device: crate::Device) -> Result<Self::Output, EinopsError> {
+                    let $input = self;
+                    Backend::to_device(&$tensor, device)
+                }
+            }
+        )*
+    };
+}
+
+// Sequences of tensors are stacked along a new leading axis before the
+// pattern is applied, like passing a list to einops in python
+fn stacked_shape(tensors: &[Tensor]) -> Vec<usize> {
+    let mut shape = vec![tensors.len()];
+    if let Some(first) = tensors.first() {
+        shape.extend_from_slice(first.dims());
+    }
+    shape
+}
+
+impl_converted_backend! {
+    [] Var => |var| {
+        shape: var.dims().to_vec(),
+        is_contiguous: var.as_tensor().is_contiguous(),
+        tensor: var.as_tensor(),
+    }
+    // Quantized tensors are dequantized on their own device once an operation
+    // needs the data, reading the shape only uses the metadata. The whole
+    // tensor is dequantized to `f32` for every call, even when the pattern
+    // keeps a few rows of it, so the output is `f32` and a call needs the
+    // memory of the dequantized tensor on top of the quantized one
+    [] QTensor => |qtensor| {
+        shape: qtensor.shape().dims().to_vec(),
+        is_contiguous: false,
+        tensor: qtensor.dequantize(&qtensor.device())?,
+    }
+    [] [Tensor] => |tensors| {
+        shape: stacked_shape(tensors),
+        is_contiguous: false,
+        tensor: Tensor::stack(tensors, 0)?,
+    }
+    [] Vec<Tensor> => |tensors| {
+        shape: stacked_shape(tensors),
+        is_contiguous: false,
+        tensor: Tensor::stack(tensors, 0)?,
+    }
+    [const N: usize] [Tensor; N] => |tensors| {
+        shape: stacked_shape(tensors),
+        is_contiguous: false,
+        tensor: Tensor::stack(tensors, 0)?,
+    }
+}
+
+// Images are read as `h w c` tensors on the CPU. 8 bit images become `u8`
+// tensors, 16 bit images `u32` tensors and float images `f32` tensors
+#[cfg(feature = "image")]
+fn image_to_tensor(image: &image::DynamicImage) -> Result<Tensor, EinopsError> {
+    let shape = (
+        image.height() as usize,
+        image.width() as usize,
+        image.color().channel_count() as usize,
+    );
+    let device = candle_core::Device::Cpu;
+    let tensor = if let Some(samples) = image.as_flat_samples_u8() {
+        Tensor::from_slice(samples.samples, shape, &device)?
+    } else if let Some(samples) = image.as_flat_samples_u16() {
+        let samples = samples
+            .samples
+            .iter()
+            .map(|&x| x as u32)
+            .collect::<Vec<_>>();
+        Tensor::from_vec(samples, shape, &device)?
+    } else if let Some(samples) = image.as_flat_samples_f32() {
+        Tensor::from_slice(samples.samples, shape, &device)?
+    } else {
+        return Err(EinopsError::new(format!(
+            "images with color type {:?} are not supported",
+            image.color()
+        )));
+    };
+    Ok(tensor)
+}
+
+#[cfg(feature = "image")]
+impl_converted_backend! {
+    [] image::DynamicImage => |image| {
+        shape: vec![
+            image.height() as usize,
+            image.width() as usize,
+            image.color().channel_count() as usize,
+        ],
+        is_contiguous: false,
+        tensor: image_to_tensor(image)?,
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use candle_core::{Device, Result};
+    use candle_core::{DType, Device, Result};
 
     #[test]
-    fn reduce() -> Result<()> {
+    #[allow(clippy::excessive_precision)]
+    fn candle_reduce() -> Result<()> {
         let tests = vec![
             (
                 Tensor::new(
@@ -89,7 +720,7 @@ mod tests {
                     ],
                     &Device::Cpu,
                 )?
-                .reshape(&[4, 2, 3]),
+                .reshape(&[4, 2, 3])?,
                 [(0, Operation::Min)],
                 Tensor::new(
                     &[
@@ -129,7 +760,7 @@ mod tests {
                     ],
                     &Device::Cpu,
                 )?
-                .reshape(&[4, 2, 3]),
+                .reshape(&[4, 2, 3])?,
                 [(0, Operation::Max)],
                 Tensor::new(
                     &[
@@ -143,7 +774,7 @@ mod tests {
 
         for (tensor, mut axes_operations, expected) in tests {
             assert_eq!(
-                tensor.reduce_axes(&mut axes_operations).to_vec2::<f32>()?,
+                tensor.reduce_axes(&mut axes_operations)?.to_vec2::<f32>()?,
                 expected.to_vec2::<f32>()?
             );
         }
@@ -154,7 +785,7 @@ mod tests {
     #[test]
     fn candle_transpose() -> Result<()> {
         let tests = vec![(
-            Tensor::arange(0f32, (2 * 3 * 4) as f32, &Device::Cpu)?.reshape(&[2, 3, 4]),
+            Tensor::arange(0f32, (2 * 3 * 4) as f32, &Device::Cpu)?.reshape(&[2, 3, 4])?,
             &[2, 0, 1],
             Tensor::new(
                 &[
@@ -169,7 +800,7 @@ mod tests {
 
         for (tensor, axes, expected) in tests {
             assert_eq!(
-                Backend::transpose(&tensor, axes).to_vec3::<f32>()?,
+                Backend::transpose(&tensor, axes)?.to_vec3::<f32>()?,
                 expected.to_vec3::<f32>()?
             );
         }
@@ -178,9 +809,9 @@ mod tests {
     }
 
     #[test]
-    fn tch_add_axes() -> Result<()> {
+    fn candle_add_axes() -> Result<()> {
         let tests = vec![(
-            Tensor::arange(0u8, 1 * 2 * 3, &Device::Cpu)?.reshape(&[1, 2, 3]),
+            Tensor::arange(0u8, 2 * 3, &Device::Cpu)?.reshape(&[1, 2, 3])?,
             5,
             &[(0, 5), (3, 3)],
             Tensor::new(
@@ -192,13 +823,13 @@ mod tests {
                 ],
                 &Device::Cpu,
             )?
-            .reshape(&[5, 1, 2, 3, 3]),
+            .reshape(&[5, 1, 2, 3, 3])?,
         )];
 
         for (tensor, naxes, pos2len, expected) in tests {
             assert_eq!(
                 tensor
-                    .add_axes(naxes, pos2len)
+                    .add_axes(naxes, pos2len)?
                     .flatten_all()?
                     .to_vec1::<u8>()?,
                 expected.flatten_all()?.to_vec1::<u8>()?
@@ -207,4 +838,381 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn candle_narrow() -> Result<()> {
+        let tests = vec![(
+            Tensor::arange(0u32, 2 * 3 * 4, &Device::Cpu)?.reshape(&[2, 3, 4])?,
+            (1, 1, 2),
+            Tensor::new(
+                &[
+                    [[4u32, 5, 6, 7], [8, 9, 10, 11]],
+                    [[16, 17, 18, 19], [20, 21, 22, 23]],
+                ],
+                &Device::Cpu,
+            )?,
+        )];
+
+        for (tensor, (axis, start, len), expected) in tests {
+            assert_eq!(
+                Backend::narrow(&tensor, axis, start, len)?.to_vec3::<u32>()?,
+                expected.to_vec3::<u32>()?
+            );
+            assert!(Backend::narrow(&tensor, axis, start, 3).is_err());
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn candle_broadcast() -> Result<()> {
+        let tests = vec![(
+            Tensor::new(&[[0u32], [1]], &Device::Cpu)?,
+            &[3, 2, 2],
+            Tensor::new(&[[[0u32, 0], [1, 1]]; 3], &Device::Cpu)?,
+        )];
+
+        for (tensor, shape, expected) in tests {
+            assert_eq!(
+                Backend::broadcast(&tensor, shape)?.to_vec3::<u32>()?,
+                expected.to_vec3::<u32>()?
+            );
+            assert!(Backend::broadcast(&tensor, &[3, 2]).is_err());
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn candle_contiguous() -> Result<()> {
+        let tensor = Tensor::arange(0u32, 6, &Device::Cpu)?
+            .reshape(&[2, 3])?
+            .t()?;
+        assert!(!Backend::is_contiguous(&tensor));
+
+        let output = Backend::contiguous(&tensor)?;
+        assert!(Backend::is_contiguous(&output));
+        assert_eq!(output.to_vec2::<u32>()?, tensor.to_vec2::<u32>()?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn candle_concat() -> Result<()> {
+        let a = Tensor::new(&[[0u32], [3]], &Device::Cpu)?;
+        let b = Tensor::new(&[[1u32, 2], [4, 5]], &Device::Cpu)?;
+
+        let output = Backend::concat(&[&a, &b], 1)?;
+        assert_eq!(output.to_vec2::<u32>()?, [[0, 1, 2], [3, 4, 5]]);
+        assert!(Backend::concat(&[&a, &b], 0).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn candle_index_select() -> Result<()> {
+        let tensor = Tensor::arange(0u32, 6, &Device::Cpu)?.reshape(&[2, 3])?;
+
+        let output = Backend::index_select(&tensor, 1, &[2, 0, 2])?;
+        assert_eq!(output.to_vec2::<u32>()?, [[2, 0, 2], [5, 3, 5]]);
+        assert!(Backend::index_select(&tensor, 1, &[3]).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn candle_contract() -> Result<()> {
+        let lhs = Tensor::arange(0f32, 12.0, &Device::Cpu)?.reshape(&[2, 2, 3])?;
+        let rhs = Tensor::ones((2, 3, 1), DType::F32, &Device::Cpu)?;
+
+        let output = Backend::contract(&lhs, &rhs)?;
+        assert_eq!(
+            output.to_vec3::<f32>()?,
+            [[[3.0], [12.0]], [[21.0], [30.0]]]
+        );
+        assert!(Backend::contract(&lhs, &lhs).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn candle_dtype() -> Result<()> {
+        let tensor = Tensor::arange(0u32, 6, &Device::Cpu)?;
+        assert_eq!(Backend::dtype(&tensor), Some(crate::DType::U32));
+
+        let output = Backend::to_dtype(&tensor, crate::DType::F32)?;
+        assert_eq!(output.to_vec1::<f32>()?, [0.0, 1.0, 2.0, 3.0, 4.0, 5.0]);
+        assert!(Backend::to_dtype(&tensor, crate::DType::I8).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn candle_device() -> Result<()> {
+        let tensor = Tensor::arange(0u32, 6, &Device::Cpu)?;
+        assert_eq!(Backend::device(&tensor), Some(crate::Device::Cpu));
+
+        let output = Backend::to_device(&tensor, crate::Device::Cpu)?;
+        assert!(output.device().is_cpu());
+
+        Ok(())
+    }
+
+    #[test]
+    fn candle_dyn_backend() -> Result<()> {
+        let tensor: Box<dyn crate::DynBackend> =
+            Box::new(Tensor::arange(0u32, 6, &Device::Cpu)?.reshape(&[2, 3])?);
+
+        let output = Backend::transpose(&tensor, &[1, 0])?;
+        assert_eq!(Backend::shape(&output), [3, 2]);
+        let output = output.downcast::<Tensor>().unwrap();
+        assert_eq!(output.to_vec2::<u32>()?, [[0, 3], [1, 4], [2, 5]]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn candle_views() -> Result<()> {
+        let tensor = Tensor::arange(0u32, 6, &Device::Cpu)?.reshape(&[2, 3])?;
+
+        let view = Backend::transpose_view(&tensor, &[1, 0]).unwrap();
+        assert_eq!(view.to_vec2::<u32>()?, [[0, 3], [1, 4], [2, 5]]);
+        assert!(Backend::reshape_view(&tensor, &[3, 2]).is_some());
+        // Reshaping the transposed tensor has to copy it
+        assert!(Backend::reshape_view(&view, &[6]).is_none());
+        assert!(Backend::reshape_view(&tensor, &[4]).is_none());
+
+        Ok(())
+    }
+
+    #[test]
+    fn candle_scan() -> Result<()> {
+        let tensor = Tensor::new(&[[1f32, 3.0, 2.0], [4.0, 0.0, 5.0]], &Device::Cpu)?;
+
+        let output = Backend::scan(&tensor, 1, ScanOp::Sum)?;
+        assert_eq!(output.to_vec2::<f32>()?, [[1.0, 4.0, 6.0], [4.0, 4.0, 9.0]]);
+        let output = Backend::scan(&tensor, 0, ScanOp::Prod)?;
+        assert_eq!(
+            output.to_vec2::<f32>()?,
+            [[1.0, 3.0, 2.0], [4.0, 0.0, 10.0]]
+        );
+        let output = Backend::scan(&tensor, 1, ScanOp::Max)?;
+        assert_eq!(output.to_vec2::<f32>()?, [[1.0, 3.0, 3.0], [4.0, 4.0, 5.0]]);
+        assert!(Backend::scan(&tensor, 2, ScanOp::Sum).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn candle_add_axes_view() -> Result<()> {
+        let tensor = Tensor::arange(0f32, 6.0, &Device::Cpu)?.reshape(&[2, 3])?;
+
+        // New axes are stride 0 views, large repeats don't allocate
+        let output = Backend::add_axes(&tensor, 4, &[(0, 1 << 20), (3, 1 << 20)])?;
+        assert_eq!(output.dims(), [1 << 20, 2, 3, 1 << 20]);
+        assert_eq!(output.stride(), [0, 3, 1, 0]);
+        assert!(!output.is_contiguous());
+
+        Ok(())
+    }
+
+    #[test]
+    fn candle_add_axes_batched() -> Result<()> {
+        let tensor = Tensor::arange(0u8, 6, &Device::Cpu)?.reshape(&[2, 3])?;
+        let expected = tensor
+            .reshape(&[1, 2, 1, 3, 1])?
+            .broadcast_as(&[4, 2, 2, 3, 5])?
+            .flatten_all()?
+            .to_vec1::<u8>()?;
+
+        let output = Backend::add_axes(&tensor, 5, &[(0, 4), (2, 2), (4, 5)])?;
+        assert_eq!(output.flatten_all()?.to_vec1::<u8>()?, expected);
+
+        // Transposed inputs get the same axes
+        let output = Backend::add_axes(&tensor.t()?, 5, &[(0, 4), (2, 2), (4, 5)])?;
+        assert_eq!(output.dims(), [4, 3, 2, 2, 5]);
+
+        assert!(Backend::add_axes(&tensor, 4, &[(3, 2), (4, 2)]).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn candle_reshape_noop() -> Result<()> {
+        let tensor = Tensor::arange(0f32, 6.0, &Device::Cpu)?
+            .reshape(&[2, 3])?
+            .t()?;
+
+        // Reshaping to the current shape returns the same tensor, even when it
+        // isn't contiguous
+        let output = Backend::reshape(&tensor, &[3, 2])?;
+        assert_eq!(output.id(), tensor.id());
+        let output = Backend::reshape_view(&tensor, &[3, 2]).unwrap();
+        assert_eq!(output.id(), tensor.id());
+
+        Ok(())
+    }
+
+    #[test]
+    fn candle_transpose_identity() -> Result<()> {
+        let tensor = Tensor::arange(0f32, 6.0, &Device::Cpu)?.reshape(&[2, 3])?;
+
+        let output = Backend::transpose(&tensor, &[0, 1])?;
+        assert_eq!(output.id(), tensor.id());
+        let output = Backend::transpose_view(&tensor, &[0, 1]).unwrap();
+        assert_eq!(output.id(), tensor.id());
+
+        let output = Backend::transpose(&tensor, &[1, 0])?;
+        assert_ne!(output.id(), tensor.id());
+        assert!(Backend::transpose(&tensor, &[0]).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn candle_reduce_adjacent() -> Result<()> {
+        let tensor = Tensor::arange(0f32, 24.0, &Device::Cpu)?.reshape(&[2, 3, 4])?;
+
+        let output =
+            Backend::reduce_axes(&tensor, &mut [(2, Operation::Max), (1, Operation::Max)])?;
+        assert_eq!(output.to_vec1::<f32>()?, [11.0, 23.0]);
+        let output =
+            Backend::reduce_axes(&tensor, &mut [(0, Operation::Mean), (1, Operation::Mean)])?;
+        assert_eq!(output.to_vec1::<f32>()?, [10.0, 11.0, 12.0, 13.0]);
+        let output = Backend::reduce_axes(
+            &tensor,
+            &mut [
+                (0, Operation::Min),
+                (1, Operation::Min),
+                (2, Operation::Sum),
+            ],
+        )?;
+        assert_eq!(output.to_vec0::<f32>()?, 6.0);
+
+        // Axes of a transposed tensor are reduced one at a time
+        let output = Backend::reduce_axes(
+            &tensor.permute((2, 0, 1))?,
+            &mut [(0, Operation::Max), (1, Operation::Max)],
+        )?;
+        assert_eq!(output.to_vec1::<f32>()?, [15.0, 19.0, 23.0]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn candle_reduce_multiple() -> Result<()> {
+        let tensor = Tensor::arange(0f32, 24.0, &Device::Cpu)?.reshape(&[2, 3, 4])?;
+
+        let output =
+            Backend::reduce_axes(&tensor, &mut [(2, Operation::Sum), (0, Operation::Sum)])?;
+        assert_eq!(output.to_vec1::<f32>()?, [60.0, 92.0, 124.0]);
+        let output = Backend::reduce_axes(
+            &tensor.permute((2, 0, 1))?,
+            &mut [(0, Operation::Mean), (2, Operation::Mean)],
+        )?;
+        assert_eq!(output.to_vec1::<f32>()?, [5.5, 17.5]);
+
+        // The max between the sums splits them into separate calls
+        let output = Backend::reduce_axes(
+            &tensor,
+            &mut [
+                (0, Operation::Sum),
+                (1, Operation::Max),
+                (2, Operation::Sum),
+            ],
+        )?;
+        assert_eq!(output.to_vec0::<f32>()?, 124.0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn candle_reshape_permuted() -> Result<()> {
+        let tensor = Tensor::arange(0f32, 24.0, &Device::Cpu)?.reshape(&[2, 3, 4])?;
+
+        // Axes 0 and 1 stay adjacent in the layout, so merging them is a view
+        let permuted = tensor.permute((2, 0, 1))?;
+        let output = Backend::reshape(&permuted, &[4, 6])?;
+        assert!(!output.is_contiguous());
+        assert_eq!(
+            output.to_vec2::<f32>()?,
+            permuted.contiguous()?.reshape((4, 6))?.to_vec2::<f32>()?
+        );
+        let output = Backend::reshape_view(&permuted, &[2, 2, 2, 3]).unwrap();
+        assert_eq!(
+            output.flatten_all()?.to_vec1::<f32>()?,
+            permuted.flatten_all()?.to_vec1::<f32>()?
+        );
+
+        // Merging axes that were swapped needs a copy
+        let permuted = tensor.permute((0, 2, 1))?;
+        assert!(Backend::reshape_view(&permuted, &[2, 12]).is_none());
+        let output = Backend::reshape(&permuted, &[2, 12])?;
+        assert_eq!(
+            output.to_vec2::<f32>()?,
+            permuted.contiguous()?.reshape((2, 12))?.to_vec2::<f32>()?
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn candle_rearrange_chunked() -> Result<()> {
+        let input = Tensor::arange(0f32, 30.0, &Device::Cpu)?.reshape(&[5, 6])?;
+        let output = Tensor::zeros(&[2, 5, 3], DType::F32, &Device::Cpu)?;
+
+        let split_heads = |chunk: &Tensor| {
+            let (v, _) = chunk.dims2()?;
+            Backend::transpose(&Backend::reshape(chunk, &[v, 2, 3])?, &[1, 0, 2])
+        };
+        rearrange_chunked(&input, 0, 2, &output, 1, split_heads).unwrap();
+        assert_eq!(
+            output.to_vec3::<f32>()?,
+            split_heads(&input).unwrap().to_vec3::<f32>()?
+        );
+
+        assert!(rearrange_chunked(&input, 2, 2, &output, 1, split_heads).is_err());
+        assert!(rearrange_chunked(&input, 0, 0, &output, 1, split_heads).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn candle_reduce_sorted() -> Result<()> {
+        let tensor = Tensor::arange(0f32, 24.0, &Device::Cpu)?.reshape(&[2, 3, 4])?;
+
+        // Sorted axes are left in place
+        let mut axes_operations = [(0, Operation::Max), (2, Operation::Sum)];
+        let output = Backend::reduce_axes(&tensor, &mut axes_operations)?;
+        assert_eq!(output.to_vec1::<f32>()?, [54.0, 70.0, 86.0]);
+        assert_eq!(axes_operations, [(0, Operation::Max), (2, Operation::Sum)]);
+
+        let output = Backend::reduce_axes(&tensor, &mut [])?;
+        assert_eq!(output.id(), tensor.id());
+
+        Ok(())
+    }
+
+    #[test]
+    fn candle_reduce_unit_axes() -> Result<()> {
+        let tensor = Tensor::arange(0f32, 6.0, &Device::Cpu)?.reshape(&[2, 1, 3, 1])?;
+
+        let output =
+            Backend::reduce_axes(&tensor, &mut [(1, Operation::Max), (3, Operation::Mean)])?;
+        assert_eq!(output.dims(), [2, 3]);
+        assert!(output.is_contiguous());
+        assert_eq!(output.to_vec2::<f32>()?, [[0.0, 1.0, 2.0], [3.0, 4.0, 5.0]]);
+
+        let output = Backend::reduce_axes(
+            &tensor,
+            &mut [
+                (1, Operation::Min),
+                (2, Operation::Sum),
+                (3, Operation::Sum),
+            ],
+        )?;
+        assert_eq!(output.to_vec1::<f32>()?, [3.0, 12.0]);
+
+        Ok(())
+    }
 }
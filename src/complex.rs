@@ -0,0 +1,178 @@
+use crate::{Backend, DType, Device, EinopsError, Operation, ScanOp};
+
+/// A complex tensor stored as a pair of real tensors of the same shape
+///
+/// Rearrangements and repeats are applied to both parts. Sums and means reduce
+/// both parts, complex numbers aren't ordered so min and max fail.
+#[derive(Debug, Clone)]
+pub struct ComplexTensor<T> {
+    re: T,
+    im: T,
+}
+
+impl<T: Backend> ComplexTensor<T> {
+    pub fn new(re: T, im: T) -> Result<Self, EinopsError> {
+        if re.shape() != im.shape() {
+            return Err(EinopsError::new(format!(
+                "real part of shape {:?} does not match imaginary part of shape {:?}",
+                re.shape(),
+                im.shape()
+            )));
+        }
+        Ok(Self { re, im })
+    }
+}
+
+impl<T> ComplexTensor<T> {
+    pub fn re(&self) -> &T {
+        &self.re
+    }
+
+    pub fn im(&self) -> &T {
+        &self.im
+    }
+
+    pub fn into_parts(self) -> (T, T) {
+        (self.re, self.im)
+    }
+
+    fn map<U>(
+        &self,
+        f: impl Fn(&T) -> Result<U, EinopsError>,
+    ) -> Result<ComplexTensor<U>, EinopsError> {
+        Ok(ComplexTensor {
+            re: f(&self.re)?,
+            im: f(&self.im)?,
+        })
+    }
+}
+
+impl<T: Backend> Backend for ComplexTensor<T> {
+    type Output = ComplexTensor<T::Output>;
+
+    fn shape(&self) -> Vec<usize> {
+        self.re.shape()
+    }
+
+    fn reshape(&self, shape: &[usize]) -> Result<Self::Output, EinopsError> {
+        self.map(|part| part.reshape(shape))
+    }
+
+    fn transpose(&self, axes: &[usize]) -> Result<Self::Output, EinopsError> {
+        self.map(|part| part.transpose(axes))
+    }
+
+    fn reshape_view(&self, shape: &[usize]) -> Option<Self::Output> {
+        Some(ComplexTensor {
+            re: self.re.reshape_view(shape)?,
+            im: self.im.reshape_view(shape)?,
+        })
+    }
+
+    fn transpose_view(&self, axes: &[usize]) -> Option<Self::Output> {
+        Some(ComplexTensor {
+            re: self.re.transpose_view(axes)?,
+            im: self.im.transpose_view(axes)?,
+        })
+    }
+
+    fn depth_to_space(&self, p1: usize, p2: usize) -> Option<Self::Output> {
+        Some(ComplexTensor {
+            re: self.re.depth_to_space(p1, p2)?,
+            im: self.im.depth_to_space(p1, p2)?,
+        })
+    }
+
+    fn space_to_depth(&self, p1: usize, p2: usize) -> Option<Self::Output> {
+        Some(ComplexTensor {
+            re: self.re.space_to_depth(p1, p2)?,
+            im: self.im.space_to_depth(p1, p2)?,
+        })
+    }
+
+    fn reduce_axes(
+        &self,
+        axes_operations: &mut [(usize, Operation)],
+    ) -> Result<Self::Output, EinopsError> {
+        if let Some((axis, operation)) = axes_operations
+            .iter()
+            .find(|(_, operation)| matches!(operation, Operation::Min | Operation::Max))
+        {
+            return Err(EinopsError::new(format!(
+                "cannot take the {:?} of axis {} of a complex tensor",
+                operation, axis
+            )));
+        }
+        Ok(ComplexTensor {
+            re: self.re.reduce_axes(axes_operations)?,
+            im: self.im.reduce_axes(axes_operations)?,
+        })
+    }
+
+    fn add_axes(
+        &self,
+        naxes: usize,
+        pos2len: &[(usize, usize)],
+    ) -> Result<Self::Output, EinopsError> {
+        self.map(|part| part.add_axes(naxes, pos2len))
+    }
+
+    fn narrow(&self, axis: usize, start: usize, len: usize) -> Result<Self::Output, EinopsError> {
+        self.map(|part| part.narrow(axis, start, len))
+    }
+
+    fn broadcast(&self, shape: &[usize]) -> Result<Self::Output, EinopsError> {
+        self.map(|part| part.broadcast(shape))
+    }
+
+    fn is_contiguous(&self) -> bool {
+        self.re.is_contiguous() && self.im.is_contiguous()
+    }
+
+    fn contiguous(&self) -> Result<Self::Output, EinopsError> {
+        self.map(|part| part.contiguous())
+    }
+
+    fn concat(inputs: &[&Self], axis: usize) -> Result<Self::Output, EinopsError> {
+        let re = inputs.iter().map(|input| &input.re).collect::<Vec<_>>();
+        let im = inputs.iter().map(|input| &input.im).collect::<Vec<_>>();
+        Ok(ComplexTensor {
+            re: T::concat(&re, axis)?,
+            im: T::concat(&im, axis)?,
+        })
+    }
+
+    fn index_select(&self, axis: usize, indices: &[usize]) -> Result<Self::Output, EinopsError> {
+        self.map(|part| part.index_select(axis, indices))
+    }
+
+    fn scan(&self, axis: usize, operation: ScanOp) -> Result<Self::Output, EinopsError> {
+        if !matches!(operation, ScanOp::Sum) {
+            return Err(EinopsError::new(format!(
+                "cannot scan axis {} of a complex tensor with {:?}",
+                axis, operation
+            )));
+        }
+        self.map(|part| part.scan(axis, operation))
+    }
+
+    // A complex product needs elementwise arithmetic the trait doesn't have,
+    // so `contract` keeps the default
+
+    /// Element type of both parts
+    fn dtype(&self) -> Option<DType> {
+        self.re.dtype()
+    }
+
+    fn to_dtype(&self, dtype: DType) -> Result<Self::Output, EinopsError> {
+        self.map(|part| part.to_dtype(dtype))
+    }
+
+    fn device(&self) -> Option<Device> {
+        self.re.device()
+    }
+
+    fn to_device(&self, device: Device) -> Result<Self::Output, EinopsError> {
+        self.map(|part| part.to_device(device))
+    }
+}
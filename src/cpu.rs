@@ -0,0 +1,1007 @@
+use std::ops::{Add, Div, Mul};
+
+#[cfg(feature = "rayon")]
+use rayon::prelude::*;
+
+use crate::{checked_product, metrics, Backend, DType, Device, EinopsError, Operation, ScanOp};
+
+/// Element types supported by [`CpuTensor`] reductions and contractions
+pub trait Element:
+    Copy + PartialOrd + Add<Output = Self> + Div<Output = Self> + Mul<Output = Self> + Send + Sync
+{
+    /// Type sums and products are accumulated in, `f32` for the half precision floats
+    type Accumulator: Element;
+    fn zero() -> Self;
+    fn from_usize(value: usize) -> Self;
+    /// `None` for the pointer sized integers
+    fn dtype() -> Option<DType>;
+    fn to_accumulator(self) -> Self::Accumulator;
+    fn from_accumulator(value: Self::Accumulator) -> Self;
+}
+
+macro_rules! impl_element {
+    ($($ty:ty => $dtype:expr),*) => {
+        $(
+            impl Element for $ty {
+                type Accumulator = $ty;
+
+                fn zero() -> Self {
+                    0 as $ty
+                }
+
+                fn from_usize(value: usize) -> Self {
+                    value as $ty
+                }
+
+                fn dtype() -> Option<DType> {
+                    $dtype
+                }
+
+                fn to_accumulator(self) -> Self::Accumulator {
+                    self
+                }
+
+                fn from_accumulator(value: Self::Accumulator) -> Self {
+                    value
+                }
+            }
+        )*
+    };
+}
+
+impl_element!(
+    f32 => Some(DType::F32), f64 => Some(DType::F64),
+    i8 => Some(DType::I8), i16 => Some(DType::I16), i32 => Some(DType::I32),
+    i64 => Some(DType::I64), isize => None,
+    u8 => Some(DType::U8), u16 => Some(DType::U16), u32 => Some(DType::U32),
+    u64 => Some(DType::U64), usize => None
+);
+
+#[cfg(feature = "half")]
+macro_rules! impl_half_element {
+    ($($ty:ty => $dtype:expr),*) => {
+        $(
+            impl Element for $ty {
+                type Accumulator = f32;
+
+                fn zero() -> Self {
+                    <$ty>::ZERO
+                }
+
+                fn from_usize(value: usize) -> Self {
+                    <$ty>::from_f32(value as f32)
+                }
+
+                fn dtype() -> Option<DType> {
+                    $dtype
+                }
+
+                fn to_accumulator(self) -> Self::Accumulator {
+                    self.to_f32()
+                }
+
+                fn from_accumulator(value: Self::Accumulator) -> Self {
+                    <$ty>::from_f32(value)
+                }
+            }
+        )*
+    };
+}
+
+#[cfg(feature = "half")]
+impl_half_element!(half::f16 => Some(DType::F16), half::bf16 => Some(DType::BF16));
+
+/// A minimal row-major tensor backed by a `Vec`
+///
+/// Lets patterns be applied to plain buffers, without depending on
+/// any tensor framework.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CpuTensor<T> {
+    data: Vec<T>,
+    shape: Vec<usize>,
+}
+
+impl<T> CpuTensor<T> {
+    /// Creates a tensor from row-major `data`, the length of `data` has to match `shape`
+    pub fn new(data: Vec<T>, shape: Vec<usize>) -> Result<Self, EinopsError> {
+        if data.len() != checked_product(&shape)? {
+            return Err(EinopsError::new(format!(
+                "buffer of length {} does not match shape {:?}",
+                data.len(),
+                shape
+            )));
+        }
+        Ok(Self { data, shape })
+    }
+
+    pub fn shape(&self) -> &[usize] {
+        &self.shape
+    }
+
+    pub fn data(&self) -> &[T] {
+        &self.data
+    }
+
+    pub fn into_data(self) -> Vec<T> {
+        self.data
+    }
+}
+
+// Number of independent accumulators of a reduction along the last axis,
+// enough to fill a SIMD register for most element types
+const LANES: usize = 8;
+
+// Folds `values` into `LANES` accumulators, which the compiler keeps in SIMD
+// registers, and folds those with `combine`
+fn fold_chunked<T: Copy, A: Copy>(
+    values: &[T],
+    init: A,
+    fold: impl Fn(A, T) -> A,
+    combine: impl Fn(A, A) -> A,
+) -> A {
+    let mut accumulators = [init; LANES];
+    let chunks = values.chunks_exact(LANES);
+    let rest = chunks
+        .remainder()
+        .iter()
+        .fold(init, |accumulator, &x| fold(accumulator, x));
+    for chunk in chunks {
+        for (accumulator, &x) in accumulators.iter_mut().zip(chunk) {
+            *accumulator = fold(*accumulator, x);
+        }
+    }
+    accumulators.into_iter().fold(rest, combine)
+}
+
+// Folds the rows of `block` elementwise, the inner loop runs over contiguous
+// elements so it vectorizes
+fn fold_rows<T: Copy, A: Copy>(
+    block: &[T],
+    row_len: usize,
+    first: impl Fn(T) -> A,
+    fold: impl Fn(A, T) -> A,
+) -> Vec<A> {
+    let mut rows = block.chunks_exact(row_len);
+    let mut output = rows
+        .next()
+        .map_or_else(Vec::new, |row| row.iter().map(|&x| first(x)).collect());
+    for row in rows {
+        for (accumulator, &x) in output.iter_mut().zip(row) {
+            *accumulator = fold(*accumulator, x);
+        }
+    }
+    output
+}
+
+fn min<T: PartialOrd>(min: T, x: T) -> T {
+    if x < min {
+        x
+    } else {
+        min
+    }
+}
+
+fn max<T: PartialOrd>(max: T, x: T) -> T {
+    if x > max {
+        x
+    } else {
+        max
+    }
+}
+
+// Reduces a lane along the last axis, which is contiguous
+fn reduce_lane<T: Element>(lane: &[T], operation: Operation) -> T {
+    let sum = || {
+        fold_chunked(
+            lane,
+            T::Accumulator::zero(),
+            |sum, x| sum + x.to_accumulator(),
+            |sum, x| sum + x,
+        )
+    };
+    match operation {
+        Operation::Min => fold_chunked(lane, lane[0], min, min),
+        Operation::Max => fold_chunked(lane, lane[0], max, max),
+        Operation::Sum => T::from_accumulator(sum()),
+        Operation::Mean => T::from_accumulator(sum() / T::Accumulator::from_usize(lane.len())),
+    }
+}
+
+// Reduces the rows of `block` into one row, for axes before the last one
+fn reduce_rows<T: Element>(block: &[T], row_len: usize, operation: Operation) -> Vec<T> {
+    let sums = || {
+        fold_rows(block, row_len, T::to_accumulator, |sum, x| {
+            sum + x.to_accumulator()
+        })
+    };
+    match operation {
+        Operation::Min => fold_rows(block, row_len, |x| x, min),
+        Operation::Max => fold_rows(block, row_len, |x| x, max),
+        Operation::Sum => sums().into_iter().map(T::from_accumulator).collect(),
+        Operation::Mean => {
+            let len = T::Accumulator::from_usize(block.len() / row_len);
+            sums()
+                .into_iter()
+                .map(|sum| T::from_accumulator(sum / len))
+                .collect()
+        }
+    }
+}
+
+// Row-major strides of a shape
+pub(crate) fn strides(shape: &[usize]) -> Vec<usize> {
+    let mut strides = vec![1; shape.len()];
+    for i in (0..shape.len().saturating_sub(1)).rev() {
+        strides[i] = strides[i + 1] * shape[i + 1];
+    }
+    strides
+}
+
+// Reports a copy of `data` to the metrics
+fn record_copy<T>(data: &[T]) {
+    metrics::record_copy(std::mem::size_of_val(data));
+}
+
+// Gathers elements for `shape`, reading the source with `source_strides`.
+// Axes with a stride of 0 repeat the source elements.
+fn gather<T: Copy + Send + Sync>(data: &[T], shape: &[usize], source_strides: &[usize]) -> Vec<T> {
+    let Some(&first) = data.first() else {
+        return Vec::new();
+    };
+    let mut gathered = vec![first; shape.iter().product()];
+    gather_into(data, shape, source_strides, &mut gathered);
+    gathered
+}
+
+// Gathers elements for `shape` into `output`, which has their number
+pub(crate) fn gather_into<T: Copy + Send + Sync>(
+    data: &[T],
+    shape: &[usize],
+    source_strides: &[usize],
+    output: &mut [T],
+) {
+    let Some((&row_len, outer_shape)) = shape.split_last() else {
+        output[0] = data[0];
+        return;
+    };
+    let row_stride = source_strides[outer_shape.len()];
+
+    // Rows along the last axis are gathered independently
+    let row = |(row, output): (usize, &mut [T])| {
+        let mut offset = 0;
+        let mut rest = row;
+        for axis in (0..outer_shape.len()).rev() {
+            offset += rest % outer_shape[axis] * source_strides[axis];
+            rest /= outer_shape[axis];
+        }
+        for (k, element) in output.iter_mut().enumerate() {
+            *element = data[offset + k * row_stride];
+        }
+    };
+    // Chunks can't be empty, there are no elements to gather then
+    let chunk = row_len.max(1);
+
+    #[cfg(feature = "rayon")]
+    output.par_chunks_mut(chunk).enumerate().for_each(row);
+    #[cfg(not(feature = "rayon"))]
+    output.chunks_mut(chunk).enumerate().for_each(row);
+
+    record_copy(output);
+}
+
+impl<T: Element> CpuTensor<T> {
+    fn reshape(&self, shape: &[usize]) -> Result<Self, EinopsError> {
+        if checked_product(shape)? != self.data.len() {
+            return Err(EinopsError::new(format!(
+                "cannot reshape tensor of shape {:?} to {:?}",
+                self.shape, shape
+            )));
+        }
+        record_copy(&self.data);
+        Ok(Self {
+            data: self.data.clone(),
+            shape: shape.to_vec(),
+        })
+    }
+
+    fn transpose(&self, axes: &[usize]) -> Result<Self, EinopsError> {
+        let mut sorted = axes.to_vec();
+        sorted.sort_unstable();
+        if sorted.len() != self.shape.len() || sorted.iter().enumerate().any(|(i, &a)| i != a) {
+            return Err(EinopsError::new(format!(
+                "invalid permutation {:?} for tensor of shape {:?}",
+                axes, self.shape
+            )));
+        }
+
+        let strides = strides(&self.shape);
+        let shape = axes.iter().map(|&a| self.shape[a]).collect::<Vec<_>>();
+        let source_strides = axes.iter().map(|&a| strides[a]).collect::<Vec<_>>();
+
+        Ok(Self {
+            data: gather(&self.data, &shape, &source_strides),
+            shape,
+        })
+    }
+
+    fn reduce_axis(&self, axis: usize, operation: Operation) -> Result<Self, EinopsError> {
+        if axis >= self.shape.len() {
+            return Err(EinopsError::new(format!(
+                "cannot reduce axis {} of tensor with shape {:?}",
+                axis, self.shape
+            )));
+        }
+        let len = self.shape[axis];
+        if len == 0 && !matches!(operation, Operation::Sum) {
+            return Err(EinopsError::new(format!(
+                "cannot take the {:?} of empty axis {}",
+                operation, axis
+            )));
+        }
+
+        let outer = self.shape[..axis].iter().product::<usize>();
+        let inner = self.shape[axis + 1..].iter().product::<usize>();
+
+        metrics::record_kernel();
+        let data = if len == 0 {
+            vec![T::zero(); outer * inner]
+        } else if inner == 1 {
+            let lane = |lane: &[T]| reduce_lane(lane, operation);
+            #[cfg(feature = "rayon")]
+            let data = self.data.par_chunks(len).map(lane).collect();
+            #[cfg(not(feature = "rayon"))]
+            let data = self.data.chunks(len).map(lane).collect();
+            data
+        } else {
+            // Every block of `len` rows is reduced into one row of the output
+            let block = |block: &[T]| reduce_rows(block, inner, operation);
+            #[cfg(feature = "rayon")]
+            let data = self
+                .data
+                .par_chunks(len * inner)
+                .flat_map_iter(block)
+                .collect();
+            #[cfg(not(feature = "rayon"))]
+            let data = self.data.chunks(len * inner).flat_map(block).collect();
+            data
+        };
+
+        let mut shape = self.shape.clone();
+        shape.remove(axis);
+
+        Ok(Self { data, shape })
+    }
+
+    fn narrow(&self, axis: usize, start: usize, len: usize) -> Result<Self, EinopsError> {
+        if axis >= self.shape.len() || start + len > self.shape[axis] {
+            return Err(EinopsError::new(format!(
+                "cannot narrow axis {} of tensor with shape {:?} to {}..{}",
+                axis,
+                self.shape,
+                start,
+                start + len
+            )));
+        }
+
+        let outer = self.shape[..axis].iter().product::<usize>();
+        let inner = self.shape[axis + 1..].iter().product::<usize>();
+        let axis_len = self.shape[axis];
+
+        let mut data = Vec::with_capacity(outer * len * inner);
+        for o in 0..outer {
+            let offset = (o * axis_len + start) * inner;
+            data.extend_from_slice(&self.data[offset..offset + len * inner]);
+        }
+
+        let mut shape = self.shape.clone();
+        shape[axis] = len;
+
+        record_copy(&data);
+        Ok(Self { data, shape })
+    }
+
+    fn add_axes(&self, naxes: usize, pos2len: &[(usize, usize)]) -> Result<Self, EinopsError> {
+        let mut shape = self.shape.clone();
+        let mut source_strides = strides(&self.shape);
+
+        for &(axis_pos, axis_len) in pos2len {
+            if axis_pos > shape.len() {
+                return Err(EinopsError::new(format!(
+                    "cannot insert axis at position {} of tensor with shape {:?}",
+                    axis_pos, shape
+                )));
+            }
+            // New axes don't move through the source
+            shape.insert(axis_pos, axis_len);
+            source_strides.insert(axis_pos, 0);
+        }
+        if shape.len() != naxes {
+            return Err(EinopsError::new(format!(
+                "expected {} axes after inserting new axes, found {}",
+                naxes,
+                shape.len()
+            )));
+        }
+        checked_product(&shape)?;
+
+        Ok(Self {
+            data: gather(&self.data, &shape, &source_strides),
+            shape,
+        })
+    }
+
+    fn concat(inputs: &[&Self], axis: usize) -> Result<Self, EinopsError> {
+        let first = inputs
+            .first()
+            .ok_or_else(|| EinopsError::new("cannot concatenate an empty list of tensors"))?;
+        let mut shape = first.shape.clone();
+        if axis >= shape.len() {
+            return Err(EinopsError::new(format!(
+                "cannot concatenate tensors of shape {:?} along axis {}",
+                shape, axis
+            )));
+        }
+        for input in &inputs[1..] {
+            let matches = input.shape.len() == shape.len()
+                && (0..shape.len()).all(|i| i == axis || input.shape[i] == shape[i]);
+            if !matches {
+                return Err(EinopsError::new(format!(
+                    "cannot concatenate tensors of shape {:?} and {:?} along axis {}",
+                    first.shape, input.shape, axis
+                )));
+            }
+            shape[axis] += input.shape[axis];
+        }
+
+        let outer = shape[..axis].iter().product::<usize>();
+        let inner = shape[axis + 1..].iter().product::<usize>();
+
+        let mut data = Vec::with_capacity(outer * shape[axis] * inner);
+        for o in 0..outer {
+            for input in inputs {
+                let chunk = input.shape[axis] * inner;
+                data.extend_from_slice(&input.data[o * chunk..(o + 1) * chunk]);
+            }
+        }
+
+        record_copy(&data);
+        Ok(Self { data, shape })
+    }
+
+    fn index_select(&self, axis: usize, indices: &[usize]) -> Result<Self, EinopsError> {
+        if axis >= self.shape.len() || indices.iter().any(|&i| i >= self.shape[axis]) {
+            return Err(EinopsError::new(format!(
+                "cannot select indices {:?} of axis {} of tensor with shape {:?}",
+                indices, axis, self.shape
+            )));
+        }
+
+        let outer = self.shape[..axis].iter().product::<usize>();
+        let inner = self.shape[axis + 1..].iter().product::<usize>();
+        let axis_len = self.shape[axis];
+
+        let mut data = Vec::with_capacity(outer * indices.len() * inner);
+        for o in 0..outer {
+            for &i in indices {
+                let offset = (o * axis_len + i) * inner;
+                data.extend_from_slice(&self.data[offset..offset + inner]);
+            }
+        }
+
+        let mut shape = self.shape.clone();
+        shape[axis] = indices.len();
+
+        record_copy(&data);
+        Ok(Self { data, shape })
+    }
+
+    fn scan(&self, axis: usize, operation: ScanOp) -> Result<Self, EinopsError> {
+        if axis >= self.shape.len() {
+            return Err(EinopsError::new(format!(
+                "cannot scan axis {} of tensor with shape {:?}",
+                axis, self.shape
+            )));
+        }
+
+        let outer = self.shape[..axis].iter().product::<usize>();
+        let len = self.shape[axis];
+        let inner = self.shape[axis + 1..].iter().product::<usize>();
+
+        if len == 0 {
+            return Ok(self.clone());
+        }
+
+        let mut data = self.data.clone();
+        record_copy(&data);
+        for o in 0..outer {
+            for i in 0..inner {
+                let index = |k: usize| (o * len + k) * inner + i;
+                let mut accumulator = data[index(0)].to_accumulator();
+                for k in 1..len {
+                    let x = data[index(k)].to_accumulator();
+                    accumulator = match operation {
+                        ScanOp::Sum => accumulator + x,
+                        ScanOp::Prod => accumulator * x,
+                        ScanOp::Max if x > accumulator => x,
+                        ScanOp::Max => accumulator,
+                    };
+                    data[index(k)] = T::from_accumulator(accumulator);
+                }
+            }
+        }
+
+        Ok(Self {
+            data,
+            shape: self.shape.clone(),
+        })
+    }
+
+    fn contract(&self, rhs: &Self) -> Result<Self, EinopsError> {
+        let rank = self.shape.len();
+        let compatible = rank >= 2
+            && rhs.shape.len() == rank
+            && self.shape[..rank - 2] == rhs.shape[..rank - 2]
+            && self.shape[rank - 1] == rhs.shape[rank - 2];
+        if !compatible {
+            return Err(EinopsError::new(format!(
+                "cannot contract tensors of shape {:?} and {:?}",
+                self.shape, rhs.shape
+            )));
+        }
+
+        let batch = self.shape[..rank - 2].iter().product::<usize>();
+        let (m, k, n) = (
+            self.shape[rank - 2],
+            self.shape[rank - 1],
+            rhs.shape[rank - 1],
+        );
+
+        metrics::record_kernel();
+        let mut data = Vec::with_capacity(batch * m * n);
+        for b in 0..batch {
+            let lhs_data = &self.data[b * m * k..(b + 1) * m * k];
+            let rhs_data = &rhs.data[b * k * n..(b + 1) * k * n];
+            for i in 0..m {
+                for j in 0..n {
+                    data.push(T::from_accumulator((0..k).fold(
+                        T::Accumulator::zero(),
+                        |sum, l| {
+                            sum + lhs_data[i * k + l].to_accumulator()
+                                * rhs_data[l * n + j].to_accumulator()
+                        },
+                    )));
+                }
+            }
+        }
+
+        let mut shape = self.shape.clone();
+        shape[rank - 1] = n;
+
+        Ok(Self { data, shape })
+    }
+
+    fn broadcast(&self, shape: &[usize]) -> Result<Self, EinopsError> {
+        let error = || {
+            EinopsError::new(format!(
+                "cannot broadcast tensor of shape {:?} to {:?}",
+                self.shape, shape
+            ))
+        };
+        let leading = shape
+            .len()
+            .checked_sub(self.shape.len())
+            .ok_or_else(error)?;
+
+        // Leading and expanded axes don't move through the source
+        let mut source_strides = vec![0; leading];
+        for ((&len, &stride), &target_len) in self
+            .shape
+            .iter()
+            .zip(&strides(&self.shape))
+            .zip(&shape[leading..])
+        {
+            match len {
+                _ if len == target_len => source_strides.push(stride),
+                1 => source_strides.push(0),
+                _ => return Err(error()),
+            }
+        }
+        checked_product(shape)?;
+
+        Ok(Self {
+            data: gather(&self.data, shape, &source_strides),
+            shape: shape.to_vec(),
+        })
+    }
+}
+
+impl<T: Element> Backend for CpuTensor<T> {
+    type Output = CpuTensor<T>;
+
+    fn shape(&self) -> Vec<usize> {
+        self.shape.clone()
+    }
+
+    fn reshape(&self, shape: &[usize]) -> Result<Self::Output, EinopsError> {
+        CpuTensor::reshape(self, shape)
+    }
+
+    fn transpose(&self, axes: &[usize]) -> Result<Self::Output, EinopsError> {
+        CpuTensor::transpose(self, axes)
+    }
+
+    fn reduce_axes(
+        &self,
+        axes_operations: &mut [(usize, Operation)],
+    ) -> Result<Self::Output, EinopsError> {
+        let mut output = self.clone();
+
+        axes_operations.sort_by_key(|(axis, _)| *axis);
+
+        for (axis, operation) in axes_operations.iter().rev() {
+            // Reducing an axis of length 1 leaves the data as is
+            if output.shape.get(*axis) == Some(&1) {
+                output.shape.remove(*axis);
+                continue;
+            }
+            output = output.reduce_axis(*axis, *operation)?;
+        }
+
+        Ok(output)
+    }
+
+    fn add_axes(
+        &self,
+        naxes: usize,
+        pos2len: &[(usize, usize)],
+    ) -> Result<Self::Output, EinopsError> {
+        CpuTensor::add_axes(self, naxes, pos2len)
+    }
+
+    fn narrow(&self, axis: usize, start: usize, len: usize) -> Result<Self::Output, EinopsError> {
+        CpuTensor::narrow(self, axis, start, len)
+    }
+
+    fn broadcast(&self, shape: &[usize]) -> Result<Self::Output, EinopsError> {
+        CpuTensor::broadcast(self, shape)
+    }
+
+    // The buffer is always kept in row-major order
+    fn is_contiguous(&self) -> bool {
+        true
+    }
+
+    fn contiguous(&self) -> Result<Self::Output, EinopsError> {
+        record_copy(&self.data);
+        Ok(self.clone())
+    }
+
+    fn concat(inputs: &[&Self], axis: usize) -> Result<Self::Output, EinopsError> {
+        CpuTensor::concat(inputs, axis)
+    }
+
+    fn index_select(&self, axis: usize, indices: &[usize]) -> Result<Self::Output, EinopsError> {
+        CpuTensor::index_select(self, axis, indices)
+    }
+
+    fn scan(&self, axis: usize, operation: ScanOp) -> Result<Self::Output, EinopsError> {
+        CpuTensor::scan(self, axis, operation)
+    }
+
+    fn contract(&self, rhs: &Self) -> Result<Self::Output, EinopsError> {
+        CpuTensor::contract(self, rhs)
+    }
+
+    fn dtype(&self) -> Option<DType> {
+        T::dtype()
+    }
+
+    // The element type is part of the tensor type, so only no-op casts succeed
+    fn to_dtype(&self, dtype: DType) -> Result<Self::Output, EinopsError> {
+        if T::dtype() != Some(dtype) {
+            return Err(EinopsError::new(format!(
+                "cannot cast tensor of dtype {:?} to {:?}",
+                T::dtype(),
+                dtype
+            )));
+        }
+        Ok(self.clone())
+    }
+
+    fn device(&self) -> Option<Device> {
+        Some(Device::Cpu)
+    }
+
+    fn to_device(&self, device: Device) -> Result<Self::Output, EinopsError> {
+        if device != Device::Cpu {
+            return Err(EinopsError::new(format!(
+                "cannot move tensor to {:?}, only the CPU is supported",
+                device
+            )));
+        }
+        Ok(self.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ErrorKind;
+
+    #[test]
+    fn cpu_reduce() {
+        let tests = vec![
+            (
+                CpuTensor::new((0..24).collect::<Vec<i32>>(), vec![4, 2, 3]).unwrap(),
+                vec![(0, Operation::Min)],
+                CpuTensor::new(vec![0, 1, 2, 3, 4, 5], vec![2, 3]).unwrap(),
+            ),
+            (
+                CpuTensor::new((0..24).collect::<Vec<i32>>(), vec![4, 2, 3]).unwrap(),
+                vec![(2, Operation::Max), (0, Operation::Sum)],
+                CpuTensor::new(vec![2 + 8 + 14 + 20, 5 + 11 + 17 + 23], vec![2]).unwrap(),
+            ),
+            (
+                CpuTensor::new(vec![0, 1, 2, 3, 4, 5], vec![2, 3]).unwrap(),
+                vec![(1, Operation::Mean)],
+                CpuTensor::new(vec![1, 4], vec![2]).unwrap(),
+            ),
+            (
+                CpuTensor::new(vec![0, 1, 2, 3, 4, 5], vec![2, 1, 3]).unwrap(),
+                vec![(1, Operation::Mean)],
+                CpuTensor::new(vec![0, 1, 2, 3, 4, 5], vec![2, 3]).unwrap(),
+            ),
+        ];
+
+        for (tensor, mut axes_operations, expected) in tests {
+            assert_eq!(tensor.reduce_axes(&mut axes_operations).unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn cpu_transpose() {
+        let tests = vec![(
+            CpuTensor::new((0..24).collect::<Vec<u32>>(), vec![2, 3, 4]).unwrap(),
+            &[2, 0, 1],
+            CpuTensor::new(
+                vec![
+                    0u32, 4, 8, 12, 16, 20, 1, 5, 9, 13, 17, 21, 2, 6, 10, 14, 18, 22, 3, 7, 11,
+                    15, 19, 23,
+                ],
+                vec![4, 2, 3],
+            )
+            .unwrap(),
+        )];
+
+        for (tensor, axes, expected) in tests {
+            assert_eq!(Backend::transpose(&tensor, axes).unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn cpu_add_axes() {
+        let tests = vec![(
+            CpuTensor::new((0..6).collect::<Vec<u8>>(), vec![1, 2, 3]).unwrap(),
+            5,
+            &[(0, 5), (3, 3)],
+            CpuTensor::new(
+                vec![
+                    0u8, 1, 2, 0, 1, 2, 0, 1, 2, 3, 4, 5, 3, 4, 5, 3, 4, 5, 0, 1, 2, 0, 1, 2, 0, 1,
+                    2, 3, 4, 5, 3, 4, 5, 3, 4, 5, 0, 1, 2, 0, 1, 2, 0, 1, 2, 3, 4, 5, 3, 4, 5, 3,
+                    4, 5, 0, 1, 2, 0, 1, 2, 0, 1, 2, 3, 4, 5, 3, 4, 5, 3, 4, 5, 0, 1, 2, 0, 1, 2,
+                    0, 1, 2, 3, 4, 5, 3, 4, 5, 3, 4, 5,
+                ],
+                vec![5, 1, 2, 3, 3],
+            )
+            .unwrap(),
+        )];
+
+        for (tensor, naxes, pos2len, expected) in tests {
+            assert_eq!(tensor.add_axes(naxes, pos2len).unwrap(), expected);
+            // The new shape can't be allocated
+            let error = tensor.add_axes(4, &[(0, usize::MAX)]).unwrap_err();
+            assert!(matches!(error.kind(), ErrorKind::Overflow { .. }));
+        }
+    }
+
+    #[test]
+    fn cpu_narrow() {
+        let tests = vec![(
+            CpuTensor::new((0..24).collect::<Vec<u32>>(), vec![2, 3, 4]).unwrap(),
+            (1, 1, 2),
+            CpuTensor::new(
+                vec![4u32, 5, 6, 7, 8, 9, 10, 11, 16, 17, 18, 19, 20, 21, 22, 23],
+                vec![2, 2, 4],
+            )
+            .unwrap(),
+        )];
+
+        for (tensor, (axis, start, len), expected) in tests {
+            assert_eq!(
+                Backend::narrow(&tensor, axis, start, len).unwrap(),
+                expected
+            );
+            assert!(Backend::narrow(&tensor, axis, start, 3).is_err());
+        }
+    }
+
+    #[test]
+    fn cpu_broadcast() {
+        let tests = vec![(
+            CpuTensor::new(vec![0u32, 1], vec![2, 1]).unwrap(),
+            &[3, 2, 2],
+            CpuTensor::new(vec![0u32, 0, 1, 1, 0, 0, 1, 1, 0, 0, 1, 1], vec![3, 2, 2]).unwrap(),
+        )];
+
+        for (tensor, shape, expected) in tests {
+            assert_eq!(Backend::broadcast(&tensor, shape).unwrap(), expected);
+            assert!(Backend::broadcast(&tensor, &[3, 2]).is_err());
+        }
+    }
+
+    #[test]
+    fn cpu_concat() {
+        let a = CpuTensor::new(vec![0u32, 3], vec![2, 1]).unwrap();
+        let b = CpuTensor::new(vec![1u32, 2, 4, 5], vec![2, 2]).unwrap();
+
+        let output = Backend::concat(&[&a, &b], 1).unwrap();
+        assert_eq!(
+            output,
+            CpuTensor::new(vec![0, 1, 2, 3, 4, 5], vec![2, 3]).unwrap()
+        );
+        assert!(Backend::concat(&[&a, &b], 0).is_err());
+    }
+
+    #[test]
+    fn cpu_index_select() {
+        let tensor = CpuTensor::new((0..6).collect::<Vec<u32>>(), vec![2, 3]).unwrap();
+
+        let output = Backend::index_select(&tensor, 1, &[2, 0, 2]).unwrap();
+        assert_eq!(
+            output,
+            CpuTensor::new(vec![2, 0, 2, 5, 3, 5], vec![2, 3]).unwrap()
+        );
+        assert!(Backend::index_select(&tensor, 1, &[3]).is_err());
+    }
+
+    #[test]
+    fn cpu_contract() {
+        let lhs = CpuTensor::new((0..12).collect::<Vec<i32>>(), vec![2, 2, 3]).unwrap();
+        let rhs = CpuTensor::new(vec![1; 6], vec![2, 3, 1]).unwrap();
+
+        let output = Backend::contract(&lhs, &rhs).unwrap();
+        assert_eq!(
+            output,
+            CpuTensor::new(vec![3, 12, 21, 30], vec![2, 2, 1]).unwrap()
+        );
+        assert!(Backend::contract(&lhs, &lhs).is_err());
+    }
+
+    #[test]
+    fn cpu_dtype() {
+        let tensor = CpuTensor::new(vec![0u8, 1, 2], vec![3]).unwrap();
+        assert_eq!(Backend::dtype(&tensor), Some(DType::U8));
+
+        assert_eq!(Backend::to_dtype(&tensor, DType::U8).unwrap(), tensor);
+        assert!(Backend::to_dtype(&tensor, DType::F32).is_err());
+    }
+
+    #[test]
+    fn cpu_device() {
+        let tensor = CpuTensor::new(vec![0u8, 1, 2], vec![3]).unwrap();
+        assert_eq!(Backend::device(&tensor), Some(Device::Cpu));
+
+        assert_eq!(Backend::to_device(&tensor, Device::Cpu).unwrap(), tensor);
+        assert!(Backend::to_device(&tensor, Device::Cuda(0)).is_err());
+    }
+
+    #[test]
+    fn cpu_dyn_concat() {
+        let a: Box<dyn crate::DynBackend> =
+            Box::new(CpuTensor::new(vec![0u8, 1], vec![2]).unwrap());
+        let b: Box<dyn crate::DynBackend> = Box::new(CpuTensor::new(vec![2u8], vec![1]).unwrap());
+        let c: Box<dyn crate::DynBackend> =
+            Box::new(CpuTensor::new(vec![2.0f32], vec![1]).unwrap());
+
+        let output = Backend::concat(&[&a, &b], 0).unwrap();
+        assert_eq!(
+            output.downcast_ref::<CpuTensor<u8>>().unwrap().data(),
+            [0, 1, 2]
+        );
+        assert!(Backend::concat(&[&a, &c], 0).is_err());
+    }
+
+    #[cfg(feature = "half")]
+    #[test]
+    fn cpu_half_reduce() {
+        // 2048 + 1 rounds back to 2048 in f16, the sum has to be accumulated in f32
+        let tensor = CpuTensor::new(vec![half::f16::ONE; 4096], vec![4096]).unwrap();
+
+        let output = Backend::reduce_axes(&tensor, &mut [(0, Operation::Sum)]).unwrap();
+        assert_eq!(output.data(), [half::f16::from_f32(4096.0)]);
+        let output = Backend::reduce_axes(&tensor, &mut [(0, Operation::Mean)]).unwrap();
+        assert_eq!(output.data(), [half::f16::ONE]);
+        assert_eq!(Backend::dtype(&tensor), Some(DType::F16));
+    }
+
+    #[test]
+    fn cpu_scan() {
+        let tensor = CpuTensor::new(vec![1, 3, 2, 4, 0, 5], vec![2, 3]).unwrap();
+
+        let output = Backend::scan(&tensor, 1, ScanOp::Sum).unwrap();
+        assert_eq!(output.data(), [1, 4, 6, 4, 4, 9]);
+        let output = Backend::scan(&tensor, 0, ScanOp::Prod).unwrap();
+        assert_eq!(output.data(), [1, 3, 2, 4, 0, 10]);
+        let output = Backend::scan(&tensor, 1, ScanOp::Max).unwrap();
+        assert_eq!(output.data(), [1, 3, 3, 4, 4, 5]);
+        assert!(Backend::scan(&tensor, 2, ScanOp::Sum).is_err());
+    }
+
+    #[test]
+    fn cpu_large() {
+        // Large enough to be split across threads with the rayon feature
+        let (a, b, c) = (64, 33, 17);
+        let tensor = CpuTensor::new((0..a * b * c).collect::<Vec<usize>>(), vec![a, b, c]).unwrap();
+
+        let output = Backend::reduce_axes(&tensor, &mut [(1, Operation::Sum)]).unwrap();
+        assert_eq!(output.shape(), [a, c]);
+        for (n, &value) in output.data().iter().enumerate() {
+            let (i, k) = (n / c, n % c);
+            assert_eq!(value, (0..b).map(|j| (i * b + j) * c + k).sum::<usize>());
+        }
+
+        let output = Backend::transpose(&tensor, &[2, 0, 1]).unwrap();
+        for (n, &value) in output.data().iter().enumerate() {
+            let (k, i, j) = (n / (a * b), n / b % a, n % b);
+            assert_eq!(value, (i * b + j) * c + k);
+        }
+
+        let output = Backend::add_axes(&tensor, 4, &[(1, 3)]).unwrap();
+        assert_eq!(output.shape(), [a, 3, b, c]);
+        assert_eq!(
+            &output.data()[2 * b * c..3 * b * c],
+            &tensor.data()[..b * c]
+        );
+    }
+
+    #[test]
+    fn cpu_reduce_chunked() {
+        // Lengths that aren't a multiple of the number of accumulators
+        let (a, b, c) = (3, 19, 5);
+        let data = (0..a * b * c)
+            .map(|n| ((n * 7919) % 101) as i64 - 50)
+            .collect::<Vec<_>>();
+        let tensor = CpuTensor::new(data.clone(), vec![a, b, c]).unwrap();
+        let value = |i: usize, j: usize, k: usize| data[(i * b + j) * c + k];
+
+        for operation in [
+            Operation::Min,
+            Operation::Max,
+            Operation::Sum,
+            Operation::Mean,
+        ] {
+            let naive = |lane: Vec<i64>| match operation {
+                Operation::Min => *lane.iter().min().unwrap(),
+                Operation::Max => *lane.iter().max().unwrap(),
+                Operation::Sum => lane.iter().sum(),
+                Operation::Mean => lane.iter().sum::<i64>() / lane.len() as i64,
+            };
+
+            let output = Backend::reduce_axes(&tensor, &mut [(2, operation)]).unwrap();
+            let expected = (0..a * b)
+                .map(|n| naive((0..c).map(|k| value(n / b, n % b, k)).collect()))
+                .collect::<Vec<_>>();
+            assert_eq!(output.data(), expected.as_slice());
+
+            let output = Backend::reduce_axes(&tensor, &mut [(1, operation)]).unwrap();
+            let expected = (0..a * c)
+                .map(|n| naive((0..b).map(|j| value(n / c, j, n % c)).collect()))
+                .collect::<Vec<_>>();
+            assert_eq!(output.data(), expected.as_slice());
+        }
+
+        let tensor = CpuTensor::new(Vec::<f32>::new(), vec![2, 0, 3]).unwrap();
+        let output = Backend::reduce_axes(&tensor, &mut [(1, Operation::Sum)]).unwrap();
+        assert_eq!(output.data(), [0.0; 6]);
+    }
+}
@@ -0,0 +1,376 @@
+use std::sync::Arc;
+
+use crate::{Backend, DType, Device, EinopsError, Operation, ScanOp, SmallAxes};
+
+// Shared with the recipes of layers, which are simplified the same way
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Step {
+    Reshape(Vec<usize>),
+    Transpose(Vec<usize>),
+    Reduce(Vec<(usize, Operation)>),
+    AddAxes(usize, Vec<(usize, usize)>),
+    Narrow(usize, usize, usize),
+    Broadcast(Vec<usize>),
+    Contiguous,
+    IndexSelect(usize, Vec<usize>),
+    Scan(usize, ScanOp),
+    ToDType(DType),
+    ToDevice(Device),
+}
+
+impl Step {
+    // Name of the backend method running the step, for the recipes of layers
+    #[cfg_attr(
+        not(any(feature = "nn", feature = "safetensors", feature = "ffi")),
+        allow(dead_code)
+    )]
+    pub(crate) fn kind(&self) -> &'static str {
+        match self {
+            Step::Reshape(_) => "reshape",
+            Step::Transpose(_) => "transpose",
+            Step::Reduce(_) => "reduce_axes",
+            Step::AddAxes(..) => "add_axes",
+            Step::Narrow(..) => "narrow",
+            Step::Broadcast(_) => "broadcast",
+            Step::Contiguous => "contiguous",
+            Step::IndexSelect(..) => "index_select",
+            Step::Scan(..) => "scan",
+            Step::ToDType(_) => "to_dtype",
+            Step::ToDevice(_) => "to_device",
+        }
+    }
+}
+
+/// A tensor whose operations are recorded instead of run
+///
+/// Operations only check shapes, so chains of `einops!` calls across function
+/// boundaries cost nothing until [`Deferred::eval`] is called. Before running
+/// the recorded steps, consecutive reshapes and transposes are merged and steps
+/// that leave the tensor unchanged are dropped. Operations on two tensors
+/// evaluate their inputs first.
+#[derive(Debug, Clone)]
+pub struct Deferred<T> {
+    input: Arc<T>,
+    // Every step with the shape of its output
+    steps: Vec<(Step, Vec<usize>)>,
+}
+
+impl<T: Backend<Output = T> + Clone> Deferred<T> {
+    pub fn new(input: T) -> Self {
+        Self {
+            input: Arc::new(input),
+            steps: Vec::new(),
+        }
+    }
+
+    /// Runs the recorded steps
+    pub fn eval(&self) -> Result<T, EinopsError> {
+        let steps = simplify(&self.input.shape(), &self.steps);
+
+        let mut steps = steps.iter();
+        let mut output = match steps.next() {
+            Some((step, _)) => run(&*self.input, step)?,
+            None => return Ok((*self.input).clone()),
+        };
+        for (step, _) in steps {
+            output = run(&output, step)?;
+        }
+
+        Ok(output)
+    }
+
+    fn then(&self, step: Step, shape: Vec<usize>) -> Self {
+        let mut steps = self.steps.clone();
+        steps.push((step, shape));
+        Self {
+            input: self.input.clone(),
+            steps,
+        }
+    }
+
+    fn check_axis(&self, axis: usize, operation: &str) -> Result<Vec<usize>, EinopsError> {
+        let shape = Backend::shape(self);
+        if axis >= shape.len() {
+            return Err(EinopsError::new(format!(
+                "cannot {} axis {} of tensor with shape {:?}",
+                operation, axis, shape
+            )));
+        }
+        Ok(shape)
+    }
+}
+
+// Steps with the shape of their output, after merging consecutive reshapes and
+// transposes and dropping the ones that leave the tensor unchanged
+pub(crate) fn simplify(
+    input_shape: &[usize],
+    steps: &[(Step, Vec<usize>)],
+) -> Vec<(Step, Vec<usize>)> {
+    let mut simplified: Vec<(Step, Vec<usize>)> = Vec::with_capacity(steps.len());
+
+    for (step, shape) in steps {
+        let (step, shape) = match (simplified.last(), step) {
+            // Only the last of consecutive reshapes matters
+            (Some((Step::Reshape(_), _)), Step::Reshape(_)) => {
+                simplified.pop();
+                (step.clone(), shape.clone())
+            }
+            (Some((Step::Transpose(first), _)), Step::Transpose(axes)) => {
+                let axes = axes.iter().map(|&axis| first[axis]).collect();
+                simplified.pop();
+                (Step::Transpose(axes), shape.clone())
+            }
+            (Some((Step::Contiguous, _)), Step::Contiguous) => continue,
+            _ => (step.clone(), shape.clone()),
+        };
+
+        let previous_shape = simplified
+            .last()
+            .map_or(input_shape, |(_, shape)| shape.as_slice());
+        let unchanged = match &step {
+            Step::Reshape(_) => previous_shape == shape,
+            Step::Transpose(axes) => axes.iter().enumerate().all(|(i, &axis)| i == axis),
+            _ => false,
+        };
+        if !unchanged {
+            simplified.push((step, shape));
+        }
+    }
+
+    simplified
+}
+
+pub(crate) fn run<T: Backend<Output = T>>(tensor: &T, step: &Step) -> Result<T, EinopsError> {
+    match step {
+        Step::Reshape(shape) => tensor.reshape(shape),
+        Step::Transpose(axes) => tensor.transpose(axes),
+        Step::Reduce(axes_operations) => {
+            // Backends may reorder the axes, the step is kept for the next run
+            let mut axes_operations = axes_operations.iter().copied().collect::<SmallAxes<_>>();
+            tensor.reduce_axes(&mut axes_operations)
+        }
+        Step::AddAxes(naxes, pos2len) => tensor.add_axes(*naxes, pos2len),
+        Step::Narrow(axis, start, len) => tensor.narrow(*axis, *start, *len),
+        Step::Broadcast(shape) => tensor.broadcast(shape),
+        Step::Contiguous => tensor.contiguous(),
+        Step::IndexSelect(axis, indices) => tensor.index_select(*axis, indices),
+        Step::Scan(axis, operation) => tensor.scan(*axis, *operation),
+        Step::ToDType(dtype) => tensor.to_dtype(*dtype),
+        Step::ToDevice(device) => tensor.to_device(*device),
+    }
+}
+
+impl<T: Backend<Output = T> + Clone> Backend for Deferred<T> {
+    type Output = Deferred<T>;
+
+    fn shape(&self) -> Vec<usize> {
+        match self.steps.last() {
+            Some((_, shape)) => shape.clone(),
+            None => self.input.shape(),
+        }
+    }
+
+    fn reshape(&self, shape: &[usize]) -> Result<Self::Output, EinopsError> {
+        let input_shape = Backend::shape(self);
+        if shape.iter().product::<usize>() != input_shape.iter().product::<usize>() {
+            return Err(EinopsError::new(format!(
+                "cannot reshape tensor of shape {:?} to {:?}",
+                input_shape, shape
+            )));
+        }
+        Ok(self.then(Step::Reshape(shape.to_vec()), shape.to_vec()))
+    }
+
+    fn transpose(&self, axes: &[usize]) -> Result<Self::Output, EinopsError> {
+        let input_shape = Backend::shape(self);
+        let mut sorted = axes.to_vec();
+        sorted.sort_unstable();
+        if sorted.len() != input_shape.len() || sorted.iter().enumerate().any(|(i, &a)| i != a) {
+            return Err(EinopsError::new(format!(
+                "invalid permutation {:?} for tensor of shape {:?}",
+                axes, input_shape
+            )));
+        }
+        let shape = axes.iter().map(|&axis| input_shape[axis]).collect();
+        Ok(self.then(Step::Transpose(axes.to_vec()), shape))
+    }
+
+    fn reduce_axes(
+        &self,
+        axes_operations: &mut [(usize, Operation)],
+    ) -> Result<Self::Output, EinopsError> {
+        let input_shape = Backend::shape(self);
+        for &(axis, _) in axes_operations.iter() {
+            self.check_axis(axis, "reduce")?;
+        }
+        let shape = input_shape
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| axes_operations.iter().all(|(axis, _)| axis != i))
+            .map(|(_, &len)| len)
+            .collect();
+        Ok(self.then(Step::Reduce(axes_operations.to_vec()), shape))
+    }
+
+    fn add_axes(
+        &self,
+        naxes: usize,
+        pos2len: &[(usize, usize)],
+    ) -> Result<Self::Output, EinopsError> {
+        let mut shape = Backend::shape(self);
+        for &(axis_pos, axis_len) in pos2len {
+            if axis_pos > shape.len() {
+                return Err(EinopsError::new(format!(
+                    "cannot insert axis at position {} of tensor with shape {:?}",
+                    axis_pos, shape
+                )));
+            }
+            shape.insert(axis_pos, axis_len);
+        }
+        if shape.len() != naxes {
+            return Err(EinopsError::new(format!(
+                "expected {} axes after inserting new axes, found {}",
+                naxes,
+                shape.len()
+            )));
+        }
+        Ok(self.then(Step::AddAxes(naxes, pos2len.to_vec()), shape))
+    }
+
+    fn narrow(&self, axis: usize, start: usize, len: usize) -> Result<Self::Output, EinopsError> {
+        let mut shape = self.check_axis(axis, "narrow")?;
+        if start + len > shape[axis] {
+            return Err(EinopsError::new(format!(
+                "cannot narrow axis {} of tensor with shape {:?} to {}..{}",
+                axis,
+                shape,
+                start,
+                start + len
+            )));
+        }
+        shape[axis] = len;
+        Ok(self.then(Step::Narrow(axis, start, len), shape))
+    }
+
+    fn broadcast(&self, shape: &[usize]) -> Result<Self::Output, EinopsError> {
+        let input_shape = Backend::shape(self);
+        let compatible = shape.len() >= input_shape.len()
+            && input_shape
+                .iter()
+                .rev()
+                .zip(shape.iter().rev())
+                .all(|(&from, &to)| from == to || from == 1);
+        if !compatible {
+            return Err(EinopsError::new(format!(
+                "cannot broadcast tensor of shape {:?} to {:?}",
+                input_shape, shape
+            )));
+        }
+        Ok(self.then(Step::Broadcast(shape.to_vec()), shape.to_vec()))
+    }
+
+    fn is_contiguous(&self) -> bool {
+        match self.steps.last() {
+            Some((step, _)) => *step == Step::Contiguous,
+            None => self.input.is_contiguous(),
+        }
+    }
+
+    fn contiguous(&self) -> Result<Self::Output, EinopsError> {
+        Ok(self.then(Step::Contiguous, Backend::shape(self)))
+    }
+
+    fn concat(inputs: &[&Self], axis: usize) -> Result<Self::Output, EinopsError> {
+        let inputs = inputs
+            .iter()
+            .map(|input| input.eval())
+            .collect::<Result<Vec<_>, _>>()?;
+        let inputs = inputs.iter().collect::<Vec<_>>();
+        Ok(Deferred::new(T::concat(&inputs, axis)?))
+    }
+
+    fn index_select(&self, axis: usize, indices: &[usize]) -> Result<Self::Output, EinopsError> {
+        let mut shape = self.check_axis(axis, "select from")?;
+        if let Some(&index) = indices.iter().find(|&&index| index >= shape[axis]) {
+            return Err(EinopsError::new(format!(
+                "cannot select index {} of axis {} of tensor with shape {:?}",
+                index, axis, shape
+            )));
+        }
+        shape[axis] = indices.len();
+        Ok(self.then(Step::IndexSelect(axis, indices.to_vec()), shape))
+    }
+
+    fn scan(&self, axis: usize, operation: ScanOp) -> Result<Self::Output, EinopsError> {
+        let shape = self.check_axis(axis, "scan")?;
+        Ok(self.then(Step::Scan(axis, operation), shape))
+    }
+
+    fn contract(&self, rhs: &Self) -> Result<Self::Output, EinopsError> {
+        Ok(Deferred::new(self.eval()?.contract(&rhs.eval()?)?))
+    }
+
+    fn dtype(&self) -> Option<DType> {
+        self.steps
+            .iter()
+            .rev()
+            .find_map(|(step, _)| match step {
+                Step::ToDType(dtype) => Some(Some(*dtype)),
+                _ => None,
+            })
+            .unwrap_or_else(|| self.input.dtype())
+    }
+
+    fn to_dtype(&self, dtype: DType) -> Result<Self::Output, EinopsError> {
+        Ok(self.then(Step::ToDType(dtype), Backend::shape(self)))
+    }
+
+    fn device(&self) -> Option<Device> {
+        self.steps
+            .iter()
+            .rev()
+            .find_map(|(step, _)| match step {
+                Step::ToDevice(device) => Some(Some(*device)),
+                _ => None,
+            })
+            .unwrap_or_else(|| self.input.device())
+    }
+
+    fn to_device(&self, device: Device) -> Result<Self::Output, EinopsError> {
+        Ok(self.then(Step::ToDevice(device), Backend::shape(self)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn deferred_simplify() {
+        let steps = [
+            (Step::Reshape(vec![6, 4]), vec![6, 4]),
+            (Step::Reshape(vec![2, 3, 4]), vec![2, 3, 4]),
+            (Step::Transpose(vec![1, 0, 2]), vec![3, 2, 4]),
+            (Step::Transpose(vec![1, 0, 2]), vec![2, 3, 4]),
+            (Step::Contiguous, vec![2, 3, 4]),
+            (Step::Contiguous, vec![2, 3, 4]),
+        ];
+        assert_eq!(
+            simplify(&[2, 3, 4], &steps),
+            [(Step::Contiguous, vec![2, 3, 4])]
+        );
+
+        let steps = [
+            (Step::Transpose(vec![1, 2, 0]), vec![3, 4, 2]),
+            (Step::Transpose(vec![1, 2, 0]), vec![4, 2, 3]),
+            (Step::Reshape(vec![4, 6]), vec![4, 6]),
+        ];
+        assert_eq!(
+            simplify(&[2, 3, 4], &steps),
+            [
+                (Step::Transpose(vec![2, 0, 1]), vec![4, 2, 3]),
+                (Step::Reshape(vec![4, 6]), vec![4, 6])
+            ]
+        );
+    }
+}
@@ -0,0 +1,300 @@
+use std::any::Any;
+
+use crate::{Backend, DType, Device, EinopsError, Operation, ScanOp};
+
+/// Object safe form of [`Backend`], for picking backends at runtime
+///
+/// Implemented for every `'static` backend whose operations return its own
+/// type, like candle and tch tensors or `CpuTensor`. `Box<dyn DynBackend>` is a
+/// backend itself, so it can be passed to `einops!`. Operations on two tensors
+/// fail if the tensors are of different types.
+pub trait DynBackend {
+    fn shape(&self) -> Vec<usize>;
+    fn reshape(&self, shape: &[usize]) -> Result<Box<dyn DynBackend>, EinopsError>;
+    fn transpose(&self, axes: &[usize]) -> Result<Box<dyn DynBackend>, EinopsError>;
+    fn reshape_view(&self, shape: &[usize]) -> Option<Box<dyn DynBackend>>;
+    fn transpose_view(&self, axes: &[usize]) -> Option<Box<dyn DynBackend>>;
+    fn depth_to_space(&self, p1: usize, p2: usize) -> Option<Box<dyn DynBackend>>;
+    fn space_to_depth(&self, p1: usize, p2: usize) -> Option<Box<dyn DynBackend>>;
+    fn reduce_axes(
+        &self,
+        axes_operations: &mut [(usize, Operation)],
+    ) -> Result<Box<dyn DynBackend>, EinopsError>;
+    fn add_axes(
+        &self,
+        naxes: usize,
+        pos2len: &[(usize, usize)],
+    ) -> Result<Box<dyn DynBackend>, EinopsError>;
+    fn narrow(
+        &self,
+        axis: usize,
+        start: usize,
+        len: usize,
+    ) -> Result<Box<dyn DynBackend>, EinopsError>;
+    fn broadcast(&self, shape: &[usize]) -> Result<Box<dyn DynBackend>, EinopsError>;
+    fn is_contiguous(&self) -> bool;
+    fn contiguous(&self) -> Result<Box<dyn DynBackend>, EinopsError>;
+    /// Joins `inputs` along `axis`, `self` only selects the implementation
+    fn concat(
+        &self,
+        inputs: &[&dyn DynBackend],
+        axis: usize,
+    ) -> Result<Box<dyn DynBackend>, EinopsError>;
+    fn index_select(
+        &self,
+        axis: usize,
+        indices: &[usize],
+    ) -> Result<Box<dyn DynBackend>, EinopsError>;
+    fn scan(&self, axis: usize, operation: ScanOp) -> Result<Box<dyn DynBackend>, EinopsError>;
+    fn contract(&self, rhs: &dyn DynBackend) -> Result<Box<dyn DynBackend>, EinopsError>;
+    fn dtype(&self) -> Option<DType>;
+    fn to_dtype(&self, dtype: DType) -> Result<Box<dyn DynBackend>, EinopsError>;
+    fn device(&self) -> Option<Device>;
+    fn to_device(&self, device: Device) -> Result<Box<dyn DynBackend>, EinopsError>;
+    /// The concrete tensor, for downcasting
+    fn as_any(&self) -> &dyn Any;
+    fn into_any(self: Box<Self>) -> Box<dyn Any>;
+}
+
+impl dyn DynBackend {
+    pub fn downcast_ref<T: 'static>(&self) -> Option<&T> {
+        self.as_any().downcast_ref()
+    }
+
+    /// Returns the boxed tensor if it is a `T`
+    pub fn downcast<T: 'static>(self: Box<Self>) -> Result<T, Box<dyn Any>> {
+        self.into_any().downcast().map(|tensor| *tensor)
+    }
+}
+
+fn boxed<T: DynBackend + 'static>(
+    output: Result<T, EinopsError>,
+) -> Result<Box<dyn DynBackend>, EinopsError> {
+    output.map(|tensor| Box::new(tensor) as Box<dyn DynBackend>)
+}
+
+fn downcast_input<T: 'static>(input: &dyn DynBackend) -> Result<&T, EinopsError> {
+    input.as_any().downcast_ref().ok_or_else(|| {
+        EinopsError::new(format!(
+            "expected a tensor of type {}",
+            std::any::type_name::<T>()
+        ))
+    })
+}
+
+impl<T: Backend<Output = T> + 'static> DynBackend for T {
+    fn shape(&self) -> Vec<usize> {
+        Backend::shape(self)
+    }
+
+    fn reshape(&self, shape: &[usize]) -> Result<Box<dyn DynBackend>, EinopsError> {
+        boxed(Backend::reshape(self, shape))
+    }
+
+    fn transpose(&self, axes: &[usize]) -> Result<Box<dyn DynBackend>, EinopsError> {
+        boxed(Backend::transpose(self, axes))
+    }
+
+    fn reshape_view(&self, shape: &[usize]) -> Option<Box<dyn DynBackend>> {
+        Backend::reshape_view(self, shape).map(|view| Box::new(view) as Box<dyn DynBackend>)
+    }
+
+    fn transpose_view(&self, axes: &[usize]) -> Option<Box<dyn DynBackend>> {
+        Backend::transpose_view(self, axes).map(|view| Box::new(view) as Box<dyn DynBackend>)
+    }
+
+    fn depth_to_space(&self, p1: usize, p2: usize) -> Option<Box<dyn DynBackend>> {
+        Backend::depth_to_space(self, p1, p2).map(|output| Box::new(output) as Box<dyn DynBackend>)
+    }
+
+    fn space_to_depth(&self, p1: usize, p2: usize) -> Option<Box<dyn DynBackend>> {
+        Backend::space_to_depth(self, p1, p2).map(|output| Box::new(output) as Box<dyn DynBackend>)
+    }
+
+    fn reduce_axes(
+        &self,
+        axes_operations: &mut [(usize, Operation)],
+    ) -> Result<Box<dyn DynBackend>, EinopsError> {
+        boxed(Backend::reduce_axes(self, axes_operations))
+    }
+
+    fn add_axes(
+        &self,
+        naxes: usize,
+        pos2len: &[(usize, usize)],
+    ) -> Result<Box<dyn DynBackend>, EinopsError> {
+        boxed(Backend::add_axes(self, naxes, pos2len))
+    }
+
+    fn narrow(
+        &self,
+        axis: usize,
+        start: usize,
+        len: usize,
+    ) -> Result<Box<dyn DynBackend>, EinopsError> {
+        boxed(Backend::narrow(self, axis, start, len))
+    }
+
+    fn broadcast(&self, shape: &[usize]) -> Result<Box<dyn DynBackend>, EinopsError> {
+        boxed(Backend::broadcast(self, shape))
+    }
+
+    fn is_contiguous(&self) -> bool {
+        Backend::is_contiguous(self)
+    }
+
+    fn contiguous(&self) -> Result<Box<dyn DynBackend>, EinopsError> {
+        boxed(Backend::contiguous(self))
+    }
+
+    fn concat(
+        &self,
+        inputs: &[&dyn DynBackend],
+        axis: usize,
+    ) -> Result<Box<dyn DynBackend>, EinopsError> {
+        let inputs = inputs
+            .iter()
+            .map(|&input| downcast_input::<T>(input))
+            .collect::<Result<Vec<_>, _>>()?;
+        boxed(T::concat(&inputs, axis))
+    }
+
+    fn index_select(
+        &self,
+        axis: usize,
+        indices: &[usize],
+    ) -> Result<Box<dyn DynBackend>, EinopsError> {
+        boxed(Backend::index_select(self, axis, indices))
+    }
+
+    fn scan(&self, axis: usize, operation: ScanOp) -> Result<Box<dyn DynBackend>, EinopsError> {
+        boxed(Backend::scan(self, axis, operation))
+    }
+
+    fn contract(&self, rhs: &dyn DynBackend) -> Result<Box<dyn DynBackend>, EinopsError> {
+        boxed(Backend::contract(self, downcast_input::<T>(rhs)?))
+    }
+
+    fn dtype(&self) -> Option<DType> {
+        Backend::dtype(self)
+    }
+
+    fn to_dtype(&self, dtype: DType) -> Result<Box<dyn DynBackend>, EinopsError> {
+        boxed(Backend::to_dtype(self, dtype))
+    }
+
+    fn device(&self) -> Option<Device> {
+        Backend::device(self)
+    }
+
+    fn to_device(&self, device: Device) -> Result<Box<dyn DynBackend>, EinopsError> {
+        boxed(Backend::to_device(self, device))
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn into_any(self: Box<Self>) -> Box<dyn Any> {
+        self
+    }
+}
+
+impl Backend for Box<dyn DynBackend> {
+    type Output = Box<dyn DynBackend>;
+
+    fn shape(&self) -> Vec<usize> {
+        DynBackend::shape(&**self)
+    }
+
+    fn reshape(&self, shape: &[usize]) -> Result<Self::Output, EinopsError> {
+        DynBackend::reshape(&**self, shape)
+    }
+
+    fn transpose(&self, axes: &[usize]) -> Result<Self::Output, EinopsError> {
+        DynBackend::transpose(&**self, axes)
+    }
+
+    fn reshape_view(&self, shape: &[usize]) -> Option<Self::Output> {
+        DynBackend::reshape_view(&**self, shape)
+    }
+
+    fn transpose_view(&self, axes: &[usize]) -> Option<Self::Output> {
+        DynBackend::transpose_view(&**self, axes)
+    }
+
+    fn depth_to_space(&self, p1: usize, p2: usize) -> Option<Self::Output> {
+        DynBackend::depth_to_space(&**self, p1, p2)
+    }
+
+    fn space_to_depth(&self, p1: usize, p2: usize) -> Option<Self::Output> {
+        DynBackend::space_to_depth(&**self, p1, p2)
+    }
+
+    fn reduce_axes(
+        &self,
+        axes_operations: &mut [(usize, Operation)],
+    ) -> Result<Self::Output, EinopsError> {
+        DynBackend::reduce_axes(&**self, axes_operations)
+    }
+
+    fn add_axes(
+        &self,
+        naxes: usize,
+        pos2len: &[(usize, usize)],
+    ) -> Result<Self::Output, EinopsError> {
+        DynBackend::add_axes(&**self, naxes, pos2len)
+    }
+
+    fn narrow(&self, axis: usize, start: usize, len: usize) -> Result<Self::Output, EinopsError> {
+        DynBackend::narrow(&**self, axis, start, len)
+    }
+
+    fn broadcast(&self, shape: &[usize]) -> Result<Self::Output, EinopsError> {
+        DynBackend::broadcast(&**self, shape)
+    }
+
+    fn is_contiguous(&self) -> bool {
+        DynBackend::is_contiguous(&**self)
+    }
+
+    fn contiguous(&self) -> Result<Self::Output, EinopsError> {
+        DynBackend::contiguous(&**self)
+    }
+
+    fn concat(inputs: &[&Self], axis: usize) -> Result<Self::Output, EinopsError> {
+        let first = inputs
+            .first()
+            .ok_or_else(|| EinopsError::new("cannot concat an empty list of tensors"))?;
+        let inputs = inputs.iter().map(|input| &***input).collect::<Vec<_>>();
+        DynBackend::concat(&***first, &inputs, axis)
+    }
+
+    fn index_select(&self, axis: usize, indices: &[usize]) -> Result<Self::Output, EinopsError> {
+        DynBackend::index_select(&**self, axis, indices)
+    }
+
+    fn scan(&self, axis: usize, operation: ScanOp) -> Result<Self::Output, EinopsError> {
+        DynBackend::scan(&**self, axis, operation)
+    }
+
+    fn contract(&self, rhs: &Self) -> Result<Self::Output, EinopsError> {
+        DynBackend::contract(&**self, &**rhs)
+    }
+
+    fn dtype(&self) -> Option<DType> {
+        DynBackend::dtype(&**self)
+    }
+
+    fn to_dtype(&self, dtype: DType) -> Result<Self::Output, EinopsError> {
+        DynBackend::to_dtype(&**self, dtype)
+    }
+
+    fn device(&self) -> Option<Device> {
+        DynBackend::device(&**self)
+    }
+
+    fn to_device(&self, device: Device) -> Result<Self::Output, EinopsError> {
+        DynBackend::to_device(&**self, device)
+    }
+}
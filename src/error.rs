@@ -0,0 +1,562 @@
+use std::borrow::Cow;
+use std::fmt;
+use std::sync::Arc;
+
+use crate::Operation;
+
+/// Error returned when a transformation can't be applied to a tensor
+///
+/// Mistakes in the pattern itself, like a malformed pattern or an axis missing
+/// from the left side, are compile errors of `einops!` and don't show up here,
+/// only the layers of the `nn` feature read their patterns at runtime.
+/// Errors returned by `einops!` carry the pattern, the shape of the input and
+/// the lengths of its axes, which are included when the error is displayed.
+/// Errors about the input's axes pair every axis of the pattern with its
+/// length, marking the one that doesn't fit.
+/// Errors of the tensor library are kept as the [`source`](std::error::Error::source).
+///
+/// With the `fancy-errors` feature the error implements [`miette::Diagnostic`],
+/// axes that don't match their sizes are underlined in the pattern.
+///
+/// Errors compare equal when their kind and context are equal, the source isn't
+/// compared. Clones share the source.
+#[derive(Debug, Clone)]
+pub struct EinopsError(Box<Inner>);
+
+// Boxed to keep the `Result`s of backends small
+#[derive(Debug, Clone)]
+struct Inner {
+    kind: ErrorKind,
+    context: Option<Context>,
+    source: Option<Arc<dyn std::error::Error + Send + Sync + 'static>>,
+    // Pattern, offset and length of the axis the error is about
+    #[cfg_attr(not(feature = "fancy-errors"), allow(dead_code))]
+    label: Option<(Cow<'static, str>, usize, usize)>,
+}
+
+// Patterns of `einops!` are literals, the ones of layers are read at runtime
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Context {
+    patterns: Vec<Cow<'static, str>>,
+    // Axes on the left side of the first pattern, as written
+    axes: Vec<Cow<'static, str>>,
+    input_shape: Vec<usize>,
+    bindings: Vec<(Cow<'static, str>, usize)>,
+}
+
+/// What went wrong in an [`EinopsError`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ErrorKind {
+    /// Input axis `axis` was decomposed into `group`, whose axes have the
+    /// lengths `sizes` multiplying to `expected`, but has length `found`
+    ShapeMismatch {
+        axis: usize,
+        group: Cow<'static, str>,
+        sizes: Vec<(Cow<'static, str>, usize)>,
+        expected: usize,
+        found: usize,
+    },
+    /// Input axis `axis` of length `size` was decomposed into `group`, whose
+    /// axes other than the inferred one have the lengths `sizes` multiplying
+    /// to `divisor`, which doesn't divide `size`
+    NotDivisible {
+        axis: usize,
+        group: Cow<'static, str>,
+        sizes: Vec<(Cow<'static, str>, usize)>,
+        size: usize,
+        divisor: usize,
+    },
+    /// The input has `found` axes but the left side of the pattern has
+    /// `expected`, not counting `..` which stands for any number of axes
+    RankMismatch { expected: usize, found: usize },
+    /// The product of `lengths`, sizes from the pattern or the lengths of a
+    /// new shape, doesn't fit in a `usize`
+    Overflow { lengths: Vec<usize> },
+    /// Input axis `axis` has length 0 and `operation` has no value for it
+    /// under the [`policy::EmptyReduction`](crate::policy::EmptyReduction)
+    EmptyReduction { axis: usize, operation: Operation },
+    /// Input axis `axis`, named `name`, has length 1 and is broadcast to the
+    /// new axes grouped with it under the [`policy::Broadcast::Strict`](crate::policy::Broadcast::Strict)
+    /// policy
+    ImplicitBroadcast {
+        axis: usize,
+        name: Cow<'static, str>,
+    },
+    /// The backend failed to apply an operation
+    Backend(String),
+    /// The pattern of a layer parses but doesn't describe a valid
+    /// transformation
+    InvalidPattern {
+        pattern: Cow<'static, str>,
+        message: String,
+    },
+    /// The pattern of a layer can't be parsed at byte `offset`
+    ParseError {
+        pattern: Cow<'static, str>,
+        offset: usize,
+        reason: String,
+    },
+    /// Axis `name` of the right side of a pattern, or given a size, isn't on
+    /// the left side so its length is unknown
+    UnknownAxis { name: Cow<'static, str> },
+    /// The lengths of several axes of `group`, the `candidates`, would have to
+    /// be inferred from the length of one input axis
+    AmbiguousInference {
+        group: Cow<'static, str>,
+        candidates: Vec<Cow<'static, str>>,
+    },
+}
+
+impl EinopsError {
+    /// Creates a [`ErrorKind::Backend`] error with `message`
+    pub fn new(message: impl Into<String>) -> Self {
+        ErrorKind::Backend(message.into()).into()
+    }
+
+    /// Creates a [`ErrorKind::Backend`] error from an error of the tensor
+    /// library, which is kept as its source
+    pub fn wrap(error: impl std::error::Error + Send + Sync + 'static) -> Self {
+        let mut wrapped = Self::new(error.to_string());
+        wrapped.0.source = Some(Arc::new(error));
+        wrapped
+    }
+
+    pub fn kind(&self) -> &ErrorKind {
+        &self.0.kind
+    }
+
+    /// Stable code of the kind of error, see [`ErrorKind::code`]
+    pub fn code(&self) -> &'static str {
+        self.0.kind.code()
+    }
+
+    /// Patterns of the failed `einops!` call, more than one if it had nested
+    /// calls fused into it, innermost first
+    pub fn patterns(&self) -> Vec<&str> {
+        self.0.context.as_ref().map_or_else(Vec::new, |context| {
+            context.patterns.iter().map(|pattern| &**pattern).collect()
+        })
+    }
+
+    /// Shape of the tensor passed to the failed `einops!` call
+    pub fn input_shape(&self) -> Option<&[usize]> {
+        Some(&self.0.context.as_ref()?.input_shape)
+    }
+
+    /// Lengths of the named axes on the left side of the first pattern, as far
+    /// as they could be read from the input shape
+    pub fn bindings(&self) -> Vec<(&str, usize)> {
+        self.0.context.as_ref().map_or_else(Vec::new, |context| {
+            context
+                .bindings
+                .iter()
+                .map(|(name, len)| (&**name, *len))
+                .collect()
+        })
+    }
+
+    // Called by `einops!` for errors about a single axis of the pattern
+    #[doc(hidden)]
+    pub fn with_label(
+        mut self,
+        pattern: impl Into<Cow<'static, str>>,
+        offset: usize,
+        len: usize,
+    ) -> Self {
+        self.0.label = Some((pattern.into(), offset, len));
+        self
+    }
+
+    // Called by `einops!`, errors of inner calls keep their own context
+    #[doc(hidden)]
+    pub fn with_context(
+        self,
+        patterns: &'static [&'static str],
+        axes: &'static [&'static str],
+        input_shape: Vec<usize>,
+        bindings: Vec<(&'static str, usize)>,
+    ) -> Self {
+        if self.0.context.is_some() {
+            return self;
+        }
+        let borrowed = |texts: &'static [&'static str]| {
+            texts.iter().map(|&text| Cow::Borrowed(text)).collect()
+        };
+        self.with_layer_context(
+            borrowed(patterns),
+            borrowed(axes),
+            input_shape,
+            bindings
+                .into_iter()
+                .map(|(name, len)| (Cow::Borrowed(name), len))
+                .collect(),
+        )
+    }
+
+    // Layers build the axes from patterns read at runtime
+    pub(crate) fn with_layer_context(
+        mut self,
+        patterns: Vec<Cow<'static, str>>,
+        axes: Vec<Cow<'static, str>>,
+        input_shape: Vec<usize>,
+        bindings: Vec<(Cow<'static, str>, usize)>,
+    ) -> Self {
+        if self.0.context.is_none() {
+            self.0.context = Some(Context {
+                patterns,
+                axes,
+                input_shape,
+                bindings,
+            });
+        }
+        self
+    }
+}
+
+impl ErrorKind {
+    /// Stable code classifying the error, which doesn't change with its message
+    ///
+    /// | Code   | Kind                                         |
+    /// |--------|----------------------------------------------|
+    /// | `E001` | [`ErrorKind::InvalidPattern`]                |
+    /// | `E002` | [`ErrorKind::ShapeMismatch`]                 |
+    /// | `E003` | [`ErrorKind::NotDivisible`]                  |
+    /// | `E004` | [`ErrorKind::RankMismatch`]                  |
+    /// | `E005` | [`ErrorKind::Backend`]                       |
+    /// | `E006` | [`ErrorKind::Overflow`]                      |
+    /// | `E007` | [`ErrorKind::EmptyReduction`]                |
+    /// | `E008` | [`ErrorKind::ImplicitBroadcast`]             |
+    /// | `E009` | [`ErrorKind::ParseError`]                    |
+    /// | `E010` | [`ErrorKind::UnknownAxis`]                   |
+    /// | `E011` | [`ErrorKind::AmbiguousInference`]            |
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::ShapeMismatch { .. } => "E002",
+            Self::NotDivisible { .. } => "E003",
+            Self::RankMismatch { .. } => "E004",
+            Self::Backend(_) => "E005",
+            Self::Overflow { .. } => "E006",
+            Self::EmptyReduction { .. } => "E007",
+            Self::ImplicitBroadcast { .. } => "E008",
+            Self::InvalidPattern { .. } => "E001",
+            Self::ParseError { .. } => "E009",
+            Self::UnknownAxis { .. } => "E010",
+            Self::AmbiguousInference { .. } => "E011",
+        }
+    }
+}
+
+/// Product of `lengths`, or an [`ErrorKind::Overflow`] error if it doesn't fit
+/// in a `usize`
+#[doc(hidden)]
+pub fn checked_product(lengths: &[usize]) -> Result<usize, EinopsError> {
+    lengths
+        .iter()
+        .try_fold(1usize, |product, &len| product.checked_mul(len))
+        .ok_or_else(|| {
+            ErrorKind::Overflow {
+                lengths: lengths.to_vec(),
+            }
+            .into()
+        })
+}
+
+impl PartialEq for EinopsError {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.kind == other.0.kind
+            && self.0.context == other.0.context
+            && self.0.label == other.0.label
+    }
+}
+
+impl Eq for EinopsError {}
+
+impl From<ErrorKind> for EinopsError {
+    fn from(kind: ErrorKind) -> Self {
+        Self(Box::new(Inner {
+            kind,
+            context: None,
+            source: None,
+            label: None,
+        }))
+    }
+}
+
+fn write_sizes(f: &mut fmt::Formatter<'_>, sizes: &[(Cow<'static, str>, usize)]) -> fmt::Result {
+    for (i, (name, len)) in sizes.iter().enumerate() {
+        let separator = if i == 0 { " (" } else { ", " };
+        write!(f, "{}{} = {}", separator, name, len)?;
+    }
+    if !sizes.is_empty() {
+        f.write_str(")")?;
+    }
+    Ok(())
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::ShapeMismatch {
+                axis,
+                group,
+                sizes,
+                expected,
+                found,
+            } => {
+                write!(
+                    f,
+                    "cannot decompose axis {} of length {} into {}: expected length {}",
+                    axis, found, group, expected
+                )?;
+                write_sizes(f, sizes)
+            }
+            Self::NotDivisible {
+                axis,
+                group,
+                sizes,
+                size,
+                divisor,
+            } => {
+                write!(
+                    f,
+                    "cannot decompose axis {} of length {} into {}: {} is not divisible by {}",
+                    axis, size, group, size, divisor
+                )?;
+                write_sizes(f, sizes)
+            }
+            Self::RankMismatch { expected, found } => write!(
+                f,
+                "expected a tensor with {} axes, found {}",
+                expected, found
+            ),
+            Self::Overflow { lengths } => {
+                write!(f, "the product of the lengths {:?} overflows", lengths)
+            }
+            Self::EmptyReduction { axis, operation } => {
+                write!(f, "cannot take the {:?} of empty axis {}", operation, axis)
+            }
+            Self::ImplicitBroadcast { axis, name } => write!(
+                f,
+                "axis {} `{}` has length 1 and would be repeated implicitly, write it as `1` or `{}:1`",
+                axis, name, name
+            ),
+            Self::Backend(message) => f.write_str(message),
+            Self::InvalidPattern { pattern, message } => {
+                write!(f, "invalid pattern {:?}: {}", pattern, message)
+            }
+            Self::ParseError {
+                pattern,
+                offset,
+                reason,
+            } => write!(
+                f,
+                "cannot parse pattern {:?} at offset {}: {}",
+                pattern, offset, reason
+            ),
+            Self::UnknownAxis { name } => write!(
+                f,
+                "unknown axis `{}`, which isn't on the left side of the pattern",
+                name
+            ),
+            Self::AmbiguousInference { group, candidates } => {
+                f.write_str("the lengths of ")?;
+                for (i, name) in candidates.iter().enumerate() {
+                    let separator = match i {
+                        0 => "",
+                        i if i + 1 == candidates.len() => " and ",
+                        _ => ", ",
+                    };
+                    write!(f, "{}`{}`", separator, name)?;
+                }
+                write!(
+                    f,
+                    " in {} can't all be inferred, give all but one of them a size",
+                    group
+                )
+            }
+        }
+    }
+}
+
+// Pairs the axes of the pattern with the lengths of the input, marking the
+// ones the error is about, like `a = 6 ok, (b:2 c:3) = 5 expected 6`
+fn write_axes(f: &mut fmt::Formatter<'_>, kind: &ErrorKind, context: &Context) -> fmt::Result {
+    let Context {
+        axes, input_shape, ..
+    } = context;
+    let ellipsis = axes.iter().position(|axis| axis == "..");
+    let named = axes.len() - usize::from(ellipsis.is_some());
+    // Number of input axes standing for `..`
+    let ignored = match ellipsis {
+        Some(_) => input_shape.len().saturating_sub(named),
+        None => 0,
+    };
+
+    // Errors of fused outer patterns are about the axes of another pattern
+    let failed = match *kind {
+        ErrorKind::ShapeMismatch {
+            axis, ref group, ..
+        }
+        | ErrorKind::NotDivisible {
+            axis, ref group, ..
+        } => {
+            let position = match ellipsis {
+                Some(ellipsis) if axis >= ellipsis + ignored => axis + 1 - ignored,
+                _ => axis,
+            };
+            if axes.get(position) != Some(group) {
+                return Ok(());
+            }
+            Some(axis)
+        }
+        ErrorKind::RankMismatch { expected, found }
+            if expected == named && found == input_shape.len() =>
+        {
+            None
+        }
+        _ => return Ok(()),
+    };
+
+    let mut lengths = input_shape.iter().copied().enumerate();
+    for (i, name) in axes.iter().enumerate() {
+        f.write_str(if i == 0 { "\n  axes " } else { ", " })?;
+        if name == ".." {
+            let lengths = lengths.by_ref().take(ignored).map(|(_, len)| len);
+            write!(f, ".. = {:?} ok", lengths.collect::<Vec<_>>())?;
+            continue;
+        }
+        match (lengths.next(), kind) {
+            (Some((axis, len)), ErrorKind::ShapeMismatch { expected, .. })
+                if failed == Some(axis) =>
+            {
+                write!(f, "{} = {} expected {}", name, len, expected)?
+            }
+            (Some((axis, len)), ErrorKind::NotDivisible { divisor, .. })
+                if failed == Some(axis) =>
+            {
+                write!(f, "{} = {} not divisible by {}", name, len, divisor)?
+            }
+            (Some((_, len)), _) => write!(f, "{} = {} ok", name, len)?,
+            (None, _) => write!(f, "{} missing", name)?,
+        }
+    }
+    for (axis, len) in lengths {
+        write!(f, ", axis {} = {} extra", axis, len)?;
+    }
+    Ok(())
+}
+
+impl fmt::Display for EinopsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "einops error: {}", self.0.kind)?;
+        if let Some(context) = &self.0.context {
+            for (i, pattern) in context.patterns.iter().enumerate() {
+                let separator = if i == 0 { "\n  in pattern" } else { " then" };
+                write!(f, "{} {:?}", separator, pattern)?;
+            }
+            write!(f, "\n  input shape {:?}", context.input_shape)?;
+            write_axes(f, &self.0.kind, context)?;
+            for (i, (name, len)) in context.bindings.iter().enumerate() {
+                let separator = if i == 0 { "\n  with" } else { "," };
+                write!(f, "{} {} = {}", separator, name, len)?;
+            }
+        } else if let (
+            ErrorKind::UnknownAxis { .. } | ErrorKind::AmbiguousInference { .. },
+            Some((pattern, _, _)),
+        ) = (&self.0.kind, &self.0.label)
+        {
+            write!(f, "\n  in pattern {:?}", pattern)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for EinopsError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        self.0
+            .source
+            .as_deref()
+            .map(|source| source as &(dyn std::error::Error + 'static))
+    }
+}
+
+#[cfg(feature = "fancy-errors")]
+impl miette::Diagnostic for EinopsError {
+    fn code<'a>(&'a self) -> Option<Box<dyn fmt::Display + 'a>> {
+        Some(Box::new(self.code()))
+    }
+
+    fn help<'a>(&'a self) -> Option<Box<dyn fmt::Display + 'a>> {
+        let input_shape = self.input_shape()?;
+        Some(Box::new(format!("the input has shape {:?}", input_shape)))
+    }
+
+    fn source_code(&self) -> Option<&dyn miette::SourceCode> {
+        match &self.0.label {
+            Some((pattern, _, _)) => Some(pattern),
+            None => self
+                .0
+                .context
+                .as_ref()?
+                .patterns
+                .first()
+                .map(|pattern| pattern as &dyn miette::SourceCode),
+        }
+    }
+
+    fn labels(&self) -> Option<Box<dyn Iterator<Item = miette::LabeledSpan> + '_>> {
+        let &(_, offset, len) = self.0.label.as_ref()?;
+        let text = match self.0.kind {
+            ErrorKind::ShapeMismatch {
+                expected, found, ..
+            } => format!("expected length {}, found {}", expected, found),
+            ErrorKind::NotDivisible { size, divisor, .. } => {
+                format!("length {} is not divisible by {}", size, divisor)
+            }
+            ErrorKind::Overflow { .. } => "overflows".to_string(),
+            ErrorKind::ParseError { ref reason, .. } => reason.clone(),
+            ErrorKind::UnknownAxis { .. } => "unknown axis".to_string(),
+            ErrorKind::AmbiguousInference { .. } => "more than one length to infer".to_string(),
+            ErrorKind::RankMismatch { .. }
+            | ErrorKind::EmptyReduction { .. }
+            | ErrorKind::ImplicitBroadcast { .. }
+            | ErrorKind::Backend(_)
+            | ErrorKind::InvalidPattern { .. } => return None,
+        };
+        Some(Box::new(std::iter::once(miette::LabeledSpan::new(
+            Some(text),
+            offset,
+            len,
+        ))))
+    }
+}
+
+#[cfg(feature = "candle")]
+impl From<candle_core::Error> for EinopsError {
+    fn from(error: candle_core::Error) -> Self {
+        Self::wrap(error)
+    }
+}
+
+// Lets `einops!(..)?` be used inside functions returning `candle_core::Result`
+#[cfg(feature = "candle")]
+impl From<EinopsError> for candle_core::Error {
+    fn from(error: EinopsError) -> Self {
+        candle_core::Error::wrap(error)
+    }
+}
+
+#[cfg(feature = "tch")]
+impl From<tch::TchError> for EinopsError {
+    fn from(error: tch::TchError) -> Self {
+        Self::wrap(error)
+    }
+}
+
+#[cfg(feature = "ndarray")]
+impl From<ndarray::ShapeError> for EinopsError {
+    fn from(error: ndarray::ShapeError) -> Self {
+        Self::wrap(error)
+    }
+}
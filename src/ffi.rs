@@ -0,0 +1,200 @@
+//! C API of the runtime patterns, for runtimes embedding this crate in a
+//! `staticlib` or `cdylib`
+//!
+//! A recipe is compiled once from its pattern, then applied to row-major `f32`
+//! buffers. Functions returning an `int` return `0` on success and `-1` on
+//! errors, whose message is read with [`einops_last_error`].
+//!
+//! ```c
+//! EinopsRecipe *recipe = einops_recipe_new("b c h w -> b h w c", EINOPS_REARRANGE);
+//! size_t shape[4] = {2, 3, 4, 4}, rank;
+//! einops_output_shape(recipe, shape, 4, out_shape, 4, &rank);
+//! einops_apply_f32(recipe, input, shape, 4, output, 96);
+//! einops_recipe_free(recipe);
+//! ```
+//!
+//! `python/candle_einops.py` binds these functions for Python with ctypes.
+
+use std::cell::RefCell;
+use std::ffi::{c_char, c_int, CStr, CString};
+
+use crate::recipe::{Cache, Kind, Recipe};
+use crate::{EinopsError, Operation};
+
+/// Kind of [`einops_recipe_new`] rearranging its input
+pub const EINOPS_REARRANGE: u32 = 0;
+/// Kind of [`einops_recipe_new`] repeating its input along new axes
+pub const EINOPS_REPEAT: u32 = 1;
+/// Kinds of [`einops_recipe_new`] reducing the axes missing on the right side
+pub const EINOPS_REDUCE_MIN: u32 = 2;
+pub const EINOPS_REDUCE_MAX: u32 = 3;
+pub const EINOPS_REDUCE_SUM: u32 = 4;
+pub const EINOPS_REDUCE_MEAN: u32 = 5;
+
+/// A compiled pattern, owned by the caller until [`einops_recipe_free`]
+pub struct EinopsRecipe(Recipe, Cache);
+
+thread_local! {
+    static LAST_ERROR: RefCell<CString> = RefCell::new(CString::default());
+}
+
+fn fail(error: impl ToString) -> c_int {
+    let message = error.to_string().replace('\0', " ");
+    LAST_ERROR.with(|last| *last.borrow_mut() = CString::new(message).unwrap_or_default());
+    -1
+}
+
+/// Message of the last error of this thread, valid until the next call
+/// failing on this thread
+#[no_mangle]
+pub extern "C" fn einops_last_error() -> *const c_char {
+    LAST_ERROR.with(|last| last.borrow().as_ptr())
+}
+
+/// Compiles `pattern` as a recipe of `kind`, or returns null on errors
+///
+/// Sizes of axes are written in the pattern, like `b (h w:2) -> b h w`. The
+/// pattern is copied into the recipe, so it can be freed afterwards.
+///
+/// # Safety
+///
+/// `pattern` is a valid nul-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn einops_recipe_new(pattern: *const c_char, kind: u32) -> *mut EinopsRecipe {
+    if pattern.is_null() {
+        fail("the pattern is null");
+        return std::ptr::null_mut();
+    }
+    let pattern = match CStr::from_ptr(pattern).to_str() {
+        Ok(pattern) => pattern,
+        Err(error) => {
+            fail(format!("the pattern isn't UTF-8: {}", error));
+            return std::ptr::null_mut();
+        }
+    };
+    let kind = match kind {
+        EINOPS_REARRANGE => Kind::Rearrange,
+        EINOPS_REPEAT => Kind::Repeat,
+        EINOPS_REDUCE_MIN => Kind::Reduce(Operation::Min),
+        EINOPS_REDUCE_MAX => Kind::Reduce(Operation::Max),
+        EINOPS_REDUCE_SUM => Kind::Reduce(Operation::Sum),
+        EINOPS_REDUCE_MEAN => Kind::Reduce(Operation::Mean),
+        _ => {
+            fail(format!("unknown recipe kind {}", kind));
+            return std::ptr::null_mut();
+        }
+    };
+    match Recipe::new(pattern, &[], kind) {
+        Ok(recipe) => Box::into_raw(Box::new(EinopsRecipe(recipe, Cache::default()))),
+        Err(error) => {
+            fail(error);
+            std::ptr::null_mut()
+        }
+    }
+}
+
+/// Frees a recipe of [`einops_recipe_new`], null is ignored
+///
+/// # Safety
+///
+/// `recipe` was returned by [`einops_recipe_new`] and isn't used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn einops_recipe_free(recipe: *mut EinopsRecipe) {
+    if !recipe.is_null() {
+        drop(Box::from_raw(recipe));
+    }
+}
+
+unsafe fn slice<'a, T>(data: *const T, len: usize) -> Result<&'a [T], EinopsError> {
+    match (data.is_null(), len) {
+        (_, 0) => Ok(&[]),
+        (true, _) => Err(EinopsError::new("the buffer is null")),
+        (false, _) => Ok(std::slice::from_raw_parts(data, len)),
+    }
+}
+
+unsafe fn slice_mut<'a, T>(data: *mut T, len: usize) -> Result<&'a mut [T], EinopsError> {
+    match (data.is_null(), len) {
+        (_, 0) => Ok(&mut []),
+        (true, _) => Err(EinopsError::new("the buffer is null")),
+        (false, _) => Ok(std::slice::from_raw_parts_mut(data, len)),
+    }
+}
+
+unsafe fn recipe<'a>(recipe: *const EinopsRecipe) -> Result<&'a EinopsRecipe, EinopsError> {
+    recipe
+        .as_ref()
+        .ok_or_else(|| EinopsError::new("the recipe is null"))
+}
+
+/// Writes the shape of the output for an input of shape `shape[..rank]` to
+/// `output_shape`, which has room for `capacity` axes, and its rank to
+/// `output_rank`
+///
+/// # Safety
+///
+/// `recipe` is a live recipe, the buffers have the given lengths and
+/// `output_rank` is valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn einops_output_shape(
+    recipe: *const EinopsRecipe,
+    shape: *const usize,
+    rank: usize,
+    output_shape: *mut usize,
+    capacity: usize,
+    output_rank: *mut usize,
+) -> c_int {
+    let run = || -> Result<(), EinopsError> {
+        let (_, shape) = self::recipe(recipe)?.0.shapes(slice(shape, rank)?)?;
+        if shape.len() > capacity {
+            return Err(EinopsError::new(format!(
+                "the output has {} axes, there is room for {}",
+                shape.len(),
+                capacity
+            )));
+        }
+        slice_mut(output_shape, shape.len())?.copy_from_slice(&shape);
+        match output_rank.as_mut() {
+            Some(output_rank) => *output_rank = shape.len(),
+            None => return Err(EinopsError::new("the output rank is null")),
+        }
+        Ok(())
+    };
+    match run() {
+        Ok(()) => 0,
+        Err(error) => fail(error),
+    }
+}
+
+/// Applies `recipe` to the row-major `input` of shape `shape[..rank]`,
+/// writing the output to `output` of `output_len` elements, which has to be
+/// its exact length
+///
+/// Recipes without reductions gather the output from `input` directly,
+/// without allocating tensors in between.
+///
+/// # Safety
+///
+/// `recipe` is a live recipe and the buffers have the lengths given by the
+/// shapes.
+#[no_mangle]
+pub unsafe extern "C" fn einops_apply_f32(
+    recipe: *const EinopsRecipe,
+    input: *const f32,
+    shape: *const usize,
+    rank: usize,
+    output: *mut f32,
+    output_len: usize,
+) -> c_int {
+    let run = || -> Result<(), EinopsError> {
+        let shape = slice(shape, rank)?;
+        let input = slice(input, crate::checked_product(shape)?)?;
+        let EinopsRecipe(recipe, cache) = self::recipe(recipe)?;
+        let output = slice_mut(output, output_len)?;
+        cache.apply_into(std::slice::from_ref(recipe), input, shape, output)
+    };
+    match run() {
+        Ok(()) => 0,
+        Err(error) => fail(error),
+    }
+}
@@ -0,0 +1,1073 @@
+//! Transformations as [`candle_nn::Module`]s, to place them in models like
+//! any other layer
+//!
+//! Unlike `einops!`, layers read their pattern at runtime. It's checked when
+//! the layer is built, mistakes are [`ErrorKind::ParseError`](crate::ErrorKind::ParseError),
+//! [`ErrorKind::UnknownAxis`](crate::ErrorKind::UnknownAxis) or
+//! [`ErrorKind::InvalidPattern`](crate::ErrorKind::InvalidPattern) errors. Patterns use the syntax of `einops!` without reductions, literal
+//! lengths, `{..}` sizes and `..` inside groups, the sizes of axes can be
+//! written in the pattern or given to the constructor, or one by one to a
+//! [`Builder`]. [`Reduce`] applies one
+//! operation to all axes missing on the right side, [`Repeat`] adds the axes
+//! missing on the left side and [`EinMix`] multiplies the input with a
+//! learned weight.
+//!
+//! ```ignore
+//! let model = candle_nn::seq()
+//!     .add(Rearrange::new("b c (h p1) (w p2) -> b (h w) (p1 p2 c)", &[("p1", 16), ("p2", 16)])?)
+//!     .add(linear)
+//!     .add(Reduce::new("b t c -> b c", Operation::Mean)?);
+//! ```
+//!
+//! Every layer has an `output_shape` method, to get the shape of its output
+//! without running it, and a `steps` method counting the backend operations
+//! it runs. Consecutive reshapes and transposes are merged and the ones that
+//! leave the tensor unchanged are dropped, a [`Chain`] of layers does so
+//! across its layers. Layers keep the steps planned for the shape of their
+//! last input, applying them to inputs of the same shape only compares the
+//! shapes and allocates nothing besides what the backend allocates. [`Inputs`] checks that the shapes of several inputs of
+//! a layer agree on the lengths of their shared axes, and a [`Registry`]
+//! hands out integer handles of layers, to apply them without looking up
+//! their pattern.
+
+use std::collections::HashMap;
+use std::marker::PhantomData;
+use std::sync::Arc;
+
+use candle_core::Tensor;
+use candle_nn::{Init, VarBuilder};
+
+#[cfg(feature = "cpu")]
+use crate::cpu::{CpuTensor, Element};
+use crate::policy::{Broadcast, EmptyReduction};
+pub use crate::recipe::Order;
+use crate::recipe::{
+    bind_inputs, invalid, names, parse_inputs, parse_side, Cache, Item, Kind, Program, Recipe,
+};
+use crate::trace::Hook;
+use crate::{Backend, Device, EinopsError, Operation};
+
+/// Pattern and sizes of axes of a layer of type `L`, which is checked by
+/// `build`
+///
+/// ```ignore
+/// let layer = Rearrange::builder("b (h w) c -> b h w c").size("h", 16).build()?;
+/// ```
+#[derive(Debug, Clone)]
+pub struct Builder<L> {
+    pattern: String,
+    sizes: Vec<(String, usize)>,
+    kind: Kind,
+    broadcast: Broadcast,
+    empty_reduction: EmptyReduction,
+    layer: PhantomData<fn() -> L>,
+}
+
+impl<L> Builder<L> {
+    fn new(pattern: &str, kind: Kind) -> Self {
+        Self {
+            pattern: pattern.to_string(),
+            sizes: Vec::new(),
+            kind,
+            broadcast: Broadcast::Implicit,
+            empty_reduction: EmptyReduction::Identity,
+            layer: PhantomData,
+        }
+    }
+
+    /// Gives `axis` the length `len`, for axes decomposed from groups or new
+    /// axes
+    pub fn size(mut self, axis: &str, len: usize) -> Self {
+        self.sizes.push((axis.to_string(), len));
+        self
+    }
+
+    fn sizes(mut self, sizes: &[(&str, usize)]) -> Self {
+        for &(axis, len) in sizes {
+            self = self.size(axis, len);
+        }
+        self
+    }
+
+    fn recipe(&self) -> Result<Recipe, EinopsError> {
+        let sizes = self
+            .sizes
+            .iter()
+            .map(|(axis, len)| (axis.as_str(), *len))
+            .collect::<Vec<_>>();
+        let mut recipe = Recipe::new(&self.pattern, &sizes, self.kind)?;
+        recipe.set_broadcast(self.broadcast);
+        recipe.set_empty_reduction(self.empty_reduction);
+        Ok(recipe)
+    }
+}
+
+impl Builder<Rearrange> {
+    pub fn build(&self) -> Result<Rearrange, EinopsError> {
+        Ok(Rearrange {
+            recipe: self.recipe()?,
+            cache: Cache::default(),
+        })
+    }
+}
+
+impl Builder<Reduce> {
+    /// Picks what reducing an axis of length 0 returns,
+    /// [`EmptyReduction::Error`] rejects every such reduction when the layer
+    /// is applied
+    pub fn empty_reduction(mut self, policy: EmptyReduction) -> Self {
+        self.empty_reduction = policy;
+        self
+    }
+
+    pub fn build(&self) -> Result<Reduce, EinopsError> {
+        Ok(Reduce {
+            recipe: self.recipe()?,
+            cache: Cache::default(),
+        })
+    }
+}
+
+impl Builder<Repeat> {
+    /// Picks how input axes of length 1 broadcast to the new axes are
+    /// treated, [`Broadcast::Strict`] rejects them when the layer is applied
+    /// and validated
+    pub fn broadcast(mut self, policy: Broadcast) -> Self {
+        self.broadcast = policy;
+        self
+    }
+
+    pub fn build(&self) -> Result<Repeat, EinopsError> {
+        Ok(Repeat {
+            recipe: self.recipe()?,
+            cache: Cache::default(),
+        })
+    }
+}
+
+/// Rearranges the axes of its input, like `einops.layers.torch.Rearrange`
+#[derive(Debug, Clone)]
+pub struct Rearrange {
+    recipe: Recipe,
+    cache: Cache,
+}
+
+impl Rearrange {
+    /// Parses `pattern`, with the sizes of axes that aren't written in it
+    pub fn new(pattern: &str, sizes: &[(&str, usize)]) -> Result<Self, EinopsError> {
+        Self::builder(pattern).sizes(sizes).build()
+    }
+
+    pub fn builder(pattern: &str) -> Builder<Self> {
+        Builder::new(pattern, Kind::Rearrange)
+    }
+
+    pub fn pattern(&self) -> &str {
+        self.recipe.pattern()
+    }
+
+    /// Shape of the output for an input of shape `input_shape`, which is
+    /// checked like in [`Rearrange::apply`]
+    pub fn output_shape(&self, input_shape: &[usize]) -> Result<Vec<usize>, EinopsError> {
+        Ok(self.recipe.shapes(input_shape)?.1)
+    }
+
+    /// Every problem of an input of shape `input_shape` rather than the first,
+    /// empty for a valid input
+    ///
+    /// A wrong rank is reported alone, otherwise every axis that doesn't fit
+    /// its group is reported.
+    pub fn validate(&self, input_shape: &[usize]) -> Vec<EinopsError> {
+        self.recipe.validate(input_shape)
+    }
+
+    /// Number of backend operations for a contiguous input of shape
+    /// `input_shape`
+    pub fn steps(&self, input_shape: &[usize]) -> Result<Steps, EinopsError> {
+        Program::compile(std::slice::from_ref(&self.recipe), input_shape).map(Steps::of)
+    }
+
+    /// Number of backend operations for `input`, with the [`Order`] picked
+    /// for its layout
+    pub fn steps_for<T: Backend<Output = T>>(&self, input: &T) -> Result<Steps, EinopsError> {
+        Program::compile_for(std::slice::from_ref(&self.recipe), input).map(Steps::of)
+    }
+
+    /// Copies of the data for a contiguous input of shape `input_shape` at
+    /// most, see [`assert_max_copies!`](crate::assert_max_copies)
+    pub fn max_copies(&self, input_shape: &[usize]) -> Result<usize, EinopsError> {
+        Ok(Program::compile(std::slice::from_ref(&self.recipe), input_shape)?.max_copies())
+    }
+
+    /// Calls `hook` before and after every operation the layer runs, instead
+    /// of the hook registered with [`set_hook`](crate::trace::set_hook)
+    pub fn with_hook(mut self, hook: Arc<dyn Hook>) -> Self {
+        self.cache.set_hook(hook);
+        self
+    }
+
+    /// Rearranges `input` of any backend
+    pub fn apply<T: Backend<Output = T> + Clone>(&self, input: &T) -> Result<T, EinopsError> {
+        self.cache.apply(std::slice::from_ref(&self.recipe), input)
+    }
+
+    /// Rearranges the row-major `input`, writing the output to `output`,
+    /// which has the length of [`Rearrange::output_shape`]
+    #[cfg(feature = "cpu")]
+    pub fn apply_into<T: Element>(
+        &self,
+        input: &CpuTensor<T>,
+        output: &mut [T],
+    ) -> Result<(), EinopsError> {
+        self.cache.apply_into(
+            std::slice::from_ref(&self.recipe),
+            input.data(),
+            input.shape(),
+            output,
+        )
+    }
+
+    /// Rearranges every tensor of `inputs`, planning once for inputs of the same
+    /// shape
+    pub fn apply_batch<T: Backend<Output = T> + Clone>(
+        &self,
+        inputs: &[T],
+    ) -> Result<Vec<T>, EinopsError> {
+        self.cache
+            .apply_batch(std::slice::from_ref(&self.recipe), inputs)
+    }
+}
+
+impl candle_nn::Module for Rearrange {
+    fn forward(&self, xs: &Tensor) -> candle_core::Result<Tensor> {
+        Ok(self.apply(xs)?)
+    }
+}
+
+/// Reduces the axes of its input missing on the right side of the pattern with
+/// one operation, like `einops.layers.torch.Reduce`
+///
+/// ```ignore
+/// // Global average pooling
+/// let pool = Reduce::new("b c h w -> b c", Operation::Mean)?;
+/// ```
+#[derive(Debug, Clone)]
+pub struct Reduce {
+    recipe: Recipe,
+    cache: Cache,
+}
+
+impl Reduce {
+    pub fn new(pattern: &str, operation: Operation) -> Result<Self, EinopsError> {
+        Self::builder(pattern, operation).build()
+    }
+
+    pub fn builder(pattern: &str, operation: Operation) -> Builder<Self> {
+        Builder::new(pattern, Kind::Reduce(operation))
+    }
+
+    pub fn pattern(&self) -> &str {
+        self.recipe.pattern()
+    }
+
+    pub fn operation(&self) -> Operation {
+        self.recipe
+            .reduction()
+            .expect("reduce layers have an operation")
+    }
+
+    /// Shape of the output for an input of shape `input_shape`, which is
+    /// checked like in [`Reduce::apply`]
+    pub fn output_shape(&self, input_shape: &[usize]) -> Result<Vec<usize>, EinopsError> {
+        Ok(self.recipe.shapes(input_shape)?.1)
+    }
+
+    /// Every problem of an input of shape `input_shape` rather than the first,
+    /// empty for a valid input
+    ///
+    /// A wrong rank is reported alone, otherwise every axis that doesn't fit
+    /// its group is reported.
+    pub fn validate(&self, input_shape: &[usize]) -> Vec<EinopsError> {
+        self.recipe.validate(input_shape)
+    }
+
+    /// Number of backend operations for a contiguous input of shape
+    /// `input_shape`
+    pub fn steps(&self, input_shape: &[usize]) -> Result<Steps, EinopsError> {
+        Program::compile(std::slice::from_ref(&self.recipe), input_shape).map(Steps::of)
+    }
+
+    /// Number of backend operations for `input`, with the [`Order`] picked
+    /// for its layout
+    pub fn steps_for<T: Backend<Output = T>>(&self, input: &T) -> Result<Steps, EinopsError> {
+        Program::compile_for(std::slice::from_ref(&self.recipe), input).map(Steps::of)
+    }
+
+    /// Copies of the data for a contiguous input of shape `input_shape` at
+    /// most, see [`assert_max_copies!`](crate::assert_max_copies)
+    pub fn max_copies(&self, input_shape: &[usize]) -> Result<usize, EinopsError> {
+        Ok(Program::compile(std::slice::from_ref(&self.recipe), input_shape)?.max_copies())
+    }
+
+    /// Calls `hook` before and after every operation the layer runs, instead
+    /// of the hook registered with [`set_hook`](crate::trace::set_hook)
+    pub fn with_hook(mut self, hook: Arc<dyn Hook>) -> Self {
+        self.cache.set_hook(hook);
+        self
+    }
+
+    /// Reduces `input` of any backend
+    pub fn apply<T: Backend<Output = T> + Clone>(&self, input: &T) -> Result<T, EinopsError> {
+        self.cache.apply(std::slice::from_ref(&self.recipe), input)
+    }
+
+    /// Reduces the row-major `input`, writing the output to `output`,
+    /// which has the length of [`Reduce::output_shape`]
+    #[cfg(feature = "cpu")]
+    pub fn apply_into<T: Element>(
+        &self,
+        input: &CpuTensor<T>,
+        output: &mut [T],
+    ) -> Result<(), EinopsError> {
+        self.cache.apply_into(
+            std::slice::from_ref(&self.recipe),
+            input.data(),
+            input.shape(),
+            output,
+        )
+    }
+
+    /// Reduces every tensor of `inputs`, planning once for inputs of the same
+    /// shape
+    pub fn apply_batch<T: Backend<Output = T> + Clone>(
+        &self,
+        inputs: &[T],
+    ) -> Result<Vec<T>, EinopsError> {
+        self.cache
+            .apply_batch(std::slice::from_ref(&self.recipe), inputs)
+    }
+}
+
+impl candle_nn::Module for Reduce {
+    fn forward(&self, xs: &Tensor) -> candle_core::Result<Tensor> {
+        Ok(self.apply(xs)?)
+    }
+}
+
+/// Repeats its input along the axes missing on the left side of the pattern,
+/// like `einops.layers.torch.Repeat`
+///
+/// Every new axis needs a size, written in the pattern or given to the
+/// constructor.
+///
+/// ```ignore
+/// // Copies a class token of shape [1, d] for a batch of 8
+/// let expand = Repeat::new("() d -> b () d", &[("b", 8)])?;
+/// ```
+#[derive(Debug, Clone)]
+pub struct Repeat {
+    recipe: Recipe,
+    cache: Cache,
+}
+
+impl Repeat {
+    pub fn new(pattern: &str, sizes: &[(&str, usize)]) -> Result<Self, EinopsError> {
+        Self::builder(pattern).sizes(sizes).build()
+    }
+
+    pub fn builder(pattern: &str) -> Builder<Self> {
+        Builder::new(pattern, Kind::Repeat)
+    }
+
+    pub fn pattern(&self) -> &str {
+        self.recipe.pattern()
+    }
+
+    /// Shape of the output for an input of shape `input_shape`, which is
+    /// checked like in [`Repeat::apply`]
+    pub fn output_shape(&self, input_shape: &[usize]) -> Result<Vec<usize>, EinopsError> {
+        Ok(self.recipe.shapes(input_shape)?.1)
+    }
+
+    /// Every problem of an input of shape `input_shape` rather than the first,
+    /// empty for a valid input
+    ///
+    /// A wrong rank is reported alone, otherwise every axis that doesn't fit
+    /// its group is reported.
+    pub fn validate(&self, input_shape: &[usize]) -> Vec<EinopsError> {
+        self.recipe.validate(input_shape)
+    }
+
+    /// Number of backend operations for a contiguous input of shape
+    /// `input_shape`
+    pub fn steps(&self, input_shape: &[usize]) -> Result<Steps, EinopsError> {
+        Program::compile(std::slice::from_ref(&self.recipe), input_shape).map(Steps::of)
+    }
+
+    /// Number of backend operations for `input`, with the [`Order`] picked
+    /// for its layout
+    pub fn steps_for<T: Backend<Output = T>>(&self, input: &T) -> Result<Steps, EinopsError> {
+        Program::compile_for(std::slice::from_ref(&self.recipe), input).map(Steps::of)
+    }
+
+    /// Copies of the data for a contiguous input of shape `input_shape` at
+    /// most, see [`assert_max_copies!`](crate::assert_max_copies)
+    pub fn max_copies(&self, input_shape: &[usize]) -> Result<usize, EinopsError> {
+        Ok(Program::compile(std::slice::from_ref(&self.recipe), input_shape)?.max_copies())
+    }
+
+    /// Calls `hook` before and after every operation the layer runs, instead
+    /// of the hook registered with [`set_hook`](crate::trace::set_hook)
+    pub fn with_hook(mut self, hook: Arc<dyn Hook>) -> Self {
+        self.cache.set_hook(hook);
+        self
+    }
+
+    /// Repeats `input` of any backend
+    pub fn apply<T: Backend<Output = T> + Clone>(&self, input: &T) -> Result<T, EinopsError> {
+        self.cache.apply(std::slice::from_ref(&self.recipe), input)
+    }
+
+    /// Repeats the row-major `input`, writing the output to `output`,
+    /// which has the length of [`Repeat::output_shape`]
+    #[cfg(feature = "cpu")]
+    pub fn apply_into<T: Element>(
+        &self,
+        input: &CpuTensor<T>,
+        output: &mut [T],
+    ) -> Result<(), EinopsError> {
+        self.cache.apply_into(
+            std::slice::from_ref(&self.recipe),
+            input.data(),
+            input.shape(),
+            output,
+        )
+    }
+
+    /// Repeats every tensor of `inputs`, planning once for inputs of the same
+    /// shape
+    pub fn apply_batch<T: Backend<Output = T> + Clone>(
+        &self,
+        inputs: &[T],
+    ) -> Result<Vec<T>, EinopsError> {
+        self.cache
+            .apply_batch(std::slice::from_ref(&self.recipe), inputs)
+    }
+}
+
+impl candle_nn::Module for Repeat {
+    fn forward(&self, xs: &Tensor) -> candle_core::Result<Tensor> {
+        Ok(self.apply(xs)?)
+    }
+}
+
+/// Backend operations of a layer for one input shape
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Steps {
+    /// One for every reshape, reduction, transpose and insertion of new axes
+    /// of the patterns
+    pub planned: usize,
+    /// Left after merging consecutive reshapes and transposes and dropping the
+    /// ones that leave the tensor unchanged, which are the ones that run
+    pub optimized: usize,
+    /// Whether the input is reshaped or transposed first
+    pub order: Order,
+    /// Device the order was picked for, unknown for an input shape
+    pub device: Option<Device>,
+}
+
+impl Steps {
+    fn of(program: Program) -> Self {
+        Self {
+            planned: program.planned(),
+            optimized: program.optimized(),
+            order: program.order(),
+            device: program.device(),
+        }
+    }
+}
+
+/// [`Rearrange`], [`Reduce`] and [`Repeat`] layers applied one after the other
+///
+/// The steps of all layers are simplified together, so a transpose followed by
+/// its inverse in the next layer doesn't run at all.
+///
+/// ```ignore
+/// let chain = Chain::from(Rearrange::new("b (h w) c -> b c h w", &[("h", 16)])?)
+///     .then(Rearrange::new("b c h w -> b (h w) c", &[])?);
+/// assert_eq!(chain.steps(&[2, 256, 3])?.optimized, 0);
+/// ```
+#[derive(Debug, Clone)]
+pub struct Chain {
+    recipes: Vec<Recipe>,
+    cache: Cache,
+}
+
+impl Chain {
+    /// Applies `layers` after the layers of `self`
+    pub fn then(mut self, layers: impl Into<Chain>) -> Self {
+        self.recipes.extend(layers.into().recipes);
+        self.cache = self.cache.cleared();
+        self
+    }
+
+    /// Patterns of the layers, in the order they're applied
+    pub fn patterns(&self) -> Vec<&str> {
+        self.recipes.iter().map(Recipe::pattern).collect()
+    }
+
+    /// Shape of the output for an input of shape `input_shape`, which is
+    /// checked like in [`Chain::apply`]
+    pub fn output_shape(&self, input_shape: &[usize]) -> Result<Vec<usize>, EinopsError> {
+        Ok(Program::compile(&self.recipes, input_shape)?
+            .output_shape()
+            .to_vec())
+    }
+
+    /// Every problem of an input of shape `input_shape` with the first layer
+    /// it doesn't fit, empty for a valid input
+    pub fn validate(&self, input_shape: &[usize]) -> Vec<EinopsError> {
+        let mut shape = input_shape.to_vec();
+        for recipe in &self.recipes {
+            let errors = recipe.validate(&shape);
+            if !errors.is_empty() {
+                return errors;
+            }
+            shape = recipe.shapes(&shape).expect("the shape was validated").1;
+        }
+        Vec::new()
+    }
+
+    /// Number of backend operations for a contiguous input of shape
+    /// `input_shape`
+    pub fn steps(&self, input_shape: &[usize]) -> Result<Steps, EinopsError> {
+        Program::compile(&self.recipes, input_shape).map(Steps::of)
+    }
+
+    /// Number of backend operations for `input`, with the [`Order`] picked
+    /// for its layout
+    pub fn steps_for<T: Backend<Output = T>>(&self, input: &T) -> Result<Steps, EinopsError> {
+        Program::compile_for(&self.recipes, input).map(Steps::of)
+    }
+
+    /// Copies of the data for a contiguous input of shape `input_shape` at
+    /// most, see [`assert_max_copies!`](crate::assert_max_copies)
+    pub fn max_copies(&self, input_shape: &[usize]) -> Result<usize, EinopsError> {
+        Ok(Program::compile(&self.recipes, input_shape)?.max_copies())
+    }
+
+    /// Calls `hook` before and after every operation the layer runs, instead
+    /// of the hook registered with [`set_hook`](crate::trace::set_hook)
+    pub fn with_hook(mut self, hook: Arc<dyn Hook>) -> Self {
+        self.cache.set_hook(hook);
+        self
+    }
+
+    /// Applies the layers to `input` of any backend
+    pub fn apply<T: Backend<Output = T> + Clone>(&self, input: &T) -> Result<T, EinopsError> {
+        self.cache.apply(&self.recipes, input)
+    }
+
+    /// Applies the layers to the row-major `input`, writing the output to `output`,
+    /// which has the length of [`Chain::output_shape`]
+    #[cfg(feature = "cpu")]
+    pub fn apply_into<T: Element>(
+        &self,
+        input: &CpuTensor<T>,
+        output: &mut [T],
+    ) -> Result<(), EinopsError> {
+        self.cache
+            .apply_into(&self.recipes, input.data(), input.shape(), output)
+    }
+
+    /// Applies the layers to every tensor of `inputs`, planning once for
+    /// inputs of the same shape
+    pub fn apply_batch<T: Backend<Output = T> + Clone>(
+        &self,
+        inputs: &[T],
+    ) -> Result<Vec<T>, EinopsError> {
+        self.cache.apply_batch(&self.recipes, inputs)
+    }
+}
+
+impl From<Rearrange> for Chain {
+    fn from(layer: Rearrange) -> Self {
+        Self {
+            recipes: vec![layer.recipe],
+            cache: layer.cache.cleared(),
+        }
+    }
+}
+
+impl From<Reduce> for Chain {
+    fn from(layer: Reduce) -> Self {
+        Self {
+            recipes: vec![layer.recipe],
+            cache: layer.cache.cleared(),
+        }
+    }
+}
+
+impl From<Repeat> for Chain {
+    fn from(layer: Repeat) -> Self {
+        Self {
+            recipes: vec![layer.recipe],
+            cache: layer.cache.cleared(),
+        }
+    }
+}
+
+impl candle_nn::Module for Chain {
+    fn forward(&self, xs: &Tensor) -> candle_core::Result<Tensor> {
+        Ok(self.apply(xs)?)
+    }
+}
+
+/// Asserts that a layer copies its input of shape `shape` at most `max` times
+///
+/// A reduction counts as one copy, and so does a reshape of a tensor whose
+/// axes were permuted or repeated, which backends like candle make views of.
+/// Runs in tests to catch changes of a pattern that add copies to a hot path.
+///
+/// ```ignore
+/// let layer = Rearrange::new("b (h w) c -> b c h w", &[("h", 16)])?;
+/// // The transpose is a view
+/// assert_max_copies!(layer, [8, 256, 3], 0);
+/// ```
+#[macro_export]
+macro_rules! assert_max_copies {
+    ($layer:expr, $shape:expr, $max:expr $(,)?) => {{
+        let copies = $layer
+            .max_copies(&$shape)
+            .expect("the shape is a valid input of the layer");
+        assert!(
+            copies <= $max,
+            "`{}` copies its input {} times, more than {}",
+            stringify!($layer),
+            copies,
+            $max
+        );
+    }};
+}
+
+/// Handle of a layer in a [`Registry`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct PatternId(u32);
+
+/// Layers parsed once and applied by their [`PatternId`], for loops running
+/// many small transformations chosen by their pattern
+///
+/// Looking up a layer by its id indexes a vector, only [`Registry::id`]
+/// hashes the pattern.
+///
+/// ```ignore
+/// let mut registry = Registry::default();
+/// let id = registry.register(Rearrange::new("h w c -> c h w", &[])?);
+/// for image in images {
+///     let chw = registry.apply(id, &image)?;
+/// }
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct Registry {
+    layers: Vec<Chain>,
+    ids: HashMap<Vec<String>, PatternId>,
+}
+
+impl Registry {
+    /// Adds `layer`, which gets a new id even if a layer with the same pattern
+    /// was added before
+    pub fn register(&mut self, layer: impl Into<Chain>) -> PatternId {
+        let layer = layer.into();
+        let id = PatternId(
+            u32::try_from(self.layers.len()).expect("fewer than 2^32 layers are registered"),
+        );
+        let patterns = layer.patterns().into_iter().map(String::from).collect();
+        self.ids.entry(patterns).or_insert(id);
+        self.layers.push(layer);
+        id
+    }
+
+    /// Id of the first layer added with `pattern`, or the patterns of a chain
+    pub fn id(&self, patterns: &[&str]) -> Option<PatternId> {
+        let patterns = patterns
+            .iter()
+            .copied()
+            .map(String::from)
+            .collect::<Vec<_>>();
+        self.ids.get(&patterns).copied()
+    }
+
+    pub fn get(&self, id: PatternId) -> Option<&Chain> {
+        self.layers.get(id.0 as usize)
+    }
+
+    /// Applies the layer `id` to `input` of any backend
+    pub fn apply<T: Backend<Output = T> + Clone>(
+        &self,
+        id: PatternId,
+        input: &T,
+    ) -> Result<T, EinopsError> {
+        match self.get(id) {
+            Some(layer) => layer.apply(input),
+            None => Err(EinopsError::new(format!(
+                "pattern id {} isn't in the registry",
+                id.0
+            ))),
+        }
+    }
+}
+
+/// Axes of several inputs, named by a pattern with one side per input
+/// separated by commas, like `"b h t d, b h s d, b h s d"` for the queries,
+/// keys and values of attention
+///
+/// The lengths of the axes are read from the shapes of all inputs together.
+/// An axis named by several inputs has to have the same length in all of
+/// them, and its length found in one input decomposes the groups of the
+/// inputs after it.
+///
+/// ```ignore
+/// let inputs = Inputs::new("b t (h d), b s (h d), b s (h d)", &[("h", 8)])?;
+/// let lengths = inputs.bind(&[q.dims(), k.dims(), v.dims()])?;
+/// ```
+#[derive(Debug, Clone)]
+pub struct Inputs {
+    sides: Vec<Recipe>,
+}
+
+impl Inputs {
+    /// Parses `pattern`, with the sizes of axes that aren't written in it
+    pub fn new(pattern: &str, sizes: &[(&str, usize)]) -> Result<Self, EinopsError> {
+        Ok(Self {
+            sides: parse_inputs(pattern, sizes)?,
+        })
+    }
+
+    pub fn pattern(&self) -> &str {
+        self.sides[0].pattern()
+    }
+
+    /// Lengths of the named axes of inputs of shapes `shapes`, in the order
+    /// they appear in the pattern
+    ///
+    /// An axis whose length differs from its length in an input before is a
+    /// [`ErrorKind::ShapeMismatch`](crate::ErrorKind::ShapeMismatch) of the
+    /// later input.
+    pub fn bind(&self, shapes: &[&[usize]]) -> Result<Vec<(&str, usize)>, EinopsError> {
+        bind_inputs(&self.sides, shapes)
+    }
+}
+
+/// Linear transformation of the axes named by a pattern, like
+/// `einops.layers.torch.EinMix`
+///
+/// The weight has the axes of `weight_shape`. Its axes on both sides of the
+/// pattern have a separate transformation for each index, like the heads of
+/// an attention layer, its axes missing on the right side are summed over and
+/// its axes missing on the left side are added. The optional bias has axes of
+/// the right side and is added to the output. Every axis of the weight and the
+/// bias needs a size.
+///
+/// The parameters are registered in `vb` as `weight` and `bias`, initialized
+/// uniformly between ±1/√n where n is the product of the summed over lengths.
+///
+/// ```ignore
+/// let mix = EinMix::builder("b t (h d) -> b h t d_out", "h d d_out")
+///     .bias("h d_out")
+///     .size("h", 8)
+///     .size("d", 64)
+///     .size("d_out", 32)
+///     .build(vb.pp("mix"))?;
+/// ```
+#[derive(Debug, Clone)]
+pub struct EinMix {
+    recipe: Recipe,
+    weight: Tensor,
+    weight_axes: Vec<String>,
+    bias: Option<(Tensor, Vec<String>)>,
+    // Axes on both sides and in the weight, on both sides only, summed over and
+    // added, the order of the matrix product
+    batch: Vec<String>,
+    rows: Vec<String>,
+    contracted: Vec<String>,
+    added: Vec<String>,
+}
+
+// Axes of a weight or bias shape, which has no groups or `..`
+fn parse_axes(shape: &str, sizes: &mut Vec<(String, usize)>) -> Result<Vec<String>, EinopsError> {
+    let items = parse_side(shape, 0, shape.len(), sizes)?;
+    let mut axes = Vec::with_capacity(items.len());
+    for item in &items {
+        match item {
+            Item::Group { text, names, .. } if !text.starts_with('(') => {
+                if axes.contains(&names[0]) {
+                    return Err(invalid(shape, format!("axis `{}` appears twice", names[0])));
+                }
+                axes.push(names[0].clone());
+            }
+            _ => {
+                return Err(invalid(
+                    shape,
+                    "only axes are allowed, without groups or `..`",
+                ))
+            }
+        }
+    }
+    Ok(axes)
+}
+
+/// Pattern, shapes of the parameters and sizes of axes of an [`EinMix`],
+/// which are checked by [`EinMixBuilder::build`]
+#[derive(Debug, Clone)]
+pub struct EinMixBuilder {
+    pattern: String,
+    weight_shape: String,
+    bias_shape: Option<String>,
+    sizes: Vec<(String, usize)>,
+}
+
+impl EinMixBuilder {
+    /// Adds a bias with the axes of `bias_shape`
+    pub fn bias(mut self, bias_shape: &str) -> Self {
+        self.bias_shape = Some(bias_shape.to_string());
+        self
+    }
+
+    pub fn size(mut self, axis: &str, len: usize) -> Self {
+        self.sizes.push((axis.to_string(), len));
+        self
+    }
+
+    /// Checks the pattern and registers the parameters in `vb`
+    pub fn build(&self, vb: VarBuilder) -> Result<EinMix, EinopsError> {
+        let sizes = self
+            .sizes
+            .iter()
+            .map(|(axis, len)| (axis.as_str(), *len))
+            .collect::<Vec<_>>();
+        EinMix::new(
+            &self.pattern,
+            &self.weight_shape,
+            self.bias_shape.as_deref(),
+            &sizes,
+            vb,
+        )
+    }
+}
+
+impl EinMix {
+    pub fn builder(pattern: &str, weight_shape: &str) -> EinMixBuilder {
+        EinMixBuilder {
+            pattern: pattern.to_string(),
+            weight_shape: weight_shape.to_string(),
+            bias_shape: None,
+            sizes: Vec::new(),
+        }
+    }
+
+    pub fn new(
+        pattern: &str,
+        weight_shape: &str,
+        bias_shape: Option<&str>,
+        sizes: &[(&str, usize)],
+        vb: VarBuilder,
+    ) -> Result<Self, EinopsError> {
+        let mut bound = sizes
+            .iter()
+            .map(|&(axis, len)| (axis.to_string(), len))
+            .collect();
+        let weight_axes = parse_axes(weight_shape, &mut bound)?;
+        let bias_axes = bias_shape
+            .map(|bias_shape| parse_axes(bias_shape, &mut bound))
+            .transpose()?;
+        let bound = bound
+            .iter()
+            .map(|(axis, len)| (axis.as_str(), *len))
+            .collect::<Vec<_>>();
+        let recipe = Recipe::new(pattern, &bound, Kind::Mix)?;
+        if recipe.left().contains(&Item::Ellipsis) {
+            return Err(invalid(pattern, "`..` isn't supported by EinMix"));
+        }
+
+        let left = names(recipe.left()).map(String::from).collect::<Vec<_>>();
+        let right = names(recipe.right()).map(String::from).collect::<Vec<_>>();
+        let in_weight = |axis: &&String| weight_axes.contains(axis);
+        let both = |axis: &&String| left.contains(axis) && right.contains(axis);
+        if let Some(axis) = weight_axes
+            .iter()
+            .find(|axis| !left.contains(axis) && !right.contains(axis))
+        {
+            return Err(invalid(
+                pattern,
+                format!("axis `{}` of the weight is missing in the pattern", axis),
+            ));
+        }
+        if let Some(axis) = left
+            .iter()
+            .chain(&right)
+            .find(|axis| !both(axis) && !in_weight(axis))
+        {
+            return Err(invalid(
+                pattern,
+                format!(
+                    "axis `{}` is on one side only and missing in the weight",
+                    axis
+                ),
+            ));
+        }
+        let sized = |shape: &str, axes: &[String]| match axes
+            .iter()
+            .find(|axis| recipe.size(axis).is_none())
+        {
+            Some(axis) => Err(invalid(shape, format!("axis `{}` needs a size", axis))),
+            None => Ok(()),
+        };
+        sized(weight_shape, &weight_axes)?;
+        if let (Some(bias_shape), Some(bias_axes)) = (bias_shape, &bias_axes) {
+            if let Some(axis) = bias_axes.iter().find(|axis| !right.contains(axis)) {
+                return Err(invalid(
+                    bias_shape,
+                    format!("axis `{}` of the bias is missing on the right side", axis),
+                ));
+            }
+            sized(bias_shape, bias_axes)?;
+        }
+
+        let lengths = |axes: &[String]| {
+            axes.iter()
+                .map(|axis| recipe.size(axis).expect("the axes have sizes"))
+                .collect::<Vec<_>>()
+        };
+        let contracted = weight_axes
+            .iter()
+            .filter(|axis| !right.contains(axis))
+            .cloned()
+            .collect::<Vec<_>>();
+        let fan_in = lengths(&contracted).iter().product::<usize>().max(1);
+        let bound = 1.0 / (fan_in as f64).sqrt();
+        let init = Init::Uniform {
+            lo: -bound,
+            up: bound,
+        };
+        let weight = vb.get_with_hints(lengths(&weight_axes), "weight", init)?;
+        let bias = match bias_axes {
+            Some(bias_axes) => Some((
+                vb.get_with_hints(lengths(&bias_axes), "bias", init)?,
+                bias_axes,
+            )),
+            None => None,
+        };
+
+        Ok(Self {
+            batch: right
+                .iter()
+                .filter(|axis| left.contains(axis) && in_weight(axis))
+                .cloned()
+                .collect(),
+            rows: left
+                .iter()
+                .filter(|axis| right.contains(axis) && !in_weight(axis))
+                .cloned()
+                .collect(),
+            added: weight_axes
+                .iter()
+                .filter(|axis| !left.contains(axis))
+                .cloned()
+                .collect(),
+            contracted,
+            recipe,
+            weight,
+            weight_axes,
+            bias,
+        })
+    }
+
+    pub fn pattern(&self) -> &str {
+        self.recipe.pattern()
+    }
+
+    pub fn weight(&self) -> &Tensor {
+        &self.weight
+    }
+
+    pub fn bias(&self) -> Option<&Tensor> {
+        self.bias.as_ref().map(|(bias, _)| bias)
+    }
+
+    /// Shape of the output for an input of shape `input_shape`, which is
+    /// checked like in [`Module::forward`](candle_nn::Module::forward)
+    pub fn output_shape(&self, input_shape: &[usize]) -> Result<Vec<usize>, EinopsError> {
+        Ok(self.recipe.shapes(input_shape)?.1)
+    }
+}
+
+// Positions of `axes` in `order`
+fn positions<A: AsRef<str>, B: AsRef<str>>(order: &[A], axes: &[B]) -> Vec<usize> {
+    axes.iter()
+        .map(|axis| {
+            order
+                .iter()
+                .position(|other| other.as_ref() == axis.as_ref())
+                .expect("the axes were checked")
+        })
+        .collect()
+}
+
+impl candle_nn::Module for EinMix {
+    fn forward(&self, xs: &Tensor) -> candle_core::Result<Tensor> {
+        let (decomposed, output_shape) = self.recipe.shapes(xs.dims())?;
+        let left = names(self.recipe.left()).collect::<Vec<_>>();
+        let len = |axis: &String| match self.recipe.size(axis) {
+            Some(size) => size,
+            None => decomposed[positions(&left, &[axis])[0]],
+        };
+        let product = |axes: &[String]| axes.iter().map(len).product::<usize>();
+
+        let input_axes = [&self.batch[..], &self.rows, &self.contracted].concat();
+        let input = xs
+            .reshape(decomposed.as_slice())?
+            .permute(positions(&left, &input_axes))?
+            .reshape((
+                product(&self.batch),
+                product(&self.rows),
+                product(&self.contracted),
+            ))?;
+        let weight_order = [&self.batch[..], &self.contracted, &self.added].concat();
+        let weight = self
+            .weight
+            .permute(positions(&self.weight_axes, &weight_order))?
+            .reshape((
+                product(&self.batch),
+                product(&self.contracted),
+                product(&self.added),
+            ))?;
+
+        let output_axes = [&self.batch[..], &self.rows, &self.added].concat();
+        let mut output = input
+            .matmul(&weight)?
+            .reshape(output_axes.iter().map(len).collect::<Vec<_>>())?;
+        if let Some((bias, bias_axes)) = &self.bias {
+            let order = output_axes
+                .iter()
+                .filter(|axis| bias_axes.contains(axis))
+                .collect::<Vec<_>>();
+            let shape = output_axes
+                .iter()
+                .map(|axis| {
+                    if bias_axes.contains(axis) {
+                        len(axis)
+                    } else {
+                        1
+                    }
+                })
+                .collect::<Vec<_>>();
+            let bias = bias.permute(positions(bias_axes, &order))?.reshape(shape)?;
+            output = output.broadcast_add(&bias)?;
+        }
+
+        let right = names(self.recipe.right()).collect::<Vec<_>>();
+        output
+            .permute(positions(&output_axes, &right))?
+            .reshape(output_shape)
+    }
+}
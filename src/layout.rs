@@ -0,0 +1,112 @@
+//! Conversions between the standard layouts of images, for any backend
+//!
+//! Tensors don't know their layout, so conversions are told the layout of the
+//! input and the number of channels the images should have, which catches
+//! images passed in the wrong layout.
+//!
+//! ```ignore
+//! let nhwc = to_layout(&image, Layout::Nchw, Layout::Nhwc, 3)?;
+//! ```
+
+use std::fmt;
+
+use crate::{einops, Backend, EinopsError, ErrorKind};
+
+/// Order of the axes of images
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Layout {
+    /// `[batch, channels, height, width]`, used by candle, PyTorch and ONNX
+    Nchw,
+    /// `[batch, height, width, channels]`, used by TensorFlow and image files
+    Nhwc,
+    /// `[channels, height, width]`, a single image of [`Layout::Nchw`]
+    Chw,
+    /// `[height, width, channels]`, a single image of [`Layout::Nhwc`]
+    Hwc,
+}
+
+impl Layout {
+    /// Number of axes of images in this layout
+    pub fn rank(self) -> usize {
+        match self {
+            Layout::Nchw | Layout::Nhwc => 4,
+            Layout::Chw | Layout::Hwc => 3,
+        }
+    }
+
+    /// Position of the channels among the axes
+    pub fn channel_axis(self) -> usize {
+        match self {
+            Layout::Nchw => 1,
+            Layout::Nhwc => 3,
+            Layout::Chw => 0,
+            Layout::Hwc => 2,
+        }
+    }
+
+    /// Whether images of this layout have a batch axis
+    pub fn is_batched(self) -> bool {
+        self.rank() == 4
+    }
+
+    /// Number of channels of images of `shape` in this layout
+    pub fn channels(self, shape: &[usize]) -> Result<usize, EinopsError> {
+        if shape.len() != self.rank() {
+            return Err(ErrorKind::RankMismatch {
+                expected: self.rank(),
+                found: shape.len(),
+            }
+            .into());
+        }
+        Ok(shape[self.channel_axis()])
+    }
+}
+
+impl fmt::Display for Layout {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Layout::Nchw => "NCHW",
+            Layout::Nhwc => "NHWC",
+            Layout::Chw => "CHW",
+            Layout::Hwc => "HWC",
+        };
+        f.write_str(name)
+    }
+}
+
+/// Converts images of `channels` channels from the layout `from` to `to`
+///
+/// Images already in `to` are returned as a clone of `x`, which shares its
+/// data on backends like candle whose clones are cheap. A batch axis can't be
+/// added or removed, converting between [`Layout::Nchw`] and [`Layout::Hwc`]
+/// is an error.
+pub fn to_layout<T: Backend<Output = T> + Clone>(
+    x: &T,
+    from: Layout,
+    to: Layout,
+    channels: usize,
+) -> Result<T, EinopsError> {
+    let found = from.channels(&x.shape())?;
+    if found != channels {
+        return Err(ErrorKind::ShapeMismatch {
+            axis: from.channel_axis(),
+            group: "c".into(),
+            sizes: Vec::new(),
+            expected: channels,
+            found,
+        }
+        .into());
+    }
+
+    match (from, to) {
+        _ if from == to => Ok(x.clone()),
+        (Layout::Nchw, Layout::Nhwc) => einops!("b c h w -> b h w c", x),
+        (Layout::Nhwc, Layout::Nchw) => einops!("b h w c -> b c h w", x),
+        (Layout::Chw, Layout::Hwc) => einops!("c h w -> h w c", x),
+        (Layout::Hwc, Layout::Chw) => einops!("h w c -> c h w", x),
+        _ => Err(EinopsError::new(format!(
+            "cannot convert images from layout {} to {}, which differ in their batch axis",
+            from, to
+        ))),
+    }
+}
@@ -1,15 +1,58 @@
+#[cfg(feature = "arrow")]
+pub mod arrow;
+pub mod attention;
 mod backend;
+pub mod backend_conformance;
+pub mod bridge;
+#[cfg(feature = "burn")]
+pub mod burn;
 #[cfg(feature = "candle")]
 pub mod candle;
+pub mod complex;
+#[cfg(feature = "cpu")]
+pub mod cpu;
+pub mod deferred;
+mod dynamic;
+mod error;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+#[cfg(feature = "nn")]
+pub mod layers;
+pub mod layout;
+pub mod metrics;
+#[cfg(feature = "nalgebra")]
+mod nalgebra;
+#[cfg(feature = "ndarray")]
+mod ndarray;
+pub mod norm;
+pub mod pattern;
+pub mod policy;
+#[cfg(any(feature = "nn", feature = "safetensors", feature = "ffi"))]
+#[cfg_attr(not(feature = "nn"), allow(dead_code))]
+mod recipe;
+#[cfg(feature = "safetensors")]
+pub mod safetensors;
+pub mod sliding;
+mod small_axes;
 #[cfg(feature = "tch")]
 mod tch;
+pub mod trace;
+pub mod vision;
+
+extern crate self as candle_einops;
 
 pub use candle_einops_macros::einops;
 
 pub use backend::Backend;
+pub use dynamic::DynBackend;
+#[doc(hidden)]
+pub use error::checked_product;
+pub use error::{EinopsError, ErrorKind};
+#[doc(hidden)]
+pub use small_axes::SmallAxes;
 
 /// Specifies the operation used to reduce an axis
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub enum Operation {
     /// Take the minimum value
     Min,
@@ -20,3 +63,41 @@ pub enum Operation {
     /// Get the mean value
     Mean,
 }
+
+/// Specifies the operation accumulated along an axis by [`Backend::scan`]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ScanOp {
+    /// Cumulative sum
+    Sum,
+    /// Cumulative product
+    Prod,
+    /// Running maximum
+    Max,
+}
+
+/// Element type of a tensor, shared by all backends
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum DType {
+    U8,
+    U16,
+    U32,
+    U64,
+    I8,
+    I16,
+    I32,
+    I64,
+    F16,
+    BF16,
+    F32,
+    F64,
+}
+
+/// Device a tensor is stored on
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Device {
+    Cpu,
+    /// CUDA device with the given ordinal
+    Cuda(usize),
+    /// Metal device with the given ordinal
+    Metal(usize),
+}
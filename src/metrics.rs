@@ -0,0 +1,156 @@
+//! Counters of the work done by each `einops!` pattern and layer
+//!
+//! Counting is enabled by the `metrics` feature, without it the functions
+//! here do nothing and [`counters`] is always empty. Backends report their
+//! copies and kernels with [`record_copy`] and [`record_kernel`], work done
+//! outside of an `einops!` call or a layer isn't counted. Layers are counted
+//! by their pattern, chains of layers by their patterns joined by ` then `.
+//!
+//! ```ignore
+//! metrics::reset();
+//! let output = einops!("b h w c -> b c (h w)", &input)?;
+//! assert_eq!(metrics::counters()[0].1.copies, 1);
+//! ```
+
+#[cfg(feature = "metrics")]
+use std::cell::RefCell;
+#[cfg(feature = "metrics")]
+use std::collections::BTreeMap;
+#[cfg(feature = "metrics")]
+use std::sync::Mutex;
+
+/// Work done by the transformations of one pattern
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Counters {
+    /// Times the pattern was run
+    pub calls: usize,
+    /// Times the data of a tensor was copied
+    pub copies: usize,
+    /// Bytes written by the copies
+    pub bytes_moved: usize,
+    /// Operations that ran over the data, copies included
+    pub kernels: usize,
+}
+
+#[cfg(feature = "metrics")]
+static COUNTERS: Mutex<BTreeMap<String, Counters>> = Mutex::new(BTreeMap::new());
+
+// Pattern of an `einops!` call, or of layers
+#[cfg(feature = "metrics")]
+#[derive(Clone)]
+enum Pattern {
+    Macro(&'static str),
+    #[cfg_attr(
+        not(any(feature = "nn", feature = "safetensors", feature = "ffi")),
+        allow(dead_code)
+    )]
+    Layer(std::sync::Arc<str>),
+}
+
+#[cfg(feature = "metrics")]
+thread_local! {
+    // Pattern of the innermost `einops!` call or layer running on this thread
+    static PATTERN: RefCell<Option<Pattern>> = const { RefCell::new(None) };
+}
+
+/// Counters of every pattern run since the last [`reset`], by pattern
+pub fn counters() -> Vec<(String, Counters)> {
+    #[cfg(feature = "metrics")]
+    return COUNTERS
+        .lock()
+        .unwrap_or_else(|error| error.into_inner())
+        .iter()
+        .map(|(pattern, &counters)| (pattern.clone(), counters))
+        .collect();
+    #[cfg(not(feature = "metrics"))]
+    Vec::new()
+}
+
+/// Clears the counters of all patterns
+pub fn reset() {
+    #[cfg(feature = "metrics")]
+    COUNTERS
+        .lock()
+        .unwrap_or_else(|error| error.into_inner())
+        .clear();
+}
+
+#[cfg(feature = "metrics")]
+fn record(update: impl FnOnce(&mut Counters)) {
+    PATTERN.with_borrow(|pattern| {
+        let pattern = match pattern {
+            Some(Pattern::Macro(pattern)) => *pattern,
+            Some(Pattern::Layer(pattern)) => pattern,
+            None => return,
+        };
+        let mut counters = COUNTERS.lock().unwrap_or_else(|error| error.into_inner());
+        // Only the first call of a pattern allocates its key
+        match counters.get_mut(pattern) {
+            Some(counters) => update(counters),
+            None => update(counters.entry(pattern.to_string()).or_default()),
+        }
+    })
+}
+
+/// Counts a copy of `bytes` bytes for the running pattern
+pub fn record_copy(bytes: usize) {
+    #[cfg(feature = "metrics")]
+    record(|counters| {
+        counters.copies += 1;
+        counters.bytes_moved += bytes;
+        counters.kernels += 1;
+    });
+    #[cfg(not(feature = "metrics"))]
+    let _ = bytes;
+}
+
+/// Counts an operation that runs over the data without copying it, like a
+/// reduction, for the running pattern
+pub fn record_kernel() {
+    #[cfg(feature = "metrics")]
+    record(|counters| counters.kernels += 1);
+}
+
+/// Attributes the work done until it's dropped to `pattern`
+#[doc(hidden)]
+pub struct Scope {
+    #[cfg(feature = "metrics")]
+    previous: Option<Pattern>,
+}
+
+#[doc(hidden)]
+pub fn enter(pattern: &'static str) -> Scope {
+    #[cfg(feature = "metrics")]
+    return enter_pattern(Pattern::Macro(pattern));
+    #[cfg(not(feature = "metrics"))]
+    {
+        let _ = pattern;
+        Scope {}
+    }
+}
+
+// Scope of layers with the pattern `pattern`
+#[cfg(any(feature = "nn", feature = "safetensors", feature = "ffi"))]
+pub(crate) fn enter_layer(pattern: &std::sync::Arc<str>) -> Scope {
+    #[cfg(feature = "metrics")]
+    return enter_pattern(Pattern::Layer(pattern.clone()));
+    #[cfg(not(feature = "metrics"))]
+    {
+        let _ = pattern;
+        Scope {}
+    }
+}
+
+#[cfg(feature = "metrics")]
+fn enter_pattern(pattern: Pattern) -> Scope {
+    let previous = PATTERN.replace(Some(pattern));
+    record(|counters| counters.calls += 1);
+    Scope { previous }
+}
+
+#[cfg(feature = "metrics")]
+impl Drop for Scope {
+    fn drop(&mut self) {
+        PATTERN.set(self.previous.take());
+    }
+}
@@ -0,0 +1,204 @@
+use nalgebra::{DMatrix, DVector, Scalar};
+
+use crate::cpu::{CpuTensor, Element};
+use crate::{Backend, DType, Device, EinopsError, Operation, ScanOp};
+
+// nalgebra stores matrices in column-major order, the data is copied into a
+// row-major `CpuTensor` before any operation is applied
+fn matrix_to_tensor<T: Scalar>(matrix: &DMatrix<T>) -> Result<CpuTensor<T>, EinopsError> {
+    let data = matrix.transpose().as_slice().to_vec();
+    CpuTensor::new(data, vec![matrix.nrows(), matrix.ncols()])
+}
+
+fn vector_to_tensor<T: Scalar>(vector: &DVector<T>) -> Result<CpuTensor<T>, EinopsError> {
+    CpuTensor::new(vector.as_slice().to_vec(), vec![vector.len()])
+}
+
+macro_rules! impl_backend {
+    ($($ty:ident => |$input:ident| { shape: $shape:expr, tensor: $tensor:expr $(,)? })*) => {
+        $(
+            impl<T: Element + Scalar> Backend for $ty<T> {
+                type Output = CpuTensor<T>;
+
+                fn shape(&self) -> Vec<usize> {
+                    let $input = self;
+                    $shape
+                }
+
+                fn reshape(&self, shape: &[usize]) -> Result<Self::Output, EinopsError> {
+                    let $input = self;
+                    Backend::reshape(&$tensor?, shape)
+                }
+
+                fn transpose(&self, axes: &[usize]) -> Result<Self::Output, EinopsError> {
+                    let $input = self;
+                    Backend::transpose(&$tensor?, axes)
+                }
+
+                fn reduce_axes(
+                    &self,
+                    axes_operations: &mut [(usize, Operation)],
+                ) -> Result<Self::Output, EinopsError> {
+                    let $input = self;
+                    Backend::reduce_axes(&$tensor?, axes_operations)
+                }
+
+                fn add_axes(
+                    &self,
+                    naxes: usize,
+                    pos2len: &[(usize, usize)],
+                ) -> Result<Self::Output, EinopsError> {
+                    let $input = self;
+                    Backend::add_axes(&$tensor?, naxes, pos2len)
+                }
+
+                fn narrow(
+                    &self,
+                    axis: usize,
+                    start: usize,
+                    len: usize,
+                ) -> Result<Self::Output, EinopsError> {
+                    let $input = self;
+                    Backend::narrow(&$tensor?, axis, start, len)
+                }
+
+                fn broadcast(&self, shape: &[usize]) -> Result<Self::Output, EinopsError> {
+                    let $input = self;
+                    Backend::broadcast(&$tensor?, shape)
+                }
+
+                // The data is always copied into a new buffer
+                fn is_contiguous(&self) -> bool {
+                    false
+                }
+
+                fn contiguous(&self) -> Result<Self::Output, EinopsError> {
+                    let $input = self;
+                    $tensor
+                }
+
+                fn concat(inputs: &[&Self], axis: usize) -> Result<Self::Output, EinopsError> {
+                    let tensors = inputs
+                        .iter()
+                        .map(|&$input| $tensor)
+                        .collect::<Result<Vec<_>, _>>()?;
+                    Backend::concat(&tensors.iter().collect::<Vec<_>>(), axis)
+                }
+
+                fn index_select(
+                    &self,
+                    axis: usize,
+                    indices: &[usize],
+                ) -> Result<Self::Output, EinopsError> {
+                    let $input = self;
+                    Backend::index_select(&$tensor?, axis, indices)
+                }
+
+                fn scan(
+                    &self,
+                    axis: usize,
+                    operation: ScanOp,
+                ) -> Result<Self::Output, EinopsError> {
+                    let $input = self;
+                    Backend::scan(&$tensor?, axis, operation)
+                }
+
+                fn contract(&self, rhs: &Self) -> Result<Self::Output, EinopsError> {
+                    let lhs = {
+                        let $input = self;
+                        $tensor?
+                    };
+                    let $input = rhs;
+                    Backend::contract(&lhs, &$tensor?)
+                }
+
+                fn dtype(&self) -> Option<DType> {
+                    T::dtype()
+                }
+
+                fn to_dtype(&self, dtype: DType) -> Result<Self::Output, EinopsError> {
+                    let $input = self;
+                    Backend::to_dtype(&$tensor?, dtype)
+                }
+
+                fn device(&self) -> Option<Device> {
+                    Some(Device::Cpu)
+                }
+
+                fn to_device(&self, device: Device) -> Result<Self::Output, EinopsError> {
+                    if device != Device::Cpu {
+                        return Err(EinopsError::new(format!(
+                            "cannot move tensor to {:?}, only the CPU is supported",
+                            device
+                        )));
+                    }
+                    let $input = self;
+                    $tensor
+                }
+            }
+        )*
+    };
+}
+
+impl_backend! {
+    DMatrix => |matrix| {
+        shape: vec![matrix.nrows(), matrix.ncols()],
+        tensor: matrix_to_tensor(matrix),
+    }
+    DVector => |vector| {
+        shape: vec![vector.len()],
+        tensor: vector_to_tensor(vector),
+    }
+}
+
+impl<T: Scalar> TryFrom<CpuTensor<T>> for DMatrix<T> {
+    type Error = EinopsError;
+
+    fn try_from(tensor: CpuTensor<T>) -> Result<Self, Self::Error> {
+        match *tensor.shape() {
+            [nrows, ncols] => Ok(DMatrix::from_row_iterator(nrows, ncols, tensor.into_data())),
+            _ => Err(EinopsError::new(format!(
+                "expected a tensor of rank 2, found shape {:?}",
+                tensor.shape()
+            ))),
+        }
+    }
+}
+
+impl<T: Scalar> TryFrom<CpuTensor<T>> for DVector<T> {
+    type Error = EinopsError;
+
+    fn try_from(tensor: CpuTensor<T>) -> Result<Self, Self::Error> {
+        match *tensor.shape() {
+            [_] => Ok(DVector::from_vec(tensor.into_data())),
+            _ => Err(EinopsError::new(format!(
+                "expected a tensor of rank 1, found shape {:?}",
+                tensor.shape()
+            ))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nalgebra_transpose() {
+        let matrix = DMatrix::from_row_slice(2, 3, &[0u32, 1, 2, 3, 4, 5]);
+
+        let output = Backend::transpose(&matrix, &[1, 0]).unwrap();
+        assert_eq!(DMatrix::try_from(output).unwrap(), matrix.transpose());
+    }
+
+    #[test]
+    fn nalgebra_reduce() {
+        let matrix = DMatrix::from_row_slice(2, 3, &[0u32, 1, 2, 3, 4, 5]);
+
+        let output = Backend::reduce_axes(&matrix, &mut [(1, Operation::Sum)]).unwrap();
+        assert_eq!(
+            DVector::try_from(output).unwrap(),
+            DVector::from_vec(vec![3, 12])
+        );
+    }
+}
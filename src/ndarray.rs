@@ -0,0 +1,465 @@
+use std::ops::{Add, Div, Mul};
+
+use ndarray::{ArrayD, Axis, IxDyn, Slice};
+use num_traits::{FromPrimitive, Zero};
+
+use crate::{Backend, Device, EinopsError, Operation, ScanOp};
+
+impl<A> Backend for ArrayD<A>
+where
+    A: Clone
+        + PartialOrd
+        + Zero
+        + FromPrimitive
+        + Add<Output = A>
+        + Div<Output = A>
+        + Mul<Output = A>,
+{
+    type Output = ArrayD<A>;
+
+    fn shape(&self) -> Vec<usize> {
+        ArrayD::shape(self).to_vec()
+    }
+
+    fn reshape(&self, shape: &[usize]) -> Result<Self::Output, EinopsError> {
+        Ok(self.to_shape(IxDyn(shape))?.into_owned())
+    }
+
+    fn transpose(&self, axes: &[usize]) -> Result<Self::Output, EinopsError> {
+        // `permuted_axes` panics on an invalid permutation
+        let mut sorted = axes.to_vec();
+        sorted.sort_unstable();
+        if sorted.len() != self.ndim() || sorted.iter().enumerate().any(|(i, &axis)| i != axis) {
+            return Err(EinopsError::new(format!(
+                "invalid permutation {:?} for array with {} dimensions",
+                axes,
+                self.ndim()
+            )));
+        }
+
+        Ok(self.view().permuted_axes(IxDyn(axes)).to_owned())
+    }
+
+    fn reduce_axes(
+        &self,
+        axes_operations: &mut [(usize, Operation)],
+    ) -> Result<Self::Output, EinopsError> {
+        let mut output = self.clone();
+
+        axes_operations.sort_by_key(|(axis, _)| *axis);
+
+        for (axis, operation) in axes_operations.iter().rev() {
+            let axis = Axis(*axis);
+            if axis.index() >= output.ndim() || output.len_of(axis) == 0 {
+                return Err(EinopsError::new(format!(
+                    "cannot reduce axis {} of array with shape {:?}",
+                    axis.index(),
+                    output.shape()
+                )));
+            }
+            output = match operation {
+                Operation::Min => output.map_axis(axis, |lane| {
+                    lane.iter().skip(1).fold(lane[0].clone(), |min, x| {
+                        if *x < min {
+                            x.clone()
+                        } else {
+                            min
+                        }
+                    })
+                }),
+                Operation::Max => output.map_axis(axis, |lane| {
+                    lane.iter().skip(1).fold(lane[0].clone(), |max, x| {
+                        if *x > max {
+                            x.clone()
+                        } else {
+                            max
+                        }
+                    })
+                }),
+                Operation::Sum => output.sum_axis(axis),
+                Operation::Mean => output
+                    .mean_axis(axis)
+                    .ok_or_else(|| EinopsError::new("cannot take the mean of an empty axis"))?,
+            };
+        }
+
+        Ok(output)
+    }
+
+    fn add_axes(
+        &self,
+        naxes: usize,
+        pos2len: &[(usize, usize)],
+    ) -> Result<Self::Output, EinopsError> {
+        let mut output = self.view();
+
+        for &(axis_pos, _) in pos2len {
+            if axis_pos > output.ndim() {
+                return Err(EinopsError::new(format!(
+                    "cannot insert axis at position {} of array with {} dimensions",
+                    axis_pos,
+                    output.ndim()
+                )));
+            }
+            output = output.insert_axis(Axis(axis_pos));
+        }
+
+        let mut repeated_shape = output.shape().to_vec();
+        if repeated_shape.len() != naxes {
+            return Err(EinopsError::new(format!(
+                "expected {} axes after inserting new axes, found {}",
+                naxes,
+                repeated_shape.len()
+            )));
+        }
+        for &(axis_pos, axis_len) in pos2len {
+            repeated_shape[axis_pos] = axis_len;
+        }
+
+        // Singleton axes are broadcast, which is the same as tiling them
+        Ok(output
+            .broadcast(IxDyn(&repeated_shape))
+            .ok_or_else(|| {
+                EinopsError::new(format!(
+                    "cannot broadcast array of shape {:?} to {:?}",
+                    output.shape(),
+                    repeated_shape
+                ))
+            })?
+            .to_owned())
+    }
+
+    fn narrow(&self, axis: usize, start: usize, len: usize) -> Result<Self::Output, EinopsError> {
+        // `slice_axis` panics on an out of bounds range
+        if axis >= self.ndim() || start + len > self.len_of(Axis(axis)) {
+            return Err(EinopsError::new(format!(
+                "cannot narrow axis {} of array with shape {:?} to {}..{}",
+                axis,
+                self.shape(),
+                start,
+                start + len
+            )));
+        }
+
+        Ok(self
+            .slice_axis(Axis(axis), Slice::from(start..start + len))
+            .to_owned())
+    }
+
+    fn broadcast(&self, shape: &[usize]) -> Result<Self::Output, EinopsError> {
+        Ok(ArrayD::broadcast(self, IxDyn(shape))
+            .ok_or_else(|| {
+                EinopsError::new(format!(
+                    "cannot broadcast array of shape {:?} to {:?}",
+                    self.shape(),
+                    shape
+                ))
+            })?
+            .to_owned())
+    }
+
+    fn is_contiguous(&self) -> bool {
+        self.is_standard_layout()
+    }
+
+    fn contiguous(&self) -> Result<Self::Output, EinopsError> {
+        Ok(self.as_standard_layout().into_owned())
+    }
+
+    fn concat(inputs: &[&Self], axis: usize) -> Result<Self::Output, EinopsError> {
+        let views = inputs.iter().map(|input| input.view()).collect::<Vec<_>>();
+        Ok(ndarray::concatenate(Axis(axis), &views)?)
+    }
+
+    fn index_select(&self, axis: usize, indices: &[usize]) -> Result<Self::Output, EinopsError> {
+        // `select` panics on out of bounds indices
+        if axis >= self.ndim() || indices.iter().any(|&i| i >= self.len_of(Axis(axis))) {
+            return Err(EinopsError::new(format!(
+                "cannot select indices {:?} of axis {} of array with shape {:?}",
+                indices,
+                axis,
+                self.shape()
+            )));
+        }
+
+        Ok(self.select(Axis(axis), indices))
+    }
+
+    fn scan(&self, axis: usize, operation: ScanOp) -> Result<Self::Output, EinopsError> {
+        // `accumulate_axis_inplace` panics on an out of bounds axis
+        if axis >= self.ndim() {
+            return Err(EinopsError::new(format!(
+                "cannot scan axis {} of array with shape {:?}",
+                axis,
+                self.shape()
+            )));
+        }
+
+        let mut output = self.clone();
+        output.accumulate_axis_inplace(Axis(axis), |previous, x| {
+            *x = match operation {
+                ScanOp::Sum => previous.clone() + x.clone(),
+                ScanOp::Prod => previous.clone() * x.clone(),
+                ScanOp::Max if *previous > *x => previous.clone(),
+                ScanOp::Max => x.clone(),
+            }
+        });
+        Ok(output)
+    }
+
+    fn contract(&self, rhs: &Self) -> Result<Self::Output, EinopsError> {
+        let rank = self.ndim();
+        let (lhs_shape, rhs_shape) = (self.shape(), rhs.shape());
+        let compatible = rank >= 2
+            && rhs.ndim() == rank
+            && lhs_shape[..rank - 2] == rhs_shape[..rank - 2]
+            && lhs_shape[rank - 1] == rhs_shape[rank - 2];
+        if !compatible {
+            return Err(EinopsError::new(format!(
+                "cannot contract arrays of shape {:?} and {:?}",
+                lhs_shape, rhs_shape
+            )));
+        }
+
+        let batch = lhs_shape[..rank - 2].iter().product::<usize>();
+        let (m, k, n) = (
+            lhs_shape[rank - 2],
+            lhs_shape[rank - 1],
+            rhs_shape[rank - 1],
+        );
+        let lhs = self.to_shape((batch, m, k))?;
+        let rhs = rhs.to_shape((batch, k, n))?;
+
+        let output = ndarray::Array3::from_shape_fn((batch, m, n), |(b, i, j)| {
+            (0..k).fold(A::zero(), |sum, l| {
+                sum + lhs[[b, i, l]].clone() * rhs[[b, l, j]].clone()
+            })
+        });
+
+        let mut shape = lhs_shape.to_vec();
+        shape[rank - 1] = n;
+        Ok(output.into_shape_with_order(IxDyn(&shape))?)
+    }
+
+    fn device(&self) -> Option<Device> {
+        Some(Device::Cpu)
+    }
+
+    fn to_device(&self, device: Device) -> Result<Self::Output, EinopsError> {
+        if device != Device::Cpu {
+            return Err(EinopsError::new(format!(
+                "cannot move tensor to {:?}, only the CPU is supported",
+                device
+            )));
+        }
+        Ok(self.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ndarray::{Array, ArrayD};
+
+    #[test]
+    #[allow(clippy::excessive_precision)]
+    fn ndarray_reduce() {
+        let tests = vec![
+            (
+                Array::from_shape_vec(
+                    IxDyn(&[4, 2, 3]),
+                    vec![
+                        0.66984287f32,
+                        0.52894678,
+                        0.85415958,
+                        0.17721198,
+                        0.81804799,
+                        0.80991797,
+                        0.64868822,
+                        0.96697902,
+                        0.08047191,
+                        0.46024353,
+                        0.21955009,
+                        0.31731976,
+                        0.05446258,
+                        0.39454557,
+                        0.40949016,
+                        0.21366165,
+                        0.2357463,
+                        0.93699481,
+                        0.64522596,
+                        0.4383618,
+                        0.54871827,
+                        0.87823442,
+                        0.01261184,
+                        0.90636503,
+                    ],
+                )
+                .unwrap(),
+                [(0, Operation::Min)],
+                Array::from_shape_vec(
+                    IxDyn(&[2, 3]),
+                    vec![
+                        0.05446258f32,
+                        0.39454557,
+                        0.08047191,
+                        0.17721198,
+                        0.01261184,
+                        0.31731976,
+                    ],
+                )
+                .unwrap(),
+            ),
+            (
+                Array::from_shape_vec(IxDyn(&[2, 3]), vec![0.0f32, 1.0, 2.0, 3.0, 4.0, 5.0])
+                    .unwrap(),
+                [(1, Operation::Mean)],
+                Array::from_shape_vec(IxDyn(&[2]), vec![1.0f32, 4.0]).unwrap(),
+            ),
+        ];
+
+        for (tensor, mut axes_operations, expected) in tests {
+            assert_eq!(tensor.reduce_axes(&mut axes_operations).unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn ndarray_transpose() {
+        let tests = vec![(
+            ArrayD::from_shape_vec(IxDyn(&[2, 3, 4]), (0..24).collect::<Vec<u32>>()).unwrap(),
+            &[2, 0, 1],
+            ArrayD::from_shape_vec(
+                IxDyn(&[4, 2, 3]),
+                vec![
+                    0u32, 4, 8, 12, 16, 20, 1, 5, 9, 13, 17, 21, 2, 6, 10, 14, 18, 22, 3, 7, 11,
+                    15, 19, 23,
+                ],
+            )
+            .unwrap(),
+        )];
+
+        for (tensor, axes, expected) in tests {
+            assert_eq!(Backend::transpose(&tensor, axes).unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn ndarray_add_axes() {
+        let tests = vec![(
+            ArrayD::from_shape_vec(IxDyn(&[1, 2, 3]), (0..6).collect::<Vec<u8>>()).unwrap(),
+            5,
+            &[(0, 5), (3, 3)],
+            ArrayD::from_shape_vec(
+                IxDyn(&[5, 1, 2, 3, 3]),
+                vec![
+                    0u8, 1, 2, 0, 1, 2, 0, 1, 2, 3, 4, 5, 3, 4, 5, 3, 4, 5, 0, 1, 2, 0, 1, 2, 0, 1,
+                    2, 3, 4, 5, 3, 4, 5, 3, 4, 5, 0, 1, 2, 0, 1, 2, 0, 1, 2, 3, 4, 5, 3, 4, 5, 3,
+                    4, 5, 0, 1, 2, 0, 1, 2, 0, 1, 2, 3, 4, 5, 3, 4, 5, 3, 4, 5, 0, 1, 2, 0, 1, 2,
+                    0, 1, 2, 3, 4, 5, 3, 4, 5, 3, 4, 5,
+                ],
+            )
+            .unwrap(),
+        )];
+
+        for (tensor, naxes, pos2len, expected) in tests {
+            assert_eq!(tensor.add_axes(naxes, pos2len).unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn ndarray_narrow() {
+        let tests = vec![(
+            ArrayD::from_shape_vec(IxDyn(&[2, 3, 4]), (0..24).collect::<Vec<u32>>()).unwrap(),
+            (1, 1, 2),
+            ArrayD::from_shape_vec(
+                IxDyn(&[2, 2, 4]),
+                vec![4u32, 5, 6, 7, 8, 9, 10, 11, 16, 17, 18, 19, 20, 21, 22, 23],
+            )
+            .unwrap(),
+        )];
+
+        for (tensor, (axis, start, len), expected) in tests {
+            assert_eq!(tensor.narrow(axis, start, len).unwrap(), expected);
+            assert!(tensor.narrow(axis, start, 3).is_err());
+        }
+    }
+
+    #[test]
+    fn ndarray_broadcast() {
+        let tests = vec![(
+            ArrayD::from_shape_vec(IxDyn(&[2, 1]), vec![0u32, 1]).unwrap(),
+            &[3, 2, 2],
+            ArrayD::from_shape_vec(
+                IxDyn(&[3, 2, 2]),
+                vec![0u32, 0, 1, 1, 0, 0, 1, 1, 0, 0, 1, 1],
+            )
+            .unwrap(),
+        )];
+
+        for (tensor, shape, expected) in tests {
+            assert_eq!(Backend::broadcast(&tensor, shape).unwrap(), expected);
+            assert!(Backend::broadcast(&tensor, &[3, 2]).is_err());
+        }
+    }
+
+    #[test]
+    fn ndarray_contiguous() {
+        let tensor = ArrayD::from_shape_vec(IxDyn(&[2, 3]), (0..6).collect::<Vec<u32>>())
+            .unwrap()
+            .reversed_axes();
+        assert!(!Backend::is_contiguous(&tensor));
+
+        let output = Backend::contiguous(&tensor).unwrap();
+        assert!(Backend::is_contiguous(&output));
+        assert_eq!(output, tensor);
+    }
+
+    #[test]
+    fn ndarray_concat() {
+        let a = ArrayD::from_shape_vec(IxDyn(&[2, 1]), vec![0u32, 3]).unwrap();
+        let b = ArrayD::from_shape_vec(IxDyn(&[2, 2]), vec![1u32, 2, 4, 5]).unwrap();
+
+        let output = Backend::concat(&[&a, &b], 1).unwrap();
+        assert_eq!(
+            output,
+            ArrayD::from_shape_vec(IxDyn(&[2, 3]), vec![0, 1, 2, 3, 4, 5]).unwrap()
+        );
+        assert!(Backend::concat(&[&a, &b], 0).is_err());
+    }
+
+    #[test]
+    fn ndarray_index_select() {
+        let tensor = ArrayD::from_shape_vec(IxDyn(&[2, 3]), (0..6).collect::<Vec<u32>>()).unwrap();
+
+        let output = Backend::index_select(&tensor, 1, &[2, 0, 2]).unwrap();
+        assert_eq!(
+            output,
+            ArrayD::from_shape_vec(IxDyn(&[2, 3]), vec![2, 0, 2, 5, 3, 5]).unwrap()
+        );
+        assert!(Backend::index_select(&tensor, 1, &[3]).is_err());
+    }
+
+    #[test]
+    fn ndarray_contract() {
+        let lhs = ArrayD::from_shape_vec(IxDyn(&[2, 2, 3]), (0..12).collect::<Vec<i32>>()).unwrap();
+        let rhs = ArrayD::from_elem(IxDyn(&[2, 3, 1]), 1);
+
+        let output = Backend::contract(&lhs, &rhs).unwrap();
+        assert_eq!(
+            output,
+            ArrayD::from_shape_vec(IxDyn(&[2, 2, 1]), vec![3, 12, 21, 30]).unwrap()
+        );
+        assert!(Backend::contract(&lhs, &lhs).is_err());
+    }
+
+    #[test]
+    fn ndarray_scan() {
+        let array = ArrayD::from_shape_vec(IxDyn(&[2, 3]), vec![1, 3, 2, 4, 0, 5]).unwrap();
+
+        let output = Backend::scan(&array, 1, ScanOp::Sum).unwrap();
+        assert_eq!(output.into_raw_vec_and_offset().0, [1, 4, 6, 4, 4, 9]);
+        let output = Backend::scan(&array, 1, ScanOp::Max).unwrap();
+        assert_eq!(output.into_raw_vec_and_offset().0, [1, 3, 3, 4, 4, 5]);
+        assert!(Backend::scan(&array, 2, ScanOp::Sum).is_err());
+    }
+}
@@ -0,0 +1,61 @@
+//! Channel groups of normalization layers, for any backend
+//!
+//! Group normalization normalizes `groups` groups of neighbouring channels of
+//! images `[batch, channels, height, width]` on their own, instance
+//! normalization is the case of one channel per group and layer normalization
+//! the case of a single group.
+
+use crate::{einops, Backend, EinopsError, ErrorKind, Operation};
+
+/// Splits the channels of `x` into `groups` groups, `b (g c) h w -> b g c h w`
+pub fn group_channels<T: Backend<Output = T>>(x: &T, groups: usize) -> Result<T, EinopsError> {
+    check_groups(x, groups)?;
+    einops!("b ({groups} c) h w -> b {groups} c h w", x)
+}
+
+/// Inverse of [`group_channels`], `b g c h w -> b (g c) h w`
+pub fn ungroup_channels<T: Backend<Output = T>>(x: &T) -> Result<T, EinopsError> {
+    einops!("b g c h w -> b (g c) h w", x)
+}
+
+/// Reduces every group of channels of `x` with `operation`, over the channels
+/// and pixels of the group, `b (g c) h w -> b g`
+///
+/// [`Operation::Mean`] gives the mean of every group, the variance is the mean
+/// of the squares minus the square of the mean.
+pub fn group_reduce<T: Backend<Output = T>>(
+    x: &T,
+    groups: usize,
+    operation: Operation,
+) -> Result<T, EinopsError> {
+    let grouped = group_channels(x, groups)?;
+    match operation {
+        Operation::Min => einops!("b g min(c) min(h) min(w) -> b g", &grouped),
+        Operation::Max => einops!("b g max(c) max(h) max(w) -> b g", &grouped),
+        Operation::Sum => einops!("b g sum(c) sum(h) sum(w) -> b g", &grouped),
+        Operation::Mean => einops!("b g mean(c) mean(h) mean(w) -> b g", &grouped),
+    }
+}
+
+fn check_groups<T: Backend>(x: &T, groups: usize) -> Result<(), EinopsError> {
+    let shape = x.shape();
+    if shape.len() != 4 {
+        return Err(ErrorKind::RankMismatch {
+            expected: 4,
+            found: shape.len(),
+        }
+        .into());
+    }
+    let channels = shape[1];
+    if groups == 0 || !channels.is_multiple_of(groups) {
+        return Err(ErrorKind::NotDivisible {
+            axis: 1,
+            group: "(g c)".into(),
+            sizes: vec![("g".into(), groups)],
+            size: channels,
+            divisor: groups,
+        }
+        .into());
+    }
+    Ok(())
+}
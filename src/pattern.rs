@@ -0,0 +1,138 @@
+//! Tokens of the patterns read at runtime, with their byte ranges
+//!
+//! Layers, the patterns of the C API and the layouts of safetensors files are
+//! parsed from these tokens, so tools highlighting axes in pattern strings see
+//! them the way the parser does.
+//!
+//! ```
+//! use candle_einops::pattern::{tokenize, TokenKind};
+//!
+//! let pattern = "b (h w:2) -> b h w";
+//! let tokens = tokenize(pattern)?;
+//! assert_eq!(tokens[2].kind, TokenKind::Axis);
+//! assert_eq!(tokens[2].text(pattern), "h");
+//! assert_eq!(tokens[2].span, 3..4);
+//! # Ok::<(), candle_einops::EinopsError>(())
+//! ```
+
+use std::borrow::Cow;
+use std::ops::Range;
+
+use crate::{EinopsError, ErrorKind};
+
+/// What a [`Token`] is
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum TokenKind {
+    /// Name of an axis, like `h`
+    Axis,
+    /// Size of the axis before it, the digits of `w:2`
+    Size,
+    /// `(`, opening a group
+    Open,
+    /// `)`, closing a group
+    Close,
+    /// `..`, standing for any number of axes
+    Ellipsis,
+    /// `->`, between the sides of a pattern
+    Arrow,
+    /// `,`, between the inputs of a pattern of several inputs
+    Comma,
+}
+
+/// Token of a pattern, at the bytes `span`
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Token {
+    pub kind: TokenKind,
+    pub span: Range<usize>,
+}
+
+impl Token {
+    /// Text of the token in `pattern`, the pattern it was read from
+    pub fn text<'a>(&self, pattern: &'a str) -> &'a str {
+        &pattern[self.span.clone()]
+    }
+}
+
+/// Splits `pattern` into tokens, skipping whitespace
+///
+/// Characters that start no token and `:` without digits after it are
+/// [`ErrorKind::ParseError`] errors. Whether the tokens form a valid pattern,
+/// with matched parentheses and an axis in every group, is checked by the
+/// layers.
+pub fn tokenize(pattern: &str) -> Result<Vec<Token>, EinopsError> {
+    tokens(pattern, 0, pattern.len())
+}
+
+// Tokens of `pattern[start..end]`, with spans in `pattern`
+pub(crate) fn tokens(pattern: &str, start: usize, end: usize) -> Result<Vec<Token>, EinopsError> {
+    let bytes = pattern.as_bytes();
+    let mut tokens = Vec::new();
+    let mut push = |kind, span: Range<usize>| tokens.push(Token { kind, span });
+
+    let mut i = start;
+    while i < end {
+        let token = i;
+        match bytes[i] {
+            byte if byte.is_ascii_whitespace() => i += 1,
+            b'(' | b')' | b',' => {
+                i += 1;
+                let kind = match bytes[token] {
+                    b'(' => TokenKind::Open,
+                    b')' => TokenKind::Close,
+                    _ => TokenKind::Comma,
+                };
+                push(kind, token..i);
+            }
+            b'.' if pattern[i..end].starts_with("..") => {
+                i += 2;
+                push(TokenKind::Ellipsis, token..i);
+            }
+            b'-' if pattern[i..end].starts_with("->") => {
+                i += 2;
+                push(TokenKind::Arrow, token..i);
+            }
+            byte if byte.is_ascii_alphabetic() || byte == b'_' => {
+                while i < end && (bytes[i].is_ascii_alphanumeric() || bytes[i] == b'_') {
+                    i += 1;
+                }
+                push(TokenKind::Axis, token..i);
+                if i < end && bytes[i] == b':' {
+                    let digits = i + 1;
+                    i = digits;
+                    while i < end && bytes[i].is_ascii_digit() {
+                        i += 1;
+                    }
+                    if i == digits {
+                        return Err(parse_error(
+                            pattern,
+                            digits,
+                            format!("expected a size after `{}:`", &pattern[token..digits - 1]),
+                        ));
+                    }
+                    push(TokenKind::Size, digits..i);
+                }
+            }
+            _ => {
+                let unexpected = pattern[i..].chars().next().unwrap_or_default();
+                return Err(parse_error(
+                    pattern,
+                    i,
+                    format!("unexpected `{}`", unexpected),
+                ));
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+// Syntax errors, underlining the byte at `offset`
+pub(crate) fn parse_error(pattern: &str, offset: usize, reason: impl Into<String>) -> EinopsError {
+    let len = usize::from(offset < pattern.len());
+    let pattern = Cow::<'static, str>::Owned(pattern.to_string());
+    EinopsError::from(ErrorKind::ParseError {
+        pattern: pattern.clone(),
+        offset,
+        reason: reason.into(),
+    })
+    .with_label(pattern, offset, len)
+}
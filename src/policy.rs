@@ -0,0 +1,85 @@
+//! Choices of how `einops!` treats edge cases
+//!
+//! Rearranging and repeating tensors with axes of length 0 always works, the
+//! outputs are empty as well. What reducing an empty axis returns is picked
+//! with the [`EmptyReduction`] policy, the same for every backend, for a call
+//! with `einops!(.., empty_reduction = error)` or for a layer with
+//! `layers::Builder::empty_reduction`.
+//!
+//! Axes of length 1 are broadcast to the new axes grouped with them like any
+//! other axis. The [`Broadcast::Strict`] policy, picked for a call with
+//! `einops!(.., broadcast = strict)` or for a layer with
+//! `layers::Builder::broadcast`, makes that an
+//! error, to catch inputs with an unexpected singleton axis that would be
+//! stretched silently:
+//!
+//! ```ignore
+//! // Fails for an input of shape [2, 1, 3], `b` would become 4 long
+//! einops!("a b c -> a (b r:4) c", &input, broadcast = strict)?;
+//! ```
+
+use crate::{EinopsError, ErrorKind, Operation};
+
+/// What reducing an axis of length 0 returns
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum EmptyReduction {
+    /// Sums are 0, minima, maxima and means have no identity element and
+    /// return an [`ErrorKind::EmptyReduction`] error
+    #[default]
+    Identity,
+    /// Every reduction of an empty axis returns an error
+    Error,
+}
+
+// Called by `einops!` before reducing a tensor of shape `shape` under the
+// policy `policy`
+#[doc(hidden)]
+pub fn check_reduction(
+    shape: &[usize],
+    axes_operations: &[(usize, Operation)],
+    policy: EmptyReduction,
+) -> Result<(), EinopsError> {
+    let empty = axes_operations.iter().find(|&&(axis, operation)| {
+        shape.get(axis) == Some(&0)
+            && (policy == EmptyReduction::Error || operation != Operation::Sum)
+    });
+    match empty {
+        Some(&(axis, operation)) => Err(ErrorKind::EmptyReduction { axis, operation }.into()),
+        None => Ok(()),
+    }
+}
+
+/// Whether input axes of length 1 are broadcast by patterns repeating the
+/// input
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Broadcast {
+    /// Axes of length 1 are accepted like axes of any other length
+    #[default]
+    Implicit,
+    /// A named input axis of length 1 grouped with new axes that make its
+    /// output axis longer, like `b` in `a b -> a (b r:4)`, is an
+    /// [`ErrorKind::ImplicitBroadcast`] error, unless it's written as `1` or
+    /// given the size 1 like `b:1`. Axes of length 1 kept as they are next to
+    /// new axes, like in `a b -> a b r:4`, are fine
+    Strict,
+}
+
+// Called by `einops!` under the strict policy before adding axes to a tensor
+// of shape `shape`, with the position and name of an input axis that has no
+// size in the pattern and the length of the new axes grouped with it
+#[doc(hidden)]
+pub fn check_broadcast(
+    shape: &[usize],
+    axis: usize,
+    name: &str,
+    repeated: usize,
+) -> Result<(), EinopsError> {
+    if shape.get(axis) == Some(&1) && repeated > 1 {
+        return Err(ErrorKind::ImplicitBroadcast {
+            axis,
+            name: name.to_string().into(),
+        }
+        .into());
+    }
+    Ok(())
+}
@@ -0,0 +1,1613 @@
+//! Patterns read at runtime by the `layers` and the named layouts of
+//! [`safetensors`](crate::safetensors)
+//!
+//! A subset of the `einops!` syntax: named axes, sizes like `h:2`, groups and
+//! `..`, read from the tokens of [`pattern`](crate::pattern). The pattern and
+//! the sizes are checked when the recipe is built, the input shape when it's
+//! applied.
+
+use std::borrow::Cow;
+use std::fmt;
+use std::sync::{Arc, Mutex, PoisonError};
+
+#[cfg(feature = "cpu")]
+use crate::cpu::{self, CpuTensor, Element};
+use crate::deferred::{self, Step};
+use crate::pattern::{self, parse_error, Token, TokenKind};
+use crate::policy::{Broadcast, EmptyReduction};
+use crate::trace::Hook;
+use crate::{
+    checked_product, metrics, policy, trace, Backend, Device, EinopsError, ErrorKind, Operation,
+    SmallAxes,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Item {
+    // An axis or a parenthesized group, with its text and offset in the pattern
+    Group {
+        text: String,
+        offset: usize,
+        names: Vec<String>,
+    },
+    Ellipsis,
+}
+
+// Axis of the input after decomposing its groups
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Flat<'a> {
+    Named(&'a str),
+    // Axis `i` of the ones standing for `..`
+    Ignored(usize),
+}
+
+// What a recipe does with the axes found on one side only
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Kind {
+    Rearrange,
+    // Reduces the axes missing on the right
+    Reduce(Operation),
+    // Adds the axes missing on the left, which need a size
+    Repeat,
+    // Axes on one side only are mixed with a weight by `EinMix`, which checks
+    // them itself and only uses the recipe to read the shapes
+    Mix,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct Recipe {
+    // Shared by the clones of layers
+    pattern: Arc<str>,
+    left: Vec<Item>,
+    right: Vec<Item>,
+    sizes: Vec<(String, usize)>,
+    kind: Kind,
+    broadcast: Broadcast,
+    empty_reduction: EmptyReduction,
+}
+
+// Shapes of the steps applying a recipe to an input shape
+#[derive(Debug)]
+struct Plan<'a> {
+    // Number of axes every input axis is decomposed into
+    groups: SmallAxes,
+    decomposed: SmallAxes,
+    reduced: SmallAxes<(usize, Operation)>,
+    permutation: SmallAxes,
+    // Positions and lengths of the new axes
+    added: SmallAxes<(usize, usize)>,
+    output_shape: SmallAxes,
+    bindings: SmallAxes<(&'a str, usize)>,
+}
+
+impl Plan<'_> {
+    // Shape after reducing, permuting and adding axes, before composing groups
+    fn expanded_shape(&self) -> SmallAxes {
+        let remaining = self.remaining_shape();
+        let mut shape = self
+            .permutation
+            .iter()
+            .map(|&axis| remaining[axis])
+            .collect::<SmallAxes>();
+        for &(position, len) in self.added.iter() {
+            shape.insert(position, len);
+        }
+        shape
+    }
+
+    // Shape after reducing
+    fn remaining_shape(&self) -> SmallAxes {
+        (0..self.decomposed.len())
+            .filter(|&axis| self.reduced.iter().all(|&(reduced, _)| reduced != axis))
+            .map(|axis| self.decomposed[axis])
+            .collect()
+    }
+
+    // One step for every transformation of the pattern, with the shape of its
+    // output
+    fn steps(&self) -> Vec<(Step, Vec<usize>)> {
+        let mut steps = vec![(
+            Step::Reshape(self.decomposed.to_vec()),
+            self.decomposed.to_vec(),
+        )];
+        if !self.reduced.is_empty() {
+            steps.push((
+                Step::Reduce(self.reduced.to_vec()),
+                self.remaining_shape().to_vec(),
+            ));
+        }
+        let expanded = self.expanded_shape();
+        let mut permuted = expanded.clone();
+        for &(position, _) in self.added.iter().rev() {
+            permuted.remove(position);
+        }
+        steps.push((
+            Step::Transpose(self.permutation.to_vec()),
+            permuted.to_vec(),
+        ));
+        if !self.added.is_empty() {
+            let naxes = self.permutation.len() + self.added.len();
+            steps.push((Step::AddAxes(naxes, self.added.to_vec()), expanded.to_vec()));
+        }
+        steps.push((
+            Step::Reshape(self.output_shape.to_vec()),
+            self.output_shape.to_vec(),
+        ));
+        steps
+    }
+
+    // Steps transposing the input axes before composing the output, for
+    // patterns keeping the axes of every input axis together and in order,
+    // like `b (h w) c -> b c (h w)`
+    fn permute_first(&self, input_shape: &[usize]) -> Option<Vec<(Step, Vec<usize>)>> {
+        if !self.reduced.is_empty() || !self.added.is_empty() || self.groups.contains(&0) {
+            return None;
+        }
+        // First decomposed axis of every input axis
+        let starts = self
+            .groups
+            .iter()
+            .scan(0, |start, &len| {
+                *start += len;
+                Some(*start - len)
+            })
+            .collect::<SmallAxes>();
+        let mut order = SmallAxes::default();
+        let mut j = 0;
+        while j < self.permutation.len() {
+            let axis = starts
+                .iter()
+                .position(|&start| start == self.permutation[j])?;
+            let run = self.permutation.get(j..j + self.groups[axis])?;
+            if run
+                .iter()
+                .enumerate()
+                .any(|(k, &flat)| flat != starts[axis] + k)
+            {
+                return None;
+            }
+            order.push(axis);
+            j += run.len();
+        }
+        let permuted = order.iter().map(|&axis| input_shape[axis]).collect();
+        Some(vec![
+            (Step::Transpose(order.to_vec()), permuted),
+            (
+                Step::Reshape(self.output_shape.to_vec()),
+                self.output_shape.to_vec(),
+            ),
+        ])
+    }
+}
+
+/// Order of the steps of a layer that only rearranges axes
+///
+/// Patterns keeping the axes of every input axis together and in order, like
+/// `b (h w) c -> b c (h w)`, can transpose the input before reshaping it,
+/// which saves the copy of reshaping a view whose axes are permuted. The order
+/// with fewer copies for the layout of the input is picked, reshaping first
+/// when they copy as much.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum Order {
+    /// Decomposes the groups of the input, transposes and composes the groups
+    /// of the output
+    #[default]
+    ReshapeFirst,
+    /// Transposes the input axes, then reshapes to the output
+    PermuteFirst,
+}
+
+// What the steps of an order copy, as described in `Program::max_copies`
+#[derive(Debug, Default, Clone, Copy)]
+struct Copies {
+    copies: usize,
+    // Elements of the copied tensors
+    elements: usize,
+    // Copies of views whose innermost axis was moved, which gather every
+    // element on their own
+    strided: usize,
+}
+
+impl Copies {
+    // Copies of `steps` from an input of shape `shape`, with the `probes` of
+    // the first step of a non-contiguous input
+    fn of(
+        shape: &[usize],
+        steps: &[(Step, SmallAxes)],
+        probes: Option<&[(Step, Option<bool>)]>,
+    ) -> Self {
+        let mut copies = Self::default();
+        let mut contiguous = probes.is_none();
+        let mut strided = false;
+        let mut input = shape;
+        for (i, (step, output)) in steps.iter().enumerate() {
+            let probed = probes
+                .filter(|_| i == 0)
+                .and_then(|probes| probes.iter().find(|(probed, _)| probed == step))
+                .and_then(|&(_, view)| view);
+            let copied = match step {
+                _ if probed.is_some() => {
+                    contiguous = probed == Some(true);
+                    false
+                }
+                Step::Reduce(_) => true,
+                Step::Reshape(_) => !contiguous,
+                Step::Transpose(axes) => {
+                    contiguous = false;
+                    strided |= axes.last() != Some(&(axes.len() - 1));
+                    false
+                }
+                Step::AddAxes(_, added) => {
+                    contiguous &= added.iter().all(|&(_, len)| len == 1);
+                    false
+                }
+                _ => false,
+            };
+            if copied {
+                copies.copies += 1;
+                copies.elements += input.iter().product::<usize>();
+                copies.strided += usize::from(strided);
+                contiguous = true;
+                strided = false;
+            }
+            input = output;
+        }
+        copies
+    }
+
+    // What is kept low on `device`: CPUs copy the fewest elements, CUDA
+    // launches the fewest copy kernels and Metal also avoids gathering
+    // the innermost axis, which it copies with a slower kernel
+    fn cost(&self, device: Option<Device>) -> (usize, usize) {
+        match device {
+            None | Some(Device::Cpu) => (self.elements, self.copies),
+            Some(Device::Cuda(_)) => (self.copies, self.elements),
+            Some(Device::Metal(_)) => (self.copies + self.strided, self.elements),
+        }
+    }
+}
+
+// What programs are compiled for, the layout and device of an input
+pub(crate) trait Layout {
+    fn is_contiguous(&self) -> bool;
+    fn device(&self) -> Option<Device>;
+    // Whether the input runs `step` as a view, and whether the view is
+    // contiguous if it does
+    fn view(&self, step: &Step) -> Option<bool>;
+}
+
+impl<T: Backend<Output = T>> Layout for T {
+    fn is_contiguous(&self) -> bool {
+        Backend::is_contiguous(self)
+    }
+
+    fn device(&self) -> Option<Device> {
+        Backend::device(self)
+    }
+
+    fn view(&self, step: &Step) -> Option<bool> {
+        match step {
+            Step::Reshape(shape) => self.reshape_view(shape),
+            Step::Transpose(axes) => self.transpose_view(axes),
+            _ => None,
+        }
+        .map(|view| Backend::is_contiguous(&view))
+    }
+}
+
+// A contiguous input of an unknown device, the one the shapes of the layers
+// are planned for
+pub(crate) struct Contiguous;
+
+impl Layout for Contiguous {
+    fn is_contiguous(&self) -> bool {
+        true
+    }
+
+    fn device(&self) -> Option<Device> {
+        None
+    }
+
+    fn view(&self, _: &Step) -> Option<bool> {
+        None
+    }
+}
+
+/// The program last compiled for recipes, reused while their inputs have the
+/// same shape, layout and device so that applying them to a stream of
+/// contiguous inputs only compares the shapes and devices, and the hook of
+/// the layer running them
+#[derive(Default)]
+pub(crate) struct Cache {
+    program: Mutex<Option<Arc<Program>>>,
+    hook: Option<Arc<dyn Hook>>,
+}
+
+impl Cache {
+    pub(crate) fn set_hook(&mut self, hook: Arc<dyn Hook>) {
+        self.hook = Some(hook);
+    }
+
+    /// A cache without programs, for other recipes run with the same hook
+    pub(crate) fn cleared(&self) -> Self {
+        Self {
+            program: Mutex::default(),
+            hook: self.hook.clone(),
+        }
+    }
+
+    pub(crate) fn program(
+        &self,
+        recipes: &[Recipe],
+        shape: &[usize],
+        input: &dyn Layout,
+    ) -> Result<Arc<Program>, EinopsError> {
+        let mut cached = self.program.lock().unwrap_or_else(PoisonError::into_inner);
+        match &*cached {
+            Some(program) if program.compiled_for(shape, input) => Ok(program.clone()),
+            _ => {
+                let program = Arc::new(Program::compile_with(recipes, shape, input)?);
+                *cached = Some(program.clone());
+                Ok(program)
+            }
+        }
+    }
+
+    pub(crate) fn apply<T: Backend<Output = T> + Clone>(
+        &self,
+        recipes: &[Recipe],
+        input: &T,
+    ) -> Result<T, EinopsError> {
+        let program = self.program(recipes, &Backend::shape(input), input)?;
+        let _span = trace::enter(&program.label, input);
+        let _scope = metrics::enter_layer(&program.label);
+        program.run(recipes, input, self.hook.as_ref())
+    }
+
+    // Applies the recipes to the row-major `input` of shape `shape`, writing
+    // the output to `output`
+    #[cfg(feature = "cpu")]
+    pub(crate) fn apply_into<T: Element>(
+        &self,
+        recipes: &[Recipe],
+        input: &[T],
+        shape: &[usize],
+        output: &mut [T],
+    ) -> Result<(), EinopsError> {
+        let program = self.program(recipes, shape, &Contiguous)?;
+        let _span = trace::enter_buffer(&program.label, shape);
+        let _scope = metrics::enter_layer(&program.label);
+        program.run_into(recipes, input, output, self.hook.as_ref())
+    }
+
+    // Plans once for a run of inputs of the same shape, comparing the shape of
+    // every input with the one planned for
+    pub(crate) fn apply_batch<T: Backend<Output = T> + Clone>(
+        &self,
+        recipes: &[Recipe],
+        inputs: &[T],
+    ) -> Result<Vec<T>, EinopsError> {
+        let mut program: Option<Arc<Program>> = None;
+        inputs
+            .iter()
+            .map(|input| {
+                let shape = Backend::shape(input);
+                let program = match &program {
+                    Some(program) if program.compiled_for(&shape, input) => program,
+                    _ => program.insert(self.program(recipes, &shape, input)?),
+                };
+                let _span = trace::enter(&program.label, input);
+                let _scope = metrics::enter_layer(&program.label);
+                program.run(recipes, input, self.hook.as_ref())
+            })
+            .collect()
+    }
+}
+
+impl Clone for Cache {
+    fn clone(&self) -> Self {
+        let cached = self.program.lock().unwrap_or_else(PoisonError::into_inner);
+        Self {
+            program: Mutex::new(cached.clone()),
+            hook: self.hook.clone(),
+        }
+    }
+}
+
+impl fmt::Debug for Cache {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let cached = self.program.lock().unwrap_or_else(PoisonError::into_inner);
+        f.debug_tuple("Cache")
+            .field(&cached.as_ref().map(|program| &*program.input_shape))
+            .finish()
+    }
+}
+
+/// Steps of recipes applied one after the other to an input shape, simplified
+/// together so that the reshapes and transposes of neighbouring recipes merge
+#[derive(Debug)]
+pub(crate) struct Program {
+    input_shape: SmallAxes,
+    // Patterns of the recipes, which the metrics count the work by
+    label: Arc<str>,
+    device: Option<Device>,
+    // What the input of a program compiled for a non-contiguous input gave
+    // for the first step of every order, which inputs are probed with to reuse
+    // the program
+    probes: Option<Vec<(Step, Option<bool>)>>,
+    order: Order,
+    planned: usize,
+    copies: usize,
+    steps: Vec<(Step, SmallAxes)>,
+    // Of the first recipe, for the context of errors
+    bindings: Vec<(String, usize)>,
+}
+
+impl Program {
+    /// Plans the steps for a contiguous input of shape `shape`
+    pub(crate) fn compile(recipes: &[Recipe], shape: &[usize]) -> Result<Self, EinopsError> {
+        Self::compile_with(recipes, shape, &Contiguous)
+    }
+
+    /// Plans the steps for `input`, with the [`Order`] that copies less for
+    /// its layout on its device
+    pub(crate) fn compile_for<T: Backend<Output = T>>(
+        recipes: &[Recipe],
+        input: &T,
+    ) -> Result<Self, EinopsError> {
+        Self::compile_with(recipes, &input.shape(), input)
+    }
+
+    fn compile_with(
+        recipes: &[Recipe],
+        shape: &[usize],
+        input: &dyn Layout,
+    ) -> Result<Self, EinopsError> {
+        let label = recipes
+            .iter()
+            .map(Recipe::pattern)
+            .collect::<Vec<_>>()
+            .join(" then ");
+        let device = input.device();
+        let _span = trace::enter_compile(&label, shape, device);
+        let mut steps = Vec::new();
+        // The same with the transposes first where the patterns allow it
+        let mut permuted = Vec::new();
+        let mut bindings = Vec::new();
+        let mut output_shape = shape.iter().copied().collect::<SmallAxes>();
+        for (i, recipe) in recipes.iter().enumerate() {
+            let plan = recipe.plan(&output_shape)?;
+            let planned = plan.steps();
+            permuted.extend(
+                plan.permute_first(&output_shape)
+                    .unwrap_or_else(|| planned.clone()),
+            );
+            steps.extend(planned);
+            output_shape = plan.output_shape;
+            if i == 0 {
+                bindings = plan
+                    .bindings
+                    .iter()
+                    .map(|&(name, len)| (name.to_string(), len))
+                    .collect();
+            }
+        }
+        let simplify = |steps: &[(Step, Vec<usize>)]| {
+            deferred::simplify(shape, steps)
+                .into_iter()
+                .map(|(step, shape)| (step, shape.into_iter().collect()))
+                .collect::<Vec<_>>()
+        };
+        let mut orders = vec![(Order::ReshapeFirst, steps.len(), simplify(&steps))];
+        if permuted != steps {
+            orders.push((Order::PermuteFirst, permuted.len(), simplify(&permuted)));
+        }
+        let probes = (!input.is_contiguous()).then(|| {
+            orders
+                .iter()
+                .filter_map(|(_, _, steps)| steps.first())
+                .map(|(step, _)| (step.clone(), input.view(step)))
+                .collect::<Vec<_>>()
+        });
+        // The first order that costs the least on the device
+        let (order, planned, steps, copies) = orders
+            .into_iter()
+            .map(|(order, planned, steps)| {
+                let copies = Copies::of(shape, &steps, probes.as_deref());
+                (order, planned, steps, copies)
+            })
+            .reduce(|first, next| {
+                if next.3.cost(device) < first.3.cost(device) {
+                    next
+                } else {
+                    first
+                }
+            })
+            .expect("there is an order");
+        Ok(Self {
+            input_shape: shape.iter().copied().collect(),
+            label: label.into(),
+            device,
+            probes,
+            order,
+            planned,
+            copies: copies.copies,
+            steps,
+            bindings,
+        })
+    }
+
+    fn compiled_for(&self, shape: &[usize], input: &dyn Layout) -> bool {
+        *self.input_shape == *shape
+            && self.device == input.device()
+            && match &self.probes {
+                None => input.is_contiguous(),
+                Some(probes) => {
+                    !input.is_contiguous()
+                        && probes.iter().all(|(step, view)| input.view(step) == *view)
+                }
+            }
+    }
+
+    pub(crate) fn device(&self) -> Option<Device> {
+        self.device
+    }
+
+    pub(crate) fn order(&self) -> Order {
+        self.order
+    }
+
+    /// Number of steps before they were simplified
+    pub(crate) fn planned(&self) -> usize {
+        self.planned
+    }
+
+    /// Number of steps that are run
+    pub(crate) fn optimized(&self) -> usize {
+        self.steps.len()
+    }
+
+    /// Copies of the data the steps make at most on a backend whose
+    /// transposes and new axes are views, like candle: a reduction writes a
+    /// new tensor and a reshape copies a tensor whose axes were permuted or
+    /// repeated since the last copy. The first step of an input that wasn't
+    /// contiguous is taken to copy unless the input ran it as a view, and the
+    /// order of the steps is the one picked for the device of the input
+    pub(crate) fn max_copies(&self) -> usize {
+        self.copies
+    }
+
+    pub(crate) fn output_shape(&self) -> &[usize] {
+        self.steps
+            .last()
+            .map_or(&self.input_shape, |(_, shape)| shape)
+    }
+
+    /// Runs the steps on `input`, of the shape the program was compiled for,
+    /// with the context of `recipes` on errors, calling `hook` around every
+    /// step
+    ///
+    /// The steps were planned when compiling, so running them allocates
+    /// nothing but what the backend and the hook allocate.
+    pub(crate) fn run<T: Backend<Output = T> + Clone>(
+        &self,
+        recipes: &[Recipe],
+        input: &T,
+        hook: Option<&Arc<dyn Hook>>,
+    ) -> Result<T, EinopsError> {
+        // The reductions run in the order of the recipes reducing their input
+        let mut policies = recipes
+            .iter()
+            .filter(|recipe| recipe.reduction().is_some())
+            .map(|recipe| recipe.empty_reduction);
+        let mut run = |tensor: &T, step: &Step, shape: &[usize], output_shape: &[usize]| {
+            if let Step::Reduce(reduced) = step {
+                let policy = policies.next().unwrap_or_default();
+                policy::check_reduction(shape, reduced, policy)?;
+            }
+            trace::before_op(hook, &self.label, step.kind(), shape);
+            let output = deferred::run(tensor, step);
+            let reported = output.as_ref().map(|_| output_shape);
+            trace::after_op(hook, &self.label, step.kind(), shape, reported);
+            output
+        };
+        let Some(((first, first_shape), steps)) = self.steps.split_first() else {
+            return Ok(input.clone());
+        };
+        let mut output = run(input, first, &self.input_shape, first_shape)
+            .map_err(|error| self.context(recipes, error))?;
+        let mut shape = &**first_shape;
+        for (step, step_shape) in steps {
+            output = run(&output, step, shape, step_shape)
+                .map_err(|error| self.context(recipes, error))?;
+            shape = step_shape;
+        }
+        Ok(output)
+    }
+
+    /// Runs the steps on the row-major `input`, writing the output to
+    /// `output`, which has its number of elements
+    ///
+    /// Reshapes of contiguous views, transposes and new axes only move a view
+    /// over `input`, which is then gathered into `output` at once. Steps that
+    /// need the data in between, reductions and reshapes of permuted views,
+    /// run on a [`CpuTensor`] whose output is copied to `output`.
+    #[cfg(feature = "cpu")]
+    pub(crate) fn run_into<T: Element>(
+        &self,
+        recipes: &[Recipe],
+        input: &[T],
+        output: &mut [T],
+        hook: Option<&Arc<dyn Hook>>,
+    ) -> Result<(), EinopsError> {
+        let len = self.output_shape().iter().product::<usize>();
+        if output.len() != len {
+            return Err(self.context(
+                recipes,
+                EinopsError::new(format!(
+                    "the output has {} elements, the buffer {}",
+                    len,
+                    output.len()
+                )),
+            ));
+        }
+        let mut shape = self.input_shape.clone();
+        let mut strides = cpu::strides(&shape).into_iter().collect::<SmallAxes>();
+        for (step, step_shape) in &self.steps {
+            let viewed = shape.clone();
+            match step {
+                Step::Reshape(reshaped) if *strides == *cpu::strides(&shape) => {
+                    shape = reshaped.iter().copied().collect();
+                    strides = cpu::strides(&shape).into_iter().collect();
+                }
+                Step::Transpose(axes) => {
+                    shape = axes.iter().map(|&axis| shape[axis]).collect();
+                    strides = axes.iter().map(|&axis| strides[axis]).collect();
+                }
+                Step::AddAxes(_, added) => {
+                    for &(axis, len) in added.iter() {
+                        shape.insert(axis, len);
+                        strides.insert(axis, 0);
+                    }
+                }
+                _ => {
+                    let input = CpuTensor::new(input.to_vec(), self.input_shape.to_vec())?;
+                    output.copy_from_slice(self.run(recipes, &input, hook)?.data());
+                    return Ok(());
+                }
+            }
+            // Views are reported like the steps run on tensors
+            trace::before_op(hook, &self.label, step.kind(), &viewed);
+            trace::after_op(hook, &self.label, step.kind(), &viewed, Ok(step_shape));
+        }
+        if len > 0 {
+            cpu::gather_into(input, &shape, &strides, output);
+        }
+        Ok(())
+    }
+
+    fn context(&self, recipes: &[Recipe], error: EinopsError) -> EinopsError {
+        let patterns = recipes
+            .iter()
+            .map(|recipe| owned(recipe.pattern()))
+            .collect();
+        let axes = recipes.first().map_or_else(Vec::new, Recipe::axes);
+        let bindings = self
+            .bindings
+            .iter()
+            .map(|(name, len)| (owned(name), *len))
+            .collect();
+        error.with_layer_context(patterns, axes, self.input_shape.to_vec(), bindings)
+    }
+}
+
+// Errors outlive the recipes, so they copy the texts of patterns
+fn owned(text: &str) -> Cow<'static, str> {
+    Cow::Owned(text.to_string())
+}
+
+pub(crate) fn invalid(pattern: &str, message: impl Into<String>) -> EinopsError {
+    ErrorKind::InvalidPattern {
+        pattern: owned(pattern),
+        message: message.into(),
+    }
+    .into()
+}
+
+// Axes whose length can't be known, underlining the first place `name` is
+// written in `items`
+fn unknown_axis(pattern: &str, items: &[Item], name: &str) -> EinopsError {
+    let error = EinopsError::from(ErrorKind::UnknownAxis { name: owned(name) });
+    match axis_offset(items, name) {
+        Some(offset) => error.with_label(owned(pattern), offset, name.len()),
+        None => error.with_label(owned(pattern), 0, 0),
+    }
+}
+
+// Offset of `name` in the pattern of `items`
+fn axis_offset(items: &[Item], name: &str) -> Option<usize> {
+    items.iter().find_map(|item| {
+        let Item::Group { text, offset, .. } = item else {
+            return None;
+        };
+        let word = |(_, c): &(usize, char)| c.is_ascii_alphanumeric() || *c == '_';
+        let mut chars = text.char_indices().peekable();
+        while let Some((start, c)) = chars.next() {
+            if c.is_ascii_alphabetic() || c == '_' {
+                let mut end = start + 1;
+                while let Some((i, _)) = chars.next_if(word) {
+                    end = i + 1;
+                }
+                if &text[start..end] == name {
+                    return Some(offset + start);
+                }
+            }
+        }
+        None
+    })
+}
+
+// Groups can infer the length of at most one of their axes
+fn check_inference(
+    pattern: &str,
+    item: &Item,
+    unknown: impl Fn(&str) -> bool,
+) -> Result<(), EinopsError> {
+    let Item::Group {
+        text,
+        offset,
+        names,
+    } = item
+    else {
+        return Ok(());
+    };
+    let candidates = names
+        .iter()
+        .filter(|name| unknown(name))
+        .map(|name| owned(name))
+        .collect::<Vec<_>>();
+    if candidates.len() < 2 {
+        return Ok(());
+    }
+    Err(EinopsError::from(ErrorKind::AmbiguousInference {
+        group: owned(text),
+        candidates,
+    })
+    .with_label(owned(pattern), *offset, text.len()))
+}
+
+fn bind(
+    pattern: &str,
+    sizes: &mut Vec<(String, usize)>,
+    name: &str,
+    size: usize,
+) -> Result<(), EinopsError> {
+    match sizes.iter().find(|(bound, _)| bound == name) {
+        Some(&(_, bound)) if bound != size => Err(invalid(
+            pattern,
+            format!("axis `{}` has the sizes {} and {}", name, bound, size),
+        )),
+        Some(_) => Ok(()),
+        None => {
+            sizes.push((name.to_string(), size));
+            Ok(())
+        }
+    }
+}
+
+// Parses `pattern[start..end]`, adding the sizes written in it to `sizes`
+pub(crate) fn parse_side(
+    pattern: &str,
+    start: usize,
+    end: usize,
+    sizes: &mut Vec<(String, usize)>,
+) -> Result<Vec<Item>, EinopsError> {
+    let mut items = Vec::new();
+    // Offset of the open group and its axes
+    let mut group: Option<(usize, Vec<String>)> = None;
+
+    let tokens = pattern::tokens(pattern, start, end)?;
+    let mut tokens = tokens.iter().peekable();
+    while let Some(Token { kind, span }) = tokens.next() {
+        match kind {
+            TokenKind::Open => {
+                if group.is_some() {
+                    return Err(parse_error(pattern, span.start, "groups can't be nested"));
+                }
+                group = Some((span.start, Vec::new()));
+            }
+            TokenKind::Close => {
+                let Some((offset, names)) = group.take() else {
+                    return Err(parse_error(pattern, span.start, "unmatched `)`"));
+                };
+                items.push(Item::Group {
+                    text: pattern[offset..span.end].to_string(),
+                    offset,
+                    names,
+                });
+            }
+            TokenKind::Ellipsis => {
+                if group.is_some() {
+                    return Err(parse_error(pattern, span.start, "`..` can't be grouped"));
+                }
+                items.push(Item::Ellipsis);
+            }
+            TokenKind::Axis => {
+                let name = &pattern[span.clone()];
+                let mut text = span.clone();
+                if let Some(size) = tokens.next_if(|token| token.kind == TokenKind::Size) {
+                    let len = pattern[size.span.clone()].parse().map_err(|_| {
+                        parse_error(
+                            pattern,
+                            size.span.start,
+                            format!("expected a size after `{}:`", name),
+                        )
+                    })?;
+                    bind(pattern, sizes, name, len)?;
+                    text.end = size.span.end;
+                }
+                match &mut group {
+                    Some((_, names)) => names.push(name.to_string()),
+                    None => items.push(Item::Group {
+                        text: pattern[text.clone()].to_string(),
+                        offset: text.start,
+                        names: vec![name.to_string()],
+                    }),
+                }
+            }
+            TokenKind::Size | TokenKind::Arrow | TokenKind::Comma => {
+                return Err(parse_error(
+                    pattern,
+                    span.start,
+                    format!("unexpected `{}`", &pattern[span.clone()]),
+                ));
+            }
+        }
+    }
+    if let Some((offset, _)) = group {
+        return Err(parse_error(pattern, offset, "unclosed `(`"));
+    }
+
+    Ok(items)
+}
+
+pub(crate) fn names(items: &[Item]) -> impl Iterator<Item = &str> + '_ {
+    items
+        .iter()
+        .flat_map(|item| match item {
+            Item::Group { names, .. } => names.as_slice(),
+            Item::Ellipsis => &[],
+        })
+        .map(String::as_str)
+}
+
+fn check_side(pattern: &str, items: &[Item], side: &str) -> Result<(), EinopsError> {
+    let ellipses = items.iter().filter(|&item| *item == Item::Ellipsis).count();
+    if ellipses > 1 {
+        return Err(invalid(
+            pattern,
+            format!("`..` appears twice on the {} side", side),
+        ));
+    }
+    let names = names(items).collect::<Vec<_>>();
+    for (i, name) in names.iter().enumerate() {
+        if names[..i].contains(name) {
+            return Err(invalid(
+                pattern,
+                format!("axis `{}` appears twice on the {} side", name, side),
+            ));
+        }
+    }
+    Ok(())
+}
+
+// Sides of a pattern with one side per input separated by commas, as recipes
+// which leave their input unchanged
+pub(crate) fn parse_inputs(
+    pattern: &str,
+    sizes: &[(&str, usize)],
+) -> Result<Vec<Recipe>, EinopsError> {
+    let mut bound = Vec::new();
+    let mut sides = Vec::new();
+    let mut starts = Vec::new();
+    let mut start = 0;
+    for part in pattern.split(',') {
+        sides.push(parse_side(pattern, start, start + part.len(), &mut bound)?);
+        starts.push(start);
+        start += part.len() + 1;
+    }
+    for &(name, size) in sizes {
+        bind(pattern, &mut bound, name, size)?;
+    }
+
+    // Axes of the inputs before, whose lengths are known by then
+    let mut known = bound
+        .iter()
+        .map(|(name, _)| name.as_str())
+        .collect::<Vec<_>>();
+    for (i, side) in sides.iter().enumerate() {
+        if side.is_empty() {
+            return Err(parse_error(
+                pattern,
+                starts[i],
+                format!("input {} has no axes", i + 1),
+            ));
+        }
+        if side.iter().filter(|&item| *item == Item::Ellipsis).count() > 1 {
+            return Err(invalid(
+                pattern,
+                format!("`..` appears twice in input {}", i + 1),
+            ));
+        }
+        let names = names(side).collect::<Vec<_>>();
+        if let Some((_, name)) = names
+            .iter()
+            .enumerate()
+            .find(|&(j, name)| names[..j].contains(name))
+        {
+            return Err(invalid(
+                pattern,
+                format!("axis `{}` appears twice in input {}", name, i + 1),
+            ));
+        }
+        for item in side {
+            let unknown = |name: &str| !known.contains(&name);
+            check_inference(pattern, item, unknown)?;
+        }
+        known.extend(names);
+    }
+    if let Some((name, _)) = bound.iter().find(|(name, _)| {
+        !sides
+            .iter()
+            .any(|side| names(side).any(|axis| axis == name))
+    }) {
+        return Err(unknown_axis(pattern, &[], name));
+    }
+
+    let pattern = Arc::<str>::from(pattern);
+    Ok(sides
+        .into_iter()
+        .map(|side| Recipe {
+            pattern: pattern.clone(),
+            left: side.clone(),
+            right: side,
+            sizes: bound.clone(),
+            kind: Kind::Rearrange,
+            broadcast: Broadcast::Implicit,
+            empty_reduction: EmptyReduction::Identity,
+        })
+        .collect())
+}
+
+// Lengths of the axes of all inputs, read one input after the other with the
+// lengths found in the inputs before
+pub(crate) fn bind_inputs<'a>(
+    sides: &'a [Recipe],
+    shapes: &[&[usize]],
+) -> Result<Vec<(&'a str, usize)>, EinopsError> {
+    if shapes.len() != sides.len() {
+        return Err(EinopsError::new(format!(
+            "expected {} inputs, found {}",
+            sides.len(),
+            shapes.len()
+        )));
+    }
+    let mut bindings = SmallAxes::<(&'a str, usize)>::default();
+    for (side, shape) in sides.iter().zip(shapes) {
+        for &(name, len) in side.plan_with(shape, &bindings)?.bindings.iter() {
+            if bindings.iter().all(|&(bound, _)| bound != name) {
+                bindings.push((name, len));
+            }
+        }
+    }
+    Ok(bindings.to_vec())
+}
+
+fn flatten(items: &[Item], ignored: usize) -> SmallAxes<Flat<'_>> {
+    let mut flat = SmallAxes::default();
+    for item in items {
+        match item {
+            Item::Group { names, .. } => flat.extend(names.iter().map(|name| Flat::Named(name))),
+            Item::Ellipsis => flat.extend((0..ignored).map(Flat::Ignored)),
+        }
+    }
+    flat
+}
+
+impl Recipe {
+    /// Parses `pattern`, with the sizes of axes given besides the ones in it
+    pub(crate) fn new(
+        pattern: &str,
+        sizes: &[(&str, usize)],
+        kind: Kind,
+    ) -> Result<Self, EinopsError> {
+        let Some(arrow) = pattern.find("->") else {
+            return Err(parse_error(pattern, pattern.len(), "expected `->`"));
+        };
+
+        let mut bound = Vec::new();
+        let left = parse_side(pattern, 0, arrow, &mut bound)?;
+        let right = parse_side(pattern, arrow + 2, pattern.len(), &mut bound)?;
+        for &(name, size) in sizes {
+            bind(pattern, &mut bound, name, size)?;
+        }
+
+        check_side(pattern, &left, "left")?;
+        check_side(pattern, &right, "right")?;
+        if left.contains(&Item::Ellipsis) != right.contains(&Item::Ellipsis) {
+            return Err(invalid(pattern, "`..` has to be on both sides or neither"));
+        }
+        let bound_name = |name: &str| bound.iter().any(|(bound, _)| bound == name);
+        let added = names(&right).find(|name| !names(&left).any(|left| left == *name));
+        match (added, kind) {
+            (Some(name), Kind::Repeat) if !bound_name(name) => {
+                return Err(invalid(
+                    pattern,
+                    format!("new axis `{}` needs a size", name),
+                ));
+            }
+            (Some(_), Kind::Repeat | Kind::Mix) | (None, _) => {}
+            (Some(name), _) => return Err(unknown_axis(pattern, &right, name)),
+        }
+        let missing = names(&left).find(|name| !names(&right).any(|right| right == *name));
+        if let (Some(name), Kind::Rearrange | Kind::Repeat) = (missing, kind) {
+            return Err(invalid(
+                pattern,
+                format!("axis `{}` is missing on the right side", name),
+            ));
+        }
+        if let Some((name, _)) = bound
+            .iter()
+            .find(|(name, _)| !names(&left).chain(names(&right)).any(|axis| axis == name))
+        {
+            return Err(unknown_axis(pattern, &[], name));
+        }
+        for item in &left {
+            let unknown = |name: &str| bound.iter().all(|(bound, _)| bound != name);
+            check_inference(pattern, item, unknown)?;
+        }
+
+        Ok(Self {
+            pattern: pattern.into(),
+            left,
+            right,
+            sizes: bound,
+            kind,
+            broadcast: Broadcast::Implicit,
+            empty_reduction: EmptyReduction::Identity,
+        })
+    }
+
+    pub(crate) fn set_broadcast(&mut self, policy: Broadcast) {
+        self.broadcast = policy;
+    }
+
+    pub(crate) fn set_empty_reduction(&mut self, policy: EmptyReduction) {
+        self.empty_reduction = policy;
+    }
+
+    pub(crate) fn pattern(&self) -> &str {
+        &self.pattern
+    }
+
+    pub(crate) fn reduction(&self) -> Option<Operation> {
+        match self.kind {
+            Kind::Reduce(operation) => Some(operation),
+            Kind::Rearrange | Kind::Repeat | Kind::Mix => None,
+        }
+    }
+
+    pub(crate) fn left(&self) -> &[Item] {
+        &self.left
+    }
+
+    pub(crate) fn right(&self) -> &[Item] {
+        &self.right
+    }
+
+    pub(crate) fn size(&self, name: &str) -> Option<usize> {
+        self.sizes
+            .iter()
+            .find(|(bound, _)| bound == name)
+            .map(|&(_, size)| size)
+    }
+
+    /// Shape of the input with its groups decomposed, and of the output
+    pub(crate) fn shapes(&self, shape: &[usize]) -> Result<(Vec<usize>, Vec<usize>), EinopsError> {
+        let plan = self.plan(shape)?;
+        Ok((plan.decomposed.to_vec(), plan.output_shape.to_vec()))
+    }
+
+    fn plan(&self, shape: &[usize]) -> Result<Plan<'_>, EinopsError> {
+        self.plan_with(shape, &[])
+    }
+
+    // Plans with the lengths `known` of axes without a size, found in other
+    // inputs
+    fn plan_with<'a>(
+        &'a self,
+        shape: &[usize],
+        known: &[(&str, usize)],
+    ) -> Result<Plan<'a>, EinopsError> {
+        let mut bindings = SmallAxes::default();
+        match self.bind(shape, known, &mut bindings) {
+            Ok(plan) => Ok(plan),
+            Err(error) => Err(self.context(error, shape, &bindings)),
+        }
+    }
+
+    // Reads the lengths of the axes from `shape`, adding the named ones to
+    // `bindings` as they are found
+    fn bind<'a>(
+        &'a self,
+        shape: &[usize],
+        known: &[(&str, usize)],
+        bindings: &mut SmallAxes<(&'a str, usize)>,
+    ) -> Result<Plan<'a>, EinopsError> {
+        let named = self
+            .left
+            .iter()
+            .filter(|&item| *item != Item::Ellipsis)
+            .count();
+        let ellipsis = named < self.left.len();
+        if shape.len() < named || (!ellipsis && shape.len() > named) {
+            return Err(ErrorKind::RankMismatch {
+                expected: named,
+                found: shape.len(),
+            }
+            .into());
+        }
+        let ignored = shape.len() - named;
+
+        let mut groups = SmallAxes::default();
+        let mut decomposed = SmallAxes::default();
+        let mut lengths = shape.iter().copied().enumerate();
+        for item in &self.left {
+            let Item::Group { names, .. } = item else {
+                groups.extend((0..ignored).map(|_| 1));
+                decomposed.extend(lengths.by_ref().take(ignored).map(|(_, len)| len));
+                continue;
+            };
+            let (axis, len) = lengths.next().expect("the rank was checked");
+            let group = self.decompose(item, axis, len, known)?;
+            if let (Kind::Repeat, [name]) = (self.kind, names.as_slice()) {
+                if self.size(name).is_none() {
+                    self.check_broadcast(item, shape, axis, name)?;
+                }
+            }
+            bindings.extend(names.iter().map(String::as_str).zip(group.iter().copied()));
+            groups.push(group.len());
+            decomposed.extend(group.iter().copied());
+        }
+
+        let mut lhs = flatten(&self.left, ignored);
+        let rhs = flatten(&self.right, ignored);
+        let mut reduced = SmallAxes::default();
+        if let Kind::Reduce(operation) = self.kind {
+            reduced = (0..lhs.len())
+                .filter(|&axis| !rhs.contains(&lhs[axis]))
+                .map(|axis| (axis, operation))
+                .collect();
+            lhs = lhs
+                .iter()
+                .copied()
+                .filter(|axis| rhs.contains(axis))
+                .collect();
+        }
+        let mut added = SmallAxes::default();
+        for (position, axis) in rhs.iter().enumerate() {
+            if let Flat::Named(name) = *axis {
+                if !lhs.contains(axis) {
+                    let len = self.size(name).expect("new axes have a size");
+                    added.push((position, len));
+                }
+            }
+        }
+        let permutation = rhs
+            .iter()
+            .filter(|&axis| lhs.contains(axis))
+            .map(|axis| {
+                lhs.iter()
+                    .position(|left| left == axis)
+                    .expect("the axes of both sides were checked")
+            })
+            .collect();
+
+        let mut plan = Plan {
+            groups,
+            decomposed,
+            reduced,
+            permutation,
+            added,
+            output_shape: SmallAxes::default(),
+            bindings: bindings.clone(),
+        };
+        let expanded = plan.expanded_shape();
+        let mut permuted = expanded.iter().copied();
+        for item in &self.right {
+            match item {
+                Item::Group { names, .. } => {
+                    let lengths = permuted.by_ref().take(names.len()).collect::<SmallAxes>();
+                    plan.output_shape.push(checked_product(&lengths)?);
+                }
+                Item::Ellipsis => plan.output_shape.extend(permuted.by_ref().take(ignored)),
+            }
+        }
+
+        Ok(plan)
+    }
+
+    // Checks an axis repeated without a size under the strict broadcast policy,
+    // with the new axes grouped with it on the right side
+    fn check_broadcast(
+        &self,
+        item: &Item,
+        shape: &[usize],
+        axis: usize,
+        name: &str,
+    ) -> Result<(), EinopsError> {
+        let Item::Group { text, offset, .. } = item else {
+            unreachable!("`..` has no name")
+        };
+        if self.broadcast == Broadcast::Implicit {
+            return Ok(());
+        }
+        let repeated = self
+            .right
+            .iter()
+            .find_map(|item| match item {
+                Item::Group { names: group, .. } if group.iter().any(|right| right == name) => {
+                    Some(
+                        group
+                            .iter()
+                            .filter(|right| names(&self.left).all(|left| left != *right))
+                            .map(|right| self.size(right).expect("new axes have a size"))
+                            .product(),
+                    )
+                }
+                _ => None,
+            })
+            .unwrap_or(1);
+        policy::check_broadcast(shape, axis, name, repeated)
+            .map_err(|error| error.with_label(owned(&self.pattern), *offset, text.len()))
+    }
+
+    // Lengths of the axes of the group `item` of the left side at `axis`, of
+    // length `len`
+    fn decompose(
+        &self,
+        item: &Item,
+        axis: usize,
+        len: usize,
+        known: &[(&str, usize)],
+    ) -> Result<SmallAxes, EinopsError> {
+        let Item::Group {
+            text,
+            offset,
+            names,
+        } = item
+        else {
+            unreachable!("`..` isn't decomposed")
+        };
+        let label =
+            |error: EinopsError| error.with_label(owned(&self.pattern), *offset, text.len());
+        let size = |name: &str| {
+            self.size(name).or_else(|| {
+                known
+                    .iter()
+                    .find(|&&(known, _)| known == name)
+                    .map(|&(_, len)| len)
+            })
+        };
+
+        let lengths = names
+            .iter()
+            .filter_map(|name| size(name))
+            .collect::<SmallAxes>();
+        let product = checked_product(&lengths).map_err(label)?;
+        // Only built for errors, as the names are copied
+        let sizes = || {
+            names
+                .iter()
+                .filter_map(|name| Some((owned(name), size(name)?)))
+                .collect::<Vec<_>>()
+        };
+        let inferred = if lengths.len() < names.len() {
+            if product == 0 || !len.is_multiple_of(product) {
+                return Err(label(
+                    ErrorKind::NotDivisible {
+                        axis,
+                        group: owned(text),
+                        sizes: sizes(),
+                        size: len,
+                        divisor: product,
+                    }
+                    .into(),
+                ));
+            }
+            len / product
+        } else {
+            if len != product {
+                return Err(label(
+                    ErrorKind::ShapeMismatch {
+                        axis,
+                        group: owned(text),
+                        sizes: sizes(),
+                        expected: product,
+                        found: len,
+                    }
+                    .into(),
+                ));
+            }
+            0
+        };
+
+        Ok(names
+            .iter()
+            .map(|name| size(name).unwrap_or(inferred))
+            .collect())
+    }
+
+    /// Every problem of an input of shape `shape`, instead of the first one
+    ///
+    /// A wrong rank is the only problem reported, as the axes can't be matched
+    /// with the groups then. Otherwise every group whose axis doesn't fit it
+    /// is reported.
+    pub(crate) fn validate(&self, shape: &[usize]) -> Vec<EinopsError> {
+        let named = self
+            .left
+            .iter()
+            .filter(|&item| *item != Item::Ellipsis)
+            .count();
+        if shape.len() < named || (!self.left.contains(&Item::Ellipsis) && shape.len() > named) {
+            return self.plan(shape).err().into_iter().collect();
+        }
+        let ignored = shape.len() - named;
+
+        let mut errors = Vec::new();
+        let mut bindings = Vec::new();
+        let mut axis = 0;
+        for item in &self.left {
+            let Item::Group { names, .. } = item else {
+                axis += ignored;
+                continue;
+            };
+            let checked = self
+                .decompose(item, axis, shape[axis], &[])
+                .and_then(|group| {
+                    if let (Kind::Repeat, [name]) = (self.kind, names.as_slice()) {
+                        if self.size(name).is_none() {
+                            self.check_broadcast(item, shape, axis, name)?;
+                        }
+                    }
+                    Ok(group)
+                });
+            match checked {
+                Ok(group) => {
+                    bindings.extend(names.iter().map(String::as_str).zip(group.iter().copied()))
+                }
+                Err(error) => errors.push(self.context(error, shape, &bindings)),
+            }
+            axis += 1;
+        }
+        if errors.is_empty() {
+            // Lengths of the output that overflow
+            errors.extend(self.plan(shape).err());
+        }
+        errors
+    }
+
+    // Groups of the left side, which name the axes of the input
+    fn axes(&self) -> Vec<Cow<'static, str>> {
+        self.left
+            .iter()
+            .map(|item| match item {
+                Item::Group { text, .. } => owned(text),
+                Item::Ellipsis => Cow::Borrowed(".."),
+            })
+            .collect()
+    }
+
+    fn context(
+        &self,
+        error: EinopsError,
+        shape: &[usize],
+        bindings: &[(&str, usize)],
+    ) -> EinopsError {
+        let bindings = bindings
+            .iter()
+            .map(|&(name, len)| (owned(name), len))
+            .collect();
+        error.with_layer_context(
+            vec![owned(&self.pattern)],
+            self.axes(),
+            shape.to_vec(),
+            bindings,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(pattern: &str, sizes: &[(&str, usize)]) -> String {
+        match Recipe::new(pattern, sizes, Kind::Rearrange)
+            .unwrap_err()
+            .kind()
+        {
+            ErrorKind::InvalidPattern { message, .. } => message.clone(),
+            ErrorKind::ParseError { offset, reason, .. } => format!("{}: {}", offset, reason),
+            ErrorKind::UnknownAxis { name } => format!("unknown axis `{}`", name),
+            ErrorKind::AmbiguousInference { group, candidates } => {
+                format!("cannot infer {:?} in {}", candidates, group)
+            }
+            kind => panic!("unexpected error {:?}", kind),
+        }
+    }
+
+    #[test]
+    fn recipe_parse() {
+        let recipe = Recipe::new("b (h w:2) .. -> b .. w h", &[], Kind::Rearrange).unwrap();
+        assert_eq!(
+            recipe.left,
+            [
+                Item::Group {
+                    text: "b".to_string(),
+                    offset: 0,
+                    names: vec!["b".to_string()]
+                },
+                Item::Group {
+                    text: "(h w:2)".to_string(),
+                    offset: 2,
+                    names: vec!["h".to_string(), "w".to_string()]
+                },
+                Item::Ellipsis,
+            ]
+        );
+        assert_eq!(recipe.sizes, [("w".to_string(), 2)]);
+
+        let plan = recipe.plan(&[2, 6, 4, 5]).unwrap();
+        assert_eq!(plan.decomposed, [2, 3, 2, 4, 5]);
+        assert_eq!(plan.permutation, [0, 3, 4, 2, 1]);
+        assert_eq!(plan.output_shape, [2, 4, 5, 2, 3]);
+        assert_eq!(plan.bindings, [("b", 2), ("h", 3), ("w", 2)]);
+
+        let recipe = Recipe::new("b (h w:2) c -> c b", &[], Kind::Reduce(Operation::Max)).unwrap();
+        let plan = recipe.plan(&[2, 6, 5]).unwrap();
+        assert_eq!(plan.reduced, [(1, Operation::Max), (2, Operation::Max)]);
+        assert_eq!(plan.permutation, [1, 0]);
+        assert_eq!(plan.output_shape, [5, 2]);
+
+        let recipe = Recipe::new("b c -> c (b r) n", &[("r", 3), ("n", 4)], Kind::Repeat).unwrap();
+        let plan = recipe.plan(&[2, 5]).unwrap();
+        assert_eq!(plan.permutation, [1, 0]);
+        assert_eq!(plan.added, [(2, 3), (3, 4)]);
+        assert_eq!(plan.output_shape, [5, 6, 4]);
+    }
+
+    #[test]
+    fn recipe_cache() {
+        let recipes = [Recipe::new("b (h w) -> b h w", &[("h", 2)], Kind::Rearrange).unwrap()];
+        let cache = Cache::default();
+        let program = cache.program(&recipes, &[3, 8], &Contiguous).unwrap();
+        assert!(Arc::ptr_eq(
+            &program,
+            &cache.program(&recipes, &[3, 8], &Contiguous).unwrap()
+        ));
+        assert_eq!(format!("{:?}", cache.clone()), "Cache(Some([3, 8]))");
+
+        let other = cache.program(&recipes, &[3, 6], &Contiguous).unwrap();
+        assert_eq!(other.output_shape(), [3, 2, 3]);
+        assert!(!Arc::ptr_eq(
+            &program,
+            &cache.program(&recipes, &[3, 8], &Contiguous).unwrap()
+        ));
+        assert!(cache.program(&recipes, &[3, 5], &Contiguous).is_err());
+    }
+
+    // A contiguous input on a device
+    struct On(Device);
+
+    impl Layout for On {
+        fn is_contiguous(&self) -> bool {
+            true
+        }
+
+        fn device(&self) -> Option<Device> {
+            Some(self.0)
+        }
+
+        fn view(&self, _: &Step) -> Option<bool> {
+            None
+        }
+    }
+
+    #[test]
+    fn recipe_device() {
+        // A copy of a large transposed view against two copies of half of it
+        let large = Copies {
+            copies: 1,
+            elements: 64,
+            strided: 1,
+        };
+        let small = Copies {
+            copies: 2,
+            elements: 32,
+            strided: 0,
+        };
+        assert!(small.cost(Some(Device::Cpu)) < large.cost(Some(Device::Cpu)));
+        assert!(large.cost(Some(Device::Cuda(0))) < small.cost(Some(Device::Cuda(0))));
+        assert_eq!(
+            large.cost(Some(Device::Metal(0))).0,
+            small.cost(Some(Device::Metal(0))).0
+        );
+
+        // Moving the innermost axis makes the copy after it strided
+        let steps = [
+            (Step::Transpose(vec![1, 0]), [3, 2].into_iter().collect()),
+            (Step::Reshape(vec![6]), [6].into_iter().collect()),
+        ];
+        let copies = Copies::of(&[2, 3], &steps, None);
+        assert_eq!((copies.copies, copies.elements, copies.strided), (1, 6, 1));
+
+        // Programs are compiled again for another device
+        let recipes = [Recipe::new("b (h w) -> b h w", &[("h", 2)], Kind::Rearrange).unwrap()];
+        let cache = Cache::default();
+        let program = cache
+            .program(&recipes, &[3, 8], &On(Device::Metal(0)))
+            .unwrap();
+        assert_eq!(program.device(), Some(Device::Metal(0)));
+        assert!(Arc::ptr_eq(
+            &program,
+            &cache
+                .program(&recipes, &[3, 8], &On(Device::Metal(0)))
+                .unwrap()
+        ));
+        assert!(!Arc::ptr_eq(
+            &program,
+            &cache
+                .program(&recipes, &[3, 8], &On(Device::Cuda(0)))
+                .unwrap()
+        ));
+    }
+
+    #[test]
+    fn recipe_invalid() {
+        assert_eq!(message("a b", &[]), "3: expected `->`");
+        assert_eq!(message("a ((b)) -> a b", &[]), "3: groups can't be nested");
+        assert_eq!(message("a .. -> (a ..)", &[]), "11: `..` can't be grouped");
+        assert_eq!(message("a b) -> a b", &[]), "3: unmatched `)`");
+        assert_eq!(message("(a b -> a b", &[]), "0: unclosed `(`");
+        assert_eq!(message("a+b -> a b", &[]), "1: unexpected `+`");
+        assert_eq!(
+            message("a b:x -> a b", &[]),
+            "4: expected a size after `b:`"
+        );
+        assert_eq!(
+            message("(a b:2) -> a b", &[("b", 3)]),
+            "axis `b` has the sizes 2 and 3"
+        );
+        assert_eq!(
+            message("a a -> a", &[]),
+            "axis `a` appears twice on the left side"
+        );
+        assert_eq!(
+            message(".. a -> a", &[]),
+            "`..` has to be on both sides or neither"
+        );
+        assert_eq!(message("a -> a b", &[]), "unknown axis `b`");
+        assert_eq!(
+            message("a b -> a", &[]),
+            "axis `b` is missing on the right side"
+        );
+        assert_eq!(message("a -> a", &[("c", 2)]), "unknown axis `c`");
+        assert_eq!(
+            message("(a b c:2) -> a b c", &[]),
+            "cannot infer [\"a\", \"b\"] in (a b c:2)"
+        );
+        assert_eq!(
+            message("(a b c) -> a b c", &[]),
+            "cannot infer [\"a\", \"b\", \"c\"] in (a b c)"
+        );
+    }
+}
@@ -0,0 +1,569 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use safetensors::tensor::TensorView;
+use safetensors::{Dtype, SafeTensors};
+
+use crate::cpu::{strides, CpuTensor, Element};
+use crate::recipe::{invalid, parse_side, Item};
+use crate::{Backend, DType, Device, EinopsError, ErrorKind, Operation, ScanOp};
+
+/// Element types that can be read from safetensors buffers
+pub trait SafetensorsElement: Element {
+    const DTYPE: Dtype;
+    fn from_le_bytes(bytes: &[u8]) -> Self;
+}
+
+macro_rules! impl_safetensors_element {
+    ($($ty:ty => $dtype:ident),*) => {
+        $(
+            impl SafetensorsElement for $ty {
+                const DTYPE: Dtype = Dtype::$dtype;
+
+                fn from_le_bytes(bytes: &[u8]) -> Self {
+                    <$ty>::from_le_bytes(bytes.try_into().unwrap())
+                }
+            }
+        )*
+    };
+}
+
+impl_safetensors_element!(
+    f32 => F32, f64 => F64, i8 => I8, i16 => I16, i32 => I32, i64 => I64,
+    u8 => U8, u16 => U16, u32 => U32, u64 => U64
+);
+
+#[cfg(feature = "half")]
+impl_safetensors_element!(half::f16 => F16, half::bf16 => BF16);
+
+#[derive(Debug, Clone)]
+enum Storage<'a, T> {
+    // Bytes of a safetensors view, usually memory-mapped
+    Borrowed(&'a [u8]),
+    // Data of operations which can't be expressed as a view
+    Owned(Arc<Vec<T>>),
+}
+
+/// A lazily evaluated view of a safetensors tensor
+///
+/// Transposes, reshapes of contiguous tensors, narrowing and repeats only change
+/// the shape and strides of the view, the data is not read until it is needed.
+/// Operations that have to read the data, like reductions, produce an owned
+/// tensor. Call [`LazyTensor::to_vec`] to read the elements.
+#[derive(Debug, Clone)]
+pub struct LazyTensor<'a, T> {
+    storage: Storage<'a, T>,
+    shape: Vec<usize>,
+    // Strides and offset are counted in elements
+    strides: Vec<usize>,
+    offset: usize,
+}
+
+impl<'a, T: SafetensorsElement> LazyTensor<'a, T> {
+    /// Wraps `view`, the dtype of the view has to match `T`
+    pub fn from_view(view: &TensorView<'a>) -> Result<Self, EinopsError> {
+        if view.dtype() != T::DTYPE {
+            return Err(EinopsError::new(format!(
+                "expected a tensor of dtype {:?}, found {:?}",
+                T::DTYPE,
+                view.dtype()
+            )));
+        }
+        let shape = view.shape().to_vec();
+        Ok(Self {
+            storage: Storage::Borrowed(view.data()),
+            strides: strides(&shape),
+            shape,
+            offset: 0,
+        })
+    }
+
+    fn from_cpu_tensor(tensor: CpuTensor<T>) -> Self {
+        let shape = tensor.shape().to_vec();
+        Self {
+            storage: Storage::Owned(Arc::new(tensor.into_data())),
+            strides: strides(&shape),
+            shape,
+            offset: 0,
+        }
+    }
+
+    pub fn shape(&self) -> &[usize] {
+        &self.shape
+    }
+
+    /// Whether the data is still read from the safetensors buffer
+    pub fn is_borrowed(&self) -> bool {
+        matches!(self.storage, Storage::Borrowed(_))
+    }
+
+    fn get(&self, index: usize) -> T {
+        match &self.storage {
+            Storage::Borrowed(bytes) => {
+                let size = std::mem::size_of::<T>();
+                T::from_le_bytes(&bytes[index * size..(index + 1) * size])
+            }
+            Storage::Owned(data) => data[index],
+        }
+    }
+
+    /// Reads the elements in row-major order
+    pub fn to_vec(&self) -> Vec<T> {
+        let len = self.shape.iter().product::<usize>();
+        let mut output = Vec::with_capacity(len);
+        let mut index = vec![0; self.shape.len()];
+        let mut offset = self.offset;
+        for _ in 0..len {
+            output.push(self.get(offset));
+            // Increment the multi-dimensional index, last axis first
+            for axis in (0..self.shape.len()).rev() {
+                index[axis] += 1;
+                offset += self.strides[axis];
+                if index[axis] < self.shape[axis] {
+                    break;
+                }
+                offset -= self.strides[axis] * index[axis];
+                index[axis] = 0;
+            }
+        }
+        output
+    }
+
+    pub fn to_cpu_tensor(&self) -> CpuTensor<T> {
+        CpuTensor::new(self.to_vec(), self.shape.clone()).unwrap()
+    }
+
+    fn with_layout(&self, shape: Vec<usize>, strides: Vec<usize>, offset: usize) -> Self {
+        Self {
+            storage: self.storage.clone(),
+            shape,
+            strides,
+            offset,
+        }
+    }
+}
+
+impl<'a, T: SafetensorsElement> Backend for LazyTensor<'a, T> {
+    type Output = LazyTensor<'a, T>;
+
+    fn shape(&self) -> Vec<usize> {
+        self.shape.clone()
+    }
+
+    fn reshape(&self, shape: &[usize]) -> Result<Self::Output, EinopsError> {
+        if !Backend::is_contiguous(self) {
+            let output = Backend::reshape(&self.to_cpu_tensor(), shape)?;
+            return Ok(Self::from_cpu_tensor(output));
+        }
+        if shape.iter().product::<usize>() != self.shape.iter().product::<usize>() {
+            return Err(EinopsError::new(format!(
+                "cannot reshape tensor of shape {:?} to {:?}",
+                self.shape, shape
+            )));
+        }
+        Ok(self.with_layout(shape.to_vec(), strides(shape), self.offset))
+    }
+
+    fn transpose(&self, axes: &[usize]) -> Result<Self::Output, EinopsError> {
+        let mut sorted = axes.to_vec();
+        sorted.sort_unstable();
+        if sorted.len() != self.shape.len() || sorted.iter().enumerate().any(|(i, &a)| i != a) {
+            return Err(EinopsError::new(format!(
+                "invalid permutation {:?} for tensor of shape {:?}",
+                axes, self.shape
+            )));
+        }
+        Ok(self.with_layout(
+            axes.iter().map(|&a| self.shape[a]).collect(),
+            axes.iter().map(|&a| self.strides[a]).collect(),
+            self.offset,
+        ))
+    }
+
+    fn reshape_view(&self, shape: &[usize]) -> Option<Self::Output> {
+        if !Backend::is_contiguous(self) {
+            return None;
+        }
+        Backend::reshape(self, shape).ok()
+    }
+
+    fn transpose_view(&self, axes: &[usize]) -> Option<Self::Output> {
+        Backend::transpose(self, axes).ok()
+    }
+
+    fn reduce_axes(
+        &self,
+        axes_operations: &mut [(usize, Operation)],
+    ) -> Result<Self::Output, EinopsError> {
+        let output = Backend::reduce_axes(&self.to_cpu_tensor(), axes_operations)?;
+        Ok(Self::from_cpu_tensor(output))
+    }
+
+    fn add_axes(
+        &self,
+        naxes: usize,
+        pos2len: &[(usize, usize)],
+    ) -> Result<Self::Output, EinopsError> {
+        let mut shape = self.shape.clone();
+        let mut strides = self.strides.clone();
+
+        for &(axis_pos, axis_len) in pos2len {
+            if axis_pos > shape.len() {
+                return Err(EinopsError::new(format!(
+                    "cannot insert axis at position {} of tensor with shape {:?}",
+                    axis_pos, shape
+                )));
+            }
+            // New axes don't move through the data
+            shape.insert(axis_pos, axis_len);
+            strides.insert(axis_pos, 0);
+        }
+        if shape.len() != naxes {
+            return Err(EinopsError::new(format!(
+                "expected {} axes after inserting new axes, found {}",
+                naxes,
+                shape.len()
+            )));
+        }
+
+        Ok(self.with_layout(shape, strides, self.offset))
+    }
+
+    fn narrow(&self, axis: usize, start: usize, len: usize) -> Result<Self::Output, EinopsError> {
+        if axis >= self.shape.len() || start + len > self.shape[axis] {
+            return Err(EinopsError::new(format!(
+                "cannot narrow axis {} of tensor with shape {:?} to {}..{}",
+                axis,
+                self.shape,
+                start,
+                start + len
+            )));
+        }
+        let mut shape = self.shape.clone();
+        shape[axis] = len;
+        let offset = self.offset + start * self.strides[axis];
+        Ok(self.with_layout(shape, self.strides.clone(), offset))
+    }
+
+    fn broadcast(&self, shape: &[usize]) -> Result<Self::Output, EinopsError> {
+        let error = || {
+            EinopsError::new(format!(
+                "cannot broadcast tensor of shape {:?} to {:?}",
+                self.shape, shape
+            ))
+        };
+        let leading = shape
+            .len()
+            .checked_sub(self.shape.len())
+            .ok_or_else(error)?;
+
+        let mut strides = vec![0; leading];
+        for ((&len, &stride), &target_len) in
+            self.shape.iter().zip(&self.strides).zip(&shape[leading..])
+        {
+            match len {
+                _ if len == target_len => strides.push(stride),
+                1 => strides.push(0),
+                _ => return Err(error()),
+            }
+        }
+
+        Ok(self.with_layout(shape.to_vec(), strides, self.offset))
+    }
+
+    fn is_contiguous(&self) -> bool {
+        // Strides of axes of length 1 don't matter
+        self.shape
+            .iter()
+            .zip(self.strides.iter().zip(strides(&self.shape)))
+            .all(|(&len, (&stride, expected))| len == 1 || stride == expected)
+    }
+
+    fn contiguous(&self) -> Result<Self::Output, EinopsError> {
+        if Backend::is_contiguous(self) {
+            return Ok(self.clone());
+        }
+        Ok(Self::from_cpu_tensor(self.to_cpu_tensor()))
+    }
+
+    fn concat(inputs: &[&Self], axis: usize) -> Result<Self::Output, EinopsError> {
+        let tensors = inputs
+            .iter()
+            .map(|input| input.to_cpu_tensor())
+            .collect::<Vec<_>>();
+        let output = Backend::concat(&tensors.iter().collect::<Vec<_>>(), axis)?;
+        Ok(Self::from_cpu_tensor(output))
+    }
+
+    fn index_select(&self, axis: usize, indices: &[usize]) -> Result<Self::Output, EinopsError> {
+        let output = Backend::index_select(&self.to_cpu_tensor(), axis, indices)?;
+        Ok(Self::from_cpu_tensor(output))
+    }
+
+    fn scan(&self, axis: usize, operation: ScanOp) -> Result<Self::Output, EinopsError> {
+        let output = Backend::scan(&self.to_cpu_tensor(), axis, operation)?;
+        Ok(Self::from_cpu_tensor(output))
+    }
+
+    fn contract(&self, rhs: &Self) -> Result<Self::Output, EinopsError> {
+        let output = Backend::contract(&self.to_cpu_tensor(), &rhs.to_cpu_tensor())?;
+        Ok(Self::from_cpu_tensor(output))
+    }
+
+    fn dtype(&self) -> Option<DType> {
+        T::dtype()
+    }
+
+    fn to_dtype(&self, dtype: DType) -> Result<Self::Output, EinopsError> {
+        if T::dtype() != Some(dtype) {
+            return Err(EinopsError::new(format!(
+                "cannot cast tensor of dtype {:?} to {:?}",
+                T::dtype(),
+                dtype
+            )));
+        }
+        Ok(self.clone())
+    }
+
+    fn device(&self) -> Option<Device> {
+        Some(Device::Cpu)
+    }
+
+    fn to_device(&self, device: Device) -> Result<Self::Output, EinopsError> {
+        if device != Device::Cpu {
+            return Err(EinopsError::new(format!(
+                "cannot move tensor to {:?}, only the CPU is supported",
+                device
+            )));
+        }
+        Ok(self.clone())
+    }
+}
+
+/// Prefix of the metadata keys holding the axis names of a tensor, the names
+/// of `weight` are stored as `"einops.axes.weight": "out_features in_features"`
+pub const AXES_PREFIX: &str = "einops.axes.";
+
+/// Metadata naming the axes of tensors, from their names and their axes
+/// separated by spaces, to pass to `safetensors::serialize`
+///
+/// ```ignore
+/// let metadata = axes_metadata([("weight", "out_features in_features")])?;
+/// let buffer = safetensors::serialize([("weight", view)], &Some(metadata))?;
+/// ```
+pub fn axes_metadata<'a>(
+    axes: impl IntoIterator<Item = (&'a str, &'a str)>,
+) -> Result<HashMap<String, String>, EinopsError> {
+    axes.into_iter()
+        .map(|(tensor, axes)| {
+            let names = axes.split_whitespace().collect::<Vec<_>>();
+            for (i, name) in names.iter().enumerate() {
+                let mut chars = name.chars();
+                let valid = chars
+                    .next()
+                    .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+                    && chars.all(|c| c.is_ascii_alphanumeric() || c == '_');
+                if !valid || names[..i].contains(name) {
+                    return Err(EinopsError::new(format!(
+                        "invalid axis names {:?} of tensor `{}`",
+                        axes, tensor
+                    )));
+                }
+            }
+            Ok((format!("{}{}", AXES_PREFIX, tensor), names.join(" ")))
+        })
+        .collect()
+}
+
+/// Safetensors whose axes are named in their metadata, see [`axes_metadata`]
+///
+/// Tensors are loaded with the layout the caller expects them in, a list of
+/// axis names with the syntax of patterns, where sizes like `in_features:768`
+/// are checked against the shape. Tensors stored with their axes in another
+/// order are transposed to the layout, without reading their data, and
+/// tensors with other axes are errors.
+///
+/// ```ignore
+/// let tensors = NamedSafeTensors::deserialize(&buffer)?;
+/// let weight = tensors.tensor::<f32>("weight", "in_features:768 out_features")?;
+/// ```
+pub struct NamedSafeTensors<'data> {
+    tensors: SafeTensors<'data>,
+    // Axis names by tensor
+    axes: HashMap<String, Vec<String>>,
+}
+
+impl<'data> NamedSafeTensors<'data> {
+    /// Reads the tensors of a safetensors buffer and the axis names of its
+    /// metadata
+    pub fn deserialize(buffer: &'data [u8]) -> Result<Self, EinopsError> {
+        let error = |error: safetensors::SafeTensorError| {
+            EinopsError::new(format!("cannot read safetensors: {}", error))
+        };
+        let (_, metadata) = SafeTensors::read_metadata(buffer).map_err(error)?;
+        let axes = metadata
+            .metadata()
+            .iter()
+            .flatten()
+            .filter_map(|(key, axes)| {
+                let tensor = key.strip_prefix(AXES_PREFIX)?;
+                let names = axes.split_whitespace().map(str::to_string).collect();
+                Some((tensor.to_string(), names))
+            })
+            .collect();
+
+        Ok(Self {
+            tensors: SafeTensors::deserialize(buffer).map_err(error)?,
+            axes,
+        })
+    }
+
+    /// Axis names of the tensor `name` as stored, `None` if they aren't in the
+    /// metadata
+    pub fn axes(&self, name: &str) -> Option<Vec<&str>> {
+        let axes = self.axes.get(name)?;
+        Some(axes.iter().map(String::as_str).collect())
+    }
+
+    /// Loads the tensor `name` in `layout`, like `out_features in_features:768`
+    pub fn tensor<T: SafetensorsElement>(
+        &self,
+        name: &str,
+        layout: &str,
+    ) -> Result<LazyTensor<'data, T>, EinopsError> {
+        let mut sizes = Vec::new();
+        let items = parse_side(layout, 0, layout.len(), &mut sizes)?;
+        let mut expected = Vec::with_capacity(items.len());
+        for item in &items {
+            match item {
+                Item::Group { names, .. } if names.len() == 1 => {
+                    if expected.contains(&names[0].as_str()) {
+                        return Err(invalid(
+                            layout,
+                            format!("axis `{}` appears twice", names[0]),
+                        ));
+                    }
+                    expected.push(names[0].as_str());
+                }
+                _ => return Err(invalid(layout, "layouts can only name single axes")),
+            }
+        }
+
+        let Some(stored) = self.axes.get(name) else {
+            return Err(EinopsError::new(format!(
+                "tensor `{}` has no axis names in the metadata",
+                name
+            )));
+        };
+        let view = self.tensors.tensor(name).map_err(|error| {
+            EinopsError::new(format!("cannot read tensor `{}`: {}", name, error))
+        })?;
+        let tensor = LazyTensor::from_view(&view)?;
+        // The metadata of the file disagrees with its tensor
+        if stored.len() != tensor.shape().len() {
+            return Err(ErrorKind::RankMismatch {
+                expected: stored.len(),
+                found: tensor.shape().len(),
+            }
+            .into());
+        }
+        // Errors about the axes of the layout show it the way layers show
+        // their pattern
+        let context = |error: EinopsError, shape: &[usize]| {
+            error.with_layer_context(
+                vec![Cow::Owned(layout.to_string())],
+                expected
+                    .iter()
+                    .map(|&axis| Cow::Owned(axis.to_string()))
+                    .collect(),
+                shape.to_vec(),
+                Vec::new(),
+            )
+        };
+        if expected.len() != stored.len() {
+            let error = ErrorKind::RankMismatch {
+                expected: expected.len(),
+                found: stored.len(),
+            };
+            return Err(context(error.into(), tensor.shape()));
+        }
+
+        let permutation = expected
+            .iter()
+            .map(|&axis| stored.iter().position(|stored| stored == axis))
+            .collect::<Option<Vec<_>>>()
+            .filter(|permutation| permutation.len() == stored.len())
+            .ok_or_else(|| {
+                EinopsError::new(format!(
+                    "tensor `{}` has the axes `{}`, expected `{}`",
+                    name,
+                    stored.join(" "),
+                    expected.join(" ")
+                ))
+            })?;
+        let tensor = Backend::transpose(&tensor, &permutation)?;
+        for (position, (&axis, &len)) in expected.iter().zip(tensor.shape()).enumerate() {
+            match sizes.iter().find(|(sized, _)| sized == axis) {
+                Some(&(_, size)) if size != len => {
+                    let error = ErrorKind::ShapeMismatch {
+                        axis: position,
+                        group: Cow::Owned(axis.to_string()),
+                        sizes: Vec::new(),
+                        expected: size,
+                        found: len,
+                    };
+                    return Err(context(error.into(), tensor.shape()));
+                }
+                _ => {}
+            }
+        }
+        Ok(tensor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn view(data: &[u8]) -> TensorView<'_> {
+        TensorView::new(Dtype::U16, vec![2, 3], data).unwrap()
+    }
+
+    #[test]
+    fn safetensors_transpose() {
+        let data = (0u16..6).flat_map(|x| x.to_le_bytes()).collect::<Vec<_>>();
+        let tensor = LazyTensor::<u16>::from_view(&view(&data)).unwrap();
+
+        let output = Backend::transpose(&tensor, &[1, 0]).unwrap();
+        assert!(output.is_borrowed());
+        assert!(!Backend::is_contiguous(&output));
+        assert_eq!(output.to_vec(), [0, 3, 1, 4, 2, 5]);
+    }
+
+    #[test]
+    fn safetensors_narrow() {
+        let data = (0u16..6).flat_map(|x| x.to_le_bytes()).collect::<Vec<_>>();
+        let tensor = LazyTensor::<u16>::from_view(&view(&data)).unwrap();
+
+        let output = Backend::narrow(&tensor, 1, 1, 2).unwrap();
+        assert!(output.is_borrowed());
+        assert_eq!(output.to_vec(), [1, 2, 4, 5]);
+    }
+
+    #[test]
+    fn safetensors_reduce() {
+        let data = (0u16..6).flat_map(|x| x.to_le_bytes()).collect::<Vec<_>>();
+        let tensor = LazyTensor::<u16>::from_view(&view(&data)).unwrap();
+
+        let output = Backend::reduce_axes(&tensor, &mut [(1, Operation::Sum)]).unwrap();
+        assert!(!output.is_borrowed());
+        assert_eq!(output.to_vec(), [3, 12]);
+    }
+
+    #[test]
+    fn safetensors_dtype_mismatch() {
+        let data = (0u16..6).flat_map(|x| x.to_le_bytes()).collect::<Vec<_>>();
+        assert!(LazyTensor::<f32>::from_view(&view(&data)).is_err());
+    }
+}
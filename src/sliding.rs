@@ -0,0 +1,83 @@
+//! Overlapping windows of an axis, for any backend
+//!
+//! Windows are gathered with [`Backend::index_select`], so they copy the data
+//! and work on every backend. Convolutions become a matrix product with the
+//! patches of [`im2col`], local attention attends within the windows of
+//! [`unfold`].
+
+use crate::{einops, Backend, EinopsError, ErrorKind};
+
+/// Slides a window of `size` elements along `axis`, moving it by `stride`
+/// elements
+///
+/// `axis` of length `n` is replaced by the windows and their elements,
+/// `(n - size) / stride + 1` windows of `size` elements, so a `[b, t, d]`
+/// tensor unfolded along its tokens is `[b, windows, size, d]`. Elements past
+/// the last full window are left out. Unlike `torch.Tensor.unfold`, which
+/// moves the elements of the windows last as `[b, windows, d, size]`, the
+/// elements stay in place of the unfolded axis.
+pub fn unfold<T: Backend<Output = T>>(
+    x: &T,
+    axis: usize,
+    size: usize,
+    stride: usize,
+) -> Result<T, EinopsError> {
+    let mut shape = x.shape();
+    let Some(&len) = shape.get(axis) else {
+        return Err(ErrorKind::RankMismatch {
+            expected: axis + 1,
+            found: shape.len(),
+        }
+        .into());
+    };
+    if size == 0 || stride == 0 {
+        return Err(EinopsError::new(format!(
+            "cannot slide windows of {} elements by {}",
+            size, stride
+        )));
+    }
+    // The axis is shorter than a single window
+    if size > len {
+        return Err(ErrorKind::ShapeMismatch {
+            axis,
+            group: "window".into(),
+            sizes: vec![("window".into(), size)],
+            expected: size,
+            found: len,
+        }
+        .into());
+    }
+
+    let windows = (len - size) / stride + 1;
+    let indices: Vec<usize> = (0..windows)
+        .flat_map(|window| (0..size).map(move |i| window * stride + i))
+        .collect();
+    shape.splice(axis..=axis, [windows, size]);
+    x.index_select(axis, &indices)?.reshape(&shape)
+}
+
+/// Patches of `kernel`x`kernel` pixels under every position of a convolution
+/// moving by `stride`, of images of shape `[b, c, h, w]`
+///
+/// The output is `[b, positions, c * kernel * kernel]` with the positions row
+/// by row, so a convolution is the product with its weight of shape
+/// `[out, c, kernel, kernel]` flattened to `[c * kernel * kernel, out]`.
+/// Images aren't padded.
+pub fn im2col<T: Backend<Output = T>>(
+    image: &T,
+    kernel: usize,
+    stride: usize,
+) -> Result<T, EinopsError> {
+    let shape = image.shape();
+    if shape.len() != 4 {
+        return Err(ErrorKind::RankMismatch {
+            expected: 4,
+            found: shape.len(),
+        }
+        .into());
+    }
+
+    let rows = unfold(image, 2, kernel, stride)?;
+    let patches = unfold(&rows, 4, kernel, stride)?;
+    einops!("b c h kh w kw -> b (h w) (c kh kw)", &patches)
+}
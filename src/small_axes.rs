@@ -0,0 +1,166 @@
+use std::ops::{Deref, DerefMut};
+
+const INLINE_LEN: usize = 8;
+
+/// Shapes, permutations and axis lists built by `einops!` at runtime
+///
+/// Stored inline for up to eight elements, which covers nearly every tensor,
+/// so patterns with `..` don't allocate for them.
+#[doc(hidden)]
+#[derive(Debug, Clone)]
+pub enum SmallAxes<T = usize> {
+    Inline { len: usize, values: [T; INLINE_LEN] },
+    Heap(Vec<T>),
+}
+
+impl<T: Copy> FromIterator<T> for SmallAxes<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut iter = iter.into_iter();
+        // An empty `Vec` doesn't allocate either
+        let Some(first) = iter.next() else {
+            return Self::Heap(Vec::new());
+        };
+        let mut values = [first; INLINE_LEN];
+        let mut len = 1;
+        for value in iter.by_ref() {
+            if len == INLINE_LEN {
+                let mut heap = values.to_vec();
+                heap.push(value);
+                heap.extend(iter);
+                return Self::Heap(heap);
+            }
+            values[len] = value;
+            len += 1;
+        }
+        Self::Inline { len, values }
+    }
+}
+
+impl<T> Default for SmallAxes<T> {
+    fn default() -> Self {
+        Self::Heap(Vec::new())
+    }
+}
+
+impl<T: Copy> SmallAxes<T> {
+    pub fn push(&mut self, value: T) {
+        let len = self.len();
+        self.insert(len, value);
+    }
+
+    /// Inserts `value` at `index`, moving the values after it
+    pub fn insert(&mut self, index: usize, value: T) {
+        match self {
+            Self::Heap(values) if values.is_empty() && values.capacity() == 0 => {
+                assert_eq!(index, 0, "insertion index out of bounds");
+                *self = Self::Inline {
+                    len: 1,
+                    values: [value; INLINE_LEN],
+                };
+            }
+            Self::Inline { len, values } if *len < INLINE_LEN => {
+                assert!(index <= *len, "insertion index out of bounds");
+                values.copy_within(index..*len, index + 1);
+                values[index] = value;
+                *len += 1;
+            }
+            Self::Inline { values, .. } => {
+                let mut heap = values.to_vec();
+                heap.insert(index, value);
+                *self = Self::Heap(heap);
+            }
+            Self::Heap(values) => values.insert(index, value),
+        }
+    }
+
+    pub fn remove(&mut self, index: usize) -> T {
+        match self {
+            Self::Inline { len, values } => {
+                assert!(index < *len, "removal index out of bounds");
+                let value = values[index];
+                values.copy_within(index + 1..*len, index);
+                *len -= 1;
+                value
+            }
+            Self::Heap(values) => values.remove(index),
+        }
+    }
+}
+
+impl<T: Copy> Extend<T> for SmallAxes<T> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        for value in iter {
+            self.push(value);
+        }
+    }
+}
+
+impl<T: PartialEq> PartialEq for SmallAxes<T> {
+    fn eq(&self, other: &Self) -> bool {
+        **self == **other
+    }
+}
+
+impl<T: Eq> Eq for SmallAxes<T> {}
+
+impl<T: PartialEq, const N: usize> PartialEq<[T; N]> for SmallAxes<T> {
+    fn eq(&self, other: &[T; N]) -> bool {
+        **self == *other
+    }
+}
+
+impl<T> Deref for SmallAxes<T> {
+    type Target = [T];
+
+    fn deref(&self) -> &[T] {
+        match self {
+            Self::Inline { len, values } => &values[..*len],
+            Self::Heap(values) => values,
+        }
+    }
+}
+
+impl<T> DerefMut for SmallAxes<T> {
+    fn deref_mut(&mut self) -> &mut [T] {
+        match self {
+            Self::Inline { len, values } => &mut values[..*len],
+            Self::Heap(values) => values,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn small_axes_collect() {
+        let axes = (0..8).collect::<SmallAxes>();
+        assert!(matches!(axes, SmallAxes::Inline { len: 8, .. }));
+        assert_eq!(*axes, [0, 1, 2, 3, 4, 5, 6, 7]);
+
+        let axes = (0..10).collect::<SmallAxes>();
+        assert!(matches!(axes, SmallAxes::Heap(_)));
+        assert_eq!(*axes, (0..10).collect::<Vec<_>>());
+
+        assert!(std::iter::empty::<usize>()
+            .collect::<SmallAxes>()
+            .is_empty());
+    }
+
+    #[test]
+    fn small_axes_insert() {
+        let mut axes = SmallAxes::default();
+        axes.push(1);
+        axes.insert(0, 0);
+        axes.extend(2..8);
+        assert!(matches!(axes, SmallAxes::Inline { len: 8, .. }));
+        assert_eq!(axes.remove(3), 3);
+        assert_eq!(axes, [0, 1, 2, 4, 5, 6, 7]);
+
+        axes.extend([8, 9]);
+        assert!(matches!(axes, SmallAxes::Heap(_)));
+        axes.insert(3, 3);
+        assert_eq!(*axes, (0..10).collect::<Vec<_>>());
+    }
+}
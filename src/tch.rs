@@ -1,57 +1,233 @@
-use tch::Tensor;
+use tch::{Kind, Tensor};
 
-use crate::{Backend, Operation};
+use crate::{Backend, DType, Device, EinopsError, Operation, ScanOp};
 
-impl<T: AsRef<Tensor>> Backend for T {
+fn dtype_from_kind(kind: Kind) -> Option<DType> {
+    Some(match kind {
+        Kind::Uint8 => DType::U8,
+        Kind::Int8 => DType::I8,
+        Kind::Int16 => DType::I16,
+        Kind::Int => DType::I32,
+        Kind::Int64 => DType::I64,
+        Kind::Half => DType::F16,
+        Kind::BFloat16 => DType::BF16,
+        Kind::Float => DType::F32,
+        Kind::Double => DType::F64,
+        _ => return None,
+    })
+}
+
+fn device_from_tch(device: tch::Device) -> Option<Device> {
+    match device {
+        tch::Device::Cpu => Some(Device::Cpu),
+        tch::Device::Cuda(ordinal) => Some(Device::Cuda(ordinal)),
+        tch::Device::Mps => Some(Device::Metal(0)),
+        tch::Device::Vulkan => None,
+    }
+}
+
+fn device_to_tch(device: Device) -> Result<tch::Device, EinopsError> {
+    Ok(match device {
+        Device::Cpu => tch::Device::Cpu,
+        Device::Cuda(ordinal) => tch::Device::Cuda(ordinal),
+        // LibTorch only exposes a single MPS device
+        Device::Metal(0) => tch::Device::Mps,
+        Device::Metal(_) => {
+            return Err(EinopsError::new(format!(
+                "tch tensors can't be moved to {:?}",
+                device
+            )))
+        }
+    })
+}
+
+fn dtype_to_kind(dtype: DType) -> Result<Kind, EinopsError> {
+    Ok(match dtype {
+        DType::U8 => Kind::Uint8,
+        DType::I8 => Kind::Int8,
+        DType::I16 => Kind::Int16,
+        DType::I32 => Kind::Int,
+        DType::I64 => Kind::Int64,
+        DType::F16 => Kind::Half,
+        DType::BF16 => Kind::BFloat16,
+        DType::F32 => Kind::Float,
+        DType::F64 => Kind::Double,
+        _ => {
+            return Err(EinopsError::new(format!(
+                "tch tensors of dtype {:?} are not supported",
+                dtype
+            )))
+        }
+    })
+}
+
+fn is_identity(tensor: &Tensor, axes: &[usize]) -> bool {
+    axes.len() == tensor.dim() && axes.iter().enumerate().all(|(i, &axis)| i == axis)
+}
+
+impl Backend for Tensor {
     type Output = Tensor;
 
-    fn shape(self) -> Vec<usize> {
-        self.as_ref()
-            .size()
-            .iter()
-            .map(|&x| x as usize)
-            .collect::<Vec<_>>()
+    fn shape(&self) -> Vec<usize> {
+        self.size().iter().map(|&x| x as usize).collect::<Vec<_>>()
+    }
+
+    fn reshape(&self, shape: &[usize]) -> Result<Self::Output, EinopsError> {
+        if Backend::shape(self) == shape {
+            return Ok(self.shallow_clone());
+        }
+        Ok(self.f_reshape(shape.iter().map(|&x| x as i64).collect::<Vec<_>>())?)
+    }
+
+    fn transpose(&self, axes: &[usize]) -> Result<Self::Output, EinopsError> {
+        if is_identity(self, axes) {
+            return Ok(self.shallow_clone());
+        }
+        Ok(self.f_permute(axes.iter().map(|&x| x as i64).collect::<Vec<_>>())?)
+    }
+
+    // `view` fails instead of copying when the strides don't allow a reshape
+    fn reshape_view(&self, shape: &[usize]) -> Option<Self::Output> {
+        if Backend::shape(self) == shape {
+            return Some(self.shallow_clone());
+        }
+        self.f_view(shape.iter().map(|&x| x as i64).collect::<Vec<_>>())
+            .ok()
+    }
+
+    fn transpose_view(&self, axes: &[usize]) -> Option<Self::Output> {
+        if is_identity(self, axes) {
+            return Some(self.shallow_clone());
+        }
+        self.f_permute(axes.iter().map(|&x| x as i64).collect::<Vec<_>>())
+            .ok()
     }
 
-    fn reshape(self, shape: &[usize]) -> Self::Output {
-        self.as_ref()
-            .reshape(shape.iter().map(|&x| x as i64).collect::<Vec<_>>())
+    // `pixel_shuffle` takes a single factor for both axes
+    fn depth_to_space(&self, p1: usize, p2: usize) -> Option<Self::Output> {
+        if p1 != p2 || self.dim() != 4 {
+            return None;
+        }
+        self.f_pixel_shuffle(p1 as i64).ok()
     }
 
-    fn transpose(self, axes: &[usize]) -> Self::Output {
-        self.as_ref()
-            .permute(axes.iter().map(|&x| x as i64).collect::<Vec<_>>())
+    fn space_to_depth(&self, p1: usize, p2: usize) -> Option<Self::Output> {
+        if p1 != p2 || self.dim() != 4 {
+            return None;
+        }
+        self.f_pixel_unshuffle(p1 as i64).ok()
     }
 
-    fn reduce_axes(self, axes_operations: &mut [(usize, Operation)]) -> Self::Output {
-        let mut output = self.as_ref().shallow_clone();
+    fn reduce_axes(
+        &self,
+        axes_operations: &mut [(usize, Operation)],
+    ) -> Result<Self::Output, EinopsError> {
+        let mut output = self.shallow_clone();
 
         axes_operations.sort_by_key(|(axis, _)| *axis);
 
         for (axis, operation) in axes_operations.iter().rev() {
+            // Reducing an axis of length 1 only removes it, squeezing it is a view
+            if output.size().get(*axis) == Some(&1) {
+                output = output.f_squeeze_dim(*axis as i64)?;
+                continue;
+            }
             output = match operation {
-                Operation::Min => output.min_dim(*axis as i64, false).0,
-                Operation::Max => output.max_dim(*axis as i64, false).0,
-                Operation::Sum => output.sum_dim_intlist(&[*axis as i64][..], false, output.kind()),
-                Operation::Mean => output.mean_dim(&[*axis as i64][..], false, output.kind()),
-                //Operation::Prod => output.prod_dim_int(*axis as i64, false, output.kind()),
+                Operation::Min => output.f_min_dim(*axis as i64, false)?.0,
+                Operation::Max => output.f_max_dim(*axis as i64, false)?.0,
+                Operation::Sum => {
+                    output.f_sum_dim_intlist(&[*axis as i64][..], false, output.kind())?
+                }
+                Operation::Mean => output.f_mean_dim(&[*axis as i64][..], false, output.kind())?, //Operation::Prod => output.prod_dim_int(*axis as i64, false, output.kind()),
             };
         }
 
-        output
+        Ok(output)
     }
 
-    fn add_axes(self, naxes: usize, pos2len: &[(usize, usize)]) -> Self::Output {
-        let mut output = self.as_ref().shallow_clone();
-
-        let mut repeats = vec![1; naxes];
+    fn add_axes(
+        &self,
+        naxes: usize,
+        pos2len: &[(usize, usize)],
+    ) -> Result<Self::Output, EinopsError> {
+        let mut unsqueezed = self.size();
+        let mut expanded = unsqueezed.clone();
 
         for &(axis_pos, axis_len) in pos2len {
-            output = output.unsqueeze(axis_pos as i64);
-            repeats[axis_pos] = axis_len as i64;
+            if axis_pos > unsqueezed.len() {
+                return Err(EinopsError::new(format!(
+                    "cannot insert axis at position {} of tensor with shape {:?}",
+                    axis_pos, unsqueezed
+                )));
+            }
+            unsqueezed.insert(axis_pos, 1);
+            expanded.insert(axis_pos, axis_len as i64);
+        }
+        if expanded.len() != naxes {
+            return Err(EinopsError::new(format!(
+                "expected {} axes after inserting new axes, found {}",
+                naxes,
+                expanded.len()
+            )));
         }
 
-        output.repeat(&repeats)
+        // Inserting axes of length 1 is always a view
+        Ok(self.f_view(unsqueezed)?.f_expand(&expanded, false)?)
+    }
+
+    fn narrow(&self, axis: usize, start: usize, len: usize) -> Result<Self::Output, EinopsError> {
+        Ok(self.f_narrow(axis as i64, start as i64, len as i64)?)
+    }
+
+    fn broadcast(&self, shape: &[usize]) -> Result<Self::Output, EinopsError> {
+        Ok(self.f_expand(shape.iter().map(|&x| x as i64).collect::<Vec<_>>(), false)?)
+    }
+
+    fn is_contiguous(&self) -> bool {
+        Tensor::is_contiguous(self)
+    }
+
+    fn contiguous(&self) -> Result<Self::Output, EinopsError> {
+        Ok(self.f_contiguous()?)
+    }
+
+    fn concat(inputs: &[&Self], axis: usize) -> Result<Self::Output, EinopsError> {
+        Ok(Tensor::f_cat(inputs, axis as i64)?)
+    }
+
+    fn index_select(&self, axis: usize, indices: &[usize]) -> Result<Self::Output, EinopsError> {
+        let indices = Tensor::from_slice(&indices.iter().map(|&i| i as i64).collect::<Vec<_>>())
+            .to_device(self.device());
+        Ok(self.f_index_select(axis as i64, &indices)?)
+    }
+
+    fn scan(&self, axis: usize, operation: ScanOp) -> Result<Self::Output, EinopsError> {
+        let axis = axis as i64;
+        Ok(match operation {
+            ScanOp::Sum => self.f_cumsum(axis, None::<Kind>)?,
+            ScanOp::Prod => self.f_cumprod(axis, None::<Kind>)?,
+            ScanOp::Max => self.f_cummax(axis)?.0,
+        })
+    }
+
+    fn contract(&self, rhs: &Self) -> Result<Self::Output, EinopsError> {
+        Ok(self.f_matmul(rhs)?)
+    }
+
+    fn dtype(&self) -> Option<DType> {
+        dtype_from_kind(self.kind())
+    }
+
+    fn to_dtype(&self, dtype: DType) -> Result<Self::Output, EinopsError> {
+        Ok(self.f_to_kind(dtype_to_kind(dtype)?)?)
+    }
+
+    fn device(&self) -> Option<Device> {
+        device_from_tch(Tensor::device(self))
+    }
+
+    fn to_device(&self, device: Device) -> Result<Self::Output, EinopsError> {
+        Ok(self.f_to_device(device_to_tch(device)?)?)
     }
 }
 
@@ -78,7 +254,7 @@ mod tests {
         )];
 
         for (tensor, mut axes_operations, expected) in tests {
-            assert_eq!(tensor.reduce_axes(&mut axes_operations), expected);
+            assert_eq!(tensor.reduce_axes(&mut axes_operations).unwrap(), expected);
         }
     }
 
@@ -99,7 +275,7 @@ mod tests {
         )];
 
         for (tensor, axes, expected) in tests {
-            assert_eq!(Backend::transpose(&tensor, axes), expected);
+            assert_eq!(Backend::transpose(&tensor, axes).unwrap(), expected);
         }
     }
 
@@ -119,7 +295,124 @@ mod tests {
         )];
 
         for (tensor, naxes, pos2len, expected) in tests {
-            assert_eq!(tensor.add_axes(naxes, pos2len), expected);
+            assert_eq!(tensor.add_axes(naxes, pos2len).unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn tch_narrow() {
+        let tests = vec![(
+            Tensor::arange(2 * 3 * 4, (Kind::Float, Device::Cpu)).reshape(&[2, 3, 4]),
+            (1, 1, 2),
+            Tensor::from_slice(&[4, 5, 6, 7, 8, 9, 10, 11, 16, 17, 18, 19, 20, 21, 22, 23])
+                .reshape(&[2, 2, 4]),
+        )];
+
+        for (tensor, (axis, start, len), expected) in tests {
+            assert_eq!(
+                Backend::narrow(&tensor, axis, start, len).unwrap(),
+                expected
+            );
+            assert!(Backend::narrow(&tensor, axis, start, 3).is_err());
         }
     }
+
+    #[test]
+    fn tch_broadcast() {
+        let tests = vec![(
+            Tensor::from_slice(&[0, 1]).reshape(&[2, 1]),
+            &[3, 2, 2],
+            Tensor::from_slice(&[0, 0, 1, 1, 0, 0, 1, 1, 0, 0, 1, 1]).reshape(&[3, 2, 2]),
+        )];
+
+        for (tensor, shape, expected) in tests {
+            assert_eq!(Backend::broadcast(&tensor, shape).unwrap(), expected);
+            assert!(Backend::broadcast(&tensor, &[3, 2]).is_err());
+        }
+    }
+
+    #[test]
+    fn tch_contiguous() {
+        let tensor = Tensor::arange(6, (Kind::Float, Device::Cpu))
+            .reshape(&[2, 3])
+            .tr();
+        assert!(!Backend::is_contiguous(&tensor));
+
+        let output = Backend::contiguous(&tensor).unwrap();
+        assert!(Backend::is_contiguous(&output));
+        assert_eq!(output, tensor);
+    }
+
+    #[test]
+    fn tch_concat() {
+        let a = Tensor::from_slice(&[0, 3]).reshape(&[2, 1]);
+        let b = Tensor::from_slice(&[1, 2, 4, 5]).reshape(&[2, 2]);
+
+        let output = Backend::concat(&[&a, &b], 1).unwrap();
+        assert_eq!(
+            output,
+            Tensor::from_slice(&[0, 1, 2, 3, 4, 5]).reshape(&[2, 3])
+        );
+        assert!(Backend::concat(&[&a, &b], 0).is_err());
+    }
+
+    #[test]
+    fn tch_index_select() {
+        let tensor = Tensor::arange(6, (Kind::Int64, Device::Cpu)).reshape(&[2, 3]);
+
+        let output = Backend::index_select(&tensor, 1, &[2, 0, 2]).unwrap();
+        assert_eq!(
+            output,
+            Tensor::from_slice(&[2i64, 0, 2, 5, 3, 5]).reshape(&[2, 3])
+        );
+        assert!(Backend::index_select(&tensor, 1, &[3]).is_err());
+    }
+
+    #[test]
+    fn tch_contract() {
+        let lhs = Tensor::arange(12, (Kind::Float, Device::Cpu)).reshape(&[2, 2, 3]);
+        let rhs = Tensor::ones(&[2, 3, 1], (Kind::Float, Device::Cpu));
+
+        let output = Backend::contract(&lhs, &rhs).unwrap();
+        assert_eq!(
+            output,
+            Tensor::from_slice(&[3.0f32, 12.0, 21.0, 30.0]).reshape(&[2, 2, 1])
+        );
+        assert!(Backend::contract(&lhs, &lhs).is_err());
+    }
+
+    #[test]
+    fn tch_dtype() {
+        let tensor = Tensor::arange(6, (Kind::Int64, Device::Cpu));
+        assert_eq!(Backend::dtype(&tensor), Some(DType::I64));
+
+        let output = Backend::to_dtype(&tensor, DType::F32).unwrap();
+        assert_eq!(output.kind(), Kind::Float);
+        assert!(Backend::to_dtype(&tensor, DType::U32).is_err());
+    }
+
+    #[test]
+    fn tch_device() {
+        let tensor = Tensor::arange(6, (Kind::Int64, Device::Cpu));
+        assert_eq!(Backend::device(&tensor), Some(crate::Device::Cpu));
+
+        let output = Backend::to_device(&tensor, crate::Device::Cpu).unwrap();
+        assert_eq!(output.device(), Device::Cpu);
+    }
+
+    #[test]
+    fn tch_scan() {
+        let tensor = Tensor::from_slice(&[1i64, 3, 2, 4, 0, 5]).reshape([2, 3]);
+
+        let output = Backend::scan(&tensor, 1, ScanOp::Sum).unwrap();
+        assert_eq!(
+            Vec::<i64>::try_from(output.flatten(0, -1)).unwrap(),
+            [1, 4, 6, 4, 4, 9]
+        );
+        let output = Backend::scan(&tensor, 1, ScanOp::Max).unwrap();
+        assert_eq!(
+            Vec::<i64>::try_from(output.flatten(0, -1)).unwrap(),
+            [1, 3, 3, 4, 4, 5]
+        );
+    }
 }
@@ -0,0 +1,303 @@
+//! Logging, hooks and `tracing` spans of the operations run by `einops!` and
+//! the layers
+//!
+//! With the `trace-steps` feature each operation emits a debug level
+//! `tracing` event with its pattern, its kind and the shapes of its input and
+//! output, failed operations report their error instead of the output shape:
+//!
+//! ```text
+//! einops "b (h:4 w) c -> b c h w": reshape [2, 12, 3] -> [2, 4, 3, 3]
+//! einops "b (h:4 w) c -> b c h w": transpose [2, 4, 3, 3] -> [2, 3, 4, 3]
+//! ```
+//!
+//! A [`Hook`] given to a layer with `with_hook` is called before and after
+//! every operation of the layer, for custom profiling or assertions. With the
+//! `hooks` feature a hook registered with [`set_hook`] is called for the
+//! operations of `einops!` and of the layers without their own hook:
+//!
+//! ```ignore
+//! struct Shapes;
+//!
+//! impl trace::Hook for Shapes {
+//!     fn before_op(&self, step: &trace::Step, shape: &[usize]) {
+//!         assert!(shape.len() <= 4, "{} of {:?}", step.kind, step.pattern);
+//!     }
+//! }
+//!
+//! trace::set_hook(Some(Arc::new(Shapes)));
+//! ```
+//!
+//! With the `tracing` feature every `einops!` call and every application of a
+//! layer runs inside a debug level `einops` span recording its pattern and the
+//! shape and device of its input, so the time spent in the rearrangements is
+//! attributed to them in profiles. Layers plan their steps for new input
+//! shapes in an `einops_compile` span with the same fields.
+
+use std::sync::Arc;
+#[cfg(feature = "hooks")]
+use std::sync::RwLock;
+
+#[cfg(any(feature = "trace-steps", feature = "hooks", feature = "tracing"))]
+use crate::Backend;
+use crate::EinopsError;
+
+/// A backend operation run by `einops!` or a layer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Step<'a> {
+    /// Pattern of the `einops!` call or the layer running the operation, the
+    /// patterns of a chain of layers are joined by ` then `
+    pub pattern: &'a str,
+    /// Name of the [`crate::Backend`] method, like `reshape` or `reduce_axes`
+    pub kind: &'static str,
+}
+
+/// Callbacks around every backend operation run by `einops!` or a layer
+pub trait Hook: Send + Sync {
+    /// Called before `step` runs on a tensor of shape `shape`
+    fn before_op(&self, step: &Step, shape: &[usize]) {
+        let _ = (step, shape);
+    }
+
+    /// Called after `step` ran, with the shape of its output or its error
+    fn after_op(&self, step: &Step, output: Result<&[usize], &EinopsError>) {
+        let _ = (step, output);
+    }
+}
+
+#[cfg(feature = "hooks")]
+static HOOK: RwLock<Option<Arc<dyn Hook>>> = RwLock::new(None);
+
+/// Registers `hook` for the operations of all threads, replacing the previous
+/// one, `None` removes it
+///
+/// Layers with a hook of their own call theirs instead.
+pub fn set_hook(hook: Option<Arc<dyn Hook>>) {
+    #[cfg(feature = "hooks")]
+    {
+        *HOOK.write().unwrap_or_else(|error| error.into_inner()) = hook;
+    }
+    #[cfg(not(feature = "hooks"))]
+    let _ = hook;
+}
+
+#[cfg(feature = "hooks")]
+fn hook() -> Option<Arc<dyn Hook>> {
+    HOOK.read()
+        .unwrap_or_else(|error| error.into_inner())
+        .clone()
+}
+
+// The hook of a layer, or the one of `set_hook` for layers without one
+#[cfg(any(feature = "nn", feature = "safetensors", feature = "ffi"))]
+fn layer_hook(hook: Option<&Arc<dyn Hook>>) -> Option<Arc<dyn Hook>> {
+    #[cfg(feature = "hooks")]
+    if hook.is_none() {
+        return self::hook();
+    }
+    hook.cloned()
+}
+
+// Calls the hook before an operation of layers with the pattern `pattern`
+// runs on a tensor of shape `shape`
+#[cfg(any(feature = "nn", feature = "safetensors", feature = "ffi"))]
+pub(crate) fn before_op(
+    hook: Option<&Arc<dyn Hook>>,
+    pattern: &str,
+    kind: &'static str,
+    shape: &[usize],
+) {
+    if let Some(hook) = layer_hook(hook) {
+        hook.before_op(&Step { pattern, kind }, shape);
+    }
+}
+
+// Logs an operation of layers with the pattern `pattern` and calls the hook
+#[cfg(any(feature = "nn", feature = "safetensors", feature = "ffi"))]
+pub(crate) fn after_op(
+    hook: Option<&Arc<dyn Hook>>,
+    pattern: &str,
+    kind: &'static str,
+    input_shape: &[usize],
+    output: Result<&[usize], &EinopsError>,
+) {
+    #[cfg(feature = "trace-steps")]
+    tracing::debug!(
+        pattern,
+        kind,
+        "{}",
+        describe(pattern, kind, input_shape, output)
+    );
+    if let Some(hook) = layer_hook(hook) {
+        hook.after_op(&Step { pattern, kind }, output);
+    }
+    let _ = input_shape;
+}
+
+/// Span of one `einops!` call or layer, exited when dropped
+#[doc(hidden)]
+pub struct Span {
+    #[cfg(feature = "tracing")]
+    _span: tracing::span::EnteredSpan,
+}
+
+#[cfg(feature = "tracing")]
+#[doc(hidden)]
+pub fn enter<T: Backend>(pattern: &str, input: &T) -> Span {
+    // The fields are only read if the span is enabled
+    let span = tracing::debug_span!(
+        "einops",
+        pattern,
+        input_shape = ?input.shape(),
+        device = ?input.device(),
+    );
+    Span {
+        _span: span.entered(),
+    }
+}
+
+#[cfg(not(feature = "tracing"))]
+#[doc(hidden)]
+#[inline(always)]
+pub fn enter<T>(pattern: &str, input: &T) -> Span {
+    let _ = (pattern, input);
+    Span {}
+}
+
+// Span of applying layers with the pattern `pattern` to a buffer
+#[cfg(all(
+    feature = "cpu",
+    any(feature = "nn", feature = "safetensors", feature = "ffi")
+))]
+pub(crate) fn enter_buffer(pattern: &str, shape: &[usize]) -> Span {
+    #[cfg(feature = "tracing")]
+    return Span {
+        _span: tracing::debug_span!(
+            "einops",
+            pattern,
+            input_shape = ?shape,
+            device = ?Some(crate::Device::Cpu),
+        )
+        .entered(),
+    };
+    #[cfg(not(feature = "tracing"))]
+    {
+        let _ = (pattern, shape);
+        Span {}
+    }
+}
+
+// Span of planning the steps of layers with the pattern `pattern`
+#[cfg(any(feature = "nn", feature = "safetensors", feature = "ffi"))]
+pub(crate) fn enter_compile(pattern: &str, shape: &[usize], device: Option<crate::Device>) -> Span {
+    #[cfg(feature = "tracing")]
+    return Span {
+        _span: tracing::debug_span!(
+            "einops_compile",
+            pattern,
+            input_shape = ?shape,
+            device = ?device,
+        )
+        .entered(),
+    };
+    #[cfg(not(feature = "tracing"))]
+    {
+        let _ = (pattern, shape, device);
+        Span {}
+    }
+}
+
+/// Calls the hook before an operation of `pattern` runs on `input`
+#[cfg(feature = "hooks")]
+#[doc(hidden)]
+pub fn before<T: Backend>(pattern: &str, kind: &'static str, input: &T) {
+    if let Some(hook) = hook() {
+        hook.before_op(&Step { pattern, kind }, &input.shape());
+    }
+}
+
+#[cfg(not(feature = "hooks"))]
+#[doc(hidden)]
+#[inline(always)]
+pub fn before<T>(pattern: &str, kind: &'static str, input: &T) {
+    let _ = (pattern, kind, input);
+}
+
+/// Logs one operation of `pattern` run on `input` and calls the hook
+#[cfg(any(feature = "trace-steps", feature = "hooks"))]
+#[doc(hidden)]
+pub fn step<T: Backend, O: Backend>(
+    pattern: &str,
+    kind: &'static str,
+    input: &T,
+    output: Result<&O, &EinopsError>,
+) {
+    #[cfg(feature = "trace-steps")]
+    tracing::debug!(
+        pattern,
+        kind,
+        "{}",
+        describe(
+            pattern,
+            kind,
+            &input.shape(),
+            output.map(O::shape).as_deref().map_err(|error| *error)
+        )
+    );
+    #[cfg(feature = "hooks")]
+    if let Some(hook) = hook() {
+        let output_shape = output.map(O::shape);
+        hook.after_op(
+            &Step { pattern, kind },
+            output_shape.as_deref().map_err(|error| *error),
+        );
+    }
+    let _ = input;
+}
+
+#[cfg(not(any(feature = "trace-steps", feature = "hooks")))]
+#[doc(hidden)]
+#[inline(always)]
+pub fn step<T, O>(pattern: &str, kind: &'static str, input: &T, output: Result<&O, &EinopsError>) {
+    let _ = (pattern, kind, input, output);
+}
+
+#[cfg(feature = "trace-steps")]
+fn describe(
+    pattern: &str,
+    kind: &str,
+    input_shape: &[usize],
+    output: Result<&[usize], &EinopsError>,
+) -> String {
+    match output {
+        Ok(output_shape) => format!(
+            "einops {:?}: {} {:?} -> {:?}",
+            pattern, kind, input_shape, output_shape
+        ),
+        Err(error) => format!(
+            "einops {:?}: {} {:?} failed: {}",
+            pattern, kind, input_shape, error
+        ),
+    }
+}
+
+#[cfg(all(test, feature = "trace-steps"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn trace_describe() {
+        assert_eq!(
+            describe("a b -> b a", "transpose", &[2, 3], Ok(&[3, 2])),
+            "einops \"a b -> b a\": transpose [2, 3] -> [3, 2]"
+        );
+        assert_eq!(
+            describe(
+                "(a b) -> a b",
+                "reshape",
+                &[5],
+                Err(&EinopsError::new("cannot reshape"))
+            ),
+            "einops \"(a b) -> a b\": reshape [5] failed: einops error: cannot reshape"
+        );
+    }
+}
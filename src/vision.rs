@@ -0,0 +1,173 @@
+//! Rearrangements of vision models, for any backend
+//!
+//! Images are `[batch, channels, height, width]` unless stated otherwise.
+
+use crate::{einops, Backend, EinopsError, ErrorKind};
+
+// Shape of a batch of images
+fn image_shape<T: Backend>(image: &T) -> Result<[usize; 4], EinopsError> {
+    let shape = image.shape();
+    shape.as_slice().try_into().map_err(|_| {
+        ErrorKind::RankMismatch {
+            expected: 4,
+            found: shape.len(),
+        }
+        .into()
+    })
+}
+
+/// Splits images into square patches of `patch` pixels a side, the tokens of a
+/// vision transformer, `b c (h p1) (w p2) -> b (h w) (p1 p2 c)`
+///
+/// Patches are ordered row by row, the features of a patch are its pixels row
+/// by row with their channels last.
+pub fn to_patches<T: Backend<Output = T>>(image: &T, patch: usize) -> Result<T, EinopsError> {
+    let [_, _, height, width] = image_shape(image)?;
+    check_blocks([2, 3], height, width, patch)?;
+    let (p1, p2) = (patch, patch);
+    einops!("b c (h {p1}) (w {p2}) -> b (h w) ({p1} {p2} c)", image)
+}
+
+/// Inverse of [`to_patches`] for images of `height` by `width` pixels,
+/// `b (h w) (p1 p2 c) -> b c (h p1) (w p2)`
+pub fn from_patches<T: Backend<Output = T>>(
+    patches: &T,
+    patch: usize,
+    height: usize,
+    width: usize,
+) -> Result<T, EinopsError> {
+    check_blocks([2, 3], height, width, patch)?;
+    let (h, w, p1, p2) = (height / patch, width / patch, patch, patch);
+    einops!(
+        "b ({h} {w}) ({p1} {p2} c) -> b c ({h} {p1}) ({w} {p2})",
+        patches
+    )
+}
+
+// Checks that images of `height` by `width` pixels, on the axes `axes`, can
+// be split into blocks of `size` pixels a side
+fn check_blocks(
+    axes: [usize; 2],
+    height: usize,
+    width: usize,
+    size: usize,
+) -> Result<(), EinopsError> {
+    let groups = [("(h p1)", "p1", height), ("(w p2)", "p2", width)];
+    for (axis, (group, block, len)) in axes.into_iter().zip(groups) {
+        if size == 0 || !len.is_multiple_of(size) {
+            return Err(ErrorKind::NotDivisible {
+                axis,
+                group: group.into(),
+                sizes: vec![(block.into(), size)],
+                size: len,
+                divisor: size,
+            }
+            .into());
+        }
+    }
+    Ok(())
+}
+
+/// Moves blocks of `r`x`r` channels into `r`x`r` pixels, upsampling the
+/// images by `r`, `b (c r1 r2) h w -> b c (h r1) (w r2)`
+///
+/// Runs [`Backend::depth_to_space`] when the backend has a kernel for it, like
+/// LibTorch's `pixel_shuffle`.
+pub fn pixel_shuffle<T: Backend<Output = T>>(x: &T, r: usize) -> Result<T, EinopsError> {
+    let (r1, r2) = (r, r);
+    einops!("b (c {r1} {r2}) h w -> b c (h {r1}) (w {r2})", x)
+}
+
+/// Inverse of [`pixel_shuffle`], `b c (h r1) (w r2) -> b (c r1 r2) h w`
+///
+/// Runs [`Backend::space_to_depth`] when the backend has a kernel for it.
+pub fn pixel_unshuffle<T: Backend<Output = T>>(x: &T, r: usize) -> Result<T, EinopsError> {
+    let (r1, r2) = (r, r);
+    einops!("b c (h {r1}) (w {r2}) -> b (c {r1} {r2}) h w", x)
+}
+
+/// Moves blocks of `block`x`block` channels into pixels like TensorFlow's and
+/// ONNX's `DepthToSpace`, `b (r1 r2 c) h w -> b c (h r1) (w r2)`
+///
+/// Unlike [`pixel_shuffle`], channels of a block are ordered by row, then
+/// column, then output channel.
+pub fn depth_to_space_nchw<T: Backend<Output = T>>(x: &T, block: usize) -> Result<T, EinopsError> {
+    let (r1, r2) = (block, block);
+    einops!("b ({r1} {r2} c) h w -> b c (h {r1}) (w {r2})", x)
+}
+
+/// [`depth_to_space_nchw`] for images of shape `[b, h, w, c]`,
+/// `b h w (r1 r2 c) -> b (h r1) (w r2) c`
+pub fn depth_to_space_nhwc<T: Backend<Output = T>>(x: &T, block: usize) -> Result<T, EinopsError> {
+    let (r1, r2) = (block, block);
+    einops!("b h w ({r1} {r2} c) -> b (h {r1}) (w {r2}) c", x)
+}
+
+/// Inverse of [`depth_to_space_nchw`], `b c (h r1) (w r2) -> b (r1 r2 c) h w`
+pub fn space_to_depth_nchw<T: Backend<Output = T>>(x: &T, block: usize) -> Result<T, EinopsError> {
+    let (r1, r2) = (block, block);
+    einops!("b c (h {r1}) (w {r2}) -> b ({r1} {r2} c) h w", x)
+}
+
+/// Inverse of [`depth_to_space_nhwc`], `b (h r1) (w r2) c -> b h w (r1 r2 c)`
+pub fn space_to_depth_nhwc<T: Backend<Output = T>>(x: &T, block: usize) -> Result<T, EinopsError> {
+    let (r1, r2) = (block, block);
+    einops!("b (h {r1}) (w {r2}) c -> b h w ({r1} {r2} c)", x)
+}
+
+/// Splits images of shape `[b, h, w, c]` into windows of `window`x`window`
+/// pixels, like Swin transformers, `b (h p1) (w p2) c -> (b h w) p1 p2 c`
+///
+/// Windows are ordered by image, then row by row. Shifted windows roll the
+/// images by half a window first:
+///
+/// ```ignore
+/// let shift = window as isize / 2;
+/// let shifted = roll(&roll(&x, 1, -shift)?, 2, -shift)?;
+/// let windows = window_partition(&shifted, window)?;
+/// // .. attention within the windows ..
+/// let shifted = window_reverse(&windows, window, h, w)?;
+/// let x = roll(&roll(&shifted, 1, shift)?, 2, shift)?;
+/// ```
+pub fn window_partition<T: Backend<Output = T>>(x: &T, window: usize) -> Result<T, EinopsError> {
+    let [_, height, width, _] = image_shape(x)?;
+    check_blocks([1, 2], height, width, window)?;
+    let (p1, p2) = (window, window);
+    einops!("b (h {p1}) (w {p2}) c -> (b h w) p1 p2 c", x)
+}
+
+/// Inverse of [`window_partition`] for images of `height` by `width` pixels,
+/// `(b h w) p1 p2 c -> b (h p1) (w p2) c`
+pub fn window_reverse<T: Backend<Output = T>>(
+    windows: &T,
+    window: usize,
+    height: usize,
+    width: usize,
+) -> Result<T, EinopsError> {
+    check_blocks([1, 2], height, width, window)?;
+    let (h, w) = (height / window, width / window);
+    einops!("(b {h} {w}) p1 p2 c -> b ({h} p1) ({w} p2) c", windows)
+}
+
+/// Shifts the elements of `axis` by `shift` positions, the ones moved past the
+/// end come back at the start, like `torch.roll`
+pub fn roll<T: Backend<Output = T>>(x: &T, axis: usize, shift: isize) -> Result<T, EinopsError> {
+    let shape = x.shape();
+    let Some(&len) = shape.get(axis) else {
+        return Err(EinopsError::new(format!(
+            "cannot roll axis {} of tensor with shape {:?}",
+            axis, shape
+        )));
+    };
+    let shift = match len {
+        0 => 0,
+        _ => shift.rem_euclid(len as isize) as usize,
+    };
+    if shift == 0 {
+        return x.narrow(axis, 0, len);
+    }
+
+    let end = x.narrow(axis, len - shift, shift)?;
+    let start = x.narrow(axis, 0, len - shift)?;
+    T::concat(&[&end, &start], axis)
+}
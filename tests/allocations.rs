@@ -0,0 +1,147 @@
+#![cfg(feature = "nn")]
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use candle_einops::layers::{Chain, Rearrange, Reduce, Repeat};
+use candle_einops::{Backend, EinopsError, Operation};
+
+// Counts the allocations of the threads that turned counting on, other tests
+// and the test harness allocate on their own threads
+struct Counting;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+thread_local! {
+    static COUNTING: Cell<bool> = const { Cell::new(false) };
+}
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        if COUNTING.try_with(Cell::get).unwrap_or(false) {
+            ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        }
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: Counting = Counting;
+
+// Allocations of `f`, outside of the backend
+fn allocations(f: impl FnOnce()) -> usize {
+    let before = ALLOCATIONS.load(Ordering::Relaxed);
+    COUNTING.with(|counting| counting.set(true));
+    f();
+    COUNTING.with(|counting| counting.set(false));
+    ALLOCATIONS.load(Ordering::Relaxed) - before
+}
+
+// Stops counting while the backend allocates its shapes
+fn uncounted<R>(f: impl FnOnce() -> R) -> R {
+    let previous = COUNTING.with(|counting| counting.replace(false));
+    let result = f();
+    COUNTING.with(|counting| counting.set(previous));
+    result
+}
+
+// A tensor that is only a shape
+#[derive(Clone, Debug)]
+struct Shape(Vec<usize>);
+
+impl Backend for Shape {
+    type Output = Shape;
+
+    fn shape(&self) -> Vec<usize> {
+        uncounted(|| self.0.clone())
+    }
+
+    fn reshape(&self, shape: &[usize]) -> Result<Self::Output, EinopsError> {
+        uncounted(|| Ok(Shape(shape.to_vec())))
+    }
+
+    fn transpose(&self, axes: &[usize]) -> Result<Self::Output, EinopsError> {
+        uncounted(|| Ok(Shape(axes.iter().map(|&axis| self.0[axis]).collect())))
+    }
+
+    fn reduce_axes(
+        &self,
+        axes_operations: &mut [(usize, Operation)],
+    ) -> Result<Self::Output, EinopsError> {
+        uncounted(|| {
+            let shape = (0..self.0.len())
+                .filter(|&axis| axes_operations.iter().all(|&(reduced, _)| reduced != axis))
+                .map(|axis| self.0[axis])
+                .collect();
+            Ok(Shape(shape))
+        })
+    }
+
+    fn add_axes(&self, _: usize, pos2len: &[(usize, usize)]) -> Result<Self::Output, EinopsError> {
+        uncounted(|| {
+            let mut shape = self.0.clone();
+            for &(axis, len) in pos2len {
+                shape.insert(axis, len);
+            }
+            Ok(Shape(shape))
+        })
+    }
+
+    fn narrow(&self, _: usize, _: usize, _: usize) -> Result<Self::Output, EinopsError> {
+        unimplemented!()
+    }
+
+    fn broadcast(&self, _: &[usize]) -> Result<Self::Output, EinopsError> {
+        unimplemented!()
+    }
+
+    fn is_contiguous(&self) -> bool {
+        true
+    }
+
+    fn contiguous(&self) -> Result<Self::Output, EinopsError> {
+        uncounted(|| Ok(self.clone()))
+    }
+
+    fn concat(_: &[&Self], _: usize) -> Result<Self::Output, EinopsError> {
+        unimplemented!()
+    }
+
+    fn index_select(&self, _: usize, _: &[usize]) -> Result<Self::Output, EinopsError> {
+        unimplemented!()
+    }
+}
+
+#[test]
+fn allocations_apply() -> Result<(), EinopsError> {
+    let input = Shape(vec![2, 3, 4, 4, 5, 6, 7, 8]);
+    let rearrange = Rearrange::new(
+        "b c (h p1) (w p2) .. -> b (h w) .. (p1 p2 c)",
+        &[("p1", 2), ("p2", 2)],
+    )?;
+    let reduce = Reduce::new("b c h w .. -> b .. c", Operation::Max)?;
+    let repeat = Repeat::new("b c h w .. -> b c (h r) w ..", &[("r", 2)])?;
+    let chain = Chain::from(rearrange.clone()).then(Rearrange::new("b n .. -> n b ..", &[])?);
+
+    // The first call compiles the recipe, the ones after it only run it
+    let output = rearrange.apply(&input)?;
+    assert_eq!(output.0, [2, 4, 5, 6, 7, 8, 12]);
+    assert_eq!(allocations(|| drop(rearrange.apply(&input))), 0);
+
+    reduce.apply(&input)?;
+    assert_eq!(allocations(|| drop(reduce.apply(&input))), 0);
+    repeat.apply(&input)?;
+    assert_eq!(allocations(|| drop(repeat.apply(&input))), 0);
+    chain.apply(&input)?;
+    assert_eq!(allocations(|| drop(chain.apply(&input))), 0);
+
+    // Compiling allocates, which the counting sees
+    assert!(allocations(|| drop(rearrange.apply(&Shape(vec![2, 3, 4, 4])))) > 0);
+
+    Ok(())
+}
@@ -0,0 +1,27 @@
+#![cfg(feature = "arrow")]
+
+use arrow_array::types::Float32Type;
+use arrow_array::{Array, FixedSizeListArray};
+use candle_einops::arrow::ArrowTensor;
+use candle_einops::cpu::CpuTensor;
+use candle_einops::{einops, EinopsError};
+
+#[test]
+fn arrow_patterns() -> Result<(), EinopsError> {
+    let features = (0..24).map(|x| x as f32).collect::<Vec<_>>();
+    let column = ArrowTensor::<Float32Type>::try_from(CpuTensor::new(features, vec![4, 2, 3])?)?;
+
+    let output = einops!("rows t f -> rows (f t)", &column)?;
+    assert_eq!(output.shape(), &[4, 6]);
+    assert_eq!(output.data()[1], 3.0);
+
+    let output = einops!("rows t mean(f) -> rows t", &column)?;
+    assert_eq!(output.data()[..2], [1.0, 4.0]);
+
+    let column = ArrowTensor::<Float32Type>::try_from(output)?;
+    let array: FixedSizeListArray = column.into_inner();
+    assert_eq!(array.len(), 4);
+    assert_eq!(array.value_length(), 2);
+
+    Ok(())
+}
@@ -0,0 +1,122 @@
+#![cfg(feature = "candle")]
+
+use candle_core::{Device, Result, Tensor};
+use candle_einops::attention::{
+    merge_heads, merge_rotary, repeat_kv_heads, split_heads, split_rotary, RotaryStyle,
+};
+use candle_einops::ErrorKind;
+
+#[test]
+fn attention_heads() -> Result<()> {
+    let x = Tensor::arange(0u32, 2 * 3 * 8, &Device::Cpu)?.reshape(&[2, 3, 8])?;
+
+    let heads = split_heads(&x, 4)?;
+    assert_eq!(heads.dims(), [2, 4, 3, 2]);
+    // Head 1 of token 2 holds features 2 and 3
+    assert_eq!(heads.get(0)?.get(1)?.get(2)?.to_vec1::<u32>()?, [18, 19]);
+    assert_eq!(merge_heads(&heads)?.to_vec3::<u32>()?, x.to_vec3::<u32>()?);
+
+    let error = split_heads(&x, 3).unwrap_err();
+    assert!(matches!(
+        error.kind(),
+        ErrorKind::NotDivisible {
+            size: 8,
+            divisor: 3,
+            ..
+        }
+    ));
+
+    Ok(())
+}
+
+#[test]
+fn attention_kv_heads() -> Result<()> {
+    let kv = Tensor::arange(0u32, 2 * 2 * 3 * 2, &Device::Cpu)?.reshape(&[2, 2, 3, 2])?;
+
+    // Query heads 0 and 1 share kv head 0, 2 and 3 share kv head 1
+    let repeated = repeat_kv_heads(&kv, 4)?;
+    assert_eq!(repeated.dims(), [2, 4, 3, 2]);
+    for head in 0..4 {
+        assert_eq!(
+            repeated.get(1)?.get(head)?.to_vec2::<u32>()?,
+            kv.get(1)?.get(head / 2)?.to_vec2::<u32>()?
+        );
+    }
+    // With as many heads the values don't change
+    assert_eq!(
+        repeat_kv_heads(&kv, 2)?.flatten_all()?.to_vec1::<u32>()?,
+        kv.flatten_all()?.to_vec1::<u32>()?
+    );
+
+    let error = repeat_kv_heads(&kv, 3).unwrap_err();
+    assert!(matches!(
+        error.kind(),
+        ErrorKind::NotDivisible {
+            axis: 1,
+            size: 3,
+            divisor: 2,
+            ..
+        }
+    ));
+    let error = repeat_kv_heads(&kv.get(0)?, 4).unwrap_err();
+    assert_eq!(
+        *error.kind(),
+        ErrorKind::RankMismatch {
+            expected: 4,
+            found: 3
+        }
+    );
+
+    Ok(())
+}
+
+#[test]
+fn attention_rotary() -> Result<()> {
+    let x = Tensor::arange(0u32, 2 * 3 * 8, &Device::Cpu)?.reshape(&[1, 2, 3, 8])?;
+
+    let neox = split_rotary(&x, RotaryStyle::NeoX)?;
+    assert_eq!(neox.dims(), [1, 2, 3, 4, 2]);
+    // Feature 1 is rotated with feature 5 by NeoX and with feature 0 by GPT-J
+    let token = neox.get(0)?.get(0)?.get(0)?;
+    assert_eq!(token.to_vec2::<u32>()?, [[0, 4], [1, 5], [2, 6], [3, 7]]);
+    let gptj = split_rotary(&x, RotaryStyle::GptJ)?;
+    let token = gptj.get(0)?.get(0)?.get(0)?;
+    assert_eq!(token.to_vec2::<u32>()?, [[0, 1], [2, 3], [4, 5], [6, 7]]);
+
+    for (pairs, style) in [(&neox, RotaryStyle::NeoX), (&gptj, RotaryStyle::GptJ)] {
+        assert_eq!(
+            merge_rotary(pairs, style)?
+                .flatten_all()?
+                .to_vec1::<u32>()?,
+            x.flatten_all()?.to_vec1::<u32>()?
+        );
+    }
+
+    // Any leading axes are kept
+    assert_eq!(
+        split_rotary(&x.get(0)?, RotaryStyle::NeoX)?.dims(),
+        [2, 3, 4, 2]
+    );
+    let error = split_rotary(&x.narrow(3, 0, 7)?, RotaryStyle::GptJ).unwrap_err();
+    assert!(matches!(
+        error.kind(),
+        ErrorKind::NotDivisible {
+            axis: 3,
+            size: 7,
+            divisor: 2,
+            ..
+        }
+    ));
+    let error = merge_rotary(&x, RotaryStyle::NeoX).unwrap_err();
+    assert!(matches!(
+        error.kind(),
+        ErrorKind::ShapeMismatch {
+            axis: 3,
+            expected: 2,
+            found: 8,
+            ..
+        }
+    ));
+
+    Ok(())
+}
@@ -0,0 +1,130 @@
+use candle_einops::bridge::{Bridge, TensorOps};
+use candle_einops::{einops, EinopsError, Operation};
+
+// Row-major buffer implementing only the required operations
+#[derive(Debug, Clone)]
+struct Dense {
+    data: Vec<f32>,
+    shape: Vec<usize>,
+}
+
+fn strides(shape: &[usize]) -> Vec<usize> {
+    let mut strides = vec![1; shape.len()];
+    for axis in (0..shape.len().saturating_sub(1)).rev() {
+        strides[axis] = strides[axis + 1] * shape[axis + 1];
+    }
+    strides
+}
+
+impl Dense {
+    // Builds a tensor of `shape` whose element at `index` is read from `offset(index)`
+    fn gather(&self, shape: &[usize], offset: impl Fn(&[usize]) -> usize) -> Dense {
+        let len = shape.iter().product::<usize>();
+        let output_strides = strides(shape);
+        let data = (0..len)
+            .map(|i| {
+                let index = output_strides
+                    .iter()
+                    .zip(shape)
+                    .map(|(stride, len)| i / stride % len)
+                    .collect::<Vec<_>>();
+                self.data[offset(&index)]
+            })
+            .collect();
+        Dense {
+            data,
+            shape: shape.to_vec(),
+        }
+    }
+}
+
+impl TensorOps for Dense {
+    fn shape(&self) -> Vec<usize> {
+        self.shape.clone()
+    }
+
+    fn reshape(&self, shape: &[usize]) -> Result<Self, EinopsError> {
+        if shape.iter().product::<usize>() != self.data.len() {
+            return Err(EinopsError::new("invalid shape"));
+        }
+        Ok(Dense {
+            data: self.data.clone(),
+            shape: shape.to_vec(),
+        })
+    }
+
+    fn permute(&self, axes: &[usize]) -> Result<Self, EinopsError> {
+        let input_strides = strides(&self.shape);
+        let shape = axes
+            .iter()
+            .map(|&axis| self.shape[axis])
+            .collect::<Vec<_>>();
+        Ok(self.gather(&shape, |index| {
+            index
+                .iter()
+                .zip(axes)
+                .map(|(i, &axis)| i * input_strides[axis])
+                .sum()
+        }))
+    }
+
+    fn reduce(&self, axis: usize, operation: Operation) -> Result<Self, EinopsError> {
+        let outer = self.shape[..axis].iter().product::<usize>();
+        let len = self.shape[axis];
+        let inner = self.shape[axis + 1..].iter().product::<usize>();
+        let mut data = Vec::with_capacity(outer * inner);
+        for o in 0..outer {
+            for i in 0..inner {
+                let values = (0..len).map(|l| self.data[(o * len + l) * inner + i]);
+                data.push(match operation {
+                    Operation::Sum => values.sum(),
+                    Operation::Mean => values.sum::<f32>() / len as f32,
+                    Operation::Min => values.fold(f32::INFINITY, f32::min),
+                    Operation::Max => values.fold(f32::NEG_INFINITY, f32::max),
+                });
+            }
+        }
+        let mut shape = self.shape.clone();
+        shape.remove(axis);
+        Ok(Dense { data, shape })
+    }
+
+    fn expand(&self, shape: &[usize]) -> Result<Self, EinopsError> {
+        let input_strides = strides(&self.shape);
+        Ok(self.gather(shape, |index| {
+            index
+                .iter()
+                .zip(&self.shape)
+                .zip(&input_strides)
+                .map(|((i, &len), stride)| if len == 1 { 0 } else { i * stride })
+                .sum()
+        }))
+    }
+}
+
+fn dense(shape: Vec<usize>) -> Dense {
+    Dense {
+        data: (0..shape.iter().product::<usize>())
+            .map(|x| x as f32)
+            .collect(),
+        shape,
+    }
+}
+
+#[test]
+fn bridge_patterns() -> Result<(), EinopsError> {
+    let input = Bridge(dense(vec![2, 3]));
+
+    let output = einops!("a b -> b a", &input)?.into_inner();
+    assert_eq!(output.shape, [3, 2]);
+    assert_eq!(output.data, [0.0, 3.0, 1.0, 4.0, 2.0, 5.0]);
+
+    let output = einops!("a sum(b) -> a", &input)?.into_inner();
+    assert_eq!(output.data, [3.0, 12.0]);
+
+    let output = einops!("a b -> a 2 b", &input)?.into_inner();
+    assert_eq!(output.shape, [2, 2, 3]);
+    assert_eq!(output.data[..6], [0.0, 1.0, 2.0, 0.0, 1.0, 2.0]);
+
+    Ok(())
+}
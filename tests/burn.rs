@@ -0,0 +1,40 @@
+#![cfg(feature = "burn")]
+
+use burn_ndarray::NdArray;
+use burn_tensor::Tensor;
+use candle_einops::burn::BurnTensor;
+use candle_einops::{einops, EinopsError};
+
+type TestBackend = NdArray<f32>;
+
+#[test]
+fn burn_patterns() -> Result<(), EinopsError> {
+    let device = Default::default();
+    let input = BurnTensor::from(
+        Tensor::<TestBackend, 1>::from_floats(
+            [
+                0.0, 1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0, 8.0, 9.0, 10.0, 11.0, 12.0, 13.0, 14.0,
+                15.0, 16.0, 17.0, 18.0, 19.0, 20.0, 21.0, 22.0, 23.0,
+            ],
+            &device,
+        )
+        .reshape([2, 3, 4]),
+    );
+
+    let output = einops!("a b c -> c (a b)", &input)?;
+    assert_eq!(output.dims(), [4, 6]);
+
+    let output = einops!(
+        "a (b max(b2:2)) c -> a b c",
+        &einops!("a b c -> a (b 2) c", &input)?
+    )?;
+    let output: Tensor<TestBackend, 3> = output.try_into()?;
+    let input: Tensor<TestBackend, 3> = input.try_into()?;
+    assert_eq!(output.into_data(), input.clone().into_data());
+
+    let output = einops!("a b sum(c) -> b a", &BurnTensor::from(input))?;
+    let output: Tensor<TestBackend, 2> = output.try_into()?;
+    assert_eq!(output.into_data().to_vec::<f32>().unwrap()[5], 86.0);
+
+    Ok(())
+}
@@ -0,0 +1,30 @@
+#![cfg(feature = "candle")]
+
+use candle_core::{Device, Result, Tensor};
+use candle_einops::complex::ComplexTensor;
+use candle_einops::einops;
+
+#[test]
+fn complex_patterns() -> Result<()> {
+    let re = Tensor::arange(0f32, 6.0, &Device::Cpu)?.reshape(&[2, 3])?;
+    let im = Tensor::arange(6f32, 12.0, &Device::Cpu)?.reshape(&[2, 3])?;
+    let input = ComplexTensor::new(re, im)?;
+
+    let (re, im) = einops!("a b -> b (a 2)", &input)?.into_parts();
+    assert_eq!(re.dims(), [3, 4]);
+    assert_eq!(re.to_vec2::<f32>()?[0], [0.0, 0.0, 3.0, 3.0]);
+    assert_eq!(im.to_vec2::<f32>()?[0], [6.0, 6.0, 9.0, 9.0]);
+
+    let (re, im) = einops!("a sum(b) -> a", &input)?.into_parts();
+    assert_eq!(re.to_vec1::<f32>()?, [3.0, 12.0]);
+    assert_eq!(im.to_vec1::<f32>()?, [21.0, 30.0]);
+
+    assert!(einops!("a max(b) -> a", &input).is_err());
+    assert!(ComplexTensor::new(
+        Tensor::zeros(2, candle_core::DType::F32, &Device::Cpu)?,
+        Tensor::zeros(3, candle_core::DType::F32, &Device::Cpu)?
+    )
+    .is_err());
+
+    Ok(())
+}
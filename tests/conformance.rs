@@ -0,0 +1,50 @@
+#[cfg(feature = "candle")]
+use candle_core::{Device, Tensor};
+
+#[cfg(feature = "candle")]
+candle_einops::backend_conformance!(
+    candle,
+    |data: Vec<f32>, shape: &[usize]| Tensor::from_vec(data, shape, &Device::Cpu).unwrap(),
+    |tensor: &Tensor| tensor.flatten_all().unwrap().to_vec1::<f32>().unwrap()
+);
+
+#[cfg(feature = "cpu")]
+candle_einops::backend_conformance!(
+    cpu,
+    |data: Vec<f32>, shape: &[usize]| {
+        candle_einops::cpu::CpuTensor::new(data, shape.to_vec()).unwrap()
+    },
+    |tensor: &candle_einops::cpu::CpuTensor<f32>| tensor.data().to_vec()
+);
+
+#[cfg(feature = "ndarray")]
+candle_einops::backend_conformance!(
+    ndarray,
+    |data: Vec<f32>, shape: &[usize]| {
+        ::ndarray::ArrayD::from_shape_vec(::ndarray::IxDyn(shape), data).unwrap()
+    },
+    |tensor: &::ndarray::ArrayD<f32>| tensor.iter().copied().collect()
+);
+
+#[cfg(feature = "burn")]
+candle_einops::backend_conformance!(
+    burn,
+    |data: Vec<f32>, shape: &[usize]| {
+        let len = data.len();
+        let tensor = burn_tensor::Tensor::<burn_ndarray::NdArray<f32>, 1>::from_data(
+            burn_tensor::TensorData::new(data, [len]),
+            &Default::default(),
+        );
+        candle_einops::Backend::reshape(&candle_einops::burn::BurnTensor::from(tensor), shape)
+            .unwrap()
+    },
+    |tensor: &candle_einops::burn::BurnTensor<burn_ndarray::NdArray<f32>>| {
+        let len = tensor.dims().iter().product::<usize>();
+        let tensor: burn_tensor::Tensor<burn_ndarray::NdArray<f32>, 1> =
+            candle_einops::Backend::reshape(tensor, &[len])
+                .unwrap()
+                .try_into()
+                .unwrap();
+        tensor.into_data().to_vec::<f32>().unwrap()
+    }
+);
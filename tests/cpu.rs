@@ -0,0 +1,27 @@
+#![cfg(feature = "cpu")]
+
+use candle_einops::cpu::CpuTensor;
+use candle_einops::{einops, EinopsError};
+
+#[test]
+fn cpu_patterns() -> Result<(), EinopsError> {
+    let input = CpuTensor::new((0..24).collect::<Vec<u32>>(), vec![2, 3, 4])?;
+
+    let output = einops!("a b c -> c (a b)", &input)?;
+    assert_eq!(output.shape(), &[4, 6]);
+    assert_eq!(output.data()[4 + 6], 1 + 4 + 12);
+
+    let output = einops!(
+        "a (b max(b2:2)) c -> a b c",
+        &einops!("a b c -> a (b 2) c", &input)?
+    )?;
+    assert_eq!(output, input);
+
+    let output = einops!("a b sum(c) -> b a", &input)?;
+    assert_eq!(output.data()[5], 20 + 21 + 22 + 23);
+
+    let output = einops!("(a1:1 a) .. -> .. a a1", &input)?;
+    assert_eq!(output.shape(), &[3, 4, 2, 1]);
+
+    Ok(())
+}
@@ -0,0 +1,50 @@
+#![cfg(feature = "candle")]
+
+use candle_core::{Device, Result, Tensor};
+use candle_einops::deferred::Deferred;
+use candle_einops::{einops, Backend};
+
+fn split_heads(input: &Deferred<Tensor>) -> Result<Deferred<Tensor>> {
+    Ok(einops!("b t (h:2 d) -> b h t d", input)?)
+}
+
+fn merge_heads(input: &Deferred<Tensor>) -> Result<Deferred<Tensor>> {
+    Ok(einops!("b h t d -> b t (h d)", input)?)
+}
+
+#[test]
+fn deferred_chain() -> Result<()> {
+    let tensor = Tensor::arange(0f32, 24.0, &Device::Cpu)?.reshape(&[1, 3, 8])?;
+    let input = Deferred::new(tensor.clone());
+
+    let heads = split_heads(&input)?;
+    assert_eq!(Backend::shape(&heads), [1, 2, 3, 4]);
+
+    // Splitting and merging the heads again cancels out
+    let output = merge_heads(&heads)?.eval()?;
+    assert_eq!(output.id(), tensor.id());
+
+    let output = einops!("b max(t) (h:2 d) -> b h d", &input)?.eval()?;
+    assert_eq!(
+        output.to_vec3::<f32>()?,
+        einops!("b max(t) (h:2 d) -> b h d", &tensor)?.to_vec3::<f32>()?
+    );
+
+    Ok(())
+}
+
+#[test]
+fn deferred_errors() -> Result<()> {
+    let input = Deferred::new(Tensor::zeros(
+        &[2, 3],
+        candle_core::DType::F32,
+        &Device::Cpu,
+    )?);
+
+    // Shapes are checked when the operation is recorded
+    assert!(Backend::reshape(&input, &[4]).is_err());
+    assert!(Backend::transpose(&input, &[0, 0]).is_err());
+    assert!(Backend::narrow(&input, 1, 2, 2).is_err());
+
+    Ok(())
+}
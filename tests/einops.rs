@@ -1,11 +1,14 @@
-use candle_core::{Device, IndexOp, Result, Tensor};
-use candle_einops::{einops, Backend};
+#![cfg(feature = "candle")]
+
+use candle_core::quantized::{GgmlDType, QTensor};
+use candle_core::{DType, Device, IndexOp, Result, Tensor, Var};
+use candle_einops::{einops, DynBackend, EinopsError, ErrorKind};
 
 #[test]
 fn candle_layers() -> Result<()> {
     let input = Tensor::randn(0.0f32, 1.0, (10, 3, 32, 32), &Device::Cpu)?;
 
-    let output1 = einops!("b c (h max(2)) (w max(2)) -> b c h w", &input);
+    let output1 = einops!("b c (h max(2)) (w max(2)) -> b c h w", &input)?;
     let output2 = input.max_pool2d(2)?;
 
     assert_eq!(
@@ -18,10 +21,10 @@ fn candle_layers() -> Result<()> {
 
 #[test]
 fn consistency_checks() -> Result<()> {
-    let input = Tensor::arange(0f32, (1 * 2 * 3 * 5 * 7 * 11) as f32, &Device::Cpu)?
-        .reshape(&[1, 2, 3, 5, 7, 11]);
+    let input = Tensor::arange(0f32, (2 * 3 * 5 * 7 * 11) as f32, &Device::Cpu)?
+        .reshape(&[1, 2, 3, 5, 7, 11])?;
 
-    let output = einops!("a b c d e f -> a (b) (c d e) f", &input);
+    let output = einops!("a b c d e f -> a (b) (c d e) f", &input)?;
     assert_eq!(
         input
             .flatten(0, input.dims().len() - 1)?
@@ -33,24 +36,24 @@ fn consistency_checks() -> Result<()> {
             .to_vec1::<f32>()?,
     );
 
-    let output1 = einops!("a b c d e f -> f e d c b a", &input);
-    let output2 = einops!("f e d c b a -> a b c d e f", &input);
+    let output1 = einops!("a b c d e f -> f e d c b a", &input)?;
+    let output2 = einops!("f e d c b a -> a b c d e f", &input)?;
     assert_eq!(output1.dims(), output2.dims());
     assert_eq!(
         output1.flatten_all()?.to_vec1::<f32>()?,
         output2.flatten_all()?.to_vec1::<f32>()?
     );
 
-    let intermediate = einops!("a b c d e f -> (f d) c (e b) a", &input);
-    let output = einops!("(f d:5) c (e b:2) a -> a b c d e f", &intermediate);
+    let intermediate = einops!("a b c d e f -> (f d) c (e b) a", &input)?;
+    let output = einops!("(f d:5) c (e b:2) a -> a b c d e f", &intermediate)?;
     assert_eq!(output.dims(), input.dims());
     assert_eq!(
         output.flatten_all()?.to_vec1::<f32>()?,
         input.flatten_all()?.to_vec1::<f32>()?
     );
 
-    let input = Tensor::arange(0f32, (2 * 3 * 4) as f32, &Device::Cpu)?.reshape(&[2, 3, 4]);
-    let output = einops!("a b c -> b c a", &input);
+    let input = Tensor::arange(0f32, (2 * 3 * 4) as f32, &Device::Cpu)?.reshape(&[2, 3, 4])?;
+    let output = einops!("a b c -> b c a", &input)?;
     assert_eq!(
         input.i((1, 2, 3))?.flatten_all()?.to_vec1::<f32>()?,
         output.i((2, 3, 1))?.flatten_all()?.to_vec1::<f32>()?
@@ -65,8 +68,8 @@ fn consistency_checks() -> Result<()> {
 
 macro_rules! test {
     ($pattern1:literal, $pattern2:literal, $tensor:ident) => {
-        let output1 = einops!($pattern1, &$tensor);
-        let output2 = einops!($pattern2, &$tensor);
+        let output1 = einops!($pattern1, &$tensor)?;
+        let output2 = einops!($pattern2, &$tensor)?;
         assert_eq!(
             output1.flatten_all()?.to_vec1::<f32>()?,
             output2.flatten_all()?.to_vec1::<f32>()?, "({}) & ({}) failed", $pattern1, $pattern2
@@ -79,8 +82,8 @@ macro_rules! test {
 
 #[test]
 fn equivalent_rearrange() -> Result<()> {
-    let input =
-        Tensor::arange(0f32, (2 * 3 * 4 * 5 * 6) as f32, &Device::Cpu)?.reshape(&[2, 3, 4, 5, 6]);
+    let input = Tensor::arange(0f32, (2 * 3 * 4 * 5 * 6) as f32, &Device::Cpu)?
+        .reshape(&[2, 3, 4, 5, 6])?;
     test![
         ("a b c d e -> (a b) c d e", "a b .. -> (a b) .."),
         ("a b c d e -> a b (c d) e", ".. c d e -> .. (c d) e"),
@@ -94,8 +97,8 @@ fn equivalent_rearrange() -> Result<()> {
 
 #[test]
 fn equivalent_reduction() -> Result<()> {
-    let input =
-        Tensor::arange(0f32, (2 * 3 * 4 * 5 * 6) as f32, &Device::Cpu)?.reshape(&[2, 3, 4, 5, 6]);
+    let input = Tensor::arange(0f32, (2 * 3 * 4 * 5 * 6) as f32, &Device::Cpu)?
+        .reshape(&[2, 3, 4, 5, 6])?;
     test![
         ("sum(a b c d e) -> ", "sum(..) -> "),
         ("a max(b c d) e -> (e a)", "a max(..) e -> (e a)"),
@@ -112,8 +115,8 @@ fn equivalent_reduction() -> Result<()> {
 
 macro_rules! seq_test {
     ($pattern1:literal, $pattern2:literal, $tensor:ident) => {
-        let intermediate = einops!($pattern1, $tensor.clone());
-        let output = einops!($pattern2, &intermediate.clone());
+        let intermediate = einops!($pattern1, $tensor.clone())?;
+        let output = einops!($pattern2, &intermediate.clone())?;
         assert_eq!(
             $tensor.clone().flatten_all()?.to_vec1::<f32>()?,
             output.flatten_all()?.to_vec1::<f32>()?, "({}) & ({}) failed", $pattern1, $pattern2
@@ -126,7 +129,7 @@ macro_rules! seq_test {
 
 #[test]
 fn equivalent_repeat() -> Result<()> {
-    let input = Tensor::arange(0f32, (1 * 2 * 4 * 6) as f32, &Device::Cpu)?.reshape(&[1, 2, 4, 6]);
+    let input = Tensor::arange(0f32, (2 * 4 * 6) as f32, &Device::Cpu)?.reshape(&[1, 2, 4, 6])?;
     seq_test![
         (
             "a b c d -> (c 2 d a b)",
@@ -148,7 +151,7 @@ fn equivalent_repeat() -> Result<()> {
         input
     ];
 
-    let input = Tensor::arange(0f32, (2 * 3 * 5) as f32, &Device::Cpu)?.reshape(&[2, 3, 5]);
+    let input = Tensor::arange(0f32, (2 * 3 * 5) as f32, &Device::Cpu)?.reshape(&[2, 3, 5])?;
     seq_test![
         ("a b c -> c a b", "c a b -> a b c"),
         (
@@ -181,8 +184,8 @@ fn equivalent_repeat() -> Result<()> {
 
 macro_rules! shape_test {
     ($pattern:literal, $shape:expr, $tensor:ident) => {
-        let output = einops!($pattern, &$tensor);
-        assert_eq!(output.shape(), $shape, "({}) pattern failed", $pattern);
+        let output = einops!($pattern, &$tensor)?;
+        assert_eq!(output.dims(), $shape, "({}) pattern failed", $pattern);
     };
     ($(($pattern:literal, $shape:expr)),*, $tensor:ident) => {
         $(shape_test!($pattern, $shape, $tensor);)*
@@ -191,8 +194,8 @@ macro_rules! shape_test {
 
 #[test]
 fn rearrange_reduce() -> Result<()> {
-    let input =
-        Tensor::arange(0f32, (10 * 20 * 30 * 40) as f32, &Device::Cpu)?.reshape(&[10, 20, 30, 40]);
+    let input = Tensor::arange(0f32, (10 * 20 * 30 * 40) as f32, &Device::Cpu)?
+        .reshape(&[10, 20, 30, 40])?;
     shape_test![
         ("b c h w -> b h w c", [10, 30, 40, 20]),
         ("b c h w -> b (c h w)", [10, 20 * 30 * 40]),
@@ -218,56 +221,484 @@ fn rearrange_reduce() -> Result<()> {
 
 #[test]
 fn decomposition_variable() -> Result<()> {
-    let input =
-        Tensor::arange(0f32, (10 * 20 * 30 * 40) as f32, &Device::Cpu)?.reshape(&[10, 20, 30, 40]);
+    let input = Tensor::arange(0f32, (10 * 20 * 30 * 40) as f32, &Device::Cpu)?
+        .reshape(&[10, 20, 30, 40])?;
 
     let d2 = 2;
-    let output1 = einops!("a b c (d1 {d2}) -> a b c d1 {d2}", &input);
-    let output2 = einops!(".. (d1 {d2}) -> .. d1 {d2}", &input);
-    assert_eq!(output1.shape(), &[10, 20, 30, 20, 2]);
-    assert_eq!(output2.shape(), &[10, 20, 30, 20, 2]);
+    let output1 = einops!("a b c (d1 {d2}) -> a b c d1 {d2}", &input)?;
+    let output2 = einops!(".. (d1 {d2}) -> .. d1 {d2}", &input)?;
+    assert_eq!(output1.dims(), &[10, 20, 30, 20, 2]);
+    assert_eq!(output2.dims(), &[10, 20, 30, 20, 2]);
 
     let d2 = 2;
-    let output1 = einops!("a b c (d1 sum({d2})) -> a b c d1", &input);
-    let output2 = einops!(".. (d1 sum({d2})) -> .. d1", &input);
-    assert_eq!(output1.shape(), &[10, 20, 30, 20]);
-    assert_eq!(output2.shape(), &[10, 20, 30, 20]);
+    let output1 = einops!("a b c (d1 sum({d2})) -> a b c d1", &input)?;
+    let output2 = einops!(".. (d1 sum({d2})) -> .. d1", &input)?;
+    assert_eq!(output1.dims(), &[10, 20, 30, 20]);
+    assert_eq!(output2.dims(), &[10, 20, 30, 20]);
 
     let a1 = 5;
-    let output = einops!("({a1} a2) .. -> a2 {a1} ..", &input);
-    assert_eq!(output.shape(), &[2, 5, 20, 30, 40]);
+    let output = einops!("({a1} a2) .. -> a2 {a1} ..", &input)?;
+    assert_eq!(output.dims(), &[2, 5, 20, 30, 40]);
 
     let shapes = (5, 2);
     let output = einops!(
         "({shapes.0} {shapes.1}) .. -> {shapes.1} {shapes.0} ..",
         &input
-    );
-    assert_eq!(output.shape(), &[2, 5, 20, 30, 40]);
+    )?;
+    assert_eq!(output.dims(), &[2, 5, 20, 30, 40]);
 
     let shapes = (5, 2);
     let output = einops!(
         "({shapes.0} {shapes.1}) .. -> ({shapes.1} {shapes.0}) ..",
         &input
-    );
-    assert_eq!(output.shape(), &[10, 20, 30, 40]);
+    )?;
+    assert_eq!(output.dims(), &[10, 20, 30, 40]);
 
     Ok(())
 }
 
 #[test]
 fn repeat_variable() -> Result<()> {
-    let input =
-        Tensor::arange(0f32, (10 * 20 * 30 * 40) as f32, &Device::Cpu)?.reshape(&[10, 20, 30, 40]);
+    let input = Tensor::arange(0f32, (10 * 20 * 30 * 40) as f32, &Device::Cpu)?
+        .reshape(&[10, 20, 30, 40])?;
 
     let repeat = 3;
-    let output1 = einops!("a b c d -> {repeat} a b c d", &input);
-    let output2 = einops!(".. -> {repeat} ..", &input);
-    assert_eq!(output1.shape(), &[3, 10, 20, 30, 40]);
-    assert_eq!(output2.shape(), &[3, 10, 20, 30, 40]);
+    let output1 = einops!("a b c d -> {repeat} a b c d", &input)?;
+    let output2 = einops!(".. -> {repeat} ..", &input)?;
+    assert_eq!(output1.dims(), &[3, 10, 20, 30, 40]);
+    assert_eq!(output2.dims(), &[3, 10, 20, 30, 40]);
 
     let repeat = 3;
-    let output = einops!("a b c d -> ({repeat} a) b c d", &input);
-    assert_eq!(output.shape(), &[30, 20, 30, 40]);
+    let output = einops!("a b c d -> ({repeat} a) b c d", &input)?;
+    assert_eq!(output.dims(), &[30, 20, 30, 40]);
+
+    Ok(())
+}
+
+#[test]
+fn backend_error() -> Result<()> {
+    let input = Tensor::arange(0f32, (6 * 5) as f32, &Device::Cpu)?.reshape(&[6, 5])?;
+
+    // 6 is not divisible by 4, the error is returned instead of panicking
+    let error = einops!("(a b:4) c -> a b c", &input).unwrap_err();
+    assert_eq!(
+        *error.kind(),
+        ErrorKind::NotDivisible {
+            axis: 0,
+            group: "(a b:4)".into(),
+            sizes: vec![("b".into(), 4)],
+            size: 6,
+            divisor: 4
+        }
+    );
+    assert_eq!(error.code(), "E003");
+
+    // Sizes given by expressions are named after them
+    let (h, w) = (2, 2);
+    let error = einops!("a ({h} {w} c) -> a {h} {w} c", &input).unwrap_err();
+    assert_eq!(
+        *error.kind(),
+        ErrorKind::NotDivisible {
+            axis: 1,
+            group: "({h} {w} c)".into(),
+            sizes: vec![("h".into(), 2), ("w".into(), 2)],
+            size: 5,
+            divisor: 4
+        }
+    );
+
+    let error = einops!("a (b:2 c:3) -> a b c", &input).unwrap_err();
+    assert_eq!(
+        *error.kind(),
+        ErrorKind::ShapeMismatch {
+            axis: 1,
+            group: "(b:2 c:3)".into(),
+            sizes: vec![("b".into(), 2), ("c".into(), 3)],
+            expected: 6,
+            found: 5
+        }
+    );
+    assert_eq!(error.code(), "E002");
+
+    // Sizes from expressions are multiplied with overflow checks
+    let n = usize::MAX / 2;
+    let error = einops!("a ({n} b:4) -> a {n} b", &input).unwrap_err();
+    assert_eq!(
+        *error.kind(),
+        ErrorKind::Overflow {
+            lengths: vec![n, 4]
+        }
+    );
+    assert_eq!(error.code(), "E006");
+    let error = einops!("a ({n} b:4 c) -> a {n} b c", &input).unwrap_err();
+    assert!(matches!(error.kind(), ErrorKind::Overflow { .. }));
+
+    // Constant shapes are checked as well, an input with the same number of
+    // elements isn't reinterpreted
+    let error = einops!("(a:2 b:2) c:5 -> a b c", &input).unwrap_err();
+    assert_eq!(
+        *error.kind(),
+        ErrorKind::ShapeMismatch {
+            axis: 0,
+            group: "(a:2 b:2)".into(),
+            sizes: vec![("a".into(), 2), ("b".into(), 2)],
+            expected: 4,
+            found: 6
+        }
+    );
+    let other = Tensor::arange(0f32, 24.0, &Device::Cpu)?.reshape(&[4, 3, 2])?;
+    for error in [
+        einops!("a:2 b:3 c:4 -> c b a", &other).unwrap_err(),
+        einops!("a:2 b:3 c:4 -> (a b c)", &other).unwrap_err(),
+    ] {
+        assert_eq!(
+            *error.kind(),
+            ErrorKind::ShapeMismatch {
+                axis: 0,
+                group: "a:2".into(),
+                sizes: vec![("a".into(), 2)],
+                expected: 2,
+                found: 4
+            }
+        );
+    }
+
+    let error = einops!(".. c:3 -> c ..", &other).unwrap_err();
+    assert!(matches!(
+        error.kind(),
+        ErrorKind::ShapeMismatch {
+            axis: 2,
+            expected: 3,
+            found: 2,
+            ..
+        }
+    ));
+
+    // Errors of the backend keep their source, like the errors of candle
+    // wrapped by `einops!`
+    let error = EinopsError::from(candle_core::Error::Msg("out of memory".to_string()));
+    assert!(matches!(error.kind(), ErrorKind::Backend(_)));
+    assert_eq!(error.code(), "E005");
+    // The candle error is kept as the source
+    let source = std::error::Error::source(&error).unwrap();
+    assert!(source.downcast_ref::<candle_core::Error>().is_some());
+
+    // Errors compare by their kind and context, clones share the source
+    let clone = error.clone();
+    assert_eq!(clone, error);
+    let source = std::error::Error::source(&clone).unwrap();
+    assert!(source.downcast_ref::<candle_core::Error>().is_some());
+    assert_ne!(error, einops!("(a b:4) c -> a b c", &input).unwrap_err());
+    assert_eq!(
+        einops!("(a b:4) c -> a b c", &input).unwrap_err(),
+        einops!("(a b:4) c -> a b c", &input).unwrap_err()
+    );
+
+    // Too few axes for the pattern, whichever transformation comes first
+    for error in [
+        einops!("a b (c d:2) -> a b c d", &input).unwrap_err(),
+        einops!("a b c -> (a b) c", &input).unwrap_err(),
+        einops!("a b .. c -> (a b) .. c", &input).unwrap_err(),
+        einops!("a b c -> c b a", &input).unwrap_err(),
+        // Constant shapes don't reach the backend either
+        einops!("a:2 b:3 c:4 -> c b a", &input).unwrap_err(),
+        einops!("a:2 b:3 c:5 -> (a b c)", &input).unwrap_err(),
+    ] {
+        assert_eq!(
+            *error.kind(),
+            ErrorKind::RankMismatch {
+                expected: 3,
+                found: 2
+            }
+        );
+        assert_eq!(error.code(), "E004");
+    }
+
+    Ok(())
+}
+
+#[test]
+fn error_context() -> Result<()> {
+    let input = Tensor::arange(0f32, (6 * 5) as f32, &Device::Cpu)?.reshape(&[6, 5])?;
+
+    let error = einops!("(a b:4) c -> a b c", &input).unwrap_err();
+    assert_eq!(error.patterns(), ["(a b:4) c -> a b c"]);
+    assert_eq!(error.input_shape(), Some([6, 5].as_slice()));
+    assert_eq!(error.bindings(), [("a", 1), ("b", 4), ("c", 5)]);
+    assert_eq!(
+        error.to_string(),
+        "einops error: cannot decompose axis 0 of length 6 into (a b:4): \
+         6 is not divisible by 4 (b = 4)\n  \
+         in pattern \"(a b:4) c -> a b c\"\n  \
+         input shape [6, 5]\n  \
+         axes (a b:4) = 6 not divisible by 4, c = 5 ok\n  \
+         with a = 1, b = 4, c = 5"
+    );
+
+    // Every axis of the pattern is paired with a length of the input
+    let error = einops!("a b c -> (a b) c", &input).unwrap_err();
+    assert!(error
+        .to_string()
+        .contains("\n  axes a = 6 ok, b = 5 ok, c missing\n"));
+    let error = einops!("a (b:2 c) -> a b c", &input).unwrap_err();
+    assert!(error
+        .to_string()
+        .contains("\n  axes a = 6 ok, (b:2 c) = 5 not divisible by 2\n"));
+
+    // Axes after `..` are counted from the end of the input shape
+    let input = Tensor::zeros(&[2, 3, 4], DType::F32, &Device::Cpu)?;
+    let error = einops!("a .. (b c:3) -> a .. b c", &input).unwrap_err();
+    assert_eq!(error.bindings(), [("a", 2), ("b", 1), ("c", 3)]);
+    assert!(error
+        .to_string()
+        .contains("\n  axes a = 2 ok, .. = [3] ok, (b c:3) = 4 not divisible by 3\n"));
+    let error = einops!("a (b c:3) -> a b c", &input).unwrap_err();
+    assert!(error
+        .to_string()
+        .contains("\n  axes a = 2 ok, (b c:3) = 3 ok, axis 2 = 4 extra\n"));
+
+    // Fused nested calls report every pattern
+    let error = einops!("(c:5 a) b -> c a b", einops!("a b c -> (c a) b", &input)?).unwrap_err();
+    assert_eq!(error.patterns(), ["a b c -> (c a) b", "(c:5 a) b -> c a b"]);
+    assert_eq!(error.input_shape(), Some([2, 3, 4].as_slice()));
+
+    Ok(())
+}
+
+#[test]
+fn var_gradients() -> Result<()> {
+    let var = Var::from_tensor(&Tensor::arange(0f32, 6f32, &Device::Cpu)?.reshape(&[2, 3])?)?;
+    let weights = Tensor::arange(0f32, 6f32, &Device::Cpu)?.reshape(&[3, 2])?;
+
+    // Rearrange, the gradient is the transposed weight
+    let output = einops!("a b -> b a", var.as_tensor())?;
+    let grads = (output * &weights)?.sum_all()?.backward()?;
+    assert_eq!(
+        grads.get(&var).unwrap().to_vec2::<f32>()?,
+        weights.t()?.to_vec2::<f32>()?
+    );
+
+    // Reduce, every element contributes once to the sum and 1/3 to the mean
+    let output = einops!("a sum(b) -> a", var.as_tensor())?;
+    let grads = output.sum_all()?.backward()?;
+    assert_eq!(grads.get(&var).unwrap().to_vec2::<f32>()?, [[1.0; 3]; 2]);
+
+    let output = einops!("a mean(b) -> a", &var)?;
+    let grads = output.sum_all()?.backward()?;
+    assert_eq!(
+        grads.get(&var).unwrap().to_vec2::<f32>()?,
+        [[1.0 / 3.0; 3]; 2]
+    );
+
+    // Repeat, every element is copied 4 times
+    let output = einops!("a b -> a 4 b", var.as_tensor())?;
+    let grads = output.sum_all()?.backward()?;
+    assert_eq!(grads.get(&var).unwrap().to_vec2::<f32>()?, [[4.0; 3]; 2]);
+
+    Ok(())
+}
+
+#[test]
+fn quantized_input() -> Result<()> {
+    let input = Tensor::arange(0f32, (2 * 4 * 32) as f32, &Device::Cpu)?.reshape(&[2, 4, 32])?;
+    let quantized = QTensor::quantize(&input, GgmlDType::F16)?;
+
+    let output1 = einops!("a b (c c2:2) -> (a c) b c2", &quantized)?;
+    let output2 = einops!("a b (c c2:2) -> (a c) b c2", &input)?;
+    assert_eq!(output1.dims(), [32, 4, 2]);
+    assert_eq!(
+        output1.flatten_all()?.to_vec1::<f32>()?,
+        output2.flatten_all()?.to_vec1::<f32>()?
+    );
+
+    let output = einops!("a max(b) c -> a c", &quantized)?;
+    assert_eq!(output.dims(), [2, 32]);
+
+    // Quantized tensors of every type are dequantized to f32
+    let half = QTensor::quantize(&input.to_dtype(DType::F16)?, GgmlDType::Q8_0)?;
+    assert_eq!(einops!("a b c -> c b a", &half)?.dtype(), DType::F32);
+    assert_eq!(output1.dtype(), DType::F32);
+
+    Ok(())
+}
+
+#[test]
+fn stacked_input() -> Result<()> {
+    let tensors = (0..3)
+        .map(|i| Tensor::full(i as f32, (2, 4), &Device::Cpu))
+        .collect::<Result<Vec<_>>>()?;
+
+    let output = einops!("n h w -> h (n w)", &tensors)?;
+    assert_eq!(output.dims(), [2, 12]);
+    assert_eq!(output.i((1, 4))?.to_scalar::<f32>()?, 1.0);
+
+    let output = einops!("n h w -> h w n", &tensors[..])?;
+    assert_eq!(output.i((0, 0))?.to_vec1::<f32>()?, [0.0, 1.0, 2.0]);
+
+    let output = einops!(
+        "sum(n) h w -> h w",
+        [tensors[1].clone(), tensors[2].clone()]
+    )?;
+    assert_eq!(output.flatten_all()?.to_vec1::<f32>()?, [3.0; 8]);
+
+    let output = einops!("n h w -> h w n", &tensors[..0]);
+    assert!(output.is_err());
+
+    Ok(())
+}
+
+#[test]
+fn contiguous_output() -> Result<()> {
+    let input = Tensor::arange(0u32, 6, &Device::Cpu)?.reshape(&[2, 3])?;
+
+    let output = einops!("a b -> b a", &input)?;
+    assert!(!output.is_contiguous());
+
+    let output = einops!("a b -> b a", &input, contiguous = true)?;
+    assert!(output.is_contiguous());
+    assert_eq!(output.to_vec2::<u32>()?, [[0, 3], [1, 4], [2, 5]]);
+
+    Ok(())
+}
+
+#[test]
+fn boxed_input() -> Result<()> {
+    let input = Tensor::randn(0.0f32, 1.0, (2, 3, 4), &Device::Cpu)?;
+    let boxed: Box<dyn DynBackend> = Box::new(input.clone());
+
+    let output = einops!("a b c -> (a c) b", &boxed)?;
+    let output = output.downcast::<Tensor>().unwrap();
+    let expected = einops!("a b c -> (a c) b", &input)?;
+    assert_eq!(output.to_vec2::<f32>()?, expected.to_vec2::<f32>()?);
+
+    Ok(())
+}
+
+#[test]
+fn repeat_is_a_view() -> Result<()> {
+    let input = Tensor::arange(0f32, 6.0, &Device::Cpu)?.reshape(&[2, 3])?;
+
+    let output = einops!("a b -> a repeat:100000 b", &input)?;
+    assert_eq!(output.dims(), [2, 100000, 3]);
+    assert_eq!(output.stride(), [3, 0, 1]);
+
+    let output = einops!("a b -> a repeat:2 b", &input, contiguous = true)?;
+    assert!(output.is_contiguous());
+
+    Ok(())
+}
+
+#[test]
+fn repeat_strategy() -> Result<()> {
+    let input = Tensor::arange(0f32, 6.0, &Device::Cpu)?.reshape(&[2, 3])?;
+
+    let view = einops!("a b -> a repeat:4 b", &input, repeat = view)?;
+    assert_eq!(view.stride(), [3, 0, 1]);
+
+    let copy = einops!("a b -> a repeat:4 b", &input, repeat = copy)?;
+    assert!(copy.is_contiguous());
+    assert_eq!(copy.to_vec3::<f32>()?, view.to_vec3::<f32>()?);
+
+    let copy = einops!(
+        "a b -> b a repeat:4",
+        &input,
+        repeat = copy,
+        contiguous = true
+    )?;
+    assert_eq!(copy.dims(), [3, 2, 4]);
+    assert!(copy.is_contiguous());
+
+    Ok(())
+}
+
+#[test]
+fn merge_after_transpose_is_a_view() -> Result<()> {
+    let input = Tensor::arange(0f32, 24.0, &Device::Cpu)?.reshape(&[1, 2, 3, 4])?;
+
+    // `h` and `w` are still adjacent after moving `c` to the front
+    let output = einops!("b h w c -> b c (h w)", &input)?;
+    assert_eq!(output.dims(), [1, 4, 6]);
+    assert_eq!(output.stride(), [24, 1, 4]);
+    assert_eq!(
+        output.to_vec3::<f32>()?,
+        input
+            .permute((0, 3, 1, 2))?
+            .reshape((1, 4, 6))?
+            .to_vec3::<f32>()?
+    );
+
+    Ok(())
+}
+
+#[test]
+fn static_shapes() -> Result<()> {
+    let input = Tensor::arange(0f32, 24.0, &Device::Cpu)?.reshape(&[6, 4])?;
+
+    // Every size is given, so the shapes are constants
+    let output = einops!("(h:2 w:3) c:4 -> c (w h) copy:2", &input)?;
+    let expected = einops!("(h:2 w) c -> c (w h) copy:2", &input)?;
+    assert_eq!(output.dims(), [4, 6, 2]);
+    assert_eq!(output.to_vec3::<f32>()?, expected.to_vec3::<f32>()?);
+
+    let output = einops!("(h:2 max(w:3)) c:4 -> (c 2 h)", &input)?;
+    let expected = einops!("(h:2 max(w:3)) c -> (c 2 h)", &input)?;
+    assert_eq!(output.to_vec1::<f32>()?, expected.to_vec1::<f32>()?);
+
+    Ok(())
+}
+
+#[test]
+fn pixel_shuffle() -> Result<()> {
+    let input = Tensor::arange(0f32, 96.0, &Device::Cpu)?.reshape(&[2, 12, 2, 2])?;
+
+    // Candle has no depth to space kernel, so the generic steps run
+    let output = einops!("b (c p1:2 p2:3) h w -> b c (h p1) (w p2)", &input)?;
+    let expected = input
+        .reshape((2, 2, 2, 3, 2, 2))?
+        .permute((0, 1, 4, 2, 5, 3))?
+        .reshape((2, 2, 4, 6))?;
+    assert_eq!(output.dims(), [2, 2, 4, 6]);
+    assert_eq!(
+        output.flatten_all()?.to_vec1::<f32>()?,
+        expected.flatten_all()?.to_vec1::<f32>()?
+    );
+
+    let output = einops!("b c (h p1:2) (w p2:3) -> b (c p1 p2) h w", &output)?;
+    assert_eq!(
+        output.flatten_all()?.to_vec1::<f32>()?,
+        input.flatten_all()?.to_vec1::<f32>()?
+    );
+
+    Ok(())
+}
+
+#[test]
+fn nested_calls() -> Result<()> {
+    let input = Tensor::arange(0f32, 24.0, &Device::Cpu)?.reshape(&[2, 3, 4])?;
+
+    // Merging `c` and `a` copies, splitting them again right after is fused
+    // into a single reshape that leaves the transpose a view
+    let output = einops!("(c:4 a) b -> c a b", einops!("a b c -> (c a) b", &input)?)?;
+    let merged = einops!("a b c -> (c a) b", &input)?;
+    let expected = einops!("(c:4 a) b -> c a b", &merged)?;
+    assert!(!output.is_contiguous());
+    assert_eq!(output.dims(), [4, 2, 3]);
+    assert_eq!(output.to_vec3::<f32>()?, expected.to_vec3::<f32>()?);
+
+    // Without reading the merged shape
+    let output = einops!(
+        "(c:4 a:2) b:3 -> c a b",
+        einops!("a b c -> (c a) b", &input)?
+    )?;
+    assert_eq!(output.to_vec3::<f32>()?, expected.to_vec3::<f32>()?);
+
+    let output = einops!(
+        "(b a:2) c -> a c b",
+        einops!("a .. (c:2 d) -> (a ..) (c d)", &input)?
+    )?;
+    let merged = einops!("a .. (c:2 d) -> (a ..) (c d)", &input)?;
+    let expected = einops!("(b a:2) c -> a c b", &merged)?;
+    assert_eq!(output.to_vec3::<f32>()?, expected.to_vec3::<f32>()?);
 
     Ok(())
 }
@@ -0,0 +1,85 @@
+#![cfg(all(feature = "fancy-errors", feature = "candle"))]
+
+use candle_core::{Device, Result, Tensor};
+use candle_einops::einops;
+use miette::Diagnostic;
+
+// Spans of the labels of `error`, with the text they point at
+fn labels(error: &candle_einops::EinopsError, pattern: &str) -> Vec<(String, String)> {
+    error
+        .labels()
+        .into_iter()
+        .flatten()
+        .map(|label| {
+            let text = pattern[label.offset()..label.offset() + label.len()].to_string();
+            (text, label.label().unwrap().to_string())
+        })
+        .collect()
+}
+
+#[test]
+fn fancy_errors_labels() -> Result<()> {
+    let input = Tensor::arange(0f32, 30.0, &Device::Cpu)?.reshape(&[6, 5])?;
+
+    let pattern = "(a b:4) c -> a b c";
+    let error = einops!("(a b:4) c -> a b c", &input).unwrap_err();
+    assert_eq!(
+        labels(&error, pattern),
+        [(
+            "(a b:4)".to_string(),
+            "length 6 is not divisible by 4".to_string()
+        )]
+    );
+    assert!(error.source_code().is_some());
+    assert_eq!(Diagnostic::code(&error).unwrap().to_string(), "E003");
+    assert_eq!(
+        error.help().unwrap().to_string(),
+        "the input has shape [6, 5]"
+    );
+
+    let pattern = "a  sum(x) (b:2 c:3) -> a b c";
+    let input = input.reshape(&[6, 1, 5])?;
+    let error = einops!("a  sum(x) (b:2 c:3) -> a b c", &input).unwrap_err();
+    assert_eq!(
+        labels(&error, pattern),
+        [(
+            "(b:2 c:3)".to_string(),
+            "expected length 6, found 5".to_string()
+        )]
+    );
+
+    // Errors of the backend point at no axis
+    let error = candle_einops::EinopsError::from(candle_core::Error::Msg("failed".to_string()))
+        .with_context(
+            &["a b c -> a (b c)"],
+            &["a", "b", "c"],
+            vec![6, 1, 5],
+            vec![],
+        );
+    assert!(error.source_code().is_some());
+    assert!(error.labels().is_none());
+
+    Ok(())
+}
+
+#[cfg(feature = "nn")]
+#[test]
+fn fancy_errors_layers() {
+    use candle_einops::layers::Rearrange;
+
+    let pattern = "b (h w -> b h w";
+    let error = Rearrange::new(pattern, &[]).unwrap_err();
+    assert_eq!(
+        labels(&error, pattern),
+        [("(".to_string(), "unclosed `(`".to_string())]
+    );
+    assert_eq!(Diagnostic::code(&error).unwrap().to_string(), "E009");
+
+    let pattern = "b (h w) -> b h x";
+    let error = Rearrange::new(pattern, &[("h", 2)]).unwrap_err();
+    assert_eq!(
+        labels(&error, pattern),
+        [("x".to_string(), "unknown axis".to_string())]
+    );
+    assert!(error.source_code().is_some());
+}
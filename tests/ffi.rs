@@ -0,0 +1,87 @@
+#![cfg(feature = "ffi")]
+
+use std::ffi::CStr;
+
+use candle_einops::ffi::*;
+
+fn last_error() -> String {
+    unsafe { CStr::from_ptr(einops_last_error()) }
+        .to_string_lossy()
+        .into_owned()
+}
+
+#[test]
+fn ffi_recipes() {
+    unsafe {
+        let recipe = einops_recipe_new(c"h (w p:2) -> p h w".as_ptr(), EINOPS_REARRANGE);
+        assert!(!recipe.is_null());
+
+        let shape = [2, 4];
+        let mut output_shape = [0; 4];
+        let mut rank = 0;
+        let status = einops_output_shape(
+            recipe,
+            shape.as_ptr(),
+            2,
+            output_shape.as_mut_ptr(),
+            4,
+            &mut rank,
+        );
+        assert_eq!(status, 0);
+        assert_eq!(output_shape[..rank], [2, 2, 2]);
+
+        let input = [0.0, 1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0f32];
+        let mut output = [0.0; 8];
+        let status = einops_apply_f32(
+            recipe,
+            input.as_ptr(),
+            shape.as_ptr(),
+            2,
+            output.as_mut_ptr(),
+            8,
+        );
+        assert_eq!(status, 0);
+        assert_eq!(output, [0.0, 2.0, 4.0, 6.0, 1.0, 3.0, 5.0, 7.0]);
+
+        let status = einops_apply_f32(
+            recipe,
+            input.as_ptr(),
+            [2, 3].as_ptr(),
+            2,
+            output.as_mut_ptr(),
+            6,
+        );
+        assert_eq!(status, -1);
+        assert!(last_error().contains("not divisible by 2"));
+        let status = einops_apply_f32(
+            recipe,
+            input.as_ptr(),
+            shape.as_ptr(),
+            2,
+            output.as_mut_ptr(),
+            6,
+        );
+        assert_eq!(status, -1);
+        assert!(last_error().contains("the output has 8 elements, the buffer 6"));
+        einops_recipe_free(recipe);
+
+        let recipe = einops_recipe_new(c"a b -> a".as_ptr(), EINOPS_REDUCE_SUM);
+        let mut output = [0.0; 2];
+        let status = einops_apply_f32(
+            recipe,
+            input.as_ptr(),
+            [2, 4].as_ptr(),
+            2,
+            output.as_mut_ptr(),
+            2,
+        );
+        assert_eq!(status, 0);
+        assert_eq!(output, [6.0, 22.0]);
+        einops_recipe_free(recipe);
+
+        assert!(einops_recipe_new(c"a b -> a c".as_ptr(), EINOPS_REARRANGE).is_null());
+        assert!(last_error().contains("unknown axis `c`"));
+        assert!(einops_recipe_new(c"a -> a".as_ptr(), 42).is_null());
+        assert_eq!(last_error(), "unknown recipe kind 42");
+    }
+}
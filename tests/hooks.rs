@@ -0,0 +1,108 @@
+#![cfg(all(feature = "hooks", feature = "candle"))]
+
+use std::sync::{Arc, Mutex};
+
+use candle_core::{Device, Result, Tensor};
+use candle_einops::trace::{self, Hook, Step};
+use candle_einops::{einops, EinopsError};
+
+// The registered hook is shared by the tests
+static HOOK: Mutex<()> = Mutex::new(());
+
+// Records every call with the step's kind and the shape it was given
+#[derive(Default)]
+struct Record(Mutex<Vec<(&'static str, String)>>);
+
+impl Hook for Record {
+    fn before_op(&self, step: &Step, shape: &[usize]) {
+        let call = format!("before {:?}", shape);
+        self.0.lock().unwrap().push((step.kind, call));
+    }
+
+    fn after_op(&self, step: &Step, output: std::result::Result<&[usize], &EinopsError>) {
+        let call = match output {
+            Ok(shape) => format!("after {:?}", shape),
+            Err(_) => "failed".to_string(),
+        };
+        self.0.lock().unwrap().push((step.kind, call));
+    }
+}
+
+#[test]
+fn hooks_calls() -> Result<()> {
+    let input = Tensor::arange(0f32, 24.0, &Device::Cpu)?.reshape(&[2, 3, 4])?;
+
+    let _lock = HOOK.lock().unwrap_or_else(|error| error.into_inner());
+    let record = Arc::new(Record::default());
+    trace::set_hook(Some(record.clone()));
+    einops!("a b c -> c (a b)", &input)?;
+    trace::set_hook(None);
+    // Not counted once the hook is removed
+    einops!("a b c -> c (a b)", &input)?;
+
+    let calls = record.0.lock().unwrap();
+    assert_eq!(
+        *calls,
+        [
+            ("transpose", "before [2, 3, 4]".to_string()),
+            ("transpose", "after [4, 2, 3]".to_string()),
+            ("reshape", "before [4, 2, 3]".to_string()),
+            ("reshape", "after [4, 6]".to_string()),
+        ]
+    );
+
+    Ok(())
+}
+
+#[cfg(feature = "nn")]
+#[test]
+fn hooks_layers() -> std::result::Result<(), EinopsError> {
+    use candle_einops::layers::{Chain, Rearrange};
+
+    let input = Tensor::arange(0f32, 24.0, &Device::Cpu)?.reshape(&[2, 3, 4])?;
+
+    let own = Arc::new(Record::default());
+    let layer = Rearrange::new("a b c -> c (a b)", &[])?.with_hook(own.clone());
+    layer.apply(&input)?;
+    assert_eq!(
+        *own.0.lock().unwrap(),
+        [
+            ("transpose", "before [2, 3, 4]".to_string()),
+            ("transpose", "after [4, 2, 3]".to_string()),
+            ("reshape", "before [4, 2, 3]".to_string()),
+            ("reshape", "after [4, 6]".to_string()),
+        ]
+    );
+
+    // Chains keep the hook of their first layer, layers without one use the
+    // registered hook
+    own.0.lock().unwrap().clear();
+    let _lock = HOOK.lock().unwrap_or_else(|error| error.into_inner());
+    let global = Arc::new(Record::default());
+    trace::set_hook(Some(global.clone()));
+    Chain::from(layer)
+        .then(Rearrange::new("c n -> n c", &[])?)
+        .apply(&input)?;
+    Rearrange::new("a b c -> a (b c)", &[])?.apply(&input)?;
+    trace::set_hook(None);
+    assert_eq!(
+        *own.0.lock().unwrap(),
+        [
+            ("transpose", "before [2, 3, 4]".to_string()),
+            ("transpose", "after [4, 2, 3]".to_string()),
+            ("reshape", "before [4, 2, 3]".to_string()),
+            ("reshape", "after [4, 6]".to_string()),
+            ("transpose", "before [4, 6]".to_string()),
+            ("transpose", "after [6, 4]".to_string()),
+        ]
+    );
+    assert_eq!(
+        *global.0.lock().unwrap(),
+        [
+            ("reshape", "before [2, 3, 4]".to_string()),
+            ("reshape", "after [2, 12]".to_string()),
+        ]
+    );
+
+    Ok(())
+}
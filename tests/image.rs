@@ -0,0 +1,29 @@
+#![cfg(feature = "image")]
+
+use candle_core::{DType, IndexOp, Result};
+use candle_einops::einops;
+use image::{DynamicImage, Rgb, Rgb32FImage, RgbImage};
+
+#[test]
+fn image_patterns() -> Result<()> {
+    let image =
+        DynamicImage::ImageRgb8(RgbImage::from_fn(3, 2, |x, y| Rgb([x as u8, y as u8, 255])));
+
+    let output = einops!("h w c -> c h w", &image)?;
+    assert_eq!(output.dims(), [3, 2, 3]);
+    assert_eq!(output.dtype(), DType::U8);
+    assert_eq!(output.i(0)?.to_vec2::<u8>()?, [[0, 1, 2], [0, 1, 2]]);
+    assert_eq!(output.i(1)?.to_vec2::<u8>()?, [[0, 0, 0], [1, 1, 1]]);
+
+    let image = DynamicImage::ImageLuma16(image.to_luma16());
+    let output = einops!("h w c -> (h w c)", &image)?;
+    assert_eq!(output.dims(), [6]);
+    assert_eq!(output.dtype(), DType::U32);
+
+    let image = DynamicImage::ImageRgb32F(Rgb32FImage::from_pixel(4, 4, Rgb([0.5, 0.25, 1.0])));
+    let output = einops!("(h h2:2) (w w2:2) c -> h w (c h2 w2)", &image)?;
+    assert_eq!(output.dims(), [2, 2, 12]);
+    assert_eq!(output.dtype(), DType::F32);
+
+    Ok(())
+}
@@ -0,0 +1,588 @@
+#![cfg(feature = "nn")]
+
+use candle_core::{DType, Device, Module, Result, Tensor};
+use candle_einops::layers::{
+    Chain, EinMix, Inputs, Order, Rearrange, Reduce, Registry, Repeat, Steps,
+};
+use candle_einops::{assert_max_copies, einops, ErrorKind, Operation};
+use candle_nn::{VarBuilder, VarMap};
+
+#[test]
+fn layers_rearrange() -> Result<()> {
+    let input = Tensor::arange(0u32, 2 * 3 * 4 * 4, &Device::Cpu)?.reshape(&[2, 3, 4, 4])?;
+
+    let layer = Rearrange::new(
+        "b c (h p1) (w p2) -> b (h w) (p1 p2 c)",
+        &[("p1", 2), ("p2", 2)],
+    )?;
+    assert_eq!(
+        layer.forward(&input)?.to_vec3::<u32>()?,
+        einops!("b c (h p1:2) (w p2:2) -> b (h w) (p1 p2 c)", &input)?.to_vec3::<u32>()?
+    );
+
+    let model = candle_nn::seq()
+        .add(Rearrange::new("b c h w -> b h w c", &[])?)
+        .add(Rearrange::new(".. h w c -> .. (h w c)", &[])?);
+    assert_eq!(
+        model.forward(&input)?.to_vec2::<u32>()?,
+        einops!("b c h w -> b (h w c)", &input)?.to_vec2::<u32>()?
+    );
+
+    // Identity patterns return the input
+    let layer = Rearrange::new("a .. -> a ..", &[])?;
+    assert_eq!(layer.forward(&input)?.dims(), [2, 3, 4, 4]);
+
+    // Patterns can be built at runtime, and outlive the strings they're read from
+    let layer = {
+        let pattern = format!("b c h w -> b {} c", "(h w)");
+        Rearrange::builder(&pattern).build()?
+    };
+    assert_eq!(layer.pattern(), "b c h w -> b (h w) c");
+    assert_eq!(layer.forward(&input)?.dims(), [2, 16, 3]);
+
+    Ok(())
+}
+
+#[test]
+fn layers_reduce() -> Result<()> {
+    let input =
+        Tensor::arange(0f32, 2.0 * 3.0 * 4.0 * 4.0, &Device::Cpu)?.reshape(&[2, 3, 4, 4])?;
+
+    let layer = Reduce::new("b c h w -> b c", Operation::Mean)?;
+    assert_eq!(layer.operation(), Operation::Mean);
+    assert_eq!(
+        layer.forward(&input)?.to_vec2::<f32>()?,
+        einops!("b c mean(h) mean(w) -> b c", &input)?.to_vec2::<f32>()?
+    );
+
+    // Max pooling with 2x2 windows, then a global head
+    let model = candle_nn::seq()
+        .add(Reduce::new(
+            "b c (h h2:2) (w w2:2) -> b h w c",
+            Operation::Max,
+        )?)
+        .add(Reduce::new("b h w c -> b c", Operation::Sum)?);
+    let windows = input.reshape(&[2, 3, 2, 2, 2, 2])?;
+    let pooled = einops!("b c h max(h2) w max(w2) -> b h w c", &windows)?;
+    assert_eq!(
+        model.forward(&input)?.to_vec2::<f32>()?,
+        einops!("b sum(h) sum(w) c -> b c", &pooled)?.to_vec2::<f32>()?
+    );
+
+    // Axes on both sides are only rearranged
+    let layer = Reduce::new("a b c d -> d c b a", Operation::Min)?;
+    assert_eq!(layer.forward(&input)?.dims(), [4, 4, 3, 2]);
+
+    Ok(())
+}
+
+#[test]
+fn layers_repeat() -> Result<()> {
+    let token = Tensor::arange(0f32, 4.0, &Device::Cpu)?.reshape(&[1, 4])?;
+
+    let layer = Repeat::new("() d -> b () d", &[("b", 3)])?;
+    let output = layer.forward(&token)?;
+    assert_eq!(output.dims(), [3, 1, 4]);
+    assert_eq!(
+        output.to_vec3::<f32>()?,
+        einops!("t d -> b:3 t d", &token)?.to_vec3::<f32>()?
+    );
+
+    // Upsampling by repeating pixels, sizes can be written in the pattern
+    let image = Tensor::arange(0u32, 4, &Device::Cpu)?.reshape(&[1, 2, 2])?;
+    let model = candle_nn::seq()
+        .add(Repeat::new("c h w -> c (h h2:2) (w w2:2)", &[])?)
+        .add(Rearrange::new("c h w -> h w c", &[])?);
+    assert_eq!(
+        model.forward(&image)?.to_vec3::<u32>()?,
+        einops!("c h w -> (h h2:2) (w w2:2) c", &image)?.to_vec3::<u32>()?
+    );
+
+    let error = Repeat::new("a -> a b", &[]).unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "einops error: invalid pattern \"a -> a b\": new axis `b` needs a size"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn layers_einmix() -> Result<()> {
+    let varmap = VarMap::new();
+    let vb = VarBuilder::from_varmap(&varmap, DType::F32, &Device::Cpu);
+    let mix = EinMix::new(
+        "b t (h d) -> b h t d_out",
+        "h d d_out",
+        Some("h d_out"),
+        &[("h", 2), ("d", 3), ("d_out", 4)],
+        vb.pp("mix"),
+    )?;
+    assert_eq!(mix.weight().dims(), [2, 3, 4]);
+    assert_eq!(mix.bias().unwrap().dims(), [2, 4]);
+    assert_eq!(varmap.all_vars().len(), 2);
+
+    let input = Tensor::arange(0f32, 5.0 * 7.0 * 6.0, &Device::Cpu)?.reshape(&[5, 7, 6])?;
+    let output = mix.forward(&input)?;
+    assert_eq!(output.dims(), [5, 2, 7, 4]);
+
+    // Each head has its own projection
+    let heads = einops!("b t (h d:3) -> h (b t) d", &input)?;
+    let projected = heads.matmul(mix.weight())?;
+    let biased = projected.broadcast_add(&mix.bias().unwrap().unsqueeze(1)?)?;
+    let expected = einops!("h (b:5 t) d_out -> b h t d_out", &biased)?;
+    let difference = (output - expected)?
+        .abs()?
+        .flatten_all()?
+        .max(0)?
+        .to_scalar::<f32>()?;
+    assert!(difference < 1e-3, "{}", difference);
+
+    // Without a bias and summing over all input axes but the batch
+    let mix = EinMix::new("b c -> b", "c", None, &[("c", 6)], vb.pp("pool"))?;
+    assert!(mix.bias().is_none());
+    let input = input.reshape(&[35, 6])?;
+    assert_eq!(mix.forward(&input)?.dims(), [35]);
+
+    let error = EinMix::new("b c -> b e", "c", None, &[("c", 6)], vb.pp("e")).unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "einops error: invalid pattern \"b c -> b e\": axis `e` is on one side only and missing in the weight"
+    );
+    let error = EinMix::new("b c -> b e", "c e", None, &[("c", 6)], vb.pp("e")).unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "einops error: invalid pattern \"c e\": axis `e` needs a size"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn layers_builder() -> Result<()> {
+    let patches = Rearrange::builder("b c (h p1) (w p2) -> b (h w) (p1 p2 c)")
+        .size("p1", 4)
+        .size("p2", 4)
+        .build()?;
+    let pool = Reduce::builder("b (t t2) c -> b t c", Operation::Max)
+        .size("t2", 2)
+        .build()?;
+    let tokens = Repeat::builder("t c -> b t c").size("b", 2).build()?;
+    let varmap = VarMap::new();
+    let vb = VarBuilder::from_varmap(&varmap, DType::F32, &Device::Cpu);
+    let mix = EinMix::builder("b t c -> b t c_out", "c c_out")
+        .bias("c_out")
+        .size("c", 48)
+        .size("c_out", 8)
+        .build(vb)?;
+    assert_eq!(mix.bias().unwrap().dims(), [8]);
+
+    // Dimensions can be chained without running the layers
+    let shape = patches.output_shape(&[2, 3, 16, 32])?;
+    assert_eq!(shape, [2, 32, 48]);
+    let shape = pool.output_shape(&shape)?;
+    assert_eq!(shape, [2, 16, 48]);
+    assert_eq!(mix.output_shape(&shape)?, [2, 16, 8]);
+    assert_eq!(tokens.output_shape(&[16, 48])?, [2, 16, 48]);
+
+    let input = Tensor::zeros(&[2, 3, 16, 32], DType::F32, &Device::Cpu)?;
+    let output = mix.forward(&pool.forward(&patches.forward(&input)?)?)?;
+    assert_eq!(output.dims(), [2, 16, 8]);
+
+    // Shapes that don't fit fail like the layers do
+    let error = pool.output_shape(&[2, 33, 48]).unwrap_err();
+    assert_eq!(error.code(), "E003");
+    let error = mix.output_shape(&[2, 16, 40]).unwrap_err();
+    assert_eq!(error.code(), "E002");
+
+    // Bindings are checked when building
+    let error = Rearrange::builder("(a b) -> a b").build().unwrap_err();
+    assert_eq!(error.code(), "E011");
+    assert_eq!(
+        error.to_string(),
+        "einops error: the lengths of `a` and `b` in (a b) can't all be inferred, give all but one of them a size\n  \
+         in pattern \"(a b) -> a b\""
+    );
+    let error = Rearrange::builder("(a b) -> a b")
+        .size("b", 2)
+        .size("c", 3)
+        .build()
+        .unwrap_err();
+    assert_eq!(*error.kind(), ErrorKind::UnknownAxis { name: "c".into() });
+
+    Ok(())
+}
+
+#[test]
+fn layers_steps() -> Result<()> {
+    let layer = Rearrange::new("b (h w) c -> b c h w", &[("h", 4)])?;
+    assert_eq!(
+        layer.steps(&[2, 16, 3])?,
+        Steps {
+            planned: 3,
+            optimized: 2,
+            order: Order::ReshapeFirst,
+            device: None
+        }
+    );
+    let layer = Reduce::new("b c h w -> b c", Operation::Mean)?;
+    assert_eq!(layer.steps(&[2, 3, 4, 4])?.optimized, 1);
+    let layer = Repeat::new("b c -> b c r", &[("r", 2)])?;
+    assert_eq!(layer.steps(&[2, 3])?.optimized, 1);
+
+    // A transpose and its inverse cancel out
+    let input = Tensor::arange(0u32, 2 * 16 * 3, &Device::Cpu)?.reshape(&[2, 16, 3])?;
+    let chain = Chain::from(Rearrange::new("b (h w) c -> b c h w", &[("h", 4)])?)
+        .then(Rearrange::new("b c h w -> b (h w) c", &[])?);
+    assert_eq!(
+        chain.steps(&[2, 16, 3])?,
+        Steps {
+            planned: 6,
+            optimized: 0,
+            order: Order::ReshapeFirst,
+            device: None
+        }
+    );
+    assert_eq!(
+        chain.forward(&input)?.to_vec3::<u32>()?,
+        input.to_vec3::<u32>()?
+    );
+
+    let chain = chain
+        .then(Rearrange::new("b t c -> t b c", &[])?)
+        .then(Reduce::new("t b c -> t c", Operation::Max)?);
+    assert_eq!(chain.steps(&[2, 16, 3])?.optimized, 2);
+    assert_eq!(chain.output_shape(&[2, 16, 3])?, [16, 3]);
+    assert_eq!(
+        chain.forward(&input)?.to_vec2::<u32>()?,
+        einops!("max(b) t c -> t c", &input)?.to_vec2::<u32>()?
+    );
+    assert_eq!(
+        chain.apply(&input.reshape(&[2, 48])?).unwrap_err().code(),
+        "E004"
+    );
+
+    Ok(())
+}
+
+#[cfg(feature = "cpu")]
+#[test]
+fn layers_apply_into() -> std::result::Result<(), candle_einops::EinopsError> {
+    use candle_einops::cpu::CpuTensor;
+
+    let input = CpuTensor::new((0..24).collect::<Vec<u32>>(), vec![2, 3, 4])?;
+    let layer = Repeat::new("a b (c d) -> (d a) b c r", &[("d", 2), ("r", 2)])?;
+    let mut output = vec![0; 48];
+    layer.apply_into(&input, &mut output)?;
+    assert_eq!(output, layer.apply(&input)?.into_data());
+
+    // Reductions run on tensors and copy their output
+    let layer = Reduce::new("a b c -> c a", Operation::Sum)?;
+    let mut output = vec![0; 8];
+    layer.apply_into(&input, &mut output)?;
+    assert_eq!(output, layer.apply(&input)?.into_data());
+
+    let layer = Chain::from(Rearrange::new("a b c -> c b a", &[])?)
+        .then(Rearrange::new("c b a -> (c b) a", &[])?);
+    let mut output = vec![0; 24];
+    layer.apply_into(&input, &mut output)?;
+    assert_eq!(output, layer.apply(&input)?.into_data());
+    let error = layer.apply_into(&input, &mut output[..4]).unwrap_err();
+    assert!(error
+        .to_string()
+        .contains("the output has 24 elements, the buffer 4"));
+
+    Ok(())
+}
+
+#[test]
+fn layers_steps_order() -> Result<()> {
+    let layer = Rearrange::new("b (h w) c -> (b c) h w", &[("h", 4)])?;
+    let input = Tensor::arange(0u32, 2 * 16 * 3, &Device::Cpu)?.reshape(&[2, 3, 16])?;
+    let transposed = input.permute((0, 2, 1))?;
+    let contiguous = transposed.contiguous()?;
+    assert_eq!(layer.steps_for(&contiguous)?.order, Order::ReshapeFirst);
+    assert_eq!(layer.steps(&[2, 16, 3])?.device, None);
+
+    // Transposing the view back first leaves it contiguous for the reshape
+    assert_eq!(
+        layer.steps_for(&transposed)?,
+        Steps {
+            planned: 2,
+            optimized: 2,
+            order: Order::PermuteFirst,
+            device: Some(candle_einops::Device::Cpu)
+        }
+    );
+    assert_eq!(
+        layer.forward(&transposed)?.to_vec3::<u32>()?,
+        layer.forward(&contiguous)?.to_vec3::<u32>()?
+    );
+
+    // Patterns splitting the axes of a group apart have one order
+    let layer = Rearrange::new("b (h w) c -> b w c h", &[("h", 4)])?;
+    assert_eq!(layer.steps_for(&transposed)?.order, Order::ReshapeFirst);
+
+    Ok(())
+}
+
+#[test]
+fn layers_inputs() -> Result<()> {
+    let inputs = Inputs::new("b t (h d), b s (h d), b s (h d)", &[("h", 2)])?;
+    assert_eq!(
+        inputs.bind(&[&[2, 5, 8], &[2, 7, 8], &[2, 7, 8]])?,
+        [("b", 2), ("t", 5), ("h", 2), ("d", 4), ("s", 7)]
+    );
+
+    // Lengths found in one input decompose the groups of the next
+    let inputs = Inputs::new("b d, b (h d)", &[])?;
+    assert_eq!(
+        inputs.bind(&[&[2, 4], &[2, 12]])?,
+        [("b", 2), ("d", 4), ("h", 3)]
+    );
+
+    let inputs = Inputs::new("b t d, b s d", &[])?;
+    let error = inputs.bind(&[&[2, 5, 8], &[3, 7, 8]]).unwrap_err();
+    assert_eq!(
+        *error.kind(),
+        ErrorKind::ShapeMismatch {
+            axis: 0,
+            group: "b".into(),
+            sizes: vec![("b".into(), 2)],
+            expected: 2,
+            found: 3,
+        }
+    );
+    assert_eq!(
+        inputs.bind(&[&[2, 5, 8]]).unwrap_err().to_string(),
+        "einops error: expected 2 inputs, found 1"
+    );
+
+    let error = Inputs::new("b t d, b (h s) d", &[]).unwrap_err();
+    assert_eq!(
+        *error.kind(),
+        ErrorKind::AmbiguousInference {
+            group: "(h s)".into(),
+            candidates: vec!["h".into(), "s".into()],
+        }
+    );
+    let error = Inputs::new("b t t, b s d", &[]).unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "einops error: invalid pattern \"b t t, b s d\": axis `t` appears twice in input 1"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn layers_registry() -> Result<()> {
+    let input = Tensor::arange(0u32, 2 * 3 * 4, &Device::Cpu)?.reshape(&[2, 3, 4])?;
+
+    let mut registry = Registry::default();
+    let transpose = registry.register(Rearrange::new("h w c -> c h w", &[])?);
+    let sum = registry.register(Reduce::new("h w c -> c", Operation::Sum)?);
+    assert_ne!(transpose, sum);
+    assert_eq!(registry.id(&["h w c -> c h w"]), Some(transpose));
+    assert_eq!(registry.id(&["h w c -> c w h"]), None);
+    assert_eq!(
+        registry.get(sum).map(Chain::patterns),
+        Some(vec!["h w c -> c"])
+    );
+
+    assert_eq!(
+        registry.apply(transpose, &input)?.to_vec3::<u32>()?,
+        einops!("h w c -> c h w", &input)?.to_vec3::<u32>()?
+    );
+    assert_eq!(
+        registry.apply(sum, &input)?.to_vec1::<u32>()?,
+        einops!("sum(h) sum(w) c -> c", &input)?.to_vec1::<u32>()?
+    );
+
+    // Ids of another registry aren't found
+    let other = Registry::default();
+    assert!(other.apply(sum, &input).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn layers_batch() -> Result<()> {
+    let layer = Rearrange::new("b (h w) c -> b c h w", &[("h", 2)])?;
+    let inputs = (0u32..3)
+        .map(|i| Tensor::arange(i * 24, (i + 1) * 24, &Device::Cpu)?.reshape(&[2, 4, 3]))
+        .collect::<Result<Vec<_>>>()?;
+    let outputs = layer.apply_batch(&inputs)?;
+    assert_eq!(outputs.len(), 3);
+    for (input, output) in inputs.iter().zip(&outputs) {
+        assert_eq!(
+            output.flatten_all()?.to_vec1::<u32>()?,
+            layer.apply(input)?.flatten_all()?.to_vec1::<u32>()?
+        );
+    }
+
+    // Inputs of another shape are planned for again, and checked
+    let mixed = [inputs[0].clone(), inputs[1].reshape(&[2, 2, 6])?];
+    let outputs = layer.apply_batch(&mixed)?;
+    assert_eq!(outputs[0].dims(), [2, 3, 2, 2]);
+    assert_eq!(outputs[1].dims(), [2, 6, 2, 1]);
+    let wrong = [inputs[0].clone(), inputs[1].reshape(&[2, 12])?];
+    assert_eq!(layer.apply_batch(&wrong).unwrap_err().code(), "E004");
+
+    let chain = Chain::from(layer).then(Reduce::new("b c h w -> b c", Operation::Sum)?);
+    assert_eq!(chain.apply_batch(&inputs)?[2].dims(), [2, 3]);
+
+    Ok(())
+}
+
+#[test]
+fn layers_copies() -> Result<()> {
+    let layer = Rearrange::new("b (h w) c -> b c h w", &[("h", 4)])?;
+    assert_eq!(layer.max_copies(&[2, 16, 3])?, 0);
+    assert_max_copies!(layer, [2, 16, 3], 0);
+
+    // Flattening permuted axes copies them
+    let layer = Rearrange::new("b h w c -> b (c h w)", &[])?;
+    assert_eq!(layer.max_copies(&[2, 4, 4, 3])?, 1);
+    let layer = Rearrange::new("b h w c -> b (h w c)", &[])?;
+    assert_eq!(layer.max_copies(&[2, 4, 4, 3])?, 0);
+    let layer = Repeat::new("b c -> b (c r)", &[("r", 2)])?;
+    assert_eq!(layer.max_copies(&[2, 3])?, 1);
+    let layer = Reduce::new("b (h p:2) w c -> b c h w", Operation::Max)?;
+    assert_eq!(layer.max_copies(&[2, 4, 4, 3])?, 1);
+
+    let chain = Chain::from(Rearrange::new("b h w c -> b c h w", &[])?)
+        .then(Rearrange::new("b c h w -> b h w c", &[])?)
+        .then(Rearrange::new("b h w c -> b (h w c)", &[])?);
+    assert_max_copies!(chain, [2, 4, 4, 3], 0);
+
+    Ok(())
+}
+
+#[test]
+#[should_panic(expected = "copies its input 1 times, more than 0")]
+fn layers_copies_exceeded() {
+    let layer = Rearrange::new("b h w c -> b (c h w)", &[]).unwrap();
+    assert_max_copies!(layer, [2, 4, 4, 3], 0);
+}
+
+#[test]
+fn layers_validate() -> Result<()> {
+    let layer = Rearrange::new("(a b:2) (c d:3) e:4 -> a b c d e", &[])?;
+    assert!(layer.validate(&[4, 6, 4]).is_empty());
+
+    // Every group that doesn't fit is reported
+    let errors = layer.validate(&[5, 7, 3]);
+    let kinds = errors
+        .iter()
+        .map(|error| error.kind().clone())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        kinds,
+        [
+            ErrorKind::NotDivisible {
+                axis: 0,
+                group: "(a b:2)".into(),
+                sizes: vec![("b".into(), 2)],
+                size: 5,
+                divisor: 2,
+            },
+            ErrorKind::NotDivisible {
+                axis: 1,
+                group: "(c d:3)".into(),
+                sizes: vec![("d".into(), 3)],
+                size: 7,
+                divisor: 3,
+            },
+            ErrorKind::ShapeMismatch {
+                axis: 2,
+                group: "e:4".into(),
+                sizes: vec![("e".into(), 4)],
+                expected: 4,
+                found: 3,
+            },
+        ]
+    );
+    assert!(errors
+        .iter()
+        .all(|error| error.patterns() == ["(a b:2) (c d:3) e:4 -> a b c d e"]));
+    assert_eq!(
+        errors[0].to_string(),
+        layer
+            .apply(&Tensor::zeros(&[5, 7, 3], DType::F32, &Device::Cpu)?)
+            .unwrap_err()
+            .to_string()
+    );
+
+    let errors = layer.validate(&[4, 6]);
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].code(), "E004");
+
+    let layer = Reduce::new("b (h p:2) .. -> b h ..", Operation::Max)?;
+    assert!(layer.validate(&[2, 4, 3, 3]).is_empty());
+    assert_eq!(layer.validate(&[2, 5, 3, 3]).len(), 1);
+
+    // Chains report the problems of the first layer that doesn't fit
+    let chain = Chain::from(Rearrange::new("b h w -> b w h", &[])?)
+        .then(Rearrange::new("b (h x:2) (w y:3) -> b h w x y", &[])?);
+    assert!(chain.validate(&[2, 3, 4]).is_empty());
+    let errors = chain.validate(&[2, 5, 3]);
+    assert_eq!(errors.len(), 2);
+    assert_eq!(errors[0].patterns(), ["b (h x:2) (w y:3) -> b h w x y"]);
+    assert_eq!(chain.validate(&[2, 3])[0].patterns(), ["b h w -> b w h"]);
+
+    Ok(())
+}
+
+#[test]
+fn layers_errors() -> Result<()> {
+    let error = Rearrange::new("b c -> b d", &[]).unwrap_err();
+    assert_eq!(error.code(), "E010");
+    assert_eq!(
+        error.to_string(),
+        "einops error: unknown axis `d`, which isn't on the left side of the pattern\n  \
+         in pattern \"b c -> b d\""
+    );
+    let error = Reduce::new("b (c -> b", Operation::Sum).unwrap_err();
+    assert_eq!(
+        *error.kind(),
+        ErrorKind::ParseError {
+            pattern: "b (c -> b".into(),
+            offset: 2,
+            reason: "unclosed `(`".to_string(),
+        }
+    );
+
+    let input = Tensor::zeros(&[6, 5], candle_core::DType::F32, &Device::Cpu)?;
+    let layer = Rearrange::new("(a b:4) c -> a b c", &[]).unwrap();
+    let error = layer.apply(&input).unwrap_err();
+    assert_eq!(
+        *error.kind(),
+        ErrorKind::NotDivisible {
+            axis: 0,
+            group: "(a b:4)".into(),
+            sizes: vec![("b".into(), 4)],
+            size: 6,
+            divisor: 4,
+        }
+    );
+    assert_eq!(
+        error.to_string(),
+        "einops error: cannot decompose axis 0 of length 6 into (a b:4): 6 is not divisible by 4 (b = 4)\n  \
+         in pattern \"(a b:4) c -> a b c\"\n  \
+         input shape [6, 5]\n  \
+         axes (a b:4) = 6 not divisible by 4, c = 5 ok"
+    );
+    assert_eq!(error.patterns(), ["(a b:4) c -> a b c"]);
+
+    let error = layer.apply(&input.reshape(&[30])?).unwrap_err();
+    assert_eq!(
+        *error.kind(),
+        ErrorKind::RankMismatch {
+            expected: 2,
+            found: 1
+        }
+    );
+
+    Ok(())
+}
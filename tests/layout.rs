@@ -0,0 +1,65 @@
+#![cfg(feature = "candle")]
+
+use candle_core::{Device, Result, Tensor};
+use candle_einops::layout::{to_layout, Layout};
+use candle_einops::ErrorKind;
+
+#[test]
+fn layout_convert() -> Result<()> {
+    let image = Tensor::arange(0u32, 2 * 3 * 4 * 5, &Device::Cpu)?.reshape(&[2, 3, 4, 5])?;
+
+    let nhwc = to_layout(&image, Layout::Nchw, Layout::Nhwc, 3)?;
+    assert_eq!(nhwc.dims(), [2, 4, 5, 3]);
+    assert_eq!(nhwc.get(0)?.get(1)?.get(2)?.to_vec1::<u32>()?, [7, 27, 47]);
+    let nchw = to_layout(&nhwc, Layout::Nhwc, Layout::Nchw, 3)?;
+    assert_eq!(
+        nchw.flatten_all()?.to_vec1::<u32>()?,
+        image.flatten_all()?.to_vec1::<u32>()?
+    );
+    // Images already in the layout are the same tensor
+    assert_eq!(
+        to_layout(&image, Layout::Nchw, Layout::Nchw, 3)?.id(),
+        image.id()
+    );
+
+    let hwc = to_layout(&image.get(1)?, Layout::Chw, Layout::Hwc, 3)?;
+    assert_eq!(hwc.dims(), [4, 5, 3]);
+    assert_eq!(
+        to_layout(&hwc, Layout::Hwc, Layout::Chw, 3)?.dims(),
+        [3, 4, 5]
+    );
+
+    assert_eq!(Layout::Nhwc.channels(&[2, 4, 5, 3]).unwrap(), 3);
+    assert_eq!(Layout::Chw.channel_axis(), 0);
+    assert!(!Layout::Hwc.is_batched());
+
+    Ok(())
+}
+
+#[test]
+fn layout_errors() -> Result<()> {
+    let image = Tensor::zeros(&[2, 3, 4, 5], candle_core::DType::F32, &Device::Cpu)?;
+
+    // Images in NCHW passed as NHWC have 5 channels
+    let error = to_layout(&image, Layout::Nhwc, Layout::Nchw, 3).unwrap_err();
+    assert!(matches!(
+        error.kind(),
+        ErrorKind::ShapeMismatch {
+            axis: 3,
+            expected: 3,
+            found: 5,
+            ..
+        }
+    ));
+    let error = to_layout(&image, Layout::Chw, Layout::Hwc, 3).unwrap_err();
+    assert_eq!(
+        *error.kind(),
+        ErrorKind::RankMismatch {
+            expected: 3,
+            found: 4
+        }
+    );
+    assert!(to_layout(&image, Layout::Nchw, Layout::Hwc, 3).is_err());
+
+    Ok(())
+}
@@ -0,0 +1,104 @@
+#![cfg(all(feature = "metrics", feature = "candle"))]
+
+use std::sync::Mutex;
+
+use candle_core::{Device, Result, Tensor};
+use candle_einops::{einops, metrics};
+
+// The counters are shared by the tests, which take turns
+static COUNTERS: Mutex<()> = Mutex::new(());
+
+#[test]
+fn metrics_counters() -> Result<()> {
+    let _counters = COUNTERS.lock().unwrap_or_else(|error| error.into_inner());
+    let input = Tensor::arange(0f32, 24.0, &Device::Cpu)?.reshape(&[2, 3, 4])?;
+    metrics::reset();
+
+    // Merging `c` and `a` can't be a view
+    einops!("a b c -> (c a) b", &input)?;
+    einops!("a b c -> (c a) b", &input)?;
+    // Permuting is a view, `h` and `w` stay adjacent
+    einops!("a h w -> w (a h)", &input)?;
+    einops!("a max(b) c -> c a", &input)?;
+    // Not inside `einops!`
+    input.t()?.contiguous()?;
+
+    let counters = metrics::counters();
+    let counters = counters
+        .iter()
+        .map(|(pattern, counters)| (pattern.as_str(), *counters))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        counters,
+        [
+            (
+                "a b c -> (c a) b",
+                metrics::Counters {
+                    calls: 2,
+                    copies: 2,
+                    bytes_moved: 2 * 24 * 4,
+                    kernels: 2,
+                }
+            ),
+            (
+                "a h w -> w (a h)",
+                metrics::Counters {
+                    calls: 1,
+                    ..Default::default()
+                }
+            ),
+            (
+                "a max(b) c -> c a",
+                metrics::Counters {
+                    calls: 1,
+                    kernels: 1,
+                    ..Default::default()
+                }
+            ),
+        ]
+    );
+
+    metrics::reset();
+    assert!(metrics::counters().is_empty());
+
+    Ok(())
+}
+
+#[cfg(feature = "nn")]
+#[test]
+fn metrics_layers() -> Result<()> {
+    use candle_einops::layers::{Chain, Rearrange};
+
+    let input = Tensor::arange(0f32, 24.0, &Device::Cpu)?.reshape(&[2, 3, 4])?;
+    let layer = Rearrange::new("a b c -> (c a) b", &[])?;
+    let chain = Chain::from(Rearrange::new("a b c -> c b a", &[])?)
+        .then(Rearrange::new("c b a -> (c b) a", &[])?);
+    let _counters = COUNTERS.lock().unwrap_or_else(|error| error.into_inner());
+    metrics::reset();
+    layer.apply(&input)?;
+    layer.apply(&input)?;
+    chain.apply(&input)?;
+
+    let counters = metrics::counters();
+    let find = |pattern: &str| {
+        counters
+            .iter()
+            .find(|(counted, _)| counted == pattern)
+            .map(|(_, counters)| *counters)
+    };
+    assert_eq!(
+        find("a b c -> (c a) b"),
+        Some(metrics::Counters {
+            calls: 2,
+            copies: 2,
+            bytes_moved: 2 * 24 * 4,
+            kernels: 2,
+        })
+    );
+    assert_eq!(
+        find("a b c -> c b a then c b a -> (c b) a").map(|counters| counters.calls),
+        Some(1)
+    );
+
+    Ok(())
+}
@@ -0,0 +1,29 @@
+#![cfg(feature = "nalgebra")]
+
+use candle_einops::{einops, EinopsError};
+use nalgebra::{DMatrix, DVector};
+
+#[test]
+fn nalgebra_patterns() -> Result<(), EinopsError> {
+    let matrix = DMatrix::from_row_slice(2, 6, &(0..12).collect::<Vec<u32>>());
+
+    let output = einops!("r (c k:3) -> (r c) k", &matrix)?;
+    let output = DMatrix::try_from(output)?;
+    assert_eq!(output.shape(), (4, 3));
+    assert_eq!(output[(2, 0)], 6);
+
+    let output = einops!("r c -> c r", &matrix)?;
+    assert_eq!(DMatrix::try_from(output)?, matrix.transpose());
+
+    let output = einops!("max(r) c -> c", &matrix)?;
+    assert_eq!(DVector::try_from(output)?, DVector::from_iterator(6, 6..12));
+
+    let vector = DVector::from_vec(vec![1.0f32, 2.0, 3.0]);
+    let output = einops!("n -> 2 n", &vector)?;
+    assert_eq!(
+        DMatrix::try_from(output)?,
+        DMatrix::from_row_slice(2, 3, &[1.0, 2.0, 3.0, 1.0, 2.0, 3.0])
+    );
+
+    Ok(())
+}
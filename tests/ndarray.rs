@@ -0,0 +1,24 @@
+#![cfg(feature = "ndarray")]
+
+use candle_einops::{einops, EinopsError};
+use ndarray::{ArrayD, IxDyn};
+
+#[test]
+fn ndarray_patterns() -> Result<(), EinopsError> {
+    let input = ArrayD::from_shape_vec(IxDyn(&[2, 3, 4]), (0..24).map(|x| x as f32).collect())?;
+
+    let output = einops!("a b c -> c (a b)", &input)?;
+    assert_eq!(output.shape(), &[4, 6]);
+    assert_eq!(output[[1, 4]], input[[1, 1, 1]]);
+
+    let output = einops!(
+        "a (b max(b2:2)) c -> a b c",
+        &einops!("a b c -> a (b 2) c", &input)?
+    )?;
+    assert_eq!(output, input);
+
+    let output = einops!("a b sum(c) -> b a", &input)?;
+    assert_eq!(output[[2, 1]], (20..24).sum::<i32>() as f32);
+
+    Ok(())
+}
@@ -0,0 +1,83 @@
+#![cfg(feature = "candle")]
+
+use candle_core::{Device, Result, Tensor};
+use candle_einops::norm::{group_channels, group_reduce, ungroup_channels};
+use candle_einops::{ErrorKind, Operation};
+
+#[test]
+fn norm_groups() -> Result<()> {
+    let input = Tensor::arange(0f32, 2. * 6. * 2. * 2., &Device::Cpu)?.reshape(&[2, 6, 2, 2])?;
+
+    let grouped = group_channels(&input, 3)?;
+    assert_eq!(grouped.dims(), [2, 3, 2, 2, 2]);
+    // Group 1 holds channels 2 and 3
+    assert_eq!(
+        grouped.get(0)?.get(1)?.flatten_all()?.to_vec1::<f32>()?,
+        input
+            .get(0)?
+            .narrow(0, 2, 2)?
+            .flatten_all()?
+            .to_vec1::<f32>()?
+    );
+    assert_eq!(
+        ungroup_channels(&grouped)?
+            .flatten_all()?
+            .to_vec1::<f32>()?,
+        input.flatten_all()?.to_vec1::<f32>()?
+    );
+
+    let error = group_channels(&input, 4).unwrap_err();
+    assert!(matches!(
+        error.kind(),
+        ErrorKind::NotDivisible {
+            axis: 1,
+            size: 6,
+            divisor: 4,
+            ..
+        }
+    ));
+    assert!(group_channels(&input, 0).is_err());
+    let error = group_channels(&input.get(0)?, 3).unwrap_err();
+    assert_eq!(
+        *error.kind(),
+        ErrorKind::RankMismatch {
+            expected: 4,
+            found: 3
+        }
+    );
+
+    Ok(())
+}
+
+#[test]
+fn norm_group_reduce() -> Result<()> {
+    let input = Tensor::arange(0f32, 2. * 6. * 2. * 2., &Device::Cpu)?.reshape(&[2, 6, 2, 2])?;
+
+    let mean = group_reduce(&input, 3, Operation::Mean)?;
+    assert_eq!(mean.dims(), [2, 3]);
+    assert_eq!(
+        mean.to_vec2::<f32>()?,
+        group_channels(&input, 3)?
+            .flatten_from(2)?
+            .mean(2)?
+            .to_vec2::<f32>()?
+    );
+    assert_eq!(
+        group_reduce(&input, 2, Operation::Max)?.to_vec2::<f32>()?,
+        [[11., 23.], [35., 47.]]
+    );
+    assert_eq!(
+        group_reduce(&input, 6, Operation::Min)?
+            .get(0)?
+            .to_vec1::<f32>()?,
+        [0., 4., 8., 12., 16., 20.]
+    );
+
+    // The variance of every group from the mean of the squares
+    let variance = (group_reduce(&input.sqr()?, 3, Operation::Mean)? - mean.sqr()?)?;
+    assert_eq!(variance.to_vec2::<f32>()?[0], [5.25, 5.25, 5.25]);
+
+    assert!(group_reduce(&input, 4, Operation::Sum).is_err());
+
+    Ok(())
+}
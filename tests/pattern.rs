@@ -0,0 +1,47 @@
+use candle_einops::pattern::{tokenize, Token, TokenKind};
+use candle_einops::ErrorKind;
+
+#[test]
+fn pattern_tokens() {
+    let pattern = "b (h w:16) .. -> b, _x";
+    let tokens = tokenize(pattern).unwrap();
+    let kinds = tokens
+        .iter()
+        .map(|token| (token.kind, token.text(pattern)))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        kinds,
+        [
+            (TokenKind::Axis, "b"),
+            (TokenKind::Open, "("),
+            (TokenKind::Axis, "h"),
+            (TokenKind::Axis, "w"),
+            (TokenKind::Size, "16"),
+            (TokenKind::Close, ")"),
+            (TokenKind::Ellipsis, ".."),
+            (TokenKind::Arrow, "->"),
+            (TokenKind::Axis, "b"),
+            (TokenKind::Comma, ","),
+            (TokenKind::Axis, "_x"),
+        ]
+    );
+    assert_eq!(
+        tokens[4],
+        Token {
+            kind: TokenKind::Size,
+            span: 7..9
+        }
+    );
+
+    // Structure isn't checked, only characters
+    assert_eq!(tokenize(") (").unwrap().len(), 2);
+    assert_eq!(
+        *tokenize("a b:x").unwrap_err().kind(),
+        ErrorKind::ParseError {
+            pattern: "a b:x".into(),
+            offset: 4,
+            reason: "expected a size after `b:`".to_string(),
+        }
+    );
+    assert_eq!(tokenize("a - b").unwrap_err().code(), "E009");
+}
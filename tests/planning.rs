@@ -0,0 +1,120 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use candle_einops::{einops, Backend, EinopsError, Operation};
+
+// A tensor without data, like one on a device that has to be synchronized to
+// be read. It records the calls `einops!` makes and panics on the ones that
+// would read the data
+#[derive(Clone)]
+struct Metadata {
+    shape: Vec<usize>,
+    calls: Rc<RefCell<Vec<String>>>,
+}
+
+impl Metadata {
+    fn new(shape: &[usize]) -> Self {
+        Self {
+            shape: shape.to_vec(),
+            calls: Rc::default(),
+        }
+    }
+
+    fn record(&self, call: String, shape: Vec<usize>) -> Self {
+        self.calls.borrow_mut().push(call);
+        Self {
+            shape,
+            calls: self.calls.clone(),
+        }
+    }
+
+    // Calls other than reading the shape, which is read first
+    fn steps(&self) -> Vec<String> {
+        let calls = self.calls.borrow();
+        assert_eq!(calls.first().map(String::as_str), Some("shape"));
+        calls
+            .iter()
+            .filter(|&call| call != "shape")
+            .cloned()
+            .collect()
+    }
+}
+
+impl Backend for Metadata {
+    type Output = Metadata;
+
+    fn shape(&self) -> Vec<usize> {
+        self.calls.borrow_mut().push("shape".to_string());
+        self.shape.clone()
+    }
+
+    fn reshape(&self, shape: &[usize]) -> Result<Self::Output, EinopsError> {
+        Ok(self.record(format!("reshape {:?}", shape), shape.to_vec()))
+    }
+
+    fn transpose(&self, axes: &[usize]) -> Result<Self::Output, EinopsError> {
+        let shape = axes.iter().map(|&axis| self.shape[axis]).collect();
+        Ok(self.record(format!("transpose {:?}", axes), shape))
+    }
+
+    fn reduce_axes(&self, _: &mut [(usize, Operation)]) -> Result<Self::Output, EinopsError> {
+        panic!("reduce_axes reads the data")
+    }
+
+    fn add_axes(
+        &self,
+        naxes: usize,
+        pos2len: &[(usize, usize)],
+    ) -> Result<Self::Output, EinopsError> {
+        let mut shape = self.shape.clone();
+        for &(axis, len) in pos2len {
+            shape.insert(axis, len);
+        }
+        assert_eq!(shape.len(), naxes);
+        Ok(self.record(format!("add_axes {:?}", pos2len), shape))
+    }
+
+    fn narrow(&self, _: usize, _: usize, _: usize) -> Result<Self::Output, EinopsError> {
+        panic!("narrow isn't used by these patterns")
+    }
+
+    fn broadcast(&self, _: &[usize]) -> Result<Self::Output, EinopsError> {
+        panic!("broadcast isn't used by these patterns")
+    }
+
+    fn is_contiguous(&self) -> bool {
+        panic!("is_contiguous isn't used by these patterns")
+    }
+
+    fn contiguous(&self) -> Result<Self::Output, EinopsError> {
+        panic!("contiguous reads the data")
+    }
+
+    fn concat(_: &[&Self], _: usize) -> Result<Self::Output, EinopsError> {
+        panic!("concat reads the data")
+    }
+
+    fn index_select(&self, _: usize, _: &[usize]) -> Result<Self::Output, EinopsError> {
+        panic!("index_select reads the data")
+    }
+}
+
+#[test]
+fn planning_reads_metadata() -> Result<(), EinopsError> {
+    let input = Metadata::new(&[2, 6, 3]);
+    let output = einops!("b (h w:2) c -> b c h w", &input)?;
+    assert_eq!(output.shape, [2, 3, 3, 2]);
+    // Only the transformations run after the shape is read
+    assert_eq!(
+        input.steps(),
+        ["reshape [2, 3, 2, 3]", "transpose [0, 3, 1, 2]"]
+    );
+
+    // Constant shapes are validated from the shape as well
+    let input = Metadata::new(&[2, 3]);
+    einops!("a:2 b:3 -> b a 4", &input)?;
+    assert_eq!(input.steps(), ["transpose [1, 0]", "add_axes [(2, 4)]"]);
+    assert!(einops!("a:3 b:2 -> b a", &Metadata::new(&[2, 3])).is_err());
+
+    Ok(())
+}
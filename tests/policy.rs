@@ -0,0 +1,127 @@
+#![cfg(feature = "candle")]
+
+use candle_core::{DType, Device, Result, Tensor};
+use candle_einops::policy::{Broadcast, EmptyReduction};
+use candle_einops::{einops, ErrorKind, Operation};
+
+#[test]
+fn policy_empty_axes() -> Result<()> {
+    let input = Tensor::zeros(&[2, 0, 3], DType::F32, &Device::Cpu)?;
+
+    // Rearranging and repeating keep the empty axis
+    assert_eq!(einops!("a b c -> b (a c)", &input)?.dims(), [0, 6]);
+    assert_eq!(einops!("a b c -> a b c r:4", &input)?.dims(), [2, 0, 3, 4]);
+
+    // Sums of nothing are 0, maxima of nothing don't exist
+    assert_eq!(EmptyReduction::default(), EmptyReduction::Identity);
+    let output = einops!("a sum(b) c -> a c", &input)?;
+    assert_eq!(output.to_vec2::<f32>()?, [[0.0; 3]; 2]);
+    let error = einops!("a max(b) c -> a c", &input).unwrap_err();
+    assert_eq!(
+        *error.kind(),
+        ErrorKind::EmptyReduction {
+            axis: 1,
+            operation: Operation::Max
+        }
+    );
+    assert_eq!(error.code(), "E007");
+
+    let error = einops!("a sum(b) c -> a c", &input, empty_reduction = error).unwrap_err();
+    assert!(matches!(error.kind(), ErrorKind::EmptyReduction { .. }));
+    // Axes of length 0 that aren't reduced are fine
+    let output = einops!("sum(a) b c -> b c", &input, empty_reduction = error)?;
+    assert_eq!(output.dims(), [0, 3]);
+    // Layers pick the policy when they're built
+    #[cfg(feature = "nn")]
+    {
+        use candle_einops::layers::{Chain, Reduce};
+        let sum = Reduce::new("a b c -> a c", Operation::Sum)?;
+        assert_eq!(sum.apply(&input)?.to_vec2::<f32>()?, [[0.0; 3]; 2]);
+        let strict = Reduce::builder("a b c -> a c", Operation::Sum)
+            .empty_reduction(EmptyReduction::Error)
+            .build()?;
+        let error = strict.apply(&input).unwrap_err();
+        assert!(matches!(error.kind(), ErrorKind::EmptyReduction { .. }));
+        // Every layer of a chain keeps its own policy
+        let strict = Reduce::builder("b c -> c", Operation::Sum)
+            .empty_reduction(EmptyReduction::Error)
+            .build()?;
+        let chain = Chain::from(Reduce::new("a b c -> b c", Operation::Sum)?).then(strict);
+        let error = chain.apply(&input).unwrap_err();
+        assert!(matches!(error.kind(), ErrorKind::EmptyReduction { .. }));
+        let chain = Chain::from(Reduce::new("a b c -> a c", Operation::Sum)?)
+            .then(Reduce::new("a c -> c", Operation::Sum)?);
+        assert_eq!(chain.apply(&input)?.to_vec1::<f32>()?, [0.0; 3]);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn policy_broadcast() -> Result<()> {
+    let input = Tensor::zeros(&[2, 1, 3], DType::F32, &Device::Cpu)?;
+
+    // Axes of length 1 are repeated like the others
+    assert_eq!(Broadcast::default(), Broadcast::Implicit);
+    assert_eq!(einops!("a b c -> a (b r:4) c", &input)?.dims(), [2, 4, 3]);
+
+    let error = einops!("a b c -> a (b r:4) c", &input, broadcast = strict).unwrap_err();
+    assert_eq!(
+        *error.kind(),
+        ErrorKind::ImplicitBroadcast {
+            axis: 1,
+            name: "b".into()
+        }
+    );
+    assert_eq!(error.code(), "E008");
+    let error = einops!(".. b c -> .. (r:4 b) c", &input, broadcast = strict).unwrap_err();
+    assert_eq!(
+        *error.kind(),
+        ErrorKind::ImplicitBroadcast {
+            axis: 1,
+            name: "b".into()
+        }
+    );
+    // Axes of length 1 next to new axes keep their length
+    let output = einops!("a b c -> a b c r:4", &input, broadcast = strict)?;
+    assert_eq!(output.dims(), [2, 1, 3, 4]);
+    // Axes written as `1` or given the size 1 are repeated on purpose, and
+    // grouping with new axes of length 1 broadcasts nothing
+    let output = einops!("a 1 c -> a c r:4", &input, broadcast = strict)?;
+    assert_eq!(output.dims(), [2, 3, 4]);
+    let output = einops!("a b:1 c -> a (b r:4) c", &input, broadcast = strict)?;
+    assert_eq!(output.dims(), [2, 4, 3]);
+    let output = einops!("a b c -> a (b r:1) c", &input, broadcast = strict)?;
+    assert_eq!(output.dims(), [2, 1, 3]);
+    // Axes longer than 1 are repeated under the policy
+    let output = einops!("a b c -> (a r:2) b c", &input, broadcast = strict)?;
+    assert_eq!(output.dims(), [4, 1, 3]);
+    // Layers pick the policy when they're built, validating reports it
+    #[cfg(feature = "nn")]
+    {
+        use candle_einops::layers::Repeat;
+        let layer = Repeat::new("a b c -> a (b r) c", &[("r", 4)])?;
+        assert_eq!(layer.apply(&input)?.dims(), [2, 4, 3]);
+        let layer = Repeat::builder("a b c -> a (b r) c")
+            .size("r", 4)
+            .broadcast(Broadcast::Strict)
+            .build()?;
+        let errors = layer.validate(input.dims());
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].code(), "E008");
+        assert_eq!(layer.apply(&input).unwrap_err().code(), "E008");
+        let layer = Repeat::builder("a b c -> a b c r")
+            .size("r", 4)
+            .broadcast(Broadcast::Strict)
+            .build()?;
+        assert_eq!(layer.apply(&input)?.dims(), [2, 1, 3, 4]);
+        let layer = Repeat::builder("a b c -> a (b r) c")
+            .size("b", 1)
+            .size("r", 4)
+            .broadcast(Broadcast::Strict)
+            .build()?;
+        assert_eq!(layer.apply(&input)?.dims(), [2, 4, 3]);
+    }
+
+    Ok(())
+}
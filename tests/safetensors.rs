@@ -0,0 +1,108 @@
+#![cfg(feature = "safetensors")]
+
+use candle_einops::safetensors::{axes_metadata, LazyTensor, NamedSafeTensors};
+use candle_einops::{einops, EinopsError, ErrorKind};
+use safetensors::tensor::TensorView;
+use safetensors::{Dtype, SafeTensors};
+
+#[test]
+fn safetensors_patterns() -> Result<(), EinopsError> {
+    let data = (0..24)
+        .flat_map(|x| (x as f32).to_le_bytes())
+        .collect::<Vec<_>>();
+    let view = TensorView::new(Dtype::F32, vec![2, 3, 4], &data).unwrap();
+    let buffer = safetensors::serialize([("weight", view)], &None).unwrap();
+    let tensors = SafeTensors::deserialize(&buffer).unwrap();
+    let weight = LazyTensor::<f32>::from_view(&tensors.tensor("weight").unwrap())?;
+
+    // Pure rearrangements keep reading from the buffer
+    let output = einops!("a b c -> c a b", &weight)?;
+    assert!(output.is_borrowed());
+    assert_eq!(output.shape(), &[4, 2, 3]);
+    assert_eq!(output.to_vec()[..3], [0.0, 4.0, 8.0]);
+
+    // Merging axes of a transposed view has to copy the data
+    let output = einops!("a b c -> c (a b)", &weight)?;
+    assert!(!output.is_borrowed());
+    assert_eq!(output.to_vec()[..3], [0.0, 4.0, 8.0]);
+
+    let output = einops!("a b c -> a 2 b c", &weight)?;
+    assert!(output.is_borrowed());
+    assert_eq!(output.to_vec()[12..15], [0.0, 1.0, 2.0]);
+
+    let output = einops!("a b mean(c) -> a b", &weight)?;
+    assert!(!output.is_borrowed());
+    assert_eq!(output.to_vec()[..2], [1.5, 5.5]);
+
+    Ok(())
+}
+
+#[test]
+fn safetensors_named_axes() -> Result<(), EinopsError> {
+    let data = (0..6)
+        .flat_map(|x| (x as f32).to_le_bytes())
+        .collect::<Vec<_>>();
+    let view = TensorView::new(Dtype::F32, vec![2, 3], &data).unwrap();
+    let metadata = axes_metadata([("weight", "out_features  in_features")])?;
+    assert_eq!(metadata["einops.axes.weight"], "out_features in_features");
+    let buffer =
+        safetensors::serialize([("weight", &view), ("bias", &view)], &Some(metadata)).unwrap();
+
+    let tensors = NamedSafeTensors::deserialize(&buffer)?;
+    assert_eq!(
+        tensors.axes("weight"),
+        Some(vec!["out_features", "in_features"])
+    );
+    assert_eq!(tensors.axes("bias"), None);
+
+    let weight = tensors.tensor::<f32>("weight", "out_features in_features:3")?;
+    assert_eq!(weight.shape(), &[2, 3]);
+    // Layouts in another order transpose the view
+    let weight = tensors.tensor::<f32>("weight", "in_features out_features")?;
+    assert!(weight.is_borrowed());
+    assert_eq!(weight.to_vec(), [0.0, 3.0, 1.0, 4.0, 2.0, 5.0]);
+
+    let error = tensors
+        .tensor::<f32>("weight", "out_features hidden")
+        .unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "einops error: tensor `weight` has the axes `out_features in_features`, expected `out_features hidden`"
+    );
+    let error = tensors
+        .tensor::<f32>("weight", "in_features out_features:4")
+        .unwrap_err();
+    assert_eq!(
+        *error.kind(),
+        ErrorKind::ShapeMismatch {
+            axis: 1,
+            group: "out_features".into(),
+            sizes: Vec::new(),
+            expected: 4,
+            found: 2
+        }
+    );
+    assert_eq!(error.patterns(), ["in_features out_features:4"]);
+    assert_eq!(error.input_shape(), Some(&[3, 2][..]));
+    let error = tensors.tensor::<f32>("weight", "out_features").unwrap_err();
+    assert_eq!(
+        *error.kind(),
+        ErrorKind::RankMismatch {
+            expected: 1,
+            found: 2
+        }
+    );
+    let error = tensors
+        .tensor::<f32>("weight", "(out_features in_features)")
+        .unwrap_err();
+    assert!(matches!(error.kind(), ErrorKind::InvalidPattern { .. }));
+    assert!(tensors.tensor::<f32>("bias", "a b").is_err());
+    assert!(tensors
+        .tensor::<u8>("weight", "out_features in_features")
+        .is_err());
+
+    assert!(axes_metadata([("weight", "a a")]).is_err());
+    assert!(axes_metadata([("weight", "a (b c)")]).is_err());
+
+    Ok(())
+}
@@ -0,0 +1,78 @@
+#![cfg(feature = "candle")]
+
+use candle_core::{Device, Result, Tensor};
+use candle_einops::sliding::{im2col, unfold};
+use candle_einops::ErrorKind;
+
+#[test]
+fn sliding_unfold() -> Result<()> {
+    let input = Tensor::arange(0u32, 2 * 7 * 2, &Device::Cpu)?.reshape(&[2, 7, 2])?;
+
+    let windows = unfold(&input, 1, 3, 2)?;
+    assert_eq!(windows.dims(), [2, 3, 3, 2]);
+    // Window 1 starts at token 2
+    assert_eq!(
+        windows.get(0)?.get(1)?.to_vec2::<u32>()?,
+        [[4, 5], [6, 7], [8, 9]]
+    );
+    // Windows overlap when the stride is smaller than them
+    assert_eq!(unfold(&input, 1, 3, 1)?.dims(), [2, 5, 3, 2]);
+    assert_eq!(unfold(&input, 2, 2, 1)?.dims(), [2, 7, 1, 2]);
+
+    let error = unfold(&input, 1, 8, 1).unwrap_err();
+    assert!(matches!(
+        error.kind(),
+        ErrorKind::ShapeMismatch {
+            axis: 1,
+            expected: 8,
+            found: 7,
+            ..
+        }
+    ));
+    assert_eq!(
+        unfold(&input, 1, 2, 0).unwrap_err().to_string(),
+        "einops error: cannot slide windows of 2 elements by 0"
+    );
+    let error = unfold(&input, 3, 2, 1).unwrap_err();
+    assert_eq!(
+        *error.kind(),
+        ErrorKind::RankMismatch {
+            expected: 4,
+            found: 3
+        }
+    );
+
+    Ok(())
+}
+
+#[test]
+fn sliding_im2col() -> Result<()> {
+    let device = Device::Cpu;
+    let image = Tensor::arange(0f32, 2. * 3. * 5. * 6., &device)?.reshape(&[2, 3, 5, 6])?;
+    let weight = Tensor::arange(0f32, 4. * 3. * 3. * 3., &device)?.reshape(&[4, 3, 3, 3])?;
+
+    let patches = im2col(&image, 3, 2)?;
+    assert_eq!(patches.dims(), [2, 4, 27]);
+
+    // A convolution is the product of the patches with the flattened weight
+    let output = patches
+        .broadcast_matmul(&weight.reshape(&[4, 27])?.t()?)?
+        .transpose(1, 2)?
+        .reshape(&[2, 4, 2, 2])?;
+    let expected = image.conv2d(&weight, 0, 2, 1, 1)?;
+    assert_eq!(
+        output.flatten_all()?.to_vec1::<f32>()?,
+        expected.flatten_all()?.to_vec1::<f32>()?
+    );
+
+    let error = im2col(&image.get(0)?, 3, 1).unwrap_err();
+    assert_eq!(
+        *error.kind(),
+        ErrorKind::RankMismatch {
+            expected: 4,
+            found: 3
+        }
+    );
+
+    Ok(())
+}
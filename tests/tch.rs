@@ -0,0 +1,37 @@
+#![cfg(feature = "tch")]
+
+use candle_einops::{einops, EinopsError};
+use tch::{Device, Kind, Tensor};
+
+#[test]
+fn tch_patterns() -> Result<(), EinopsError> {
+    let input = Tensor::arange(2 * 3 * 4, (Kind::Float, Device::Cpu)).reshape([2, 3, 4]);
+
+    let output = einops!("a b c -> c (a b)", &input)?;
+    assert_eq!(output.size(), [4, 6]);
+    assert_eq!(output.double_value(&[1, 4]), input.double_value(&[1, 1, 1]));
+
+    let output = einops!(
+        "a (b max(b2:2)) c -> a b c",
+        &einops!("a b c -> a (b 2) c", &input)?
+    )?;
+    assert_eq!(output, input);
+
+    let output = einops!("a b sum(c) -> b a", &input)?;
+    assert_eq!(output.size(), [3, 2]);
+
+    Ok(())
+}
+
+#[test]
+fn tch_pixel_shuffle() -> Result<(), EinopsError> {
+    let input = Tensor::arange(2 * 8 * 3 * 3, (Kind::Float, Device::Cpu)).reshape([2, 8, 3, 3]);
+
+    let output = einops!("b (c p1:2 p2:2) h w -> b c (h p1) (w p2)", &input)?;
+    assert_eq!(output, input.pixel_shuffle(2));
+
+    let output = einops!("b c (h p1:2) (w p2:2) -> b (c p1 p2) h w", &output)?;
+    assert_eq!(output, input);
+
+    Ok(())
+}
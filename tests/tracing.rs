@@ -0,0 +1,151 @@
+#![cfg(all(any(feature = "tracing", feature = "trace-steps"), feature = "candle"))]
+
+use std::fmt::Debug;
+use std::sync::{Arc, Mutex};
+
+use candle_core::{Device, Result, Tensor};
+use candle_einops::einops;
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id, Record};
+use tracing::{Event, Metadata, Subscriber};
+
+// Name and value of each field of a span
+type Fields = Vec<(String, String)>;
+
+// Records the name and fields of every span created, and the fields of every
+// event
+#[derive(Clone, Default)]
+struct Spans(Arc<Mutex<Vec<(String, Fields)>>>, Arc<Mutex<Vec<Fields>>>);
+
+struct Visitor(Fields);
+
+impl Visit for Visitor {
+    fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+        self.0
+            .push((field.name().to_string(), format!("{:?}", value)));
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.0.push((field.name().to_string(), value.to_string()));
+    }
+}
+
+impl Subscriber for Spans {
+    fn enabled(&self, _metadata: &Metadata<'_>) -> bool {
+        true
+    }
+
+    fn new_span(&self, span: &Attributes<'_>) -> Id {
+        let mut fields = Visitor(Vec::new());
+        span.record(&mut fields);
+        let mut spans = self.0.lock().unwrap();
+        spans.push((span.metadata().name().to_string(), fields.0));
+        Id::from_u64(spans.len() as u64)
+    }
+
+    fn record(&self, _span: &Id, _values: &Record<'_>) {}
+
+    fn record_follows_from(&self, _span: &Id, _follows: &Id) {}
+
+    fn event(&self, event: &Event<'_>) {
+        let mut fields = Visitor(Vec::new());
+        event.record(&mut fields);
+        self.1.lock().unwrap().push(fields.0);
+    }
+
+    fn enter(&self, _span: &Id) {}
+
+    fn exit(&self, _span: &Id) {}
+}
+
+#[cfg(feature = "tracing")]
+#[test]
+fn tracing_spans() -> Result<()> {
+    let input = Tensor::arange(0f32, 24.0, &Device::Cpu)?.reshape(&[2, 3, 4])?;
+
+    let spans = Spans::default();
+    tracing::subscriber::with_default(spans.clone(), || {
+        einops!("a b c -> c (a b)", &input).map(|_| ())
+    })?;
+
+    let spans = spans.0.lock().unwrap();
+    assert_eq!(
+        *spans,
+        [(
+            "einops".to_string(),
+            vec![
+                ("pattern".to_string(), "a b c -> c (a b)".to_string()),
+                ("input_shape".to_string(), "[2, 3, 4]".to_string()),
+                ("device".to_string(), "Some(Cpu)".to_string()),
+            ]
+        )]
+    );
+
+    Ok(())
+}
+
+#[cfg(all(feature = "trace-steps", feature = "nn"))]
+#[test]
+fn tracing_steps() -> Result<()> {
+    use candle_einops::layers::Rearrange;
+
+    let input = Tensor::arange(0f32, 24.0, &Device::Cpu)?.reshape(&[2, 3, 4])?;
+    let layer = Rearrange::new("a b c -> c (a b)", &[])?;
+
+    let spans = Spans::default();
+    tracing::subscriber::with_default(spans.clone(), || {
+        einops!("a b c -> c (a b)", &input)?;
+        layer.apply(&input).map(|_| ())
+    })?;
+
+    let events = spans.1.lock().unwrap();
+    let messages = events
+        .iter()
+        .map(|fields| {
+            let field = |name: &str| fields.iter().find(|(field, _)| field == name).unwrap();
+            assert_eq!(field("pattern").1, "a b c -> c (a b)");
+            field("message").1.clone()
+        })
+        .collect::<Vec<_>>();
+    // The layer runs the same steps as the macro
+    let step = [
+        "einops \"a b c -> c (a b)\": transpose [2, 3, 4] -> [4, 2, 3]",
+        "einops \"a b c -> c (a b)\": reshape [4, 2, 3] -> [4, 6]",
+    ];
+    assert_eq!(messages, [step, step].concat());
+
+    Ok(())
+}
+
+#[cfg(all(feature = "tracing", feature = "nn"))]
+#[test]
+fn tracing_layers() -> Result<()> {
+    use candle_einops::layers::Rearrange;
+
+    let input = Tensor::arange(0f32, 24.0, &Device::Cpu)?.reshape(&[2, 3, 4])?;
+    let layer = Rearrange::new("a b c -> c (a b)", &[])?;
+
+    let spans = Spans::default();
+    tracing::subscriber::with_default(spans.clone(), || {
+        layer.apply(&input)?;
+        // Planned once for the shape
+        layer.apply(&input).map(|_| ())
+    })?;
+
+    let spans = spans.0.lock().unwrap();
+    let fields = vec![
+        ("pattern".to_string(), "a b c -> c (a b)".to_string()),
+        ("input_shape".to_string(), "[2, 3, 4]".to_string()),
+        ("device".to_string(), "Some(Cpu)".to_string()),
+    ];
+    assert_eq!(
+        *spans,
+        [
+            ("einops_compile".to_string(), fields.clone()),
+            ("einops".to_string(), fields.clone()),
+            ("einops".to_string(), fields),
+        ]
+    );
+
+    Ok(())
+}
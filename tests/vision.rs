@@ -0,0 +1,172 @@
+#![cfg(feature = "candle")]
+
+use candle_core::{Device, Result, Tensor};
+use candle_einops::vision::{
+    depth_to_space_nchw, depth_to_space_nhwc, from_patches, pixel_shuffle, pixel_unshuffle, roll,
+    space_to_depth_nchw, space_to_depth_nhwc, to_patches, window_partition, window_reverse,
+};
+use candle_einops::ErrorKind;
+
+#[test]
+fn vision_patches() -> Result<()> {
+    let image = Tensor::arange(0u32, 2 * 3 * 4 * 6, &Device::Cpu)?.reshape(&[2, 3, 4, 6])?;
+
+    let patches = to_patches(&image, 2)?;
+    assert_eq!(patches.dims(), [2, 6, 12]);
+    // Patch 1 is the second one of the first row, its first pixel is at (0, 2)
+    let patch = patches.get(0)?.get(1)?.to_vec1::<u32>()?;
+    assert_eq!(patch[..3], [2, 26, 50]);
+    assert_eq!(patch[3..6], [3, 27, 51]);
+    assert_eq!(patch[6..9], [8, 32, 56]);
+
+    let restored = from_patches(&patches, 2, 4, 6)?;
+    assert_eq!(
+        restored.flatten_all()?.to_vec1::<u32>()?,
+        image.flatten_all()?.to_vec1::<u32>()?
+    );
+
+    let error = to_patches(&image, 4).unwrap_err();
+    assert!(matches!(
+        error.kind(),
+        ErrorKind::NotDivisible {
+            axis: 3,
+            size: 6,
+            divisor: 4,
+            ..
+        }
+    ));
+    let error = from_patches(&patches, 2, 4, 4).unwrap_err();
+    assert!(matches!(
+        error.kind(),
+        ErrorKind::ShapeMismatch {
+            expected: 4,
+            found: 6,
+            ..
+        }
+    ));
+    let error = to_patches(&image.get(0)?, 2).unwrap_err();
+    assert_eq!(
+        *error.kind(),
+        ErrorKind::RankMismatch {
+            expected: 4,
+            found: 3
+        }
+    );
+
+    Ok(())
+}
+
+#[test]
+fn vision_pixel_shuffle() -> Result<()> {
+    let input = Tensor::arange(0u32, 2 * 8 * 2 * 3, &Device::Cpu)?.reshape(&[2, 8, 2, 3])?;
+
+    let output = pixel_shuffle(&input, 2)?;
+    assert_eq!(output.dims(), [2, 2, 4, 6]);
+    // Channels 4 to 7 become the 2x2 blocks of output channel 1
+    let block = output.get(0)?.get(1)?.narrow(0, 2, 2)?.narrow(1, 4, 2)?;
+    assert_eq!(block.to_vec2::<u32>()?, [[29, 35], [41, 47]]);
+
+    assert_eq!(
+        pixel_unshuffle(&output, 2)?
+            .flatten_all()?
+            .to_vec1::<u32>()?,
+        input.flatten_all()?.to_vec1::<u32>()?
+    );
+
+    let error = pixel_shuffle(&input, 3).unwrap_err();
+    assert!(matches!(
+        error.kind(),
+        ErrorKind::NotDivisible {
+            size: 8,
+            divisor: 9,
+            ..
+        }
+    ));
+    assert!(pixel_unshuffle(&output, 4).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn vision_depth_to_space() -> Result<()> {
+    // A 2x2 block of 3 channels per pixel
+    let input = Tensor::arange(0u32, 12, &Device::Cpu)?.reshape(&[1, 12, 1, 1])?;
+
+    // Channels are ordered by row, column and channel, as in TensorFlow
+    let output = depth_to_space_nchw(&input, 2)?;
+    assert_eq!(output.dims(), [1, 3, 2, 2]);
+    assert_eq!(output.get(0)?.get(1)?.to_vec2::<u32>()?, [[1, 4], [7, 10]]);
+    assert_eq!(
+        space_to_depth_nchw(&output, 2)?
+            .flatten_all()?
+            .to_vec1::<u32>()?,
+        (0..12).collect::<Vec<_>>()
+    );
+
+    let input = input.reshape(&[1, 1, 1, 12])?;
+    let output = depth_to_space_nhwc(&input, 2)?;
+    assert_eq!(output.dims(), [1, 2, 2, 3]);
+    assert_eq!(
+        output.get(0)?.to_vec3::<u32>()?,
+        [[[0, 1, 2], [3, 4, 5]], [[6, 7, 8], [9, 10, 11]]]
+    );
+    let restored = space_to_depth_nhwc(&output, 2)?;
+    assert_eq!(restored.dims(), [1, 1, 1, 12]);
+    assert_eq!(
+        restored.flatten_all()?.to_vec1::<u32>()?,
+        (0..12).collect::<Vec<_>>()
+    );
+
+    // Read as NHWC, the image has a single channel
+    assert!(depth_to_space_nhwc(&input.reshape(&[1, 12, 1, 1])?, 2).is_err());
+    assert!(space_to_depth_nhwc(&output, 3).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn vision_windows() -> Result<()> {
+    let image = Tensor::arange(0u32, 2 * 4 * 6 * 3, &Device::Cpu)?.reshape(&[2, 4, 6, 3])?;
+
+    let windows = window_partition(&image, 2)?;
+    assert_eq!(windows.dims(), [12, 2, 2, 3]);
+    // Window 4 is the second one of the second row of the first image
+    let window = windows.get(4)?.get(0)?.get(0)?.to_vec1::<u32>()?;
+    assert_eq!(window, image.get(0)?.get(2)?.get(2)?.to_vec1::<u32>()?);
+
+    let restored = window_reverse(&windows, 2, 4, 6)?;
+    assert_eq!(
+        restored.flatten_all()?.to_vec1::<u32>()?,
+        image.flatten_all()?.to_vec1::<u32>()?
+    );
+
+    let error = window_partition(&image, 4).unwrap_err();
+    assert!(matches!(
+        error.kind(),
+        ErrorKind::NotDivisible {
+            axis: 2,
+            size: 6,
+            divisor: 4,
+            ..
+        }
+    ));
+    assert!(window_reverse(&windows, 2, 4, 8).is_err());
+
+    let row = Tensor::arange(0u32, 5, &Device::Cpu)?;
+    assert_eq!(roll(&row, 0, 2)?.to_vec1::<u32>()?, [3, 4, 0, 1, 2]);
+    assert_eq!(roll(&row, 0, -1)?.to_vec1::<u32>()?, [1, 2, 3, 4, 0]);
+    assert_eq!(roll(&row, 0, 10)?.to_vec1::<u32>()?, [0, 1, 2, 3, 4]);
+    assert!(roll(&row, 1, 1).is_err());
+
+    // Rolling the images back after the windows restores them
+    let shifted = roll(&roll(&image, 1, -1)?, 2, -1)?;
+    let windows = window_partition(&shifted, 2)?;
+    let shifted = window_reverse(&windows, 2, 4, 6)?;
+    let restored = roll(&roll(&shifted, 1, 1)?, 2, 1)?;
+    assert_eq!(
+        restored.flatten_all()?.to_vec1::<u32>()?,
+        image.flatten_all()?.to_vec1::<u32>()?
+    );
+
+    Ok(())
+}